@@ -25,6 +25,95 @@ impl Default for LogRecord {
     }
 }
 
+/// The number of bytes of entry data that fit in a `LogReadout`
+pub const LOG_READOUT_DATA_LEN: usize = 4096 - 24;
+
+/// Each entry in `LogReadout::data` starts with a header of this many bytes: the sequence number
+/// as a little-endian `u64`, the sending PID, the level, the line number as a little-endian `u32`
+/// (0 if unknown), and the lengths of the module, file and message as little-endian `u16`s. The
+/// module, file and message bytes follow the header in that order.
+pub const LOG_ENTRY_HEADER_LEN: usize = 20;
+
+/// A page of records read back from the log server's ring buffer with `ReadLog`
+#[repr(C, align(4096))]
+pub struct LogReadout {
+    /// In: the sequence number of the first record wanted. Out: the sequence number to ask for
+    /// next time.
+    pub since: u64,
+    /// Out: the sequence number of the oldest record still in the ring buffer. If this is later
+    /// than the `since` that was asked for, the records in between have been overwritten.
+    pub oldest: u64,
+    /// Out: the number of entries in `data`
+    pub count: u32,
+    /// Out: the number of valid bytes in `data`
+    pub length: u32,
+    pub data: [u8; LOG_READOUT_DATA_LEN],
+}
+
+impl Default for LogReadout {
+    fn default() -> Self {
+        LogReadout { since: 0, oldest: 0, count: 0, length: 0, data: [0u8; LOG_READOUT_DATA_LEN] }
+    }
+}
+
+/// Passed in `ModuleLevel::level` to remove the filter for a module
+pub const LEVEL_FILTER_UNSET: u32 = u32::MAX;
+
+/// A per-module level filter, applied by the log server to every record it receives
+#[repr(C, align(4096))]
+pub struct ModuleLevel {
+    pub module_length: u32,
+    /// The filter applies to this module and every module below it
+    pub module: [u8; 128],
+    /// A `log::LevelFilter` as a `u32`, or `LEVEL_FILTER_UNSET`
+    pub level: u32,
+}
+
+impl Default for ModuleLevel {
+    fn default() -> Self { ModuleLevel { module_length: 0, module: [0u8; 128], level: LEVEL_FILTER_UNSET } }
+}
+
+/// A server that wants to hear about panics. Every time a process finishes reporting a panic,
+/// the log server sends a non-blocking Scalar message with ID `id` to `sid`, with the panicking
+/// process' PID in `arg1` and the ID of its crash report in `arg2`.
+#[repr(C, align(4096))]
+pub struct PanicHook {
+    pub sid: [u32; 4],
+    pub id: u32,
+}
+
+/// The number of bytes of panic text that fit in a `CrashReport`
+pub const CRASH_TEXT_LEN: usize = 4096 - 80;
+
+/// A crash report assembled by the log server from a panicking process' `ProgramName` and
+/// `PanicMessage`s, read back with `ReadCrashReport`. Only the most recent reports are kept.
+///
+/// The kernel does not pass exception state on to userspace, so reports carry no register dump.
+#[repr(C, align(4096))]
+pub struct CrashReport {
+    /// In: the ID of the report wanted. Out: unchanged if the report was found, or 0 if not.
+    pub id: u32,
+    pub pid: u32,
+    /// 0 if the process never sent its `ProgramName`
+    pub name_length: u32,
+    pub name: [u8; 64],
+    pub text_length: u32,
+    pub text: [u8; CRASH_TEXT_LEN],
+}
+
+impl Default for CrashReport {
+    fn default() -> Self {
+        CrashReport {
+            id: 0,
+            pid: 0,
+            name_length: 0,
+            name: [0u8; 64],
+            text_length: 0,
+            text: [0u8; CRASH_TEXT_LEN],
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, num_derive::FromPrimitive, num_derive::ToPrimitive)]
 pub enum Opcode {
     /// A `LogRecord` message, delivering structured log output
@@ -44,6 +133,19 @@ pub enum Opcode {
     TryHookUsbMirror = 4,
     UnhookUsbMirror = 5,
 
+    /// Read back records from the ring buffer of recent log records, as a mutably-lent `LogReadout`
+    ReadLog = 6,
+
+    /// Set or clear the level filter of a module, as a lent `ModuleLevel`. Only accepted from the shell and
+    /// the status process.
+    SetModuleLevel = 7,
+
+    /// Ask to be notified of panics, as a lent `PanicHook`
+    HookPanicNotifier = 8,
+
+    /// Read back a crash report, as a mutably-lent `CrashReport`
+    ReadCrashReport = 9,
+
     /// A panic occurred, and a panic log is forthcoming
     PanicStarted = 1000,
 
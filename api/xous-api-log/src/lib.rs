@@ -85,6 +85,7 @@ pub fn init() -> Result<(), LogError> {
     );
     log::set_logger(&XOUS_LOGGER).map_err(|_| LogError::LoggerExists)?;
     log::set_max_level(log::LevelFilter::Info);
+    send_hosted_program_name();
     Ok(())
 }
 
@@ -94,7 +95,216 @@ pub fn init_wait() -> Result<(), ()> {
     XOUS_LOGGER_CONNECTION.store(cid, Ordering::Relaxed);
     log::set_logger(&XOUS_LOGGER).or(Err(()))?;
     log::set_max_level(log::LevelFilter::Info);
+    send_hosted_program_name();
     Ok(())
 }
 
+/// In hosted mode, the process name is handed to us by the launcher
+fn send_hosted_program_name() {
+    #[cfg(not(any(target_os = "none", target_os = "xous", feature = "nostd")))]
+    if let Ok(name) = std::env::var("XOUS_PROCESS_NAME") {
+        set_program_name(&name).ok();
+    }
+}
+
 pub fn resume() { XOUS_LOGGER.resume(); }
+
+fn connection() -> Result<xous::CID, xous::Error> {
+    match XOUS_LOGGER_CONNECTION.load(Ordering::Relaxed) {
+        0 => Err(xous::Error::ServerNotFound),
+        cid => Ok(cid),
+    }
+}
+
+/// Set the level filter for `module` and every module below it, or remove it if `level` is
+/// `None`. The filter is applied by the log server to records from every process, and the
+/// most specific filter wins. Records that are filtered out are neither printed nor kept in
+/// the ring buffer. Only the shell and the status process may set filters; requests from
+/// any other process are ignored.
+pub fn set_module_level(module: &str, level: Option<log::LevelFilter>) -> Result<(), xous::Error> {
+    let mut request = api::ModuleLevel::default();
+    let module = module.as_bytes();
+    if module.len() > request.module.len() {
+        return Err(xous::Error::InvalidString);
+    }
+    request.module[..module.len()].copy_from_slice(module);
+    request.module_length = module.len() as u32;
+    request.level = level.map(|l| l as u32).unwrap_or(api::LEVEL_FILTER_UNSET);
+
+    let buf = unsafe {
+        xous::MemoryRange::new(
+            &request as *const api::ModuleLevel as usize,
+            core::mem::size_of::<api::ModuleLevel>(),
+        )?
+    };
+    xous::send_message(
+        connection()?,
+        xous::Message::new_lend(api::Opcode::SetModuleLevel.to_usize().unwrap(), buf, None, None),
+    )
+    .map(|_| ())
+}
+
+/// Ask the log server to send a Scalar message with ID `id` to `sid` every time a process
+/// panics. The PID of the process is passed in `arg1`.
+pub fn hook_panic_notifier(sid: xous::SID, id: u32) -> Result<(), xous::Error> {
+    let hook = api::PanicHook { sid: sid.to_array(), id };
+    let buf = unsafe {
+        xous::MemoryRange::new(
+            &hook as *const api::PanicHook as usize,
+            core::mem::size_of::<api::PanicHook>(),
+        )?
+    };
+    xous::send_message(
+        connection()?,
+        xous::Message::new_lend(api::Opcode::HookPanicNotifier.to_usize().unwrap(), buf, None, None),
+    )
+    .map(|_| ())
+}
+
+/// Tell the log server what this program is called, so that crash reports can name it
+pub fn set_program_name(name: &str) -> Result<(), xous::Error> {
+    let conn = connection()?;
+    let mut buf = xous::StringBuffer::with_capacity(name.len().max(1));
+    write!(buf, "{}", name).map_err(|_| xous::Error::InvalidString)?;
+    buf.lend(conn, api::Opcode::ProgramName.to_u32().unwrap()).map(|_| ())
+}
+
+/// A crash report read back from the log server
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+#[derive(Debug, Clone)]
+pub struct CrashInfo {
+    pub id: u32,
+    pub pid: u8,
+    /// The name the process gave with `set_program_name()`, if any
+    pub name: Option<String>,
+    /// The text of the panic
+    pub text: String,
+}
+
+/// Read back crash report `id`, as given by a panic notification. Returns `None` if the log
+/// server no longer holds that report.
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+pub fn read_crash_report(id: u32) -> Result<Option<CrashInfo>, xous::Error> {
+    let mut report = api::CrashReport { id, ..Default::default() };
+    let buf = unsafe {
+        xous::MemoryRange::new(
+            &mut report as *mut api::CrashReport as usize,
+            core::mem::size_of::<api::CrashReport>(),
+        )?
+    };
+    xous::send_message(
+        connection()?,
+        xous::Message::new_lend_mut(api::Opcode::ReadCrashReport.to_usize().unwrap(), buf, None, None),
+    )?;
+    if id == 0 || report.id != id {
+        return Ok(None);
+    }
+    let name = &report.name[..(report.name_length as usize).min(report.name.len())];
+    let text = &report.text[..(report.text_length as usize).min(report.text.len())];
+    Ok(Some(CrashInfo {
+        id,
+        pid: report.pid as u8,
+        name: if name.is_empty() { None } else { Some(String::from_utf8_lossy(name).into_owned()) },
+        text: String::from_utf8_lossy(text).into_owned(),
+    }))
+}
+
+/// A record read back from the log server's ring buffer
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Sequence numbers increase by one for every record the log server keeps
+    pub seq: u64,
+    pub pid: u8,
+    pub level: log::Level,
+    pub module: String,
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+impl LogEntry {
+    /// Decode the entry at the start of `data`, returning it along with its encoded length
+    fn decode(data: &[u8]) -> Option<(LogEntry, usize)> {
+        let header = data.get(..api::LOG_ENTRY_HEADER_LEN)?;
+        let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]) as usize;
+        let (module_len, file_len, message_len) = (u16_at(14), u16_at(16), u16_at(18));
+        let module_start = api::LOG_ENTRY_HEADER_LEN;
+        let file_start = module_start + module_len;
+        let message_start = file_start + file_len;
+        let end = message_start + message_len;
+        let body = data.get(..end)?;
+        let level = match header[9] {
+            1 => log::Level::Error,
+            2 => log::Level::Warn,
+            3 => log::Level::Info,
+            4 => log::Level::Debug,
+            _ => log::Level::Trace,
+        };
+        let mut seq = [0u8; 8];
+        seq.copy_from_slice(&header[..8]);
+        let line = u32::from_le_bytes([header[10], header[11], header[12], header[13]]);
+        let entry = LogEntry {
+            seq: u64::from_le_bytes(seq),
+            pid: header[8],
+            level,
+            module: String::from_utf8_lossy(&body[module_start..file_start]).into_owned(),
+            file: String::from_utf8_lossy(&body[file_start..message_start]).into_owned(),
+            line: core::num::NonZeroU32::new(line).map(|l| l.get()),
+            message: String::from_utf8_lossy(&body[message_start..end]).into_owned(),
+        };
+        Some((entry, end))
+    }
+}
+
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+impl core::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} PID {} {}:{}: {} ({}",
+            self.seq, self.pid, self.level, self.module, self.message, self.file
+        )?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Read back every record in the log server's ring buffer with a sequence number of `since`
+/// or later. Returns the records along with the sequence number to pass next time in order to
+/// pick up where this call left off. The ring buffer only holds recent records, so the first
+/// record returned may be later than `since`.
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+pub fn read_log(since: u64) -> Result<(Vec<LogEntry>, u64), xous::Error> {
+    let conn = connection()?;
+    let mut entries = Vec::new();
+    let mut readout = api::LogReadout { since, ..Default::default() };
+    loop {
+        let buf = unsafe {
+            xous::MemoryRange::new(
+                &mut readout as *mut api::LogReadout as usize,
+                core::mem::size_of::<api::LogReadout>(),
+            )?
+        };
+        xous::send_message(
+            conn,
+            xous::Message::new_lend_mut(api::Opcode::ReadLog.to_usize().unwrap(), buf, None, None),
+        )?;
+        if readout.count == 0 {
+            break;
+        }
+        let data = &readout.data[..(readout.length as usize).min(readout.data.len())];
+        let mut offset = 0;
+        for _ in 0..readout.count {
+            let Some((entry, len)) = LogEntry::decode(&data[offset..]) else {
+                return Err(xous::Error::InternalError);
+            };
+            entries.push(entry);
+            offset += len;
+        }
+    }
+    Ok((entries, readout.since))
+}
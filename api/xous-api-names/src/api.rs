@@ -81,8 +81,60 @@ pub enum Opcode {
     /// }
     /// ```
     TryConnect = 7,
+
+    /// Sent by the kernel when a process that is connected to the name server exits.
+    /// Any names registered by that process are removed. Not for use by clients.
+    ///
+    /// # Message Types
+    ///
+    ///     * Scalar
+    ///
+    /// # Arguments
+    ///
+    /// `arg1` is the PID of the process that exited.
+    PeerExited = 8,
+
+    /// Return the most recent connections refused by the connection policy.
+    ///
+    /// # Message Types
+    ///
+    ///     * MutableLend
+    ///
+    /// # Arguments
+    ///
+    /// A `DenialList`, which is overwritten with the denial log.
+    ListDenials = 9,
+
+    /// Return a page of the servers that are currently registered, sorted by name.
+    ///
+    /// # Message Types
+    ///
+    ///     * MutableLend
+    ///
+    /// # Arguments
+    ///
+    /// A `ServerList` with `start` set to the index of the first record to return. It is
+    /// overwritten with up to `SERVER_LIST_LEN` records and the total number of servers.
+    ListServers = 10,
+
+    /// Return the registration details of a single server.
+    ///
+    /// # Message Types
+    ///
+    ///     * MutableLend
+    ///
+    /// # Arguments
+    ///
+    /// A `ServerQuery` with `name` set. `record` is filled in if the server is registered.
+    ServerInfo = 11,
 }
 
+/// The number of policy denials kept by the name server
+pub const DENIAL_LOG_LEN: usize = 16;
+
+/// The number of servers returned by each `ListServers` request
+pub const SERVER_LIST_LEN: usize = 16;
+
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct Registration {
     pub name: xous_ipc::String<64>,
@@ -102,6 +154,56 @@ pub struct AuthenticatedLookup {
     pub response: [u32; 8],
 }
 
+/// A connection that was refused by the name server's connection policy
+#[derive(Debug, Copy, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Denial {
+    /// The server that was requested
+    pub name: xous_ipc::String<64>,
+    /// The PID of the process that requested the connection
+    pub pid: u8,
+    pub reason: DenialReason,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DenialList {
+    /// Denials in the order they happened, oldest first
+    pub list: [Option<Denial>; DENIAL_LOG_LEN],
+}
+
+/// The registration details of a server. The SID is never disclosed.
+#[derive(Debug, Copy, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct ServerRecord {
+    pub name: xous_ipc::String<64>,
+    /// The PID of the process that registered the name, if known
+    pub owner: Option<u8>,
+    /// The number of connections currently brokered to the server
+    pub connections: u32,
+    /// The connection limit given at registration. `None` means unlimited.
+    pub max_connections: Option<u32>,
+}
+
+impl ServerRecord {
+    /// The number of connections that may still be made, or `None` if unlimited
+    pub fn remaining(&self) -> Option<u32> {
+        self.max_connections.map(|max| max.saturating_sub(self.connections))
+    }
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct ServerList {
+    /// The index of the first record in `list`
+    pub start: u32,
+    /// The total number of servers registered
+    pub total: u32,
+    pub list: [Option<ServerRecord>; SERVER_LIST_LEN],
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct ServerQuery {
+    pub name: xous_ipc::String<64>,
+    pub record: Option<ServerRecord>,
+}
+
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 #[repr(C)]
 pub struct AuthenticateRequest {
@@ -32,3 +32,14 @@ pub enum Return {
     /// disconnect)
     Success,
 }
+
+/// Why the connection policy refused a connection
+#[derive(Debug, Copy, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub enum DenialReason {
+    /// The requesting process is not on the server's client list
+    NotListed,
+    /// The requesting process already holds as many connections as it is allowed
+    ConnectionLimit,
+    /// The server only accepts connections during boot, and `TrustedInitDone` has passed
+    AfterTrustedInit,
+}
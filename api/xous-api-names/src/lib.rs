@@ -199,6 +199,49 @@ impl XousNames {
             Err(xous::Error::InternalError)
         }
     }
+
+    /// Returns the most recent connection requests that were refused by the name
+    /// server's connection policy, oldest first.
+    pub fn list_denials(&self) -> Result<Vec<api::Denial>, xous::Error> {
+        let list = api::DenialList { list: [None; api::DENIAL_LOG_LEN] };
+        let mut buf = Buffer::into_buf(list).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, api::Opcode::ListDenials.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let list = buf.to_original::<api::DenialList, _>().or(Err(xous::Error::InternalError))?;
+        Ok(list.list.iter().filter_map(|d| *d).collect())
+    }
+
+    /// Returns every server that is currently registered, sorted by name. This is meant
+    /// for debugging, and does not reveal the servers' SIDs.
+    pub fn list(&self) -> Result<Vec<api::ServerRecord>, xous::Error> {
+        let mut servers = Vec::new();
+        loop {
+            let page =
+                api::ServerList { start: servers.len() as u32, total: 0, list: [None; api::SERVER_LIST_LEN] };
+            let mut buf = Buffer::into_buf(page).or(Err(xous::Error::InternalError))?;
+            buf.lend_mut(self.conn, api::Opcode::ListServers.to_u32().unwrap())
+                .or(Err(xous::Error::InternalError))?;
+            let page = buf.to_original::<api::ServerList, _>().or(Err(xous::Error::InternalError))?;
+            let previous = servers.len();
+            servers.extend(page.list.iter().filter_map(|r| *r));
+            // servers may come and go between pages, so stop as soon as a page comes back empty
+            if servers.len() >= page.total as usize || servers.len() == previous {
+                break;
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Returns the registration details of the server with `name`, or `None` if no such
+    /// server is registered.
+    pub fn server_info(&self, name: &str) -> Result<Option<api::ServerRecord>, xous::Error> {
+        let query = api::ServerQuery { name: String::<64>::from_str(name), record: None };
+        let mut buf = Buffer::into_buf(query).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, api::Opcode::ServerInfo.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let query = buf.to_original::<api::ServerQuery, _>().or(Err(xous::Error::InternalError))?;
+        Ok(query.record)
+    }
 }
 
 use core::sync::atomic::{AtomicU32, Ordering};
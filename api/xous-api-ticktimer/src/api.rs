@@ -71,6 +71,80 @@ pub enum Opcode {
     /// *arg1*: The integer that matches the Condition value
     FreeCondition = 11,
 
+    /// Schedule a one-shot or periodic timer that delivers a Scalar message to a server of
+    /// the caller's choosing each time it expires.
+    ///
+    /// # Arguments
+    ///
+    /// A `TimerHook`, lent mutably. On return its `timer_id` field is set to the ID of the
+    /// new timer, or `None` if the timer could not be created.
+    ScheduleTimer = 12,
+
+    /// Cancel a timer previously created with `ScheduleTimer`. Only the process that
+    /// scheduled a timer may cancel it.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: The ID of the timer to cancel
+    ///
+    /// # Returns
+    ///
+    /// `1` if the timer was cancelled, `0` if no such timer was pending
+    CancelTimer = 13,
+
+    /// Lock the given RwLock for reading, blocking if it is held for writing or if a writer
+    /// is already waiting for it. Waiters are served in the order they arrived.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: An integer of some sort, such as the address of the RwLock
+    LockRead = 14,
+
+    /// Lock the given RwLock for writing, blocking until every reader and writer that is
+    /// holding or waiting for it has released it. Waiters are served in the order they arrived.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: An integer of some sort, such as the address of the RwLock
+    LockWrite = 15,
+
+    /// Release an RwLock that was locked for reading or writing
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: An integer of some sort, such as the address of the RwLock
+    Unlock = 16,
+
+    /// Free an RwLock. Any threads still waiting for it are released.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: The integer that matches the RwLock value
+    FreeRwLock = 17,
+
+    /// Take one permit from the given counting semaphore, blocking until one is available.
+    /// Semaphores start out with no permits. Waiters are served in the order they arrived.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: An integer of some sort, such as the address of the semaphore
+    AcquireSemaphore = 18,
+
+    /// Add permits to the given counting semaphore, waking waiters as permits allow
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: An integer of some sort, such as the address of the semaphore
+    /// *arg2*: The number of permits to add
+    ReleaseSemaphore = 19,
+
+    /// Free a semaphore. Any threads still waiting for it are released.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: The integer that matches the semaphore value
+    FreeSemaphore = 20,
+
     /// Invalid call -- an error occurred decoding the opcode
     InvalidCall = u32::MAX as usize,
 }
@@ -79,3 +153,19 @@ pub enum Opcode {
 pub struct VersionString {
     pub version: xous_ipc::String<512>,
 }
+
+/// Describes a timer requested with `ScheduleTimer`. Each expiry is delivered straight to the
+/// server `sid` as a Scalar message with ID `opcode`, carrying the timer ID in `arg1`.
+#[derive(Debug, Copy, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct TimerHook {
+    /// The server to deliver expiries to. The ticktimer makes its own connection to it.
+    pub sid: (u32, u32, u32, u32),
+    /// ID of the scalar message to send (e.g. the discriminant of the caller's opcode enum)
+    pub opcode: u32,
+    /// Time until the first expiry, and between subsequent expiries, in milliseconds
+    pub period_ms: u32,
+    /// If `true`, the timer is removed after it first expires
+    pub one_shot: bool,
+    /// Filled in by the ticktimer server
+    pub timer_id: Option<u32>,
+}
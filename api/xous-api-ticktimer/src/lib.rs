@@ -3,7 +3,7 @@
 pub mod api;
 
 use num_traits::ToPrimitive;
-use xous::{send_message, Error, CID};
+use xous::{send_message, Error, Message, CID};
 use xous_semver::SemVer;
 
 #[derive(Debug)]
@@ -163,6 +163,161 @@ impl Ticktimer {
         .map(|r| r == xous::Result::Scalar1(0))
         .expect("couldn't notify condition");
     }
+
+    /// Lock the given RwLock for reading, blocking while it is held for writing. Unlike Mutexes,
+    /// RwLocks are managed entirely by the ticktimer, so the lock must be released with
+    /// `unlock_rwlock()` rather than by the caller.
+    ///
+    /// Waiters are served in the order they arrived: once a writer is waiting, later
+    /// readers queue up behind it even if the lock is currently held for reading.
+    ///
+    /// # Arguments:
+    ///
+    ///     * rwlock: A `usize` referring to the RwLock. This is probably a pointer, but can be any `usize`
+    ///
+    /// # Returns:
+    ///
+    ///     * Ok(()) once the lock is held
+    ///     * Err(ServerNotFound) if the RwLock was freed while waiting for it
+    pub fn lock_read(&self, rwlock: usize) -> Result<(), Error> {
+        self.blocking_sync_op(api::Opcode::LockRead, rwlock)
+    }
+
+    /// Lock the given RwLock for writing, blocking while it is held by anyone else. Waiters
+    /// are served in the order they arrived.
+    ///
+    /// # Arguments:
+    ///
+    ///     * rwlock: A `usize` referring to the RwLock. This is probably a pointer, but can be any `usize`
+    ///
+    /// # Returns:
+    ///
+    ///     * Ok(()) once the lock is held
+    ///     * Err(ServerNotFound) if the RwLock was freed while waiting for it
+    pub fn lock_write(&self, rwlock: usize) -> Result<(), Error> {
+        self.blocking_sync_op(api::Opcode::LockWrite, rwlock)
+    }
+
+    /// Release an RwLock that was locked with `lock_read()` or `lock_write()`. Does not block.
+    ///
+    /// # Arguments:
+    ///
+    ///     * rwlock: A `usize` referring to the RwLock. This is probably a pointer, but can be any `usize`
+    pub fn unlock_rwlock(&self, rwlock: usize) {
+        send_message(
+            self.conn,
+            Message::new_scalar(api::Opcode::Unlock.to_usize().unwrap(), rwlock, 0, 0, 0),
+        )
+        .expect("couldn't unlock rwlock");
+    }
+
+    /// Release the ticktimer's state for an RwLock that is no longer in use
+    pub fn free_rwlock(&self, rwlock: usize) {
+        send_message(
+            self.conn,
+            Message::new_scalar(api::Opcode::FreeRwLock.to_usize().unwrap(), rwlock, 0, 0, 0),
+        )
+        .expect("couldn't free rwlock");
+    }
+
+    /// Take one permit from the given counting semaphore, blocking until one is available.
+    /// Semaphores start out with no permits, so the owner should call `release_semaphore()`
+    /// with the initial count before using it. Waiters are served in the order they arrived.
+    ///
+    /// # Arguments:
+    ///
+    ///     * sem: A `usize` referring to the semaphore. This is probably a pointer, but can be any `usize`
+    ///
+    /// # Returns:
+    ///
+    ///     * Ok(()) once a permit has been taken
+    ///     * Err(ServerNotFound) if the semaphore was freed while waiting for it
+    pub fn acquire_semaphore(&self, sem: usize) -> Result<(), Error> {
+        self.blocking_sync_op(api::Opcode::AcquireSemaphore, sem)
+    }
+
+    /// Add `count` permits to the given counting semaphore. Does not block.
+    ///
+    /// # Arguments:
+    ///
+    ///     * sem: A `usize` referring to the semaphore. This is probably a pointer, but can be any `usize`
+    ///     * count: The number of permits to add
+    pub fn release_semaphore(&self, sem: usize, count: usize) {
+        send_message(
+            self.conn,
+            Message::new_scalar(api::Opcode::ReleaseSemaphore.to_usize().unwrap(), sem, count, 0, 0),
+        )
+        .expect("couldn't release semaphore");
+    }
+
+    /// Release the ticktimer's state for a semaphore that is no longer in use
+    pub fn free_semaphore(&self, sem: usize) {
+        send_message(
+            self.conn,
+            Message::new_scalar(api::Opcode::FreeSemaphore.to_usize().unwrap(), sem, 0, 0, 0),
+        )
+        .expect("couldn't free semaphore");
+    }
+
+    fn blocking_sync_op(&self, op: api::Opcode, id: usize) -> Result<(), Error> {
+        match send_message(self.conn, Message::new_blocking_scalar(op.to_usize().unwrap(), id, 0, 0, 0))? {
+            xous::Result::Scalar1(0) => Ok(()),
+            xous::Result::Scalar1(_) => Err(Error::ServerNotFound),
+            _ => Err(Error::InternalError),
+        }
+    }
+
+    /// Schedule a timer that sends a Scalar message with ID `opcode` to the server `sid` once
+    /// `period_ms` milliseconds have passed, and then every `period_ms` milliseconds unless
+    /// `one_shot` is set. `sid` is typically the caller's own main loop; the ticktimer connects to
+    /// it directly, as a CID has no meaning outside the process that created it. The timer ID is
+    /// passed in `arg1` of every message it sends.
+    ///
+    /// A timer keeps running after the `Ticktimer` that created it is dropped, until it is
+    /// cancelled, its one expiry is delivered, or its server can no longer be reached. If the
+    /// server's queue is full when a periodic timer expires, that expiry is skipped; a one-shot
+    /// timer is retried shortly afterwards instead.
+    ///
+    /// # Returns:
+    ///
+    ///     * The ID of the new timer, which may be passed to `cancel_timer()`
+    pub fn schedule_timer(
+        &self,
+        period_ms: u32,
+        one_shot: bool,
+        sid: xous::SID,
+        opcode: u32,
+    ) -> Result<u32, Error> {
+        if period_ms == 0 {
+            return Err(Error::InvalidLimit);
+        }
+        let hook = api::TimerHook { sid: sid.to_u32(), opcode, period_ms, one_shot, timer_id: None };
+        let mut buf = xous_ipc::Buffer::into_buf(hook).or(Err(Error::InternalError))?;
+        buf.lend_mut(self.conn, api::Opcode::ScheduleTimer.to_u32().unwrap())?;
+        let hook = buf.to_original::<api::TimerHook, _>().or(Err(Error::InternalError))?;
+        hook.timer_id.ok_or(Error::OutOfMemory)
+    }
+
+    /// Cancel a timer created by `schedule_timer()`. A message from the timer may already be
+    /// in flight, so callers should be prepared to receive one more event after cancelling.
+    ///
+    /// # Returns:
+    ///
+    ///     * true: the timer was cancelled
+    ///     * false: there was no such timer, or a one-shot timer had already expired
+    pub fn cancel_timer(&self, timer_id: u32) -> Result<bool, Error> {
+        let response = send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                api::Opcode::CancelTimer.to_usize().unwrap(),
+                timer_id as usize,
+                0,
+                0,
+                0,
+            ),
+        )?;
+        if let xous::Result::Scalar1(result) = response { Ok(result != 0) } else { Err(Error::InternalError) }
+    }
 }
 
 use core::sync::atomic::{AtomicU32, Ordering};
@@ -13,6 +13,7 @@ use crate::SERVER_NAME_APP_LOADER;
 pub(crate) struct AppLoader {
     gam: Gam,
     modals: Modals,
+    net: net::NetManager,
     auth: [u32; 4],
     ticktimer: ticktimer_server::Ticktimer,
     menu: MenuMatic,
@@ -82,6 +83,7 @@ impl AppLoader {
         AppLoader {
             gam,
             modals,
+            net: net::NetManager::new(),
             auth,
             conn,
             ticktimer,
@@ -165,6 +167,16 @@ impl AppLoader {
             xous::send_message(spawn.cid, xous::Message::new_blocking_scalar(2, 1, 2, 3, 4)).unwrap();
         assert_eq!(xous::Result::Scalar1(2), result);
 
+        // the app only gets the network access the user grants it
+        if let Err(e) = self.net.sandbox_process(spawn.pid, name.to_str()) {
+            log::error!("Couldn't sandbox the app: {:?}", e);
+            self.modals.finish_progress().expect("Couldn't close progressbar");
+            self.modals
+                .show_notification(t!("apploader.addapp.error", locales::LANG), None)
+                .expect("Couldn't show modal");
+            return;
+        }
+
         self.modals.update_progress(2).expect("Couldn't update progress");
 
         // load the app from the binary file
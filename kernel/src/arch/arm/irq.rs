@@ -19,6 +19,9 @@ static mut PREVIOUS_PAIR: Option<(PID, TID)> = None;
 
 pub unsafe fn set_isr_return_pair(pid: PID, tid: TID) { PREVIOUS_PAIR = Some((pid, tid)); }
 
+/// The process that was running when the interrupt currently being handled came in
+pub fn interrupted_pid() -> Option<PID> { unsafe { PREVIOUS_PAIR.map(|(pid, _)| pid) } }
+
 #[cfg(feature = "gdb-stub")]
 pub unsafe fn take_isr_return_pair() -> Option<(PID, TID)> { PREVIOUS_PAIR.take() }
 
@@ -170,7 +170,16 @@ impl Process {
         process.hardware_thread - 1
     }
 
-    pub fn thread_exists(&self, tid: TID) -> bool { self.thread(tid).resume_addr != 0 }
+    pub fn thread_exists(&self, tid: TID) -> bool {
+        let process = unsafe { &*PROCESS };
+        process.threads.get(tid).map(|thread| thread.resume_addr != 0).unwrap_or(false)
+    }
+
+    /// Return the number of threads allocated in this process
+    pub fn thread_count(&self) -> usize {
+        let process = unsafe { &*PROCESS };
+        process.threads.iter().filter(|thread| thread.resume_addr != 0).count()
+    }
 
     /// Set the current thread number.
     pub fn set_tid(&mut self, thread: TID) -> Result<(), xous_kernel::Error> {
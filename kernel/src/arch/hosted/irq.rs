@@ -8,3 +8,6 @@ pub fn enable_irq(_irq_no: usize) { unimplemented!() }
 pub fn disable_irq(_irq_no: usize) { unimplemented!() }
 
 pub unsafe fn set_isr_return_pair(_pid: PID, _ctx: TID) { unimplemented!() }
+
+/// There are no interrupts in hosted mode
+pub fn interrupted_pid() -> Option<PID> { None }
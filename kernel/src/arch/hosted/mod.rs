@@ -349,7 +349,7 @@ pub fn idle() -> bool {
     let pid1_init = ProcessInit { key: ProcessKey::new(pid1_key) };
     let process_1 = SystemServices::with_mut(|ss| ss.create_process(pid1_init)).unwrap();
     assert_eq!(process_1.pid().get(), 1);
-    let _tid1 = SystemServices::with_mut(|ss| ss.create_thread(process_1.pid(), ThreadInit {})).unwrap();
+    let _tid1 = SystemServices::with_mut(|ss| ss.create_thread(process_1.pid(), ThreadInit::default())).unwrap();
 
     let listen_addr = env::var("XOUS_LISTEN_ADDR")
         .map(|s| {
@@ -426,7 +426,7 @@ pub fn idle() -> bool {
                 // similar to having one core for each process
                 if new_pid != PID::new(1).unwrap() {
                     SystemServices::with_mut(|ss| {
-                        ss.create_thread(new_pid, ThreadInit {})?;
+                        ss.create_thread(new_pid, ThreadInit::default())?;
                         ss.switch_to_thread(new_pid, None)
                     })
                     .unwrap();
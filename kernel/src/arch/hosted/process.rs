@@ -259,6 +259,19 @@ impl Process {
         })
     }
 
+    /// Return the number of threads allocated in this process
+    pub fn thread_count(&self) -> usize {
+        PROCESS_TABLE.with(|pt| {
+            let process_table = pt.borrow();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            if let Some(Some(process)) = process_table.table.get(current_pid_idx) {
+                process.threads.iter().filter(|t| t.allocated).count()
+            } else {
+                0
+            }
+        })
+    }
+
     pub fn set_thread_result(&mut self, tid: TID, result: xous_kernel::Result) {
         assert!(tid > 0);
         PROCESS_TABLE.with(|pt| {
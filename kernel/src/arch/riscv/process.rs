@@ -227,8 +227,15 @@ impl Process {
     }
 
     pub fn thread_exists(&self, tid: TID) -> bool {
+        let process = unsafe { &*PROCESS };
         let tid = fixup_irq(tid);
-        self.thread(tid).sepc != 0
+        process.threads.get(tid).map(|thread| thread.sepc != 0).unwrap_or(false)
+    }
+
+    /// Return the number of threads allocated in this process
+    pub fn thread_count(&self) -> usize {
+        let process = unsafe { &*PROCESS };
+        process.threads.iter().filter(|thread| thread.sepc != 0).count()
     }
 
     /// Set the current thread number.
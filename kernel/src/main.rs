@@ -72,21 +72,7 @@ pub unsafe extern "C" fn init(
 /// Loop through the SystemServices list to determine the next PID to be run.
 /// If no process is ready, return `None`.
 fn next_pid_to_run(last_pid: Option<PID>) -> Option<PID> {
-    // PIDs are 1-indexed but arrays are 0-indexed.  By not subtracting
-    // 1 from the PID when we use it as an array index, we automatically
-    // pick the next process in the list.
-    let next_pid = last_pid.map(|v| v.get() as usize).unwrap_or(1);
-
-    SystemServices::with(|system_services| {
-        for process in
-            system_services.processes[next_pid..].iter().chain(system_services.processes[..next_pid].iter())
-        {
-            if process.runnable() {
-                return Some(process.pid);
-            }
-        }
-        None
-    })
+    SystemServices::with_mut(|system_services| system_services.next_pid_to_run(last_pid))
 }
 
 /// Common main function for baremetal and hosted environments.
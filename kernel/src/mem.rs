@@ -269,6 +269,29 @@ impl MemoryManager {
         owned_bytes
     }
 
+    /// Return the number of pages owned by the specified process, including
+    /// pages in extra regions such as peripherals and CSRs.
+    pub fn pages_owned_by(&self, pid: PID) -> usize {
+        #[cfg(baremetal)]
+        {
+            let mut owned_pages = self.ram_used_by(pid) / PAGE_SIZE;
+            unsafe {
+                for owner in EXTRA_ALLOCATIONS.iter() {
+                    if owner == &Some(pid) {
+                        owned_pages += 1;
+                    }
+                }
+            }
+            owned_pages
+        }
+        // Hosted mode does not track page ownership
+        #[cfg(not(baremetal))]
+        {
+            let _ = pid;
+            0
+        }
+    }
+
     #[cfg(all(baremetal, feature = "debug-print"))]
     pub fn print_ownership(&self) {
         println!("Ownership ({} bytes in all):", unsafe {
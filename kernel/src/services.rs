@@ -7,7 +7,8 @@ use xous_kernel::arch::ProcessStartup;
 use xous_kernel::MemoryRange;
 // use core::mem;
 use xous_kernel::{
-    pid_from_usize, Error, MemoryAddress, Message, ProcessInit, ThreadInit, CID, PID, SID, TID,
+    pid_from_usize, Error, MemoryAddress, Message, MessageEnvelope, ProcessInit, ScalarMessage, ThreadInit,
+    ThreadPriority, CID, PID, SID, TID,
 };
 
 use crate::arch;
@@ -17,12 +18,21 @@ pub use crate::arch::process::Process as ArchProcess;
 pub use crate::arch::process::Thread;
 use crate::filled_array;
 use crate::platform;
-use crate::server::Server;
+use crate::server::{SenderID, Server};
 
 const MAX_SERVER_COUNT: usize = 128;
 
 pub use crate::arch::process::{INITIAL_TID, MAX_PROCESS_COUNT};
 
+/// The number of per-thread scheduling slots kept for each process. This matches
+/// the width of the thread masks stored in `ProcessState`.
+pub const THREAD_SLOTS: usize = usize::BITS as usize;
+
+/// The number of times a ready thread may be passed over in favour of a
+/// higher-priority thread before it is boosted above every other thread. The
+/// same limit applies to processes passed over for another process.
+pub const STARVATION_LIMIT: u8 = 8;
+
 #[allow(dead_code)]
 const MINIELF_FLG_W: u8 = 1;
 #[allow(dead_code)]
@@ -162,6 +172,26 @@ pub struct Process {
 
     /// When an exception is hit, the kernel will switch to this Thread.
     exception_handler: Option<ExceptionHandler>,
+
+    /// Scheduling priority of each thread in this process
+    thread_priority: [ThreadPriority; THREAD_SLOTS],
+
+    /// How many times each thread was ready to run but was passed over
+    /// for a higher-priority thread. Used to prevent starvation.
+    thread_skipped: [u8; THREAD_SLOTS],
+
+    /// How many times this process was ready to run but was passed over for
+    /// a process with higher-priority threads. Used to prevent starvation.
+    skipped: u8,
+
+    /// The number of preemption ticks that came in while this process was
+    /// running, which is how its CPU time is measured
+    ticks: u64,
+
+    /// A server owned by this process, and the opcode to send it, when a
+    /// process that this process is connected to (or that is connected to
+    /// this process) exits.
+    peer_exit_notification: Option<(SID, usize)>,
 }
 
 impl Default for Process {
@@ -173,6 +203,11 @@ impl Default for Process {
             current_thread: 0,
             previous_thread: 0,
             exception_handler: None,
+            thread_priority: [ThreadPriority::Normal; THREAD_SLOTS],
+            thread_skipped: [0; THREAD_SLOTS],
+            skipped: 0,
+            ticks: 0,
+            peer_exit_notification: None,
             mapping: Default::default(),
         }
     }
@@ -242,6 +277,35 @@ impl Process {
     /// This process slot is unallocated and may be turn into a process
     pub fn free(&self) -> bool { matches!(self.state, ProcessState::Free) }
 
+    /// The highest priority among the threads of this process that are ready
+    /// to run. A process that is being set up or is handling an exception runs
+    /// a thread of its own choosing, which is given the default priority.
+    fn ready_priority(&self) -> ThreadPriority {
+        match self.state {
+            ProcessState::Ready(mut remaining) => {
+                let mut priority = ThreadPriority::Idle;
+                while remaining != 0 {
+                    let tid = remaining.trailing_zeros() as usize;
+                    remaining &= remaining - 1;
+                    priority = priority.max(self.thread_priority[tid]);
+                }
+                priority
+            }
+            _ => ThreadPriority::Normal,
+        }
+    }
+
+    /// Pick the next thread to run out of `thread_mask`, taking thread
+    /// priorities into account.
+    fn next_thread(&mut self, thread_mask: usize) -> TID {
+        SystemServices::find_next_thread_by_priority(
+            thread_mask,
+            self.current_thread,
+            &self.thread_priority,
+            &mut self.thread_skipped,
+        )
+    }
+
     pub fn activate(&self) -> Result<(), xous_kernel::Error> {
         crate::arch::process::set_current_pid(self.pid);
         self.mapping.activate()?;
@@ -283,6 +347,11 @@ std::thread_local!(static SYSTEM_SERVICES: core::cell::RefCell<SystemServices> =
         current_thread: 0_usize,
         previous_thread: INITIAL_TID as TID,
         exception_handler: None,
+        thread_priority: [ThreadPriority::Normal; THREAD_SLOTS],
+        thread_skipped: [0; THREAD_SLOTS],
+        skipped: 0,
+        ticks: 0,
+        peer_exit_notification: None,
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
@@ -300,6 +369,11 @@ static mut SYSTEM_SERVICES: SystemServices = SystemServices {
         current_thread: INITIAL_TID,
         previous_thread: INITIAL_TID as TID,
         exception_handler: None,
+        thread_priority: [ThreadPriority::Normal; THREAD_SLOTS],
+        thread_skipped: [0; THREAD_SLOTS],
+        skipped: 0,
+        ticks: 0,
+        peer_exit_notification: None,
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
@@ -473,6 +547,11 @@ impl SystemServices {
             entry.pid = new_pid.unwrap();
             entry.ppid = PID::new(1).unwrap();
             entry.state = ProcessState::Allocated;
+            entry.thread_priority = [ThreadPriority::Normal; THREAD_SLOTS];
+            entry.thread_skipped = [0; THREAD_SLOTS];
+            entry.skipped = 0;
+            entry.ticks = 0;
+            entry.peer_exit_notification = None;
             unsafe { entry.mapping.allocate(new_pid.unwrap()).or(Err(xous_kernel::Error::InternalError))? };
             break;
         }
@@ -834,6 +913,224 @@ impl SystemServices {
         }
     }
 
+    /// Pick the next thread to run out of `thread_mask`. Only the threads with
+    /// the highest priority are considered, and the existing round-robin
+    /// scheduler picks between them.
+    ///
+    /// Every ready thread that is passed over has its entry in `skipped`
+    /// incremented. Once a thread has been skipped `STARVATION_LIMIT` times it is
+    /// treated as being above every other priority level, ensuring that even
+    /// `Idle` threads eventually get to run.
+    pub fn find_next_thread_by_priority(
+        thread_mask: usize,
+        current_thread: usize,
+        priorities: &[ThreadPriority; THREAD_SLOTS],
+        skipped: &mut [u8; THREAD_SLOTS],
+    ) -> usize {
+        if thread_mask & thread_mask.wrapping_sub(1) == 0 {
+            let tid = Self::find_next_thread(thread_mask, current_thread);
+            skipped[tid] = 0;
+            return tid;
+        }
+
+        // Collect the set of threads at the highest effective priority
+        let mut best_level = 0;
+        let mut best_mask = 0;
+        let mut remaining = thread_mask;
+        while remaining != 0 {
+            let tid = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            let level = if skipped[tid] >= STARVATION_LIMIT {
+                ThreadPriority::Realtime as usize + 1
+            } else {
+                priorities[tid] as usize
+            };
+            if best_mask == 0 || level > best_level {
+                best_level = level;
+                best_mask = 1 << tid;
+            } else if level == best_level {
+                best_mask |= 1 << tid;
+            }
+        }
+
+        let next = Self::find_next_thread(best_mask, current_thread);
+
+        // Age every thread that was ready but not picked
+        let mut remaining = thread_mask;
+        while remaining != 0 {
+            let tid = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            skipped[tid] = if tid == next { 0 } else { skipped[tid].saturating_add(1) };
+        }
+        next
+    }
+
+    /// Pick the next process to run. `priorities` holds, for each process, the
+    /// highest priority among its ready threads, or `None` if it can't run.
+    /// Only the processes with the highest priority are considered, and they
+    /// are picked round-robin, starting the search at index `first`.
+    ///
+    /// As with threads, every ready process that is passed over has its entry
+    /// in `skipped` incremented, and a process that has been skipped
+    /// `STARVATION_LIMIT` times is treated as being above every other priority
+    /// level. This keeps a process full of busy background threads from
+    /// holding off the threads of another process, without shutting it out.
+    pub fn find_next_process_by_priority(
+        priorities: &[Option<ThreadPriority>; MAX_PROCESS_COUNT],
+        first: usize,
+        skipped: &mut [u8; MAX_PROCESS_COUNT],
+    ) -> Option<usize> {
+        let mut best = None;
+        let mut best_level = 0;
+        for index in (first..MAX_PROCESS_COUNT).chain(0..first) {
+            if let Some(priority) = priorities[index] {
+                let level = if skipped[index] >= STARVATION_LIMIT {
+                    ThreadPriority::Realtime as usize + 1
+                } else {
+                    priority as usize
+                };
+                if best.is_none() || level > best_level {
+                    best_level = level;
+                    best = Some(index);
+                }
+            }
+        }
+        let next = best?;
+
+        // Age every process that was ready but not picked
+        for (index, (skipped, priority)) in skipped.iter_mut().zip(priorities.iter()).enumerate() {
+            if priority.is_some() {
+                *skipped = if index == next { 0 } else { skipped.saturating_add(1) };
+            }
+        }
+        Some(next)
+    }
+
+    /// Pick the next process to run after `last_pid`, favouring the processes
+    /// with the highest-priority ready threads. Returns `None` if no process is
+    /// ready.
+    pub fn next_pid_to_run(&mut self, last_pid: Option<PID>) -> Option<PID> {
+        // PIDs are 1-indexed but arrays are 0-indexed.  By not subtracting
+        // 1 from the PID when we use it as an array index, we automatically
+        // pick the next process in the list.
+        let first = last_pid.map(|v| v.get() as usize).unwrap_or(1) % MAX_PROCESS_COUNT;
+
+        let mut priorities = [None; MAX_PROCESS_COUNT];
+        let mut skipped = [0u8; MAX_PROCESS_COUNT];
+        for (index, process) in self.processes.iter().enumerate() {
+            if process.runnable() {
+                priorities[index] = Some(process.ready_priority());
+            }
+            skipped[index] = process.skipped;
+        }
+        let next = Self::find_next_process_by_priority(&priorities, first, &mut skipped)?;
+        for (process, skipped) in self.processes.iter_mut().zip(skipped.iter()) {
+            process.skipped = *skipped;
+        }
+        Some(self.processes[next].pid)
+    }
+
+    /// Set the scheduling priority of thread `tid` in process `pid`, returning
+    /// the previous priority.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidThread**: The thread ID is out of range
+    /// * **ThreadNotAvailable**: The thread does not exist in this process
+    pub fn set_thread_priority(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        priority: ThreadPriority,
+    ) -> Result<ThreadPriority, xous_kernel::Error> {
+        if tid >= THREAD_SLOTS {
+            return Err(xous_kernel::Error::InvalidThread);
+        }
+        let process = self.get_process_mut(pid)?;
+        if !ArchProcess::current().thread_exists(tid) {
+            return Err(xous_kernel::Error::ThreadNotAvailable);
+        }
+        let previous = process.thread_priority[tid];
+        process.thread_priority[tid] = priority;
+        process.thread_skipped[tid] = 0;
+        Ok(previous)
+    }
+
+    /// Gather the resources currently held by process `pid`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process does not exist
+    pub fn process_info(&self, pid: PID) -> Result<xous_kernel::ProcessInfo, xous_kernel::Error> {
+        if pid.get() as usize > MAX_PROCESS_COUNT {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        let current_pid = self.current_pid();
+        let process = self.get_process(pid)?;
+        let cpu_time_ms = process.ticks.wrapping_mul(xous_kernel::BASE_QUANTA_MS as u64);
+
+        // Per-process data is only reachable while that process is active
+        if pid != current_pid {
+            process.activate()?;
+        }
+        let (heap_size, connection_count) = ArchProcess::with_inner(|process_inner| {
+            (process_inner.mem_heap_size, process_inner.connection_map.iter().flatten().count())
+        });
+        let thread_count = ArchProcess::current().thread_count();
+        if pid != current_pid {
+            self.get_process(current_pid)?.activate()?;
+        }
+
+        let server_count = self.servers.iter().flatten().filter(|server| server.pid == pid).count();
+        let mapped_pages = crate::mem::MemoryManager::with_mut(|mm| mm.pages_owned_by(pid));
+
+        Ok(xous_kernel::ProcessInfo {
+            heap_size,
+            mapped_pages,
+            thread_count,
+            server_count,
+            connection_count,
+            cpu_time_ms,
+        })
+    }
+
+    /// Read up to 16 bytes of the name the loader gave process `pid`, starting at
+    /// `offset`. Returns the length of the whole name along with the bytes,
+    /// packed four to a word in little-endian order.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process does not exist
+    pub fn process_name_chunk(
+        &self,
+        pid: PID,
+        offset: usize,
+    ) -> Result<(usize, [usize; 4]), xous_kernel::Error> {
+        if pid.get() as usize > MAX_PROCESS_COUNT {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        self.get_process(pid)?;
+        // Hosted processes are not started by the loader, and so have no name
+        #[cfg(baremetal)]
+        let name = self.process_name(pid).unwrap_or("").as_bytes();
+        #[cfg(not(baremetal))]
+        let name: &[u8] = &[];
+
+        let mut words = [0usize; 4];
+        for (i, byte) in name.iter().skip(offset).take(16).enumerate() {
+            words[i / 4] |= (*byte as usize) << ((i % 4) * 8);
+        }
+        Ok((name.len(), words))
+    }
+
+    /// Charge a preemption tick to `pid`, which was running when the
+    /// preemption timer fired.
+    pub fn charge_tick(&mut self, pid: PID) {
+        if let Ok(process) = self.get_process_mut(pid) {
+            process.ticks = process.ticks.wrapping_add(1);
+        }
+    }
+
     /// Set the "current thread" of a given process. It is designed
     /// to set where the next thread will run in order to avoid starving threads
     /// when messages are passed around.
@@ -914,8 +1211,7 @@ impl SystemServices {
                 panic!("ProcessState was `Ready(0)`, which is invalid!");
             }
             ProcessState::Ready(ready_threads) => {
-                let new_thread =
-                    tid.unwrap_or_else(|| Self::find_next_thread(ready_threads, process.current_thread));
+                let new_thread = tid.unwrap_or_else(|| process.next_thread(ready_threads));
 
                 if ready_threads & (1 << new_thread) == 0 {
                     panic!("invalid thread ID");
@@ -931,8 +1227,7 @@ impl SystemServices {
                 // Ensure we can switch back to this thread, if necessary
                 let ready_threads = ready_threads | (1 << process.current_thread);
 
-                let new_thread =
-                    tid.unwrap_or_else(|| Self::find_next_thread(ready_threads, process.current_thread));
+                let new_thread = tid.unwrap_or_else(|| process.next_thread(ready_threads));
 
                 // Ensure the specified context is ready to run, or is
                 // currently running.
@@ -1081,7 +1376,7 @@ impl SystemServices {
                     // search for the next available context.
                     assert!(x != 0, "process was {:?} but had no runnable threads", new.state);
                     if new_tid == 0 {
-                        new_tid = Self::find_next_thread(x, new.current_thread);
+                        new_tid = new.next_thread(x);
                     }
                     if x & (1 << new_tid) == 0 {
                         println!(
@@ -1226,7 +1521,7 @@ impl SystemServices {
                 // thread.  If that is not runnable, do a round-robin
                 // search for the next available thread.
                 if new_tid == 0 {
-                    new_tid = Self::find_next_thread(x, new.current_thread);
+                    new_tid = new.next_thread(x);
                 }
 
                 if x & (1 << new_tid) == 0 {
@@ -1633,8 +1928,18 @@ impl SystemServices {
         let mut arch_process = ArchProcess::current();
         let new_tid = arch_process.find_free_thread().ok_or(xous_kernel::Error::ThreadNotAvailable)?;
 
+        let priority = thread_init.priority;
         arch_process.setup_thread(new_tid, thread_init)?;
 
+        // New threads start at the requested priority, or else inherit the
+        // priority of the thread that created them
+        process.thread_priority[new_tid] = match (priority, process.state) {
+            (Some(priority), _) => priority,
+            (None, ProcessState::Running(_)) => process.thread_priority[process.current_thread],
+            (None, _) => ThreadPriority::Normal,
+        };
+        process.thread_skipped[new_tid] = 0;
+
         // klog!("KERNEL({}): Created new thread {}", pid, new_tid);
 
         // Queue the thread to run
@@ -1859,6 +2164,61 @@ impl SystemServices {
         Ok(())
     }
 
+    /// Ask the kernel to send a message with ID `opcode` to server `sid` whenever
+    /// a peer of process `pid` exits. The server must be owned by `pid`.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist or is not owned by `pid`
+    pub fn set_peer_exit_notification(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        opcode: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        self.sidx_from_sid(sid, pid).ok_or(xous_kernel::Error::ServerNotFound)?;
+        self.get_process_mut(pid)?.peer_exit_notification = Some((sid, opcode));
+        Ok(())
+    }
+
+    /// Deliver a `Scalar` message to server `sid` owned by `pid`, telling it
+    /// that `exited_pid` has exited. The message appears to come from the
+    /// exited process, and its PID is also passed in `arg1`.
+    fn notify_peer_exit(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        opcode: usize,
+        exited_pid: PID,
+    ) -> Result<(), xous_kernel::Error> {
+        let sidx = self.sidx_from_sid(sid, pid).ok_or(xous_kernel::Error::ServerNotFound)?;
+        let message = Message::Scalar(ScalarMessage {
+            id: opcode,
+            arg1: exited_pid.get() as usize,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        });
+
+        let server = self.server_from_sidx_mut(sidx).ok_or(xous_kernel::Error::ServerNotFound)?;
+        if let Some(server_tid) = server.take_available_thread() {
+            let sender = SenderID::new(sidx, 0, Some(exited_pid));
+            let envelope = MessageEnvelope { sender: sender.into(), body: message };
+            #[cfg(baremetal)]
+            self.ready_thread(pid, server_tid).map_err(|e| {
+                self.server_from_sidx_mut(sidx)
+                    .expect("server couldn't be located")
+                    .return_available_thread(server_tid);
+                e
+            })?;
+            #[cfg(not(baremetal))]
+            self.switch_to_thread(pid, Some(server_tid))?;
+            self.set_thread_result(pid, server_tid, xous_kernel::Result::MessageEnvelope(envelope))
+        } else {
+            self.queue_server_message(sidx, exited_pid, 0, message, None).map(|_| ())
+        }
+    }
+
     /// Connect to a server on behalf of another process.
     pub fn connect_process_to_server(
         &mut self,
@@ -2114,6 +2474,23 @@ impl SystemServices {
         // 3. If there are any incoming server requests queued, dequeue them and return an error
         // 4. Mark all "Borrowed" memory as "Free-when-returned". That way, if we've shared memory to a
         //    Server, it will be reclaimed by the system when it comes back
+        // 5. Let any peers that asked to be told about process exits know about this one
+
+        // Note down every process that owns a server this process is connected to.
+        let mut peers = [false; MAX_PROCESS_COUNT];
+        self.get_process(target_pid)?.activate()?;
+        ArchProcess::with_inner(|process_inner| {
+            for mapping in process_inner.connection_map.iter().flatten() {
+                let server = mapping
+                    .get()
+                    .checked_sub(2)
+                    .and_then(|sidx| self.servers.get(sidx as usize))
+                    .and_then(|server| server.as_ref());
+                if let Some(server) = server {
+                    peers[server.pid.get() as usize - 1] = true;
+                }
+            }
+        });
 
         // 1. Find all servers associated with this PID and remove them.
         for (idx, server) in self.servers.iter_mut().enumerate() {
@@ -2134,6 +2511,7 @@ impl SystemServices {
                             for mapping in process_inner.connection_map.iter_mut().flatten() {
                                 if mapping.get() == (idx as u8) + 2 {
                                     *mapping = NonZeroU8::new(1).unwrap();
+                                    peers[process.pid.get() as usize - 1] = true;
                                 }
                             }
                         })
@@ -2161,6 +2539,21 @@ impl SystemServices {
         let process = self.get_process_mut(target_pid)?;
         process.activate()?;
         let parent_pid = process.ppid;
+
+        // 5. Notify peers that have registered an interest in process exits.
+        for (idx, is_peer) in peers.iter().enumerate() {
+            let peer = self.processes[idx];
+            if !is_peer || peer.free() || peer.pid == target_pid {
+                continue;
+            }
+            if let Some((sid, opcode)) = peer.peer_exit_notification {
+                if let Err(_e) = self.notify_peer_exit(peer.pid, sid, opcode, target_pid) {
+                    klog!("couldn't notify PID {} that PID {} exited: {:?}", peer.pid, target_pid, _e);
+                }
+            }
+        }
+
+        let process = self.get_process_mut(target_pid)?;
         process.terminate()?;
 
         self.switch_to_thread(parent_pid, None).unwrap();
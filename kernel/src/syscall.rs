@@ -835,6 +835,11 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
         }
         SysCall::Yield => do_yield(pid, tid),
         SysCall::ReturnToParent(_pid, _cpuid) => {
+            // This is only called by the preemption timer's interrupt handler, so the process it
+            // interrupted has used up a tick of CPU time.
+            if let Some(interrupted) = crate::arch::irq::interrupted_pid() {
+                SystemServices::with_mut(|ss| ss.charge_tick(interrupted));
+            }
             unsafe {
                 if let Some((parent_pid, parent_ctx)) = SWITCHTO_CALLER.take() {
                     crate::arch::irq::set_isr_return_pair(parent_pid, parent_ctx)
@@ -992,6 +997,22 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             }),
             _ => Err(xous_kernel::Error::InvalidLimit),
         },
+        SysCall::SetThreadPriority(target_tid, priority) => {
+            let target_tid = if target_tid == 0 { tid } else { target_tid };
+            SystemServices::with_mut(|ss| ss.set_thread_priority(pid, target_tid, priority))
+                .map(|previous| xous_kernel::Result::Scalar1(previous.to_usize()))
+        }
+        SysCall::SetPeerExitNotification(sid, opcode) => SystemServices::with_mut(|ss| {
+            ss.set_peer_exit_notification(pid, sid, opcode).map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::GetProcessInfo(target_pid) => SystemServices::with(|ss| {
+            let info = ss.process_info(target_pid)?.to_scalar5();
+            Ok(xous_kernel::Result::Scalar5(info[0], info[1], info[2], info[3], info[4]))
+        }),
+        SysCall::GetProcessName(target_pid, offset) => SystemServices::with(|ss| {
+            let (len, words) = ss.process_name_chunk(target_pid, offset)?;
+            Ok(xous_kernel::Result::Scalar5(len, words[0], words[1], words[2], words[3]))
+        }),
         #[cfg(feature = "v2p")]
         SysCall::VirtToPhys(vaddr) => {
             let phys_addr = crate::arch::mem::virt_to_phys(vaddr as usize);
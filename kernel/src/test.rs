@@ -1210,3 +1210,244 @@ fn renormalizer() {
         assert!(max_element < TEST_LEN as u32);
     }
 }
+
+#[test]
+fn thread_priority_scheduling() {
+    use xous_kernel::ThreadPriority;
+
+    use crate::services::{SystemServices, STARVATION_LIMIT, THREAD_SLOTS};
+
+    let mut priorities = [ThreadPriority::Normal; THREAD_SLOTS];
+    let mut skipped = [0u8; THREAD_SLOTS];
+
+    // Threads of equal priority are picked round-robin
+    let mask = (1 << 2) | (1 << 3) | (1 << 4);
+    assert_eq!(SystemServices::find_next_thread_by_priority(mask, 2, &priorities, &mut skipped), 3);
+    assert_eq!(SystemServices::find_next_thread_by_priority(mask, 3, &priorities, &mut skipped), 4);
+    assert_eq!(SystemServices::find_next_thread_by_priority(mask, 4, &priorities, &mut skipped), 2);
+
+    // A higher-priority thread always wins, until the others are starved
+    priorities[4] = ThreadPriority::High;
+    priorities[2] = ThreadPriority::Idle;
+    skipped = [0u8; THREAD_SLOTS];
+    for _ in 0..STARVATION_LIMIT {
+        assert_eq!(SystemServices::find_next_thread_by_priority(mask, 4, &priorities, &mut skipped), 4);
+    }
+    let next = SystemServices::find_next_thread_by_priority(mask, 4, &priorities, &mut skipped);
+    assert!(next == 2 || next == 3, "starved thread was not boosted (picked {})", next);
+    assert_eq!(skipped[next], 0);
+    assert_eq!(skipped[4], 1);
+
+    // Within a few more rounds, every thread has had a chance to run
+    let mut seen = 1 << next;
+    for _ in 0..(STARVATION_LIMIT as usize * 3) {
+        seen |= 1 << SystemServices::find_next_thread_by_priority(mask, 4, &priorities, &mut skipped);
+    }
+    assert_eq!(seen, mask);
+
+    // A lone ready thread is picked regardless of its priority
+    assert_eq!(SystemServices::find_next_thread_by_priority(1 << 2, 4, &priorities, &mut skipped), 2);
+}
+
+#[test]
+fn process_priority_scheduling() {
+    use xous_kernel::ThreadPriority;
+
+    use crate::services::{SystemServices, MAX_PROCESS_COUNT, STARVATION_LIMIT};
+
+    // A process doing background work and a process handling the UI, both with
+    // threads ready to run
+    const BACKGROUND: usize = 4;
+    const UI: usize = 6;
+    let mut priorities = [None; MAX_PROCESS_COUNT];
+    let mut skipped = [0u8; MAX_PROCESS_COUNT];
+
+    // Processes of equal priority are picked round-robin, and those that can't
+    // run are passed by
+    priorities[BACKGROUND] = Some(ThreadPriority::Normal);
+    priorities[UI] = Some(ThreadPriority::Normal);
+    assert_eq!(SystemServices::find_next_process_by_priority(&priorities, 0, &mut skipped), Some(BACKGROUND));
+    assert_eq!(
+        SystemServices::find_next_process_by_priority(&priorities, BACKGROUND + 1, &mut skipped),
+        Some(UI)
+    );
+    assert_eq!(
+        SystemServices::find_next_process_by_priority(&priorities, UI + 1, &mut skipped),
+        Some(BACKGROUND)
+    );
+
+    // The UI process wins whichever process ran last, even though the
+    // background process comes first in the round-robin order, until the
+    // background process has been starved
+    priorities[BACKGROUND] = Some(ThreadPriority::Low);
+    priorities[UI] = Some(ThreadPriority::High);
+    skipped = [0u8; MAX_PROCESS_COUNT];
+    for _ in 0..STARVATION_LIMIT {
+        assert_eq!(
+            SystemServices::find_next_process_by_priority(&priorities, UI + 1, &mut skipped),
+            Some(UI)
+        );
+    }
+    assert_eq!(
+        SystemServices::find_next_process_by_priority(&priorities, UI + 1, &mut skipped),
+        Some(BACKGROUND),
+        "starved process was not boosted"
+    );
+    assert_eq!(skipped[BACKGROUND], 0);
+    assert_eq!(skipped[UI], 1);
+    assert_eq!(
+        SystemServices::find_next_process_by_priority(&priorities, BACKGROUND + 1, &mut skipped),
+        Some(UI)
+    );
+
+    // With the UI process blocked, the background process runs
+    priorities[UI] = None;
+    assert_eq!(
+        SystemServices::find_next_process_by_priority(&priorities, UI + 1, &mut skipped),
+        Some(BACKGROUND)
+    );
+
+    // Nothing is picked when no process is ready
+    priorities[BACKGROUND] = None;
+    assert_eq!(SystemServices::find_next_process_by_priority(&priorities, 0, &mut skipped), None);
+}
+
+#[test]
+fn set_thread_priority() {
+    use xous_kernel::ThreadPriority;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let internal_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "set_thread_priority process",
+        move || {
+            let tid = xous_kernel::current_tid().expect("couldn't get thread id");
+            assert_eq!(xous_kernel::set_thread_priority(0, ThreadPriority::High), Ok(ThreadPriority::Normal));
+            assert_eq!(xous_kernel::set_thread_priority(tid, ThreadPriority::Low), Ok(ThreadPriority::High));
+
+            // New threads inherit the priority of their parent
+            let (send, recv) = unbounded();
+            let child = xous_kernel::create_thread(move || {
+                send.send(xous_kernel::set_thread_priority(0, ThreadPriority::Normal)).unwrap();
+            })
+            .expect("couldn't spawn thread");
+            xous_kernel::wait_thread(child).expect("couldn't join thread");
+            assert_eq!(recv.recv().unwrap(), Ok(ThreadPriority::Low));
+
+            // ...unless they are created with a priority of their own
+            let (send, recv) = unbounded();
+            let child = xous_kernel::create_thread_with_priority(ThreadPriority::Realtime, move || {
+                send.send(xous_kernel::set_thread_priority(0, ThreadPriority::Normal)).unwrap();
+            })
+            .expect("couldn't spawn thread");
+            xous_kernel::wait_thread(child).expect("couldn't join thread");
+            assert_eq!(recv.recv().unwrap(), Ok(ThreadPriority::Realtime));
+
+            assert_eq!(
+                xous_kernel::set_thread_priority(1000, ThreadPriority::Idle),
+                Err(xous_kernel::Error::InvalidThread)
+            );
+            assert_eq!(
+                xous_kernel::set_thread_priority(crate::arch::process::MAX_THREAD, ThreadPriority::Idle),
+                Err(xous_kernel::Error::ThreadNotAvailable)
+            );
+        },
+    ))
+    .expect("couldn't create process");
+
+    xous_kernel::wait_process_as_thread(internal_server).expect("couldn't join process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn process_info() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let internal_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "process_info process",
+        move || {
+            let pid = xous_kernel::current_pid().expect("couldn't get pid");
+            let before = xous_kernel::process_info(pid).expect("couldn't get process info");
+            assert_eq!(before.server_count, 0);
+            assert_eq!(before.connection_count, 0);
+            assert!(before.thread_count >= 1);
+
+            let server = xous_kernel::create_server().expect("couldn't create server");
+            let _connection = xous_kernel::try_connect(server).expect("couldn't connect to our own server");
+            let after = xous_kernel::process_info(pid).expect("couldn't get process info");
+            assert_eq!(after.server_count, 1);
+            assert_eq!(after.connection_count, 1);
+            assert!(after.cpu_time_ms >= before.cpu_time_ms);
+
+            assert_eq!(
+                xous_kernel::process_info(xous_kernel::PID::new(200).unwrap()),
+                Err(xous_kernel::Error::ProcessNotFound)
+            );
+
+            // hosted processes are not started by the loader, so they have no name
+            let mut name = [0u8; 32];
+            assert_eq!(xous_kernel::process_name(pid, &mut name), Ok(0));
+            assert_eq!(
+                xous_kernel::process_name(xous_kernel::PID::new(200).unwrap(), &mut name),
+                Err(xous_kernel::Error::ProcessNotFound)
+            );
+        },
+    ))
+    .expect("couldn't create process");
+
+    xous_kernel::wait_process_as_thread(internal_server).expect("couldn't join process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn peer_exit_notification() {
+    const PEER_EXITED: usize = 0x1234;
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = unbounded();
+    let (client_pid_send, client_pid_recv) = unbounded();
+
+    let watcher = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "peer_exit_notification watcher",
+        move || {
+            let sid = xous_kernel::create_server().expect("couldn't create server");
+            xous_kernel::set_peer_exit_notification(sid, PEER_EXITED)
+                .expect("couldn't register for peer exit notifications");
+            server_addr_send.send(sid).unwrap();
+
+            let msg = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let client_pid: xous_kernel::PID = client_pid_recv.recv().unwrap();
+            assert_eq!(msg.sender.pid(), Some(client_pid));
+            if let xous_kernel::Message::Scalar(scalar) = msg.body {
+                assert_eq!(scalar.id, PEER_EXITED);
+                assert_eq!(scalar.arg1, client_pid.get() as usize);
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start watcher process");
+
+    let client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "peer_exit_notification client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            client_pid_send.send(xous_kernel::current_pid().unwrap()).unwrap();
+        },
+    ))
+    .expect("couldn't start client process");
+
+    xous_kernel::wait_process_as_thread(client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(watcher).expect("couldn't join watcher process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
@@ -50,6 +50,10 @@ pub struct UserPrefs {
     pub headset_volume: u32,
     pub autotype_rate: usize,
     pub lefty_mode: bool,
+    /// a `dns::DnsTlsMode`
+    pub dns_tls_mode: u32,
+    /// keep the recent log in the system basis across suspends; passed on to the PDDB by `push_save_log()`
+    pub save_log: bool,
 }
 
 pub struct Manager {
@@ -139,6 +143,13 @@ impl Manager {
     pub fn set_utc_offset(&self, offset: i64) -> Result<(), Error> {
         self.store_i64(offset, TIME_SERVER_UTC_OFFSET)
     }
+
+    /// Tells the PDDB whether to save the log. The PDDB can't read this preference itself, so this
+    /// has to be called at boot, and again whenever `set_save_log()` changes it.
+    pub fn push_save_log(&self) -> Result<(), Error> {
+        let enabled = self.save_log_or_default()?;
+        Ok(self.pddb_handle.set_log_saving(enabled)?)
+    }
 }
 
 impl Default for Manager {
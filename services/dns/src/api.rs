@@ -1,8 +1,13 @@
 #[allow(dead_code)]
 // note: this name cannot be changed, because it is baked into `libstd`
 pub(crate) const SERVER_NAME_DNS: &str = "_DNS Resolver Middleware_";
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 use net::NetIpAddr;
+use num_traits::FromPrimitive;
 use rkyv::{Archive, Deserialize, Serialize};
+use xous_ipc::String;
 
 #[allow(dead_code)]
 pub(crate) const DNS_NAME_LENGTH_LIMIT: usize = 256;
@@ -50,6 +55,34 @@ pub(crate) enum Opcode {
     ///     * 4: Ipv4 Address -- 4 octets follow, for a total of 5 bytes
     ///     * 6: Ipv6 Address -- 16 octets follow, for a total of 17 bytes
     RawLookup = 6,
+
+    /// Advertises a service over DNS-SD on the local network. Takes a `MdnsServiceIpc` as a lend;
+    /// registering the same instance of a service again replaces it.
+    MdnsRegister = 7,
+
+    /// Withdraws a service advertised with `MdnsRegister`. Only the instance and service fields of
+    /// the `MdnsServiceIpc` are used.
+    MdnsUnregister = 8,
+
+    /// Perform a DNS lookup for records of a given type, and return the results in a raw format.
+    ///
+    /// The query is passed as with `RawLookup`, with the `DnsRecordType` in the `offset` field.
+    /// CNAME chains are followed, except when the CNAME records themselves are asked for.
+    ///
+    /// The result starts as with `RawLookup`: a `0` and the number of records, or a `1` and a
+    /// `DnsResponseCode`. Each record is its type (u16), its TTL (u32) and the length of its data
+    /// (u16), all big-endian, followed by the data:
+    ///
+    ///     * A: 4 octets
+    ///     * AAAA: 16 octets
+    ///     * CNAME: the canonical name, in dotted form
+    ///     * MX: the preference (u16), then the exchange name
+    ///     * TXT: the character-strings, each prefixed with its length as on the wire
+    ///     * SRV: the priority, weight and port (u16 each), then the target name
+    RawRecordLookup = 9,
+
+    /// Re-reads the DNS-over-TLS mode and upstreams from the PDDB, and drops any open connection.
+    ReloadTlsConfig = 10,
 }
 
 #[derive(
@@ -75,6 +108,211 @@ pub struct DnsResponse {
     pub code: DnsResponseCode,
 }
 
+/// How lookups use the DNS-over-TLS upstreams. Kept in the user preferences as a `u32`.
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DnsTlsMode {
+    /// queries go out as plain DNS, to the servers handed out by DHCP
+    Off = 0,
+    /// queries go to the TLS upstreams, falling back to plain DNS if none can be reached
+    Opportunistic = 1,
+    /// queries only ever go to the TLS upstreams, and fail if none can be reached
+    Strict = 2,
+}
+impl Default for DnsTlsMode {
+    fn default() -> Self { DnsTlsMode::Off }
+}
+
+/// The record types that can be asked for with `RawRecordLookup`.
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, Copy, Clone, PartialEq, Eq)]
+#[repr(u16)]
+pub enum DnsRecordType {
+    A = 1,
+    Cname = 5,
+    Mx = 15,
+    Txt = 16,
+    Aaaa = 28,
+    Srv = 33,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DnsRecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(std::string::String),
+    Mx {
+        preference: u16,
+        exchange: std::string::String,
+    },
+    /// the character-strings of the record; these are not necessarily UTF-8
+    Txt(Vec<Vec<u8>>),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: std::string::String,
+    },
+}
+impl DnsRecordData {
+    pub fn rtype(&self) -> DnsRecordType {
+        match self {
+            DnsRecordData::A(_) => DnsRecordType::A,
+            DnsRecordData::Aaaa(_) => DnsRecordType::Aaaa,
+            DnsRecordData::Cname(_) => DnsRecordType::Cname,
+            DnsRecordData::Mx { .. } => DnsRecordType::Mx,
+            DnsRecordData::Txt(_) => DnsRecordType::Txt,
+            DnsRecordData::Srv { .. } => DnsRecordType::Srv,
+        }
+    }
+
+    pub fn addr(&self) -> Option<IpAddr> {
+        match self {
+            DnsRecordData::A(a) => Some(IpAddr::V4(*a)),
+            DnsRecordData::Aaaa(a) => Some(IpAddr::V6(*a)),
+            _ => None,
+        }
+    }
+
+    /// Appends the data in the format used by `RawRecordLookup`.
+    #[allow(dead_code)]
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            DnsRecordData::A(a) => out.extend_from_slice(&a.octets()),
+            DnsRecordData::Aaaa(a) => out.extend_from_slice(&a.octets()),
+            DnsRecordData::Cname(name) => out.extend_from_slice(name.as_bytes()),
+            DnsRecordData::Mx { preference, exchange } => {
+                out.extend_from_slice(&preference.to_be_bytes());
+                out.extend_from_slice(exchange.as_bytes());
+            }
+            DnsRecordData::Txt(strings) => {
+                for string in strings.iter() {
+                    out.push(string.len() as u8);
+                    out.extend_from_slice(string);
+                }
+            }
+            DnsRecordData::Srv { priority, weight, port, target } => {
+                out.extend_from_slice(&priority.to_be_bytes());
+                out.extend_from_slice(&weight.to_be_bytes());
+                out.extend_from_slice(&port.to_be_bytes());
+                out.extend_from_slice(target.as_bytes());
+            }
+        }
+    }
+
+    /// The inverse of `encode()`.
+    #[allow(dead_code)]
+    pub(crate) fn decode(rtype: DnsRecordType, data: &[u8]) -> Option<Self> {
+        let name = |bytes: &[u8]| std::str::from_utf8(bytes).ok().map(|s| s.to_owned());
+        let u16_at = |i: usize| data.get(i..i + 2).map(|b| u16::from_be_bytes(b.try_into().unwrap()));
+        match rtype {
+            DnsRecordType::A => {
+                let octets: [u8; 4] = data.try_into().ok()?;
+                Some(DnsRecordData::A(Ipv4Addr::from(octets)))
+            }
+            DnsRecordType::Aaaa => {
+                let octets: [u8; 16] = data.try_into().ok()?;
+                Some(DnsRecordData::Aaaa(Ipv6Addr::from(octets)))
+            }
+            DnsRecordType::Cname => Some(DnsRecordData::Cname(name(data)?)),
+            DnsRecordType::Mx => {
+                Some(DnsRecordData::Mx { preference: u16_at(0)?, exchange: name(data.get(2..)?)? })
+            }
+            DnsRecordType::Txt => {
+                let mut strings = Vec::new();
+                let mut rest = data;
+                while let Some((&len, tail)) = rest.split_first() {
+                    strings.push(tail.get(..len as usize)?.to_vec());
+                    rest = &tail[len as usize..];
+                }
+                Some(DnsRecordData::Txt(strings))
+            }
+            DnsRecordType::Srv => Some(DnsRecordData::Srv {
+                priority: u16_at(0)?,
+                weight: u16_at(2)?,
+                port: u16_at(4)?,
+                target: name(data.get(6..)?)?,
+            }),
+        }
+    }
+}
+impl From<IpAddr> for DnsRecordData {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(a) => DnsRecordData::A(a),
+            IpAddr::V6(a) => DnsRecordData::Aaaa(a),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRecord {
+    /// seconds the record may still be cached for
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+/// Decodes the result of a `RawRecordLookup`.
+#[allow(dead_code)]
+pub(crate) fn records_from_raw(raw: &[u8]) -> Result<Vec<DnsRecord>, DnsResponseCode> {
+    use DnsResponseCode::FormatError;
+    match raw.get(0..2).ok_or(FormatError)? {
+        [0, count] => {
+            let mut records = Vec::new();
+            let mut index = 2;
+            for _ in 0..*count {
+                let header = raw.get(index..index + 8).ok_or(FormatError)?;
+                let rtype = u16::from_be_bytes(header[0..2].try_into().unwrap());
+                let ttl = u32::from_be_bytes(header[2..6].try_into().unwrap());
+                let len = u16::from_be_bytes(header[6..8].try_into().unwrap()) as usize;
+                index += 8;
+                let data = raw.get(index..index + len).ok_or(FormatError)?;
+                index += len;
+                let data = DnsRecordType::from_u16(rtype)
+                    .and_then(|rtype| DnsRecordData::decode(rtype, data))
+                    .ok_or(FormatError)?;
+                records.push(DnsRecord { ttl, data });
+            }
+            Ok(records)
+        }
+        [_, code] => Err(DnsResponseCode::from_u8(*code).unwrap_or(DnsResponseCode::UnknownError)),
+        _ => Err(FormatError),
+    }
+}
+
+/// A service offered by an app, as advertised over DNS-SD.
+#[allow(dead_code)]
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub(crate) struct MdnsServiceIpc {
+    /// a single DNS label naming this instance, e.g. "Precursor chat"
+    pub instance: String<64>,
+    /// the service type and transport, e.g. "_http._tcp"
+    pub service: String<64>,
+    pub port: u16,
+    /// TXT record entries, separated by newlines
+    pub txt: String<512>,
+}
+
+/// Checks that a service can be advertised: the instance is one label, the service is
+/// `_name._tcp` or `_name._udp`, and each TXT entry fits in a character-string.
+#[allow(dead_code)]
+pub(crate) fn mdns_service_valid(instance: &str, service: &str, txt: &[&str]) -> bool {
+    let mut labels = service.split('.');
+    let name_ok = match (labels.next(), labels.next(), labels.next()) {
+        (Some(name), Some(proto), None) => {
+            name.len() > 1
+                && name.len() <= 16
+                && name.starts_with('_')
+                && (proto == "_tcp" || proto == "_udp")
+        }
+        _ => false,
+    };
+    name_ok
+        && !instance.is_empty()
+        && instance.len() <= 63
+        && !instance.contains('.')
+        && txt.iter().all(|entry| entry.len() <= 255 && !entry.contains('\n'))
+}
+
 // Time API items. Time is in the DNS crate because it has the resources
 // to accommodate the time server, while the more logically grouped status
 // crate does not.
@@ -0,0 +1,156 @@
+//! DNS-over-TLS (RFC 7858) transport for the resolver.
+//!
+//! Queries go over TLS to one of the upstreams configured in the PDDB, framed with a two-byte length as
+//! for DNS over TCP. Upstream certificates are checked against the user's trust anchors in `tls.trusted`,
+//! so the CA of an upstream has to be trusted (e.g. with the `tls` shellchat commands) before it can be
+//! used. The connection is kept open between lookups, and moves on to the next upstream when one fails.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use num_traits::FromPrimitive;
+use rustls::{ClientConnection, StreamOwned};
+
+use crate::api::*;
+use crate::upstreams::{load_upstreams, TlsUpstream};
+
+/// how long to wait on an upstream, both to connect and for each read or write
+const DOT_TIMEOUT_MS: u64 = 5_000;
+
+type DotStream = StreamOwned<ClientConnection, TcpStream>;
+
+struct DotConfig {
+    mode: DnsTlsMode,
+    upstreams: Vec<TlsUpstream>,
+}
+
+pub(crate) struct Dot {
+    tls: tls::Tls,
+    poller: pddb::PddbMountPoller,
+    /// `None` until the PDDB is mounted and the configuration can be read out of it
+    config: Option<DotConfig>,
+    /// the open connection, and the index of the upstream it goes to
+    conn: Option<(usize, DotStream)>,
+    /// the upstream to try first when there is no open connection
+    preferred: usize,
+}
+impl Dot {
+    pub fn new() -> Self {
+        Dot {
+            tls: tls::Tls::new(),
+            poller: pddb::PddbMountPoller::new(),
+            config: None,
+            conn: None,
+            preferred: 0,
+        }
+    }
+
+    /// Forgets the configuration, so that it is read again on the next lookup.
+    pub fn reload(&mut self) {
+        self.config = None;
+        self.conn = None;
+        self.preferred = 0;
+    }
+
+    fn config(&mut self) -> Option<&DotConfig> {
+        if self.config.is_none() && self.poller.is_mounted_nonblocking() {
+            // a mode that can't be read is taken to be the strictest, as it may be what the user chose
+            let mode = userprefs::Manager::new()
+                .dns_tls_mode_or_default()
+                .ok()
+                .and_then(DnsTlsMode::from_u32)
+                .unwrap_or(DnsTlsMode::Strict);
+            let upstreams = load_upstreams(&pddb::Pddb::new());
+            log::info!("DNS-over-TLS mode {:?}, {} upstream(s)", mode, upstreams.len());
+            self.config = Some(DotConfig { mode, upstreams });
+        }
+        self.config.as_ref()
+    }
+
+    /// Until the PDDB is mounted, the mode can't be known and is taken to be `Strict`, so that no
+    /// lookups go out in plaintext against the user's choice; they fail until the PDDB is mounted.
+    pub fn mode(&mut self) -> DnsTlsMode {
+        self.config().map(|config| config.mode).unwrap_or(DnsTlsMode::Strict)
+    }
+
+    /// Sends `query` to an upstream and returns its response.
+    pub fn exchange(&mut self, query: &[u8]) -> Result<Vec<u8>, DnsResponseCode> {
+        let upstreams = match self.config() {
+            Some(config) if !config.upstreams.is_empty() => config.upstreams.clone(),
+            _ => return Err(DnsResponseCode::NoServerSpecified),
+        };
+        if let Some((index, mut stream)) = self.conn.take() {
+            match exchange_on(&mut stream, query) {
+                Ok(response) => {
+                    self.conn = Some((index, stream));
+                    return Ok(response);
+                }
+                // upstreams close idle connections, so this gets another try on a fresh one below
+                Err(e) => {
+                    log::debug!("DNS-over-TLS connection to {} dropped: {:?}", upstreams[index].addr, e)
+                }
+            }
+        }
+        for offset in 0..upstreams.len() {
+            let index = (self.preferred + offset) % upstreams.len();
+            let upstream = &upstreams[index];
+            let result = connect(&self.tls, upstream)
+                .and_then(|mut stream| exchange_on(&mut stream, query).map(|response| (stream, response)));
+            match result {
+                Ok((stream, response)) => {
+                    self.conn = Some((index, stream));
+                    self.preferred = index;
+                    return Ok(response);
+                }
+                Err(e) => log::warn!(
+                    "DNS-over-TLS upstream {} ({}) failed: {:?}",
+                    upstream.addr,
+                    upstream.auth_name,
+                    e
+                ),
+            }
+        }
+        Err(DnsResponseCode::NetworkError)
+    }
+}
+
+/// Sends a query with `exchange`, unless `mode` is `Off`. Returns the response, `None` if the query is
+/// to go out as plain DNS instead, or the error that ends the lookup.
+pub(crate) fn over_tls<F>(mode: DnsTlsMode, exchange: F) -> Result<Option<Vec<u8>>, DnsResponseCode>
+where
+    F: FnOnce() -> Result<Vec<u8>, DnsResponseCode>,
+{
+    match mode {
+        DnsTlsMode::Off => Ok(None),
+        mode => match exchange() {
+            Ok(response) => Ok(Some(response)),
+            Err(e) if mode == DnsTlsMode::Strict => Err(e),
+            Err(e) => {
+                log::warn!("DNS-over-TLS failed ({:?}), falling back to plain DNS", e);
+                Ok(None)
+            }
+        },
+    }
+}
+
+fn connect(tls: &tls::Tls, upstream: &TlsUpstream) -> std::io::Result<DotStream> {
+    let timeout = Duration::from_millis(DOT_TIMEOUT_MS);
+    let sock = TcpStream::connect_timeout(&upstream.addr, timeout)?;
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+    // the handshake happens on first use, and fails there if the certificate isn't trusted
+    tls.stream_owned(&upstream.auth_name, sock)
+}
+
+fn exchange_on(stream: &mut DotStream, query: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed)?;
+    stream.flush()?;
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut response)?;
+    Ok(response)
+}
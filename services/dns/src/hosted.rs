@@ -1,8 +1,8 @@
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, ToSocketAddrs};
 
 use net::NetIpAddr;
 
-use crate::DnsResponseCode;
+use crate::{DnsRecord, DnsRecordType, DnsResponseCode, TlsUpstream};
 
 #[derive(Debug)]
 pub struct Dns {}
@@ -32,6 +32,41 @@ impl Dns {
         }
     }
 
+    pub fn lookup_records(
+        &self,
+        _name: &str,
+        _rtype: DnsRecordType,
+    ) -> Result<Vec<DnsRecord>, DnsResponseCode> {
+        log::warn!("DNS record lookups not implemented in hosted mode!");
+        Err(DnsResponseCode::NotImplemented)
+    }
+
+    pub fn mdns_register_service(
+        &self,
+        _instance: &str,
+        _service: &str,
+        _port: u16,
+        _txt: &[&str],
+    ) -> Result<(), xous::Error> {
+        log::warn!("mDNS service advertisement not implemented in hosted mode!");
+        Ok(())
+    }
+
+    pub fn mdns_unregister_service(&self, _instance: &str, _service: &str) -> Result<(), xous::Error> {
+        Ok(())
+    }
+
+    pub fn tls_upstreams(&self) -> Vec<TlsUpstream> { Vec::new() }
+
+    pub fn add_tls_upstream(&self, _upstream: &TlsUpstream) -> Result<(), xous::Error> {
+        log::warn!("DNS-over-TLS not implemented in hosted mode!");
+        Ok(())
+    }
+
+    pub fn remove_tls_upstream(&self, _addr: &SocketAddr) -> Result<(), xous::Error> { Ok(()) }
+
+    pub fn reload_tls_config(&self) -> Result<(), xous::Error> { Ok(()) }
+
     pub fn flush_cache(&self) -> Result<(), xous::Error> {
         log::warn!("DNS cache flush not implemented in hosted mode!");
         Ok(())
@@ -1,5 +1,5 @@
 #![cfg_attr(target_os = "none", no_std)]
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use net::NetIpAddr;
 use num_traits::ToPrimitive;
@@ -7,6 +7,7 @@ use xous::CID;
 use xous_ipc::{Buffer, String};
 
 use crate::api::*;
+use crate::upstreams::{load_upstreams, remove_upstream, store_upstream, TlsUpstream};
 
 #[derive(Debug)]
 pub struct Dns {
@@ -36,6 +37,100 @@ impl Dns {
         }
     }
 
+    /// Looks up the records of type `rtype` for `name`, following CNAME chains unless `rtype` is
+    /// `DnsRecordType::Cname`. An empty list means the name exists but has no such records.
+    pub fn lookup_records(
+        &self,
+        name: &str,
+        rtype: DnsRecordType,
+    ) -> Result<Vec<DnsRecord>, DnsResponseCode> {
+        if name.is_empty() || name.len() > DNS_NAME_LENGTH_LIMIT {
+            return Err(DnsResponseCode::FormatError);
+        }
+        let mut request = xous::map_memory(None, None, 4096, xous::MemoryFlags::R | xous::MemoryFlags::W)
+            .or(Err(DnsResponseCode::UnknownError))?;
+        // Safety: `u8` contains no undefined values
+        unsafe { request.as_slice_mut()[..name.len()].copy_from_slice(name.as_bytes()) };
+        let msg = xous::MemoryMessage {
+            id: Opcode::RawRecordLookup.to_usize().unwrap(),
+            buf: request,
+            offset: xous::MemoryAddress::new(rtype as usize),
+            valid: xous::MemorySize::new(name.len()),
+        };
+        let result = match xous::send_message(self.conn, xous::Message::MutableBorrow(msg)) {
+            // Safety: `u8` contains no undefined values
+            Ok(xous::Result::MemoryReturned(_, _)) => records_from_raw(unsafe { request.as_slice() }),
+            _ => Err(DnsResponseCode::UnknownError),
+        };
+        xous::unmap_memory(request).unwrap();
+        result
+    }
+
+    /// Advertises a service on the local network over mDNS/DNS-SD, as `instance` of the `service` type
+    /// (for example `"_http._tcp"`), on `port` of this device. Registering the same instance again
+    /// replaces it. The advertisement lasts until `mdns_unregister_service()` or a reboot.
+    pub fn mdns_register_service(
+        &self,
+        instance: &str,
+        service: &str,
+        port: u16,
+        txt: &[&str],
+    ) -> Result<(), xous::Error> {
+        if !mdns_service_valid(instance, service, txt) {
+            return Err(xous::Error::InvalidString);
+        }
+        let joined = txt.join("\n");
+        if joined.len() > 512 {
+            return Err(xous::Error::OutOfMemory);
+        }
+        let ipc = MdnsServiceIpc {
+            instance: String::from_str(instance),
+            service: String::from_str(service),
+            port,
+            txt: String::from_str(&joined),
+        };
+        let buf = Buffer::into_buf(ipc).or(Err(xous::Error::InternalError))?;
+        buf.lend(self.conn, Opcode::MdnsRegister.to_u32().unwrap()).map(|_| ())
+    }
+
+    pub fn mdns_unregister_service(&self, instance: &str, service: &str) -> Result<(), xous::Error> {
+        let ipc = MdnsServiceIpc {
+            instance: String::from_str(instance),
+            service: String::from_str(service),
+            port: 0,
+            txt: String::new(),
+        };
+        let buf = Buffer::into_buf(ipc).or(Err(xous::Error::InternalError))?;
+        buf.lend(self.conn, Opcode::MdnsUnregister.to_u32().unwrap()).map(|_| ())
+    }
+
+    /// The DNS-over-TLS upstreams. Whether they're used is up to the `dns_tls_mode` user preference.
+    pub fn tls_upstreams(&self) -> Vec<TlsUpstream> { load_upstreams(&pddb::Pddb::new()) }
+
+    /// Adds a DNS-over-TLS upstream, or changes the name checked for the one at the same address. The CA
+    /// of the upstream's certificate must be among the trusted TLS anchors for it to be used.
+    pub fn add_tls_upstream(&self, upstream: &TlsUpstream) -> Result<(), xous::Error> {
+        if upstream.auth_name.is_empty() || upstream.auth_name.len() > DNS_NAME_LENGTH_LIMIT {
+            return Err(xous::Error::InvalidString);
+        }
+        store_upstream(&pddb::Pddb::new(), upstream).or(Err(xous::Error::InternalError))?;
+        self.reload_tls_config()
+    }
+
+    pub fn remove_tls_upstream(&self, addr: &SocketAddr) -> Result<(), xous::Error> {
+        remove_upstream(&pddb::Pddb::new(), addr).or(Err(xous::Error::ServerNotFound))?;
+        self.reload_tls_config()
+    }
+
+    /// Has the resolver pick up changes to the DNS-over-TLS mode or upstreams.
+    pub fn reload_tls_config(&self) -> Result<(), xous::Error> {
+        xous::send_message(
+            self.conn,
+            xous::Message::new_scalar(Opcode::ReloadTlsConfig.to_usize().unwrap(), 0, 0, 0, 0),
+        )
+        .map(|_| ())
+    }
+
     pub fn flush_cache(&self) -> Result<(), xous::Error> {
         xous::send_message(
             self.conn,
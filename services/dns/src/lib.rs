@@ -1,6 +1,8 @@
 pub mod api;
 #[allow(unused_imports)]
 pub use api::*;
+mod upstreams;
+pub use upstreams::{TlsUpstream, DNS_TLS_PORT};
 
 #[cfg(any(feature = "precursor", feature = "renode"))]
 mod hw;
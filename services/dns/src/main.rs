@@ -2,11 +2,18 @@
 #![cfg_attr(target_os = "none", no_main)]
 
 mod api;
+mod dot;
+mod mdns;
 mod time; // why is this here? because it's the only place it'll fit. :-/
+mod upstreams;
+
+#[cfg(test)]
+mod tests;
+
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io::ErrorKind;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::thread;
 use std::time::Duration;
 
@@ -22,18 +29,6 @@ use xous_ipc::{Buffer, String};
 // MOROS is MIT licensed.
 // See RFC 1035 for implementation details
 
-#[repr(u16)]
-enum QueryType {
-    A = 1,
-    // NS = 2,
-    // MD = 3,
-    // MF = 4,
-    // CNAME = 5,
-    // SOA = 6,
-    // MX = 15,
-    // TXT = 16,
-}
-
 #[repr(u16)]
 enum QueryClass {
     IN = 1,
@@ -44,11 +39,15 @@ struct Message {
 }
 
 const FLAG_RD: u16 = 0x0100; // Recursion desired
+/// 10 seconds for DNS to resolve by default
+const DNS_TIMEOUT_MS: u64 = 10_000;
+/// how many times a lookup may ask again to follow a CNAME chain out of the server's answer
+const MAX_CNAME_QUERIES: usize = 8;
 
 impl Message {
     pub fn from(datagram: &[u8]) -> Self { Self { datagram: Vec::from(datagram) } }
 
-    pub fn query(qname: &str, qtype: QueryType, qclass: QueryClass, id: u16) -> Self {
+    pub fn query(qname: &str, qtype: DnsRecordType, qclass: QueryClass, id: u16) -> Self {
         let mut datagram = Vec::new();
 
         for b in id.to_be_bytes().iter() {
@@ -86,132 +85,151 @@ impl Message {
 
     pub fn is_response(&self) -> bool { if (self.header() & (1 << 15)) == 0 { false } else { true } }
 
-    fn fast_foward_name(&self, start: usize) -> Result<usize, DnsResponseCode> {
+    /// Reads the name starting at `start`, following compression pointers. Returns the name, and the
+    /// index just past it.
+    pub fn read_name(&self, start: usize) -> Result<(std::string::String, usize), DnsResponseCode> {
         use DnsResponseCode::FormatError;
+        let mut labels = Vec::<std::string::String>::new();
         let mut index = start;
+        let mut end = None;
+        // every pointer must go backwards, so this bounds the number of jumps
+        let mut limit = index;
         loop {
-            log::trace!("cname index: {}", index);
-            if *(self.datagram.get(index).ok_or(FormatError)?) == 0 {
-                index += 1;
+            let len = *(self.datagram.get(index).ok_or(FormatError)?) as usize;
+            if len >= 0xc0 {
+                let pointer =
+                    (len & 0x3f) << 8 | *(self.datagram.get(index + 1).ok_or(FormatError)?) as usize;
+                if pointer >= limit {
+                    return Err(FormatError);
+                }
+                end.get_or_insert(index + 2);
+                limit = pointer;
+                index = pointer;
+            } else if len == 0 {
                 break;
             } else {
-                index += *(self.datagram.get(index).ok_or(FormatError)?) as usize;
-                index += 1;
+                let label = self.datagram.get(index + 1..index + 1 + len).ok_or(FormatError)?;
+                labels.push(std::string::String::from_utf8_lossy(label).into_owned());
+                index += 1 + len;
             }
         }
-        Ok(index)
+        Ok((labels.join("."), end.unwrap_or(index + 1)))
     }
 
-    pub fn parse_response(&self) -> Result<HashMap<IpAddr, u32>, DnsResponseCode> {
+    /// Reads the data of a record of type `rtype` spanning `start..end`. Types we don't handle give `None`.
+    fn read_rdata(
+        &self,
+        rtype: u16,
+        start: usize,
+        end: usize,
+    ) -> Result<Option<DnsRecordData>, DnsResponseCode> {
+        use DnsResponseCode::FormatError;
+        let rdata = self.datagram.get(start..end).ok_or(FormatError)?;
+        let u16_at = |i: usize| {
+            rdata.get(i..i + 2).map(|b| u16::from_be_bytes(b.try_into().unwrap())).ok_or(FormatError)
+        };
+        let rtype = match DnsRecordType::from_u16(rtype) {
+            Some(rtype) => rtype,
+            None => return Ok(None),
+        };
+        // names are read out of the datagram rather than `rdata`, as they may be compressed
+        let data = match rtype {
+            DnsRecordType::Cname => DnsRecordData::Cname(self.read_name(start)?.0),
+            DnsRecordType::Mx => {
+                DnsRecordData::Mx { preference: u16_at(0)?, exchange: self.read_name(start + 2)?.0 }
+            }
+            DnsRecordType::Srv => DnsRecordData::Srv {
+                priority: u16_at(0)?,
+                weight: u16_at(2)?,
+                port: u16_at(4)?,
+                target: self.read_name(start + 6)?.0,
+            },
+            // the rest are laid out on the wire just as we hand them out
+            _ => DnsRecordData::decode(rtype, rdata).ok_or(FormatError)?,
+        };
+        Ok(Some(data))
+    }
+
+    /// Parses the answer section into the owner name, TTL and data of each record, skipping the
+    /// records of types we don't handle.
+    pub fn parse_records(&self) -> Result<Vec<(std::string::String, u32, DnsRecordData)>, DnsResponseCode> {
         use DnsResponseCode::FormatError;
         log::trace!("parsing packet: {:?}", self.datagram);
+        let u16_at = |i: usize| {
+            self.datagram.get(i..i + 2).map(|b| u16::from_be_bytes(b.try_into().unwrap())).ok_or(FormatError)
+        };
 
-        let mut map = HashMap::<IpAddr, u32>::new();
         // ASSUME: the query ID and response bit fields have already been checked
         // and that the rcode is valid
-        let qdcount = u16::from_be_bytes(self.datagram[4..6].try_into().unwrap());
-        let ancount = u16::from_be_bytes(self.datagram[6..8].try_into().unwrap());
+        let qdcount = u16_at(4)?;
+        let ancount = u16_at(6)?;
 
         let mut index = 12;
-        // fast forward past the qname
-        for queries in 0..qdcount {
-            log::trace!("parsing query{}, index {}", queries, index);
-            index = self.fast_foward_name(index)?;
-            log::trace!("fast forward through qname to {}", index);
-            // index is now at qtype
-            let qtype = u16::from_be_bytes(self.datagram[index..index + 2].try_into().unwrap());
-            // A = 1, AAAA = 28
-            if qtype != 1 && qtype != 28 {
-                log::error!("Problem parsing qname, qtype is not 1 or 28: {}", qtype);
-                return Err(FormatError);
-            }
-            index += 2;
-            let qclass = u16::from_be_bytes(self.datagram[index..index + 2].try_into().unwrap());
-            if qclass != 1 {
-                log::error!("Problem parsing qname, qclass is not 1: {}", qclass);
-                return Err(FormatError);
-            }
-            index += 2;
+        for _ in 0..qdcount {
+            // skip the qname, qtype and qclass
+            index = self.read_name(index)?.1 + 4;
         }
-        // index is now at the aname section
+        let mut records = Vec::new();
         for aname in 0..ancount {
             log::trace!("parsing aname{}, index {}", aname, index);
-            // first check to see if we're dealing with a pointer or a name
-            if self.datagram[index] >= 0xc0 {
-                // pointer
-                index += 1;
-                if self.datagram[index] != 0xc {
-                    log::error!("Found aname pointer, but value does not conform to length of aname header");
-                    return Err(FormatError);
+            let (owner, next) = self.read_name(index)?;
+            let atype = u16_at(next)?;
+            let aclass = u16_at(next + 2)?;
+            let ttl = u32::from_be_bytes(
+                self.datagram.get(next + 4..next + 8).ok_or(FormatError)?.try_into().unwrap(),
+            );
+            let len = u16_at(next + 8)? as usize;
+            index = next + 10;
+            if aclass == QueryClass::IN as u16 {
+                if let Some(data) = self.read_rdata(atype, index, index + len)? {
+                    records.push((owner, ttl, data));
                 }
-                index += 1;
-            } else {
-                // name, fast forward past the name
-                index = self.fast_foward_name(index)?;
-                log::trace!("fast forward aname to {}", index);
             }
-            // index is now at type
-            let atype = u16::from_be_bytes(self.datagram[index..index + 2].try_into().unwrap());
-            // A = 1, AAAA = 28
-            if atype != 1 && atype != 28 {
-                log::error!("Problem parsing aname, type is not 1 or 28: {}", atype);
-                return Err(FormatError);
-            }
-            index += 2;
-            let aclass = u16::from_be_bytes(self.datagram[index..index + 2].try_into().unwrap());
-            if aclass != 1 {
-                log::error!("Problem parsing aname, aclass is not 1: {}", aclass);
-                return Err(FormatError);
+            index += len;
+        }
+        Ok(records)
+    }
+
+    /// Picks out the records of type `rtype` for `qname`, following any CNAME chain through the
+    /// message. Records reached through CNAMEs have their TTLs capped at those of the CNAMEs.
+    ///
+    /// If the chain leads to a name the message has nothing more for, no records are returned, along
+    /// with that name and the TTL cap so far, so it can be asked for in turn.
+    pub fn answers(
+        &self,
+        qname: &str,
+        rtype: DnsRecordType,
+    ) -> Result<(HashMap<DnsRecordData, u32>, Option<(std::string::String, u32)>), DnsResponseCode> {
+        let records = self.parse_records()?;
+        let mut name = qname.trim_end_matches('.');
+        let mut ttl_cap = u32::MAX;
+        // each step uses up a CNAME record, so a loop can't keep us here
+        for _ in 0..=records.len() {
+            let owned = records.iter().filter(|(owner, _, _)| owner.eq_ignore_ascii_case(name));
+            let found: HashMap<DnsRecordData, u32> = owned
+                .clone()
+                .filter(|(_, _, data)| data.rtype() == rtype)
+                .map(|(_, ttl, data)| (data.clone(), (*ttl).min(ttl_cap)))
+                .collect();
+            if !found.is_empty() || rtype == DnsRecordType::Cname {
+                return Ok((found, None));
             }
-            index += 2;
-            // this is our TTL
-            let ttl = u32::from_be_bytes(self.datagram[index..index + 4].try_into().unwrap());
-            log::trace!("got ttl: {}", ttl);
-            index += 4;
-            // this is the payload length
-            let addr_len = u16::from_be_bytes(self.datagram[index..index + 2].try_into().unwrap());
-            index += 2;
-            match addr_len {
-                // ipv4
-                4 => {
-                    if atype != 1 {
-                        log::error!("Got a 4-byte address, but ATYPE != A (1)");
-                        return Err(FormatError);
-                    }
-                    // this copy happens because I can't figure out how to get Ipv4Addr::from() to realize
-                    // it's casting from a [u8;4]
-                    let mut rdata: [u8; 4] = [0; 4];
-                    for (&src, dst) in self.datagram[index..index + 4].iter().zip(rdata.iter_mut()) {
-                        *dst = src;
-                    }
-                    let addr = IpAddr::V4(Ipv4Addr::from(rdata));
-                    index += 4;
-                    map.insert(addr, ttl);
-                }
-                // ipv6
-                16 => {
-                    if atype != 28 {
-                        log::error!("Got a 16-byte address, but ATYPE != AAAA (28)");
-                        return Err(FormatError);
-                    }
-                    // this copy happens because I can't figure out how to get Ipv6Addr::from() to realize
-                    // it's casting from a [u8;4]
-                    let mut rdata: [u8; 16] = [0; 16];
-                    for (&src, dst) in self.datagram[index..index + 16].iter().zip(rdata.iter_mut()) {
-                        *dst = src;
-                    }
-                    let addr = IpAddr::V6(Ipv6Addr::from(rdata));
-                    index += 16;
-                    map.insert(addr, ttl);
-                }
-                _ => {
-                    log::error!("Length field does not match a known record type");
-                    return Err(FormatError);
+            let cname = owned
+                .filter_map(|(_, ttl, data)| match data {
+                    DnsRecordData::Cname(target) => Some((*ttl, target)),
+                    _ => None,
+                })
+                .next();
+            match cname {
+                Some((ttl, target)) => {
+                    ttl_cap = ttl_cap.min(ttl);
+                    name = target;
                 }
+                None if name.eq_ignore_ascii_case(qname.trim_end_matches('.')) => return Ok((found, None)),
+                None => return Ok((found, Some((name.to_owned(), ttl_cap)))),
             }
         }
-
-        Ok(map)
+        Err(DnsResponseCode::FormatError)
     }
 
     /*
@@ -293,6 +311,8 @@ pub struct Resolver {
     buf: [u8; DNS_PKT_MAX_LEN],
     trng: trng::Trng,
     freeze: bool,
+    /// DNS-over-TLS, when the user has turned it on
+    dot: dot::Dot,
 }
 impl Resolver {
     pub fn new(xns: &xous_names::XousNames) -> Resolver {
@@ -300,7 +320,7 @@ impl Resolver {
         let local_port = (49152 + trng.get_u32().unwrap() % 16384) as u16;
         let socket = UdpSocket::bind(format!("0.0.0.0:{}", local_port))
             .expect("couldn't create socket for DNS resolver");
-        let timeout = Duration::from_millis(10_000); // 10 seconds for DNS to resolve by default
+        let timeout = Duration::from_millis(DNS_TIMEOUT_MS);
         socket.set_read_timeout(Some(timeout)).unwrap();
         socket.set_nonblocking(false).unwrap(); // we want this to block.
         // we /could/ do a non-blocking DNS resolver, but...what would you do in the meantime??
@@ -313,6 +333,7 @@ impl Resolver {
             buf: [0; DNS_PKT_MAX_LEN],
             trng,
             freeze: false,
+            dot: dot::Dot::new(),
         }
     }
 
@@ -329,33 +350,65 @@ impl Resolver {
 
     pub fn get_freeze(&self) -> bool { self.freeze }
 
+    pub fn reload_tls_config(&mut self) { self.dot.reload(); }
+
+    /// Tells the net service's firewall the addresses `name` was found at, so that its rules can name hosts.
+    pub fn report_lookup(&self, name: &str, records: &HashMap<DnsRecordData, u32>) {
+        let found = addrs(records);
+        if found.is_empty() {
+            return;
+        }
+        let ttl = records.iter().filter(|(data, _)| data.addr().is_some()).map(|(_, ttl)| *ttl).min();
+        self.mgr.report_lookup(name, &found, ttl.unwrap_or(0));
+    }
+
     /// this allows us to re-use the TRNG object
     pub fn trng_u32(&self) -> u32 { self.trng.get_u32().unwrap() }
 
-    pub fn resolve(&mut self, name: &str) -> Result<HashMap<IpAddr, u32>, DnsResponseCode> {
+    pub fn resolve(&mut self, name: &str) -> Result<HashMap<DnsRecordData, u32>, DnsResponseCode> {
+        self.resolve_records(name, DnsRecordType::A)
+    }
+
+    /// Looks up the records of type `rtype` for `name`, asking again wherever a CNAME chain leaves
+    /// the server's answer.
+    pub fn resolve_records(
+        &mut self,
+        name: &str,
+        rtype: DnsRecordType,
+    ) -> Result<HashMap<DnsRecordData, u32>, DnsResponseCode> {
+        if mdns::is_local_name(name) {
+            if rtype != DnsRecordType::A && rtype != DnsRecordType::Aaaa {
+                // that would be DNS-SD browsing, which we only do as a responder
+                return Err(DnsResponseCode::NotImplemented);
+            }
+            // `.local` names are answered by the hosts themselves, not by the configured servers
+            let id = self.trng.get_u32().unwrap() as u16;
+            let result = mdns::resolve(&self.socket, &mut self.buf, name, rtype, id);
+            self.socket.set_read_timeout(Some(Duration::from_millis(DNS_TIMEOUT_MS))).unwrap();
+            return result;
+        }
+        follow_cnames(name, rtype, |qname| self.query(qname, rtype))
+    }
+
+    /// Asks a server for the records of type `qtype` for `qname`, returning its response. This goes over
+    /// TLS if the user has set that up, and otherwise to a server from the DNS server list.
+    fn query(&mut self, qname: &str, qtype: DnsRecordType) -> Result<Message, DnsResponseCode> {
+        let qclass = QueryClass::IN;
+        let query = Message::query(qname, qtype, qclass, self.trng.get_u32().unwrap() as u16);
+
+        let mode = self.dot.mode();
+        if let Some(response) = dot::over_tls(mode, || self.dot.exchange(&query.datagram))? {
+            return Self::check_response(&query, Message::from(&response));
+        }
+
         if let Some(dns_address) = self.mgr.get_random() {
             let dns_port = 53;
             let server = SocketAddr::new(dns_address, dns_port);
 
-            let qname = name;
-            let qtype = QueryType::A;
-            let qclass = QueryClass::IN;
-            let query = Message::query(qname, qtype, qclass, self.trng.get_u32().unwrap() as u16);
-
             self.socket.send_to(&query.datagram, &server).map_err(|_| DnsResponseCode::NetworkError)?;
 
             match self.socket.recv(&mut self.buf) {
-                Ok(len) => {
-                    let message = Message::from(&self.buf[..len]);
-                    if message.id() == query.id() && message.is_response() {
-                        return match message.rcode() {
-                            DnsResponseCode::NoError => message.parse_response(),
-                            rcode => Err(rcode),
-                        };
-                    } else {
-                        Err(DnsResponseCode::NetworkError)
-                    }
-                }
+                Ok(len) => Self::check_response(&query, Message::from(&self.buf[..len])),
                 Err(e) => match e.kind() {
                     ErrorKind::WouldBlock => Err(DnsResponseCode::NetworkError),
                     _ => Err(DnsResponseCode::UnknownError),
@@ -365,6 +418,46 @@ impl Resolver {
             Err(DnsResponseCode::NoServerSpecified)
         }
     }
+
+    fn check_response(query: &Message, message: Message) -> Result<Message, DnsResponseCode> {
+        if message.datagram.len() >= 12 && message.id() == query.id() && message.is_response() {
+            match message.rcode() {
+                DnsResponseCode::NoError => Ok(message),
+                rcode => Err(rcode),
+            }
+        } else {
+            Err(DnsResponseCode::NetworkError)
+        }
+    }
+}
+
+/// Looks up the records of type `rtype` for `name` with `query`, asking again wherever a CNAME chain
+/// leaves the answer, up to `MAX_CNAME_QUERIES` times.
+fn follow_cnames<F>(
+    name: &str,
+    rtype: DnsRecordType,
+    mut query: F,
+) -> Result<HashMap<DnsRecordData, u32>, DnsResponseCode>
+where
+    F: FnMut(&str) -> Result<Message, DnsResponseCode>,
+{
+    let mut qname = name.to_owned();
+    let mut ttl_cap = u32::MAX;
+    for _ in 0..MAX_CNAME_QUERIES {
+        let message = query(&qname)?;
+        match message.answers(&qname, rtype)? {
+            (records, Some((next, cap))) if records.is_empty() => {
+                log::debug!("following CNAME {} -> {}", qname, next);
+                qname = next;
+                ttl_cap = ttl_cap.min(cap);
+            }
+            (records, _) => {
+                return Ok(records.into_iter().map(|(data, ttl)| (data, ttl.min(ttl_cap))).collect());
+            }
+        }
+    }
+    log::warn!("CNAME chain for {} is too long", name);
+    Err(DnsResponseCode::ServerFailure)
 }
 
 #[derive(PartialEq, Debug)]
@@ -394,7 +487,7 @@ fn name_from_msg(env: &xous::MessageEnvelope) -> Result<&str, NameConversionErro
     Ok(name_string)
 }
 
-fn fill_response(mut env: xous::MessageEnvelope, entries: &HashMap<IpAddr, u32>) -> Option<()> {
+fn fill_response(mut env: xous::MessageEnvelope, entries: &[IpAddr]) -> Option<()> {
     let mem = env.body.memory_message_mut()?;
 
     let s: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
@@ -413,7 +506,7 @@ fn fill_response(mut env: xous::MessageEnvelope, entries: &HashMap<IpAddr, u32>)
     *i.next()? = entry_count.try_into().ok()?;
 
     // Start filling in the addreses
-    for addr in entries.keys() {
+    for addr in entries.iter() {
         match addr {
             &IpAddr::V4(a) => {
                 // IPv4
@@ -439,6 +532,38 @@ fn fill_response(mut env: xous::MessageEnvelope, entries: &HashMap<IpAddr, u32>)
     None
 }
 
+fn fill_records(mut env: xous::MessageEnvelope, records: &HashMap<DnsRecordData, u32>) -> Option<()> {
+    let mem = env.body.memory_message_mut()?;
+
+    let s: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+
+    // Success, and the count is filled in at the end. Records that don't fit in the buffer are left out.
+    let mut out = vec![0u8, 0];
+    let mut count = 0u8;
+    for (data, ttl) in records.iter() {
+        let mut rdata = Vec::new();
+        data.encode(&mut rdata);
+        if count == u8::MAX || out.len() + 8 + rdata.len() > s.len() {
+            log::warn!("dropping DNS records that don't fit in the response");
+            break;
+        }
+        out.extend_from_slice(&(data.rtype() as u16).to_be_bytes());
+        out.extend_from_slice(&ttl.to_be_bytes());
+        out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&rdata);
+        count += 1;
+    }
+    out[1] = count;
+    s.get_mut(..out.len())?.copy_from_slice(&out);
+
+    None
+}
+
+/// The addresses among a name's records.
+fn addrs(records: &HashMap<DnsRecordData, u32>) -> Vec<IpAddr> {
+    records.keys().filter_map(|data| data.addr()).collect()
+}
+
 fn fill_error(mut env: xous::MessageEnvelope, code: DnsResponseCode) -> Option<()> {
     let mem = env.body.memory_message_mut()?;
 
@@ -472,8 +597,11 @@ fn main() -> ! {
     // if you wanted to force a server into the initial config, you can do it here, for example:
     // resolver.add_server(IpAddr::V4(Ipv4Addr::new(1,1,1,1)));
 
-    // the `u32` value is the TTL of the IpAddr
-    let mut dns_cache = HashMap::<std::string::String, HashMap<IpAddr, u32>>::new();
+    // DNS-SD advertisement of services registered by apps; only started once one registers
+    let mut mdns_responder = mdns::Responder::new();
+
+    // the `u32` value is the TTL of the record. Records of every type for a name share its entry.
+    let mut dns_cache = HashMap::<std::string::String, HashMap<DnsRecordData, u32>>::new();
 
     // build a thread that pings the UpdateTtl function once every few minutes to expire the DNS cache
     thread::spawn({
@@ -507,23 +635,26 @@ fn main() -> ! {
                     Ok(owned_name) => {
                         // handle the special case of "localhost" as a string
                         if owned_name == "localhost" {
-                            let mut local = HashMap::<IpAddr, u32>::new();
-                            local.insert(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 86400);
-                            fill_response(msg, &local);
+                            fill_response(msg, &[IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))]);
                             continue;
                         }
                         log::trace!("performing a lookup of {}", owned_name);
                         // Try to get the result out of the DNS cache
                         if let Some(entries) = dns_cache.get(&owned_name) {
-                            fill_response(msg, entries);
-                            continue;
+                            let cached = addrs(entries);
+                            if !cached.is_empty() {
+                                resolver.report_lookup(&owned_name, entries);
+                                fill_response(msg, &cached);
+                                continue;
+                            }
                         }
 
                         // This entry is not in the cache, so perform a lookup
                         match resolver.resolve(&owned_name) {
                             Ok(cache_entry) => {
-                                fill_response(msg, &cache_entry);
-                                dns_cache.insert(owned_name, cache_entry);
+                                resolver.report_lookup(&owned_name, &cache_entry);
+                                fill_response(msg, &addrs(&cache_entry));
+                                dns_cache.entry(owned_name).or_default().extend(cache_entry);
                                 continue;
                             }
                             Err(e) => {
@@ -539,60 +670,89 @@ fn main() -> ! {
                     }
                 };
             }
+            Some(Opcode::RawRecordLookup) => {
+                let rtype = msg
+                    .body
+                    .memory_message()
+                    .and_then(|mem| mem.offset)
+                    .and_then(|offset| DnsRecordType::from_usize(offset.get()));
+                let rtype = match rtype {
+                    Some(rtype) => rtype,
+                    None => {
+                        fill_error(msg, DnsResponseCode::NotImplemented);
+                        continue;
+                    }
+                };
+                match name_from_msg(&msg).map(|s| s.to_owned()) {
+                    Ok(owned_name) => {
+                        let cached: HashMap<DnsRecordData, u32> = dns_cache
+                            .get(&owned_name)
+                            .map(|entries| {
+                                entries
+                                    .iter()
+                                    .filter(|(data, _)| data.rtype() == rtype)
+                                    .map(|(data, ttl)| (data.clone(), *ttl))
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        if !cached.is_empty() {
+                            resolver.report_lookup(&owned_name, &cached);
+                            fill_records(msg, &cached);
+                            continue;
+                        }
+                        log::trace!("performing a {:?} lookup of {}", rtype, owned_name);
+                        match resolver.resolve_records(&owned_name, rtype) {
+                            Ok(records) => {
+                                resolver.report_lookup(&owned_name, &records);
+                                fill_records(msg, &records);
+                                dns_cache.entry(owned_name).or_default().extend(records);
+                            }
+                            Err(e) => {
+                                fill_error(msg, e);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("unable to do record lookup: {:?}", e);
+                        fill_error(msg, DnsResponseCode::NameError);
+                    }
+                }
+            }
             Some(Opcode::Lookup) => {
                 let mut buf =
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
                 let name = buf.to_original::<String<DNS_NAME_LENGTH_LIMIT>, _>().unwrap();
                 let name_std = std::string::String::from(name.as_str().unwrap());
-                if let Some(cache_entry) = dns_cache.get(&name_std) {
-                    // pick a random entry
-                    let rand = resolver.trng_u32() as usize % cache_entry.len();
-                    for (index, (ip_addr, _)) in cache_entry.iter().enumerate() {
-                        if rand == index {
-                            log::debug!("DNS cached: {}->{:?}", name, ip_addr);
-                            let response = DnsResponse {
-                                addr: Some(NetIpAddr::from(*ip_addr)),
-                                code: DnsResponseCode::NoError,
-                            };
-                            buf.replace(response).unwrap();
-                            break;
-                        }
-                    }
+                let cached = dns_cache.get(&name_std).map(addrs).unwrap_or_default();
+                let result = if !cached.is_empty() {
+                    log::debug!("DNS cached: {}->{:?}", name, cached);
+                    resolver.report_lookup(&name_std, &dns_cache[&name_std]);
+                    Ok(cached)
                 } else {
-                    match resolver.resolve(name.as_str().unwrap()) {
-                        Ok(cache_entry) => {
-                            if cache_entry.len() > 0 {
-                                dns_cache.insert(name_std, cache_entry);
-
-                                // now pick the entry back out again, as it was consumed...
-                                let name_std = std::string::String::from(name.as_str().unwrap());
-                                let cache_entry = dns_cache.get(&name_std).unwrap();
-
-                                // pick a random entry from the query response
-                                let rand = resolver.trng_u32() as usize % cache_entry.len();
-                                for (index, (ip_addr, _)) in cache_entry.iter().enumerate() {
-                                    if rand == index {
-                                        let response = DnsResponse {
-                                            addr: Some(NetIpAddr::from(*ip_addr)),
-                                            code: DnsResponseCode::NoError,
-                                        };
-                                        buf.replace(response).unwrap();
-                                        break;
-                                    }
-                                }
-                            } else {
-                                // no names found
-                                let response = DnsResponse { addr: None, code: DnsResponseCode::NameError };
-                                buf.replace(response).unwrap();
-                            }
-                        }
-                        Err(e) => {
-                            log::debug!("DNS query failed: {}->{:?}", name, e);
-                            let response = DnsResponse { addr: None, code: e };
-                            buf.replace(response).unwrap();
+                    resolver.resolve(name.as_str().unwrap()).map(|cache_entry| {
+                        resolver.report_lookup(&name_std, &cache_entry);
+                        let found = addrs(&cache_entry);
+                        dns_cache.entry(name_std).or_default().extend(cache_entry);
+                        found
+                    })
+                };
+                let response = match result {
+                    Ok(found) if !found.is_empty() => {
+                        // pick a random entry
+                        let rand = resolver.trng_u32() as usize % found.len();
+                        DnsResponse {
+                            addr: Some(NetIpAddr::from(found[rand])),
+                            code: DnsResponseCode::NoError,
                         }
                     }
-                }
+                    // no names found
+                    Ok(_) => DnsResponse { addr: None, code: DnsResponseCode::NameError },
+                    Err(e) => {
+                        log::debug!("DNS query failed: {}->{:?}", name, e);
+                        DnsResponse { addr: None, code: e }
+                    }
+                };
+                buf.replace(response).unwrap();
             }
             Some(Opcode::UpdateTtl) => msg_scalar_unpack!(msg, incr_secs, _, _, _, {
                 let increment = if incr_secs < u32::MAX as usize { incr_secs as u32 } else { u32::MAX };
@@ -601,12 +761,12 @@ fn main() -> ! {
                     for (name, cache_map) in dns_cache.iter_mut() {
                         // each entry can have multiple names with a different TTL
                         // decrement the TTL, and note which go to zero
-                        let mut expired_entries = Vec::<IpAddr>::new();
+                        let mut expired_entries = Vec::<DnsRecordData>::new();
                         for (entry, ttl) in cache_map.iter_mut() {
                             log::debug!("entry: {:?}, ttl: {}, incr: {}", entry, ttl, increment);
                             if *ttl < increment {
                                 *ttl = 0;
-                                expired_entries.push(*entry);
+                                expired_entries.push(entry.clone());
                             } else {
                                 *ttl = *ttl - increment as u32;
                             }
@@ -629,6 +789,24 @@ fn main() -> ! {
                     }
                 }
             }),
+            Some(Opcode::MdnsRegister) => {
+                let buf = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let ipc = buf.to_original::<MdnsServiceIpc, _>().unwrap();
+                match mdns::MdnsService::from_ipc(&ipc) {
+                    Some(service) => mdns_responder.register(service),
+                    None => log::warn!("ignoring invalid mDNS service registration: {:?}", ipc),
+                }
+            }
+            Some(Opcode::MdnsUnregister) => {
+                let buf = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let ipc = buf.to_original::<MdnsServiceIpc, _>().unwrap();
+                if let (Ok(instance), Ok(service)) = (ipc.instance.as_str(), ipc.service.as_str()) {
+                    mdns_responder.unregister(instance, service);
+                }
+            }
+            Some(Opcode::ReloadTlsConfig) => {
+                resolver.reload_tls_config();
+            }
             Some(Opcode::Flush) => {
                 dns_cache.clear();
             }
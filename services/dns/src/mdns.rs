@@ -0,0 +1,752 @@
+//! Multicast DNS (RFC 6762): resolution of `.local` names, and a DNS-SD (RFC 6763) responder that
+//! advertises the services registered by apps.
+//!
+//! Only IPv4 multicast is used, as the net service can't join IPv6 multicast groups. The responder
+//! does not probe for conflicting names before announcing.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api::*;
+use crate::Message;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+/// TTL of the records we give out, as recommended for records naming a host
+const MDNS_TTL: u32 = 120;
+/// TTL of the records in replies to legacy resolvers, which don't understand mDNS caching
+const MDNS_LEGACY_TTL: u32 = 10;
+/// How long to wait for a responder to answer a query
+const MDNS_QUERY_TIMEOUT_MS: u64 = 1_000;
+const MDNS_PKT_MAX_LEN: usize = 1500;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Top bit of the class: "unicast response requested" in a question, "cache flush" in a record
+const CLASS_TOP_BIT: u16 = 0x8000;
+/// QR and AA: an authoritative response
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+const SERVICES_META_QUERY: &str = "_services._dns-sd._udp.local";
+
+/// Names in the `.local` domain are resolved with mDNS instead of the configured servers.
+pub(crate) fn is_local_name(name: &str) -> bool {
+    name.trim_end_matches('.').to_ascii_lowercase().ends_with(".local")
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) { out.extend_from_slice(&value.to_be_bytes()); }
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+struct Question {
+    name: String,
+    qtype: u16,
+    unicast: bool,
+}
+
+struct Record {
+    name: String,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    rdata: Vec<u8>,
+}
+
+/// Splits a message into its questions and its records, from all sections.
+fn parse(message: &Message) -> Result<(Vec<Question>, Vec<Record>), DnsResponseCode> {
+    use DnsResponseCode::FormatError;
+    let datagram = &message.datagram;
+    let count = |offset: usize| -> Result<u16, DnsResponseCode> {
+        Ok(u16::from_be_bytes(datagram.get(offset..offset + 2).ok_or(FormatError)?.try_into().unwrap()))
+    };
+    let qdcount = count(4)?;
+    let rrcount = count(6)? as usize + count(8)? as usize + count(10)? as usize;
+
+    let mut index = 12;
+    let mut questions = Vec::new();
+    for _ in 0..qdcount {
+        let (name, next) = message.read_name(index)?;
+        let qtype = count(next)?;
+        let qclass = count(next + 2)?;
+        index = next + 4;
+        questions.push(Question { name, qtype, unicast: qclass & CLASS_TOP_BIT != 0 });
+    }
+    let mut records = Vec::new();
+    for _ in 0..rrcount {
+        let (name, next) = message.read_name(index)?;
+        let rtype = count(next)?;
+        let class = count(next + 2)?;
+        let ttl =
+            u32::from_be_bytes(datagram.get(next + 4..next + 8).ok_or(FormatError)?.try_into().unwrap());
+        let rdlength = count(next + 8)? as usize;
+        let rdata = datagram.get(next + 10..next + 10 + rdlength).ok_or(FormatError)?.to_vec();
+        index = next + 10 + rdlength;
+        records.push(Record { name, rtype, class: class & !CLASS_TOP_BIT, ttl, rdata });
+    }
+    Ok((questions, records))
+}
+
+/// Asks the local network for the addresses of type `rtype` (A or AAAA) of `name`, through `socket`.
+/// The answers from the first responder to reply with records of that type are returned.
+pub(crate) fn resolve(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    name: &str,
+    rtype: DnsRecordType,
+    id: u16,
+) -> Result<HashMap<DnsRecordData, u32>, DnsResponseCode> {
+    let name = name.trim_end_matches('.');
+    let mut query = Vec::new();
+    put_u16(&mut query, id);
+    put_u16(&mut query, 0); // no recursion in mDNS
+    put_u16(&mut query, 1);
+    query.extend_from_slice(&[0; 6]);
+    put_name(&mut query, name);
+    put_u16(&mut query, rtype as u16);
+    // we're not listening on the mDNS port, so ask for the answer to come straight back to us
+    put_u16(&mut query, CLASS_IN | CLASS_TOP_BIT);
+    socket
+        .send_to(&query, SocketAddr::from((MDNS_GROUP, MDNS_PORT)))
+        .map_err(|_| DnsResponseCode::NetworkError)?;
+
+    let mut map = HashMap::<DnsRecordData, u32>::new();
+    let deadline = Instant::now() + Duration::from_millis(MDNS_QUERY_TIMEOUT_MS);
+    while map.is_empty() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        socket.set_read_timeout(Some(deadline - now)).ok();
+        let len = match socket.recv_from(buf) {
+            Ok((len, _)) => len,
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => break,
+                _ => return Err(DnsResponseCode::UnknownError),
+            },
+        };
+        if len < 12 {
+            continue;
+        }
+        let message = Message::from(&buf[..len]);
+        if !message.is_response() {
+            continue;
+        }
+        let records = match parse(&message) {
+            Ok((_, records)) => records,
+            Err(_) => continue,
+        };
+        map.extend(addresses(&records, name, rtype));
+    }
+    if map.is_empty() { Err(DnsResponseCode::NameError) } else { Ok(map) }
+}
+
+/// Picks the records of type `rtype` for `name` out of a response. Responders may add records of
+/// other types, or for other names, so those are left out.
+fn addresses(records: &[Record], name: &str, rtype: DnsRecordType) -> HashMap<DnsRecordData, u32> {
+    records
+        .iter()
+        .filter(|r| r.class == CLASS_IN && r.rtype == rtype as u16 && r.name.eq_ignore_ascii_case(name))
+        .filter_map(|r| DnsRecordData::decode(rtype, &r.rdata).map(|data| (data, r.ttl)))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MdnsService {
+    pub(crate) instance: String,
+    pub(crate) service: String,
+    pub(crate) port: u16,
+    pub(crate) txt: Vec<String>,
+}
+impl MdnsService {
+    pub(crate) fn from_ipc(ipc: &MdnsServiceIpc) -> Option<Self> {
+        let instance = ipc.instance.as_str().ok()?;
+        let service = ipc.service.as_str().ok()?;
+        let txt: Vec<&str> = ipc.txt.as_str().ok()?.split('\n').filter(|entry| !entry.is_empty()).collect();
+        if !mdns_service_valid(instance, service, &txt) {
+            return None;
+        }
+        Some(MdnsService {
+            instance: instance.to_owned(),
+            service: service.to_owned(),
+            port: ipc.port,
+            txt: txt.iter().map(|entry| entry.to_string()).collect(),
+        })
+    }
+
+    fn same_instance(&self, instance: &str, service: &str) -> bool {
+        self.instance.eq_ignore_ascii_case(instance) && self.service.eq_ignore_ascii_case(service)
+    }
+
+    fn type_name(&self) -> String { format!("{}.local", self.service) }
+
+    fn instance_name(&self) -> String { format!("{}.{}.local", self.instance, self.service) }
+}
+
+/// Our host name, and the addresses it stands for.
+struct Host {
+    name: String,
+    addrs: Vec<IpAddr>,
+}
+
+struct Answer {
+    name: String,
+    rtype: u16,
+    /// set for records that only we can own, telling caches to drop what they had for the name
+    unique: bool,
+    rdata: Vec<u8>,
+}
+impl Answer {
+    fn name_rdata(name: &str) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        put_name(&mut rdata, name);
+        rdata
+    }
+
+    fn ptr(name: String, target: &str) -> Self {
+        Answer { name, rtype: TYPE_PTR, unique: false, rdata: Self::name_rdata(target) }
+    }
+
+    fn srv(service: &MdnsService, host: &Host) -> Self {
+        let mut rdata = vec![0, 0, 0, 0]; // priority and weight
+        put_u16(&mut rdata, service.port);
+        put_name(&mut rdata, &host.name);
+        Answer { name: service.instance_name(), rtype: TYPE_SRV, unique: true, rdata }
+    }
+
+    fn txt(service: &MdnsService) -> Self {
+        let mut rdata = Vec::new();
+        for entry in service.txt.iter() {
+            rdata.push(entry.len() as u8);
+            rdata.extend_from_slice(entry.as_bytes());
+        }
+        if rdata.is_empty() {
+            // a TXT record must hold at least one string, even an empty one
+            rdata.push(0);
+        }
+        Answer { name: service.instance_name(), rtype: TYPE_TXT, unique: true, rdata }
+    }
+
+    fn addrs(host: &Host, qtype: u16) -> Vec<Self> {
+        host.addrs
+            .iter()
+            .filter_map(|addr| match addr {
+                IpAddr::V4(v4) if qtype == TYPE_A || qtype == TYPE_ANY => Some(Answer {
+                    name: host.name.clone(),
+                    rtype: TYPE_A,
+                    unique: true,
+                    rdata: v4.octets().to_vec(),
+                }),
+                IpAddr::V6(v6) if qtype == TYPE_AAAA || qtype == TYPE_ANY => Some(Answer {
+                    name: host.name.clone(),
+                    rtype: TYPE_AAAA,
+                    unique: true,
+                    rdata: v6.octets().to_vec(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn put(&self, out: &mut Vec<u8>, ttl: u32, legacy: bool) {
+        put_name(out, &self.name);
+        put_u16(out, self.rtype);
+        // legacy resolvers don't know about the cache flush bit
+        put_u16(out, if self.unique && !legacy { CLASS_IN | CLASS_TOP_BIT } else { CLASS_IN });
+        out.extend_from_slice(&ttl.to_be_bytes());
+        put_u16(out, self.rdata.len() as u16);
+        out.extend_from_slice(&self.rdata);
+    }
+}
+
+/// Builds a response. For a legacy resolver, the questions are repeated and the id echoed.
+fn response(
+    id: u16,
+    questions: &[Question],
+    answers: &[Answer],
+    additional: &[Answer],
+    ttl: u32,
+    legacy: bool,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u16(&mut out, if legacy { id } else { 0 });
+    put_u16(&mut out, FLAGS_RESPONSE);
+    put_u16(&mut out, if legacy { questions.len() as u16 } else { 0 });
+    put_u16(&mut out, answers.len() as u16);
+    put_u16(&mut out, 0);
+    put_u16(&mut out, additional.len() as u16);
+    if legacy {
+        for question in questions.iter() {
+            put_name(&mut out, &question.name);
+            put_u16(&mut out, question.qtype);
+            put_u16(&mut out, CLASS_IN);
+        }
+    }
+    let ttl = if legacy { ttl.min(MDNS_LEGACY_TTL) } else { ttl };
+    for answer in answers.iter().chain(additional.iter()) {
+        answer.put(&mut out, ttl, legacy);
+    }
+    out
+}
+
+/// The records announcing `service`: its PTR records, SRV, TXT and our addresses.
+fn announcement(service: &MdnsService, host: &Host) -> Vec<Answer> {
+    let mut answers = vec![
+        Answer::ptr(SERVICES_META_QUERY.to_owned(), &service.type_name()),
+        Answer::ptr(service.type_name(), &service.instance_name()),
+        Answer::srv(service, host),
+        Answer::txt(service),
+    ];
+    answers.extend(Answer::addrs(host, TYPE_ANY));
+    answers
+}
+
+/// Works out the answers to a query. Returns the answers and the additional records.
+fn answer(questions: &[Question], services: &[MdnsService], host: &Host) -> (Vec<Answer>, Vec<Answer>) {
+    let mut answers = Vec::new();
+    let mut additional = Vec::new();
+    let wants = |qtype: u16, rtype: u16| qtype == rtype || qtype == TYPE_ANY;
+    for question in questions.iter() {
+        let qname = question.name.trim_end_matches('.');
+        if qname.eq_ignore_ascii_case(SERVICES_META_QUERY) && wants(question.qtype, TYPE_PTR) {
+            let mut types: Vec<String> =
+                services.iter().map(|s| s.type_name().to_ascii_lowercase()).collect();
+            types.sort();
+            types.dedup();
+            for type_name in types.iter() {
+                answers.push(Answer::ptr(SERVICES_META_QUERY.to_owned(), type_name));
+            }
+        }
+        for service in services.iter() {
+            if qname.eq_ignore_ascii_case(&service.type_name()) && wants(question.qtype, TYPE_PTR) {
+                answers.push(Answer::ptr(service.type_name(), &service.instance_name()));
+                additional.push(Answer::srv(service, host));
+                additional.push(Answer::txt(service));
+                additional.extend(Answer::addrs(host, TYPE_ANY));
+            }
+            if qname.eq_ignore_ascii_case(&service.instance_name()) {
+                if wants(question.qtype, TYPE_SRV) {
+                    answers.push(Answer::srv(service, host));
+                    additional.extend(Answer::addrs(host, TYPE_ANY));
+                }
+                if wants(question.qtype, TYPE_TXT) {
+                    answers.push(Answer::txt(service));
+                }
+            }
+        }
+        if qname.eq_ignore_ascii_case(&host.name) {
+            answers.extend(Answer::addrs(host, question.qtype));
+        }
+    }
+    // an address asked for directly needn't be repeated in the additional section
+    additional.retain(|extra| {
+        !answers.iter().any(|answer| answer.rtype == extra.rtype && answer.rdata == extra.rdata)
+    });
+    (answers, additional)
+}
+
+/// Whether any of the questions name our host, or one of our services.
+fn asks_for_us(questions: &[Question], services: &[MdnsService], host_name: &str) -> bool {
+    questions.iter().any(|question| {
+        let qname = question.name.trim_end_matches('.');
+        qname.eq_ignore_ascii_case(SERVICES_META_QUERY)
+            || qname.eq_ignore_ascii_case(host_name)
+            || services.iter().any(|service| {
+                qname.eq_ignore_ascii_case(&service.type_name())
+                    || qname.eq_ignore_ascii_case(&service.instance_name())
+            })
+    })
+}
+
+enum ResponderOp {
+    Register(MdnsService),
+    Unregister(String, String),
+}
+
+/// Advertises the services registered by apps. The responder only starts listening on the mDNS
+/// port once the first service is registered, and leaves the mDNS group whenever none are left;
+/// until another is registered, it doesn't poll the socket.
+pub(crate) struct Responder {
+    tx: Option<Sender<ResponderOp>>,
+}
+impl Responder {
+    pub(crate) fn new() -> Self { Responder { tx: None } }
+
+    pub(crate) fn register(&mut self, service: MdnsService) {
+        log::info!("mDNS: advertising {} on port {}", service.instance_name(), service.port);
+        let tx = self.tx.get_or_insert_with(|| {
+            let (tx, rx) = channel();
+            thread::spawn(move || responder_thread(rx));
+            tx
+        });
+        tx.send(ResponderOp::Register(service)).ok();
+    }
+
+    pub(crate) fn unregister(&mut self, instance: &str, service: &str) {
+        if let Some(tx) = self.tx.as_ref() {
+            tx.send(ResponderOp::Unregister(instance.to_owned(), service.to_owned())).ok();
+        }
+    }
+}
+
+fn host_info(netmgr: &net::NetManager, name: &str) -> Host {
+    let config = netmgr.get_net_config();
+    let mut addrs = Vec::new();
+    if let Some(ipv4) = config.ipv4 {
+        addrs.push(IpAddr::V4(Ipv4Addr::from(ipv4.addr)));
+    }
+    if let Some(global) = config.ipv6_global {
+        addrs.push(IpAddr::V6(Ipv6Addr::from(global.addr)));
+    }
+    if let Some(link_local) = config.ipv6_link_local {
+        addrs.push(IpAddr::V6(Ipv6Addr::from(link_local)));
+    }
+    Host { name: name.to_owned(), addrs }
+}
+
+fn responder_thread(rx: Receiver<ResponderOp>) {
+    let netmgr = net::NetManager::new();
+    let socket = match UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT))) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::error!("mDNS: couldn't bind to the mDNS port, services won't be advertised: {:?}", e);
+            return;
+        }
+    };
+    // how often registrations are picked up while waiting for queries
+    socket.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    let mac = netmgr.get_net_config().mac;
+    let host_name = format!("xous-{:02x}{:02x}{:02x}.local", mac[3], mac[4], mac[5]);
+    let group = SocketAddr::from((MDNS_GROUP, MDNS_PORT));
+    let mut services = Vec::<MdnsService>::new();
+    let mut joined = false;
+    let mut buf = [0u8; MDNS_PKT_MAX_LEN];
+    loop {
+        let idle = if services.is_empty() {
+            match rx.recv() {
+                Ok(op) => Some(op),
+                // the `Responder` is gone, so nothing will be registered again
+                Err(_) => return,
+            }
+        } else {
+            None
+        };
+        for op in idle.into_iter().chain(rx.try_iter()) {
+            match op {
+                ResponderOp::Register(service) => {
+                    services.retain(|s| !s.same_instance(&service.instance, &service.service));
+                    let host = host_info(&netmgr, &host_name);
+                    let answers = announcement(&service, &host);
+                    socket.send_to(&response(0, &[], &answers, &[], MDNS_TTL, false), group).ok();
+                    services.push(service);
+                }
+                ResponderOp::Unregister(instance, service) => {
+                    if let Some(pos) = services.iter().position(|s| s.same_instance(&instance, &service)) {
+                        let service = services.remove(pos);
+                        log::info!("mDNS: withdrawing {}", service.instance_name());
+                        // a zero TTL tells everyone to forget the service
+                        let goodbye = [
+                            Answer::ptr(service.type_name(), &service.instance_name()),
+                            Answer::srv(&service, &host_info(&netmgr, &host_name)),
+                            Answer::txt(&service),
+                        ];
+                        socket.send_to(&response(0, &[], &goodbye, &[], 0, false), group).ok();
+                    }
+                }
+            }
+        }
+        if services.is_empty() {
+            if joined {
+                netmgr.leave_multicast_group(MDNS_GROUP).ok();
+                joined = false;
+            }
+            continue;
+        }
+        if !joined {
+            joined = netmgr.join_multicast_group(MDNS_GROUP).is_ok();
+        }
+
+        let (len, src) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => continue,
+                _ => {
+                    log::warn!("mDNS: receive error: {:?}", e);
+                    continue;
+                }
+            },
+        };
+        if len < 12 {
+            continue;
+        }
+        let message = Message::from(&buf[..len]);
+        if message.is_response() {
+            continue;
+        }
+        let questions = match parse(&message) {
+            Ok((questions, _)) => questions,
+            Err(_) => continue,
+        };
+        // only look up our addresses once we know the query is for us
+        if !asks_for_us(&questions, &services, &host_name) {
+            continue;
+        }
+        let host = host_info(&netmgr, &host_name);
+        let (answers, additional) = answer(&questions, &services, &host);
+        if answers.is_empty() {
+            continue;
+        }
+        // queries from anything but the mDNS port come from resolvers that expect a plain DNS reply
+        let legacy = src.port() != MDNS_PORT;
+        let unicast = legacy || questions.iter().all(|q| q.unicast);
+        let reply = response(message.id(), &questions, &answers, &additional, MDNS_TTL, legacy);
+        socket.send_to(&reply, if unicast { src } else { group }).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A query for the `_http._tcp` and `_ipp._tcp` services, the second name compressed against the
+    /// first, asking for unicast answers to the first question only.
+    #[rustfmt::skip]
+    const BROWSE_QUERY: [u8; 45] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // header
+        0x05, b'_', b'h', b't', b't', b'p', 0x04, b'_', b't', b'c', b'p', 0x05, b'l', b'o', b'c', b'a', b'l', 0x00,
+        0x00, 0x0c, 0x80, 0x01, // _http._tcp.local PTR, QU
+        0x04, b'_', b'i', b'p', b'p', 0xc0, 0x12, 0x00, 0x0c, 0x00, 0x01, // _ipp._tcp.local PTR
+    ];
+
+    /// A printer announcing itself: PTR, SRV, TXT and A records, with its names compressed and the
+    /// cache flush bit set on the records it owns.
+    #[rustfmt::skip]
+    const PRINTER_ANNOUNCEMENT: [u8; 118] = [
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, // header
+        0x04, b'_', b'i', b'p', b'p', 0x04, b'_', b't', b'c', b'p', 0x05, b'l', b'o', b'c', b'a', b'l', 0x00,
+        0x00, 0x0c, 0x00, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x0a, // _ipp._tcp.local PTR
+        0x07, b'P', b'r', b'i', b'n', b't', b'e', b'r', 0xc0, 0x0c, // Printer._ipp._tcp.local
+        0xc0, 0x27, 0x00, 0x21, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x10, // SRV
+        0x00, 0x00, 0x00, 0x00, 0x02, 0x77, // priority, weight, port 631
+        0x07, b'p', b'r', b'i', b'n', b't', b'e', b'r', 0xc0, 0x16, // printer.local
+        0xc0, 0x27, 0x00, 0x10, 0x80, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x0d, // TXT
+        0x0c, b'r', b'p', b'=', b'i', b'p', b'p', b'/', b'p', b'r', b'i', b'n', b't', // rp=ipp/print
+        0xc0, 0x43, 0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04, // printer.local A
+        192, 168, 1, 23,
+    ];
+
+    fn query(questions: &[(&str, u16)]) -> Vec<Question> {
+        let mut datagram = vec![0, 0, 0, 0];
+        put_u16(&mut datagram, questions.len() as u16);
+        datagram.extend_from_slice(&[0; 6]);
+        for &(name, qtype) in questions.iter() {
+            put_name(&mut datagram, name);
+            put_u16(&mut datagram, qtype);
+            put_u16(&mut datagram, CLASS_IN);
+        }
+        parse(&Message::from(&datagram[..])).unwrap().0
+    }
+
+    fn services() -> Vec<MdnsService> {
+        vec![
+            MdnsService {
+                instance: "Precursor chat".to_owned(),
+                service: "_chat._tcp".to_owned(),
+                port: 5222,
+                txt: vec!["v=1".to_owned()],
+            },
+            MdnsService {
+                instance: "Other chat".to_owned(),
+                service: "_chat._tcp".to_owned(),
+                port: 5223,
+                txt: vec![],
+            },
+        ]
+    }
+
+    fn host() -> Host {
+        Host {
+            name: "xous-abcdef.local".to_owned(),
+            addrs: vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)),
+                IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ],
+        }
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_name(&mut out, name);
+        out
+    }
+
+    #[test]
+    fn put_name_writes_labels() {
+        let mut expected = vec![7];
+        expected.extend_from_slice(b"Printer");
+        expected.push(4);
+        expected.extend_from_slice(b"_ipp");
+        expected.push(4);
+        expected.extend_from_slice(b"_tcp");
+        expected.push(5);
+        expected.extend_from_slice(b"local");
+        expected.push(0);
+        assert_eq!(name("Printer._ipp._tcp.local"), expected);
+        // a fully qualified name is written the same way
+        assert_eq!(name("Printer._ipp._tcp.local."), expected);
+
+        let mut datagram = vec![0; 12];
+        datagram.extend_from_slice(&expected);
+        let (read, next) = Message::from(&datagram[..]).read_name(12).unwrap();
+        assert_eq!((read.as_str(), next), ("Printer._ipp._tcp.local", datagram.len()));
+    }
+
+    #[test]
+    fn parse_reads_compressed_questions() {
+        let (questions, records) = parse(&Message::from(&BROWSE_QUERY[..])).unwrap();
+        assert!(records.is_empty());
+        let questions: Vec<(&str, u16, bool)> =
+            questions.iter().map(|q| (q.name.as_str(), q.qtype, q.unicast)).collect();
+        assert_eq!(
+            questions,
+            vec![("_http._tcp.local", TYPE_PTR, true), ("_ipp._tcp.local", TYPE_PTR, false)]
+        );
+    }
+
+    #[test]
+    fn parse_reads_announcements() {
+        let message = Message::from(&PRINTER_ANNOUNCEMENT[..]);
+        assert!(message.is_response());
+        let (questions, records) = parse(&message).unwrap();
+        assert!(questions.is_empty());
+        let summary: Vec<(&str, u16, u16, u32)> =
+            records.iter().map(|r| (r.name.as_str(), r.rtype, r.class, r.ttl)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("_ipp._tcp.local", TYPE_PTR, CLASS_IN, 4500),
+                ("Printer._ipp._tcp.local", TYPE_SRV, CLASS_IN, 120),
+                ("Printer._ipp._tcp.local", TYPE_TXT, CLASS_IN, 4500),
+                ("printer.local", TYPE_A, CLASS_IN, 120),
+            ]
+        );
+        assert_eq!(records[2].rdata, b"\x0crp=ipp/print".to_vec());
+
+        let mut expected = HashMap::new();
+        expected.insert(DnsRecordData::A(Ipv4Addr::new(192, 168, 1, 23)), 120);
+        assert_eq!(addresses(&records, "Printer.LOCAL", DnsRecordType::A), expected);
+        assert!(addresses(&records, "printer.local", DnsRecordType::Aaaa).is_empty());
+        assert!(addresses(&records, "scanner.local", DnsRecordType::A).is_empty());
+    }
+
+    #[test]
+    fn parse_rejects_truncated_packets() {
+        for packet in [&BROWSE_QUERY[..], &PRINTER_ANNOUNCEMENT[..]].iter() {
+            for len in 0..packet.len() {
+                assert!(
+                    parse(&Message::from(&packet[..len])).is_err(),
+                    "accepted a packet cut to {} bytes",
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn responder_answers_only_for_us() {
+        let services = services();
+        let host = host();
+        for qname in
+            ["_chat._tcp.local", "Precursor chat._chat._tcp.local", "XOUS-ABCDEF.local.", SERVICES_META_QUERY]
+                .iter()
+        {
+            assert!(asks_for_us(&query(&[(qname, TYPE_ANY)]), &services, &host.name), "ignored {}", qname);
+        }
+        let (questions, _) = parse(&Message::from(&BROWSE_QUERY[..])).unwrap();
+        assert!(!asks_for_us(&questions, &services, &host.name));
+        assert!(!asks_for_us(&query(&[("_chat._tcp.local", TYPE_PTR)]), &[], &host.name));
+    }
+
+    #[test]
+    fn responder_answers_browsing() {
+        let services = services();
+        let host = host();
+        let (answers, additional) = answer(&query(&[("_chat._tcp.local", TYPE_PTR)]), &services, &host);
+        assert!(answers.iter().all(|a| a.rtype == TYPE_PTR && a.name == "_chat._tcp.local"));
+        let targets: Vec<Vec<u8>> = answers.iter().map(|a| a.rdata.clone()).collect();
+        assert_eq!(
+            targets,
+            vec![name("Precursor chat._chat._tcp.local"), name("Other chat._chat._tcp.local")]
+        );
+        // the SRV and TXT records of both instances, and our addresses once each
+        let mut types: Vec<u16> = additional.iter().map(|a| a.rtype).collect();
+        types.sort();
+        types.dedup();
+        assert_eq!(types, vec![TYPE_A, TYPE_TXT, TYPE_AAAA, TYPE_SRV]);
+
+        // both instances are of the same type, which is only listed once
+        let (answers, _) = answer(&query(&[(SERVICES_META_QUERY, TYPE_PTR)]), &services, &host);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].rdata, name("_chat._tcp.local"));
+    }
+
+    #[test]
+    fn responder_answers_by_type() {
+        let services = services();
+        let host = host();
+        let (answers, additional) = answer(&query(&[("xous-abcdef.local", TYPE_A)]), &services, &host);
+        assert_eq!(answers.len(), 1);
+        assert_eq!((answers[0].rtype, answers[0].rdata.clone()), (TYPE_A, vec![10, 0, 0, 5]));
+        assert!(additional.is_empty());
+
+        let (answers, additional) =
+            answer(&query(&[("Precursor chat._chat._tcp.local", TYPE_SRV)]), &services, &host);
+        assert_eq!(answers.len(), 1);
+        let mut srv = vec![0, 0, 0, 0, 0x14, 0x66];
+        srv.extend_from_slice(&name("xous-abcdef.local"));
+        assert_eq!((answers[0].rtype, answers[0].rdata.clone()), (TYPE_SRV, srv));
+        assert_eq!(additional.len(), 2);
+
+        let (answers, _) = answer(&query(&[("Other chat._chat._tcp.local", TYPE_TXT)]), &services, &host);
+        assert_eq!(answers.len(), 1);
+        // an empty TXT record still holds one empty string
+        assert_eq!((answers[0].rtype, answers[0].rdata.clone()), (TYPE_TXT, vec![0]));
+    }
+
+    #[test]
+    fn legacy_replies_echo_the_query() {
+        let services = services();
+        let host = host();
+        let questions = query(&[("xous-abcdef.local", TYPE_AAAA)]);
+        let (answers, additional) = answer(&questions, &services, &host);
+        let reply = Message::from(&response(0x1234, &questions, &answers, &additional, MDNS_TTL, true)[..]);
+        assert!(reply.is_response());
+        assert_eq!(reply.id(), 0x1234);
+        let (echoed, records) = parse(&reply).unwrap();
+        assert_eq!(echoed.len(), 1);
+        assert_eq!((echoed[0].name.as_str(), echoed[0].qtype), ("xous-abcdef.local", TYPE_AAAA));
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].rtype, records[0].ttl), (TYPE_AAAA, MDNS_LEGACY_TTL));
+
+        // mDNS replies leave the questions out, and set the cache flush bit on our own records
+        let reply = response(0x1234, &questions, &answers, &additional, MDNS_TTL, false);
+        assert_eq!(&reply[..6], &[0, 0, 0x84, 0, 0, 0]);
+        let class = 12 + name("xous-abcdef.local").len() + 2;
+        assert_eq!(&reply[class..class + 2], &(CLASS_IN | CLASS_TOP_BIT).to_be_bytes());
+    }
+}
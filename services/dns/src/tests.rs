@@ -0,0 +1,314 @@
+#![cfg(test)]
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::api::*;
+use crate::dot::over_tls;
+use crate::upstreams::{parse_upstream, upstream_key, TlsUpstream};
+use crate::{follow_cnames, Message, QueryClass, MAX_CNAME_QUERIES};
+
+const TYPE_UNKNOWN: u16 = 99;
+
+/// The wire form of `name`, uncompressed.
+fn name(name: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out
+}
+
+/// A pointer to the name of the question, which always starts right after the header.
+fn qname_pointer() -> Vec<u8> { vec![0xc0, 12] }
+
+/// A response to a query for `qname`, with `records` of (owner, type, TTL, data) as its answers.
+fn response(qname: &str, qtype: DnsRecordType, records: &[(Vec<u8>, u16, u32, Vec<u8>)]) -> Message {
+    let mut message = Message::query(qname, qtype, QueryClass::IN, 0x61ca);
+    message.datagram[2] |= 0x80;
+    message.datagram[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+    for (owner, rtype, ttl, rdata) in records.iter() {
+        message.datagram.extend_from_slice(owner);
+        message.datagram.extend_from_slice(&rtype.to_be_bytes());
+        message.datagram.extend_from_slice(&(QueryClass::IN as u16).to_be_bytes());
+        message.datagram.extend_from_slice(&ttl.to_be_bytes());
+        message.datagram.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        message.datagram.extend_from_slice(rdata);
+    }
+    message
+}
+
+fn cname(owner: &str, ttl: u32, target: &str) -> (Vec<u8>, u16, u32, Vec<u8>) {
+    (name(owner), DnsRecordType::Cname as u16, ttl, name(target))
+}
+
+fn a(owner: &str, ttl: u32, addr: [u8; 4]) -> (Vec<u8>, u16, u32, Vec<u8>) {
+    (name(owner), DnsRecordType::A as u16, ttl, addr.to_vec())
+}
+
+#[test]
+fn parse_records_reads_each_type() {
+    let mut mx = vec![0, 10];
+    mx.extend_from_slice(&qname_pointer());
+    let mut srv = vec![0, 1, 0, 2, 0x14, 0xe9];
+    srv.extend_from_slice(&name("host.example.com"));
+    let message = response(
+        "example.com",
+        DnsRecordType::A,
+        &[
+            (qname_pointer(), DnsRecordType::A as u16, 3600, vec![185, 199, 108, 153]),
+            (qname_pointer(), DnsRecordType::Aaaa as u16, 60, Ipv6Addr::LOCALHOST.octets().to_vec()),
+            (qname_pointer(), TYPE_UNKNOWN, 60, vec![1, 2, 3]),
+            (qname_pointer(), DnsRecordType::Mx as u16, 300, mx),
+            (qname_pointer(), DnsRecordType::Txt as u16, 300, b"\x05hello\x00\x03xyz".to_vec()),
+            (name("_x._tcp.example.com"), DnsRecordType::Srv as u16, 5, srv),
+            cname("www.example.com", 7, "example.com"),
+        ],
+    );
+
+    let records = message.parse_records().unwrap();
+    assert_eq!(
+        records,
+        vec![
+            ("example.com".to_owned(), 3600, DnsRecordData::A(Ipv4Addr::new(185, 199, 108, 153))),
+            ("example.com".to_owned(), 60, DnsRecordData::Aaaa(Ipv6Addr::LOCALHOST)),
+            (
+                "example.com".to_owned(),
+                300,
+                DnsRecordData::Mx { preference: 10, exchange: "example.com".to_owned() }
+            ),
+            (
+                "example.com".to_owned(),
+                300,
+                DnsRecordData::Txt(vec![b"hello".to_vec(), vec![], b"xyz".to_vec()])
+            ),
+            (
+                "_x._tcp.example.com".to_owned(),
+                5,
+                DnsRecordData::Srv {
+                    priority: 1,
+                    weight: 2,
+                    port: 5353,
+                    target: "host.example.com".to_owned()
+                }
+            ),
+            ("www.example.com".to_owned(), 7, DnsRecordData::Cname("example.com".to_owned())),
+        ]
+    );
+}
+
+#[test]
+fn parse_records_rejects_truncated_answers() {
+    let message = response("example.com", DnsRecordType::A, &[a("example.com", 60, [10, 0, 0, 1])]);
+    for len in 29..message.datagram.len() {
+        let truncated = Message::from(&message.datagram[..len]);
+        assert!(
+            matches!(truncated.parse_records(), Err(DnsResponseCode::FormatError)),
+            "accepted a message cut to {} bytes",
+            len
+        );
+    }
+    // an answer that claims more records than it has
+    let mut extra = Message::from(&message.datagram);
+    extra.datagram[7] = 2;
+    assert!(matches!(extra.parse_records(), Err(DnsResponseCode::FormatError)));
+}
+
+#[test]
+fn read_rdata_checks_lengths() {
+    let message = response("example.com", DnsRecordType::A, &[]);
+    let end = message.datagram.len();
+    let mut padded = Message::from(&message.datagram);
+    padded.datagram.extend_from_slice(&[10, 0, 0, 1, 2]);
+
+    assert_eq!(
+        padded.read_rdata(DnsRecordType::A as u16, end, end + 4).unwrap(),
+        Some(DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 1)))
+    );
+    assert!(matches!(
+        padded.read_rdata(DnsRecordType::A as u16, end, end + 5),
+        Err(DnsResponseCode::FormatError)
+    ));
+    assert!(matches!(
+        padded.read_rdata(DnsRecordType::A as u16, end, end + 6),
+        Err(DnsResponseCode::FormatError)
+    ));
+    // MX and SRV records too short for their fixed fields
+    assert!(matches!(
+        padded.read_rdata(DnsRecordType::Mx as u16, end, end + 1),
+        Err(DnsResponseCode::FormatError)
+    ));
+    assert!(matches!(
+        padded.read_rdata(DnsRecordType::Srv as u16, end, end + 5),
+        Err(DnsResponseCode::FormatError)
+    ));
+    // types we don't handle are skipped, whatever they hold
+    assert_eq!(padded.read_rdata(TYPE_UNKNOWN, end, end + 5).unwrap(), None);
+}
+
+#[test]
+fn answers_follow_cname_chain() {
+    let message = response(
+        "www.example.com",
+        DnsRecordType::A,
+        &[
+            cname("www.example.com", 60, "cdn.example.net"),
+            cname("cdn.example.net", 30, "edge.example.net"),
+            a("edge.example.net", 300, [10, 0, 0, 1]),
+            a("edge.example.net", 10, [10, 0, 0, 2]),
+            a("other.example.net", 300, [10, 0, 0, 3]),
+        ],
+    );
+    let (records, next) = message.answers("www.example.com.", DnsRecordType::A).unwrap();
+    assert_eq!(next, None);
+    let mut expected = HashMap::new();
+    expected.insert(DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 1)), 30);
+    expected.insert(DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 2)), 10);
+    assert_eq!(records, expected);
+
+    // asking for the CNAME itself doesn't follow it
+    let (records, next) = message.answers("www.example.com", DnsRecordType::Cname).unwrap();
+    assert_eq!(next, None);
+    assert_eq!(
+        records.into_iter().collect::<Vec<_>>(),
+        vec![(DnsRecordData::Cname("cdn.example.net".to_owned()), 60)]
+    );
+}
+
+#[test]
+fn answers_hand_back_a_dangling_cname() {
+    let message = response(
+        "www.example.com",
+        DnsRecordType::A,
+        &[cname("www.example.com", 60, "cdn.example.net"), cname("cdn.example.net", 30, "edge.example.net")],
+    );
+    let (records, next) = message.answers("www.example.com", DnsRecordType::A).unwrap();
+    assert!(records.is_empty());
+    assert_eq!(next, Some(("edge.example.net".to_owned(), 30)));
+
+    // a name with nothing at all isn't worth asking about again
+    let empty = response("www.example.com", DnsRecordType::A, &[]);
+    assert_eq!(empty.answers("www.example.com", DnsRecordType::A).unwrap(), (HashMap::new(), None));
+}
+
+#[test]
+fn answers_reject_cname_loops() {
+    let message = response(
+        "a.example.com",
+        DnsRecordType::A,
+        &[cname("a.example.com", 60, "b.example.com"), cname("b.example.com", 60, "a.example.com")],
+    );
+    assert!(matches!(message.answers("a.example.com", DnsRecordType::A), Err(DnsResponseCode::FormatError)));
+}
+
+#[test]
+fn follow_cnames_asks_again_and_caps_ttls() {
+    let mut asked = Vec::new();
+    let records = follow_cnames("www.example.com", DnsRecordType::A, |qname| {
+        asked.push(qname.to_owned());
+        Ok(match qname {
+            "www.example.com" => {
+                response(qname, DnsRecordType::A, &[cname("www.example.com", 20, "cdn.example.net")])
+            }
+            _ => response(qname, DnsRecordType::A, &[a("cdn.example.net", 300, [10, 0, 0, 1])]),
+        })
+    })
+    .unwrap();
+    assert_eq!(asked, vec!["www.example.com".to_owned(), "cdn.example.net".to_owned()]);
+    assert_eq!(
+        records.into_iter().collect::<Vec<_>>(),
+        vec![(DnsRecordData::A(Ipv4Addr::new(10, 0, 0, 1)), 20)]
+    );
+}
+
+#[test]
+fn follow_cnames_gives_up_on_long_chains() {
+    let queries = Cell::new(0);
+    let result = follow_cnames("n0.example.com", DnsRecordType::A, |qname| {
+        queries.set(queries.get() + 1);
+        let next = format!("n{}.example.com", queries.get());
+        Ok(response(qname, DnsRecordType::A, &[cname(qname, 60, &next)]))
+    });
+    assert!(matches!(result, Err(DnsResponseCode::ServerFailure)));
+    assert_eq!(queries.get(), MAX_CNAME_QUERIES);
+
+    // errors from the server end the lookup
+    let result = follow_cnames("www.example.com", DnsRecordType::A, |_| Err(DnsResponseCode::NameError));
+    assert!(matches!(result, Err(DnsResponseCode::NameError)));
+}
+
+#[test]
+fn record_data_round_trips() {
+    let records = [
+        DnsRecordData::A(Ipv4Addr::new(192, 168, 1, 1)),
+        DnsRecordData::Aaaa(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+        DnsRecordData::Cname("example.com".to_owned()),
+        DnsRecordData::Mx { preference: 5, exchange: "mail.example.com".to_owned() },
+        DnsRecordData::Txt(vec![b"v=spf1 -all".to_vec(), vec![0xff, 0x00], vec![]]),
+        DnsRecordData::Txt(vec![]),
+        DnsRecordData::Srv { priority: 0, weight: 5, port: 443, target: "example.com".to_owned() },
+    ];
+    for record in records.iter() {
+        let mut encoded = Vec::new();
+        record.encode(&mut encoded);
+        assert_eq!(DnsRecordData::decode(record.rtype(), &encoded).as_ref(), Some(record));
+    }
+}
+
+#[test]
+fn record_data_rejects_malformed_data() {
+    assert_eq!(DnsRecordData::decode(DnsRecordType::A, &[10, 0, 0]), None);
+    assert_eq!(DnsRecordData::decode(DnsRecordType::Aaaa, &[0; 4]), None);
+    assert_eq!(DnsRecordData::decode(DnsRecordType::Cname, &[0xff, 0xfe]), None);
+    assert_eq!(DnsRecordData::decode(DnsRecordType::Mx, &[0]), None);
+    assert_eq!(DnsRecordData::decode(DnsRecordType::Txt, b"\x05abc"), None);
+    assert_eq!(DnsRecordData::decode(DnsRecordType::Srv, &[0, 0, 0, 0, 0]), None);
+}
+
+#[test]
+fn upstream_keys_round_trip() {
+    let entries = [("9.9.9.9:853", "dns.quad9.net"), ("[2620:fe::fe]:853", "dns.quad9.net")];
+    for (addr, auth_name) in entries.iter() {
+        let upstream = TlsUpstream { addr: addr.parse().unwrap(), auth_name: auth_name.to_string() };
+        let key = upstream_key(&upstream);
+        assert_eq!(key, *addr);
+        assert_eq!(parse_upstream(&key, auth_name.as_bytes().to_vec()), Some(upstream));
+    }
+}
+
+#[test]
+fn upstream_keys_reject_bad_entries() {
+    // upstreams are given by address, as the resolver can't look up its own upstream
+    assert_eq!(parse_upstream("dns.quad9.net:853", b"dns.quad9.net".to_vec()), None);
+    assert_eq!(parse_upstream("9.9.9.9", b"dns.quad9.net".to_vec()), None);
+    assert_eq!(parse_upstream("9.9.9.9:853", Vec::new()), None);
+    assert_eq!(parse_upstream("9.9.9.9:853", vec![0xff, 0xfe]), None);
+}
+
+#[test]
+fn tls_mode_decides_fallback() {
+    let mut tried = false;
+    let result = over_tls(DnsTlsMode::Off, || {
+        tried = true;
+        Ok(vec![1])
+    });
+    assert!(matches!(result, Ok(None)));
+    assert!(!tried, "tried TLS with it turned off");
+
+    for mode in [DnsTlsMode::Opportunistic, DnsTlsMode::Strict].iter() {
+        assert_eq!(over_tls(*mode, || Ok(vec![1, 2])).unwrap(), Some(vec![1, 2]));
+    }
+    // only an opportunistic lookup may fall back to plain DNS
+    assert!(matches!(over_tls(DnsTlsMode::Opportunistic, || Err(DnsResponseCode::NetworkError)), Ok(None)));
+    assert!(matches!(
+        over_tls(DnsTlsMode::Strict, || Err(DnsResponseCode::NetworkError)),
+        Err(DnsResponseCode::NetworkError)
+    ));
+    assert!(matches!(
+        over_tls(DnsTlsMode::Strict, || Err(DnsResponseCode::NoServerSpecified)),
+        Err(DnsResponseCode::NoServerSpecified)
+    ));
+}
@@ -0,0 +1,82 @@
+//! The DNS-over-TLS (RFC 7858) upstreams. These live in the PDDB, where the resolver reads them and
+//! apps configure them, one key per upstream: the key is its socket address, and the value is the name
+//! its certificate is checked against.
+
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+
+/// PDDB dict holding the upstreams
+pub(crate) const DNS_TLS_DICT: &str = "dns.tls";
+/// the well-known port for DNS-over-TLS
+#[allow(dead_code)]
+pub const DNS_TLS_PORT: u16 = 853;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsUpstream {
+    /// given as an address, since the resolver can't look up the name of its own upstream
+    pub addr: SocketAddr,
+    /// the name the upstream's certificate must be valid for, e.g. "dns.quad9.net"
+    pub auth_name: String,
+}
+
+/// The configured upstreams, in key order. Entries that don't parse are skipped.
+#[allow(dead_code)]
+pub(crate) fn load_upstreams(pddb: &pddb::Pddb) -> Vec<TlsUpstream> {
+    let keys = match pddb.list_keys(DNS_TLS_DICT, None) {
+        Ok(keys) => keys,
+        // the dict doesn't exist until an upstream is added
+        Err(_) => return Vec::new(),
+    };
+    let mut upstreams = Vec::new();
+    for key in keys.iter() {
+        let mut data = Vec::new();
+        match pddb.get(DNS_TLS_DICT, key, None, false, false, None, None::<fn()>) {
+            Ok(mut record) => match record.read_to_end(&mut data) {
+                Ok(_) => upstreams.extend(parse_upstream(key, data)),
+                Err(e) => log::warn!("couldn't read DNS-over-TLS upstream {}: {:?}", key, e),
+            },
+            Err(e) => log::warn!("couldn't open DNS-over-TLS upstream {}: {:?}", key, e),
+        }
+    }
+    upstreams.sort_by_key(upstream_key);
+    upstreams
+}
+
+/// The name of the key `upstream` is stored under.
+pub(crate) fn upstream_key(upstream: &TlsUpstream) -> String { upstream.addr.to_string() }
+
+/// The upstream stored under `key` with the value `data`, if both are well-formed.
+pub(crate) fn parse_upstream(key: &str, data: Vec<u8>) -> Option<TlsUpstream> {
+    let addr = match key.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => {
+            log::warn!("ignoring DNS-over-TLS upstream with a bad address: {}", key);
+            return None;
+        }
+    };
+    match String::from_utf8(data) {
+        Ok(auth_name) if !auth_name.is_empty() => Some(TlsUpstream { addr, auth_name }),
+        _ => {
+            log::warn!("ignoring DNS-over-TLS upstream {} with a bad name", key);
+            None
+        }
+    }
+}
+
+/// Adds `upstream`, or replaces the name of the one at the same address.
+#[allow(dead_code)]
+pub(crate) fn store_upstream(pddb: &pddb::Pddb, upstream: &TlsUpstream) -> std::io::Result<()> {
+    let key_name = upstream_key(upstream);
+    // the name may be shorter than the one it replaces
+    pddb.delete_key(DNS_TLS_DICT, &key_name, None).ok();
+    let mut key =
+        pddb.get(DNS_TLS_DICT, &key_name, None, true, true, Some(upstream.auth_name.len()), None::<fn()>)?;
+    key.write_all(upstream.auth_name.as_bytes())?;
+    pddb.sync()
+}
+
+#[allow(dead_code)]
+pub(crate) fn remove_upstream(pddb: &pddb::Pddb, addr: &SocketAddr) -> std::io::Result<()> {
+    pddb.delete_key(DNS_TLS_DICT, &addr.to_string(), None)?;
+    pddb.sync()
+}
@@ -6,6 +6,8 @@ pub use ping::NetPingCallback;
 #[allow(unused_imports)]
 pub(crate) use tcp::*;
 
+pub mod firewall;
+pub use firewall::{FirewallAccess, FirewallDest, FirewallRule};
 pub mod rkyv_enum;
 use std::fmt;
 use std::fmt::Debug;
@@ -25,6 +27,12 @@ use xous_semver::SemVer;
 pub(crate) const SERVER_NAME_NET: &str = "_Middleware Network Server_";
 #[allow(dead_code)]
 pub const AP_DICT_NAME: &'static str = "wlan.networks";
+#[allow(dead_code)]
+pub const IPCONFIG_DICT_NAME: &'static str = "net.ipconfig";
+#[allow(dead_code)]
+pub const IPCONFIG_KEY_NAME: &'static str = "settings";
+#[allow(dead_code)]
+pub const IPCONFIG_SECRET_KEY_NAME: &'static str = "stable_secret";
 
 #[allow(dead_code)]
 /// minimum revision required for compatibility with Net crate
@@ -361,6 +369,53 @@ pub(crate) enum Opcode {
     StdTcpStreamShutdown = 46,
 
     LoopbackRx = 47,
+
+    /// Returns a `NetConfig` describing the addresses of the interface, and where they came from.
+    /// Supersedes `GetIpv4Config`, which is kept for existing callers.
+    GetNetConfig = 48,
+
+    /// Returns the `IpSettings` in effect.
+    GetIpSettings = 49,
+
+    /// Replaces the `IpSettings`. They take effect at once, and are saved to the PDDB if it is mounted.
+    SetIpSettings = 50,
+
+    /// Joins the interface to an IPv4 multicast group, so that UDP sockets receive the traffic sent to
+    /// it. Memberships are counted, so each join should be paired with a `LeaveMulticastGroup`.
+    ///
+    /// Blocking scalar: arg1 is the group address as a big-endian u32. Returns 0 on success.
+    JoinMulticastGroup = 51,
+
+    /// Undoes one `JoinMulticastGroup`. Same arguments.
+    LeaveMulticastGroup = 52,
+
+    /// Fills in a `Netstat` with the open sockets and the traffic counters of the interface. Only
+    /// shellchat and the status bar are told about the sockets of other processes.
+    Netstat = 53,
+
+    /// Puts a process under the firewall, so that it is prompted for any access its rules don't cover.
+    /// Only the app loader may do this, for the apps it starts.
+    ///
+    /// Memory message holding a `SandboxRequest`.
+    SandboxProcess = 54,
+
+    /// Replaces the firewall rules of a process. Only shellchat may do this, on the user's behalf.
+    ///
+    /// Memory message holding a `FirewallRulesUpdate`.
+    SetFirewallRules = 55,
+
+    /// Private: the answer to a firewall prompt. arg1 is the prompt, arg2 the choice.
+    FirewallDecision = 56,
+
+    /// Private: the DNS service tells us which addresses a name was resolved to, so that firewall rules
+    /// can name hosts. Memory message holding a `ResolvedHost`.
+    FirewallResolved = 57,
+
+    /// From the kernel: a process that was connected to us has exited. arg1 is its PID.
+    PeerExited = 58,
+
+    /// Fills in a `FirewallRuleList` with the rules of every process that has any.
+    ListFirewallRules = 59,
     // do not use any numbers higher than 0x8000 as that is reserved for the nonblocking flag
 }
 #[allow(dead_code)]
@@ -385,6 +440,164 @@ pub(crate) struct SsidList {
     pub(crate) state: ScanState,
 }
 
+/// IPv4 settings of the interface: the static settings chosen by the user, or the settings in effect.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Ipv4Settings {
+    pub addr: [u8; 4],
+    pub prefix_len: u8,
+    pub gateway: Option<[u8; 4]>,
+    pub dns: [Option<[u8; 4]>; 2],
+}
+impl Ipv4Settings {
+    /// Static settings must name a unicast address, and a gateway within its subnet.
+    pub fn is_valid(&self) -> bool {
+        if self.prefix_len == 0 || self.prefix_len > 32 {
+            return false;
+        }
+        let addr = Ipv4Addr::from(self.addr);
+        if addr.is_unspecified() || addr.is_loopback() || addr.is_multicast() || addr.is_broadcast() {
+            return false;
+        }
+        let mask = u32::MAX << (32 - self.prefix_len as u32);
+        match self.gateway {
+            Some(gateway) => u32::from_be_bytes(gateway) & mask == u32::from_be_bytes(self.addr) & mask,
+            None => true,
+        }
+    }
+}
+
+/// An IPv6 address of the interface, with the length of its on-link prefix.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Ipv6Settings {
+    pub addr: [u8; 16],
+    pub prefix_len: u8,
+}
+
+/// How the user wants the interface addressed. Saved in the PDDB under `IPCONFIG_DICT_NAME`.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub struct IpSettings {
+    pub ipv4: Ipv4Mode,
+    /// Configure a global IPv6 address from router advertisements. A link-local address is always
+    /// configured.
+    pub slaac: bool,
+}
+impl Default for IpSettings {
+    fn default() -> Self { IpSettings { ipv4: Ipv4Mode::Ec, slaac: true } }
+}
+impl IpSettings {
+    pub fn is_valid(&self) -> bool {
+        match self.ipv4 {
+            Ipv4Mode::Static(settings) => settings.is_valid(),
+            _ => true,
+        }
+    }
+}
+
+/// The addresses of the interface, and where they came from.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default)]
+pub struct NetConfig {
+    pub mac: [u8; 6],
+    pub ipv4_source: Ipv4Source,
+    pub ipv4: Option<Ipv4Settings>,
+    pub ipv6_link_local: Option<[u8; 16]>,
+    /// The address configured by SLAAC, if a router has advertised a prefix
+    pub ipv6_global: Option<Ipv6Settings>,
+    pub ipv6_router: Option<[u8; 16]>,
+}
+
+/// Traffic counters. Bytes are counted as whole Ethernet frames, headers included.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TrafficCounters {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+}
+
+/// Counters of the WLAN interface since boot. Loopback traffic is counted too, once each way.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    pub traffic: TrafficCounters,
+    /// frames received that weren't valid Ethernet
+    pub rx_errors: u64,
+    /// frames that arrived before smoltcp had drained the one ahead of them
+    pub rx_dropped: u64,
+    /// frames the WLAN driver failed to send
+    pub tx_errors: u64,
+}
+
+/// An open TCP or UDP socket.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub struct SocketInfo {
+    /// The process that owns the socket. `None` for sockets of the net service itself, and TCP sockets
+    /// that have been closed by their process but are still finishing off the connection.
+    pub pid: Option<u8>,
+    pub state: SocketState,
+    /// `None` if the socket is bound to every address of the interface
+    pub local_addr: Option<NetIpAddr>,
+    pub local_port: u16,
+    /// `None` for UDP sockets, and TCP sockets that aren't connected
+    pub remote_addr: Option<NetIpAddr>,
+    pub remote_port: u16,
+    /// Traffic of the socket, counted from when the net service first saw its endpoints. For a UDP
+    /// socket, this is all the traffic to and from its local port.
+    pub traffic: TrafficCounters,
+}
+
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default)]
+pub struct Netstat {
+    /// As with `SsidList`, the list is limited to 32 entries so the structure can be pre-allocated.
+    pub sockets: [Option<SocketInfo>; 32],
+    /// The number of sockets open, which is more than are listed if the list is full.
+    pub socket_count: u32,
+    pub iface: InterfaceStats,
+}
+
+/// The addresses a host name was resolved to.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub(crate) struct ResolvedHost {
+    pub(crate) name: xous_ipc::String<256>,
+    pub(crate) addrs: [Option<NetIpAddr>; 8],
+    /// how long the lookup may be relied on, in seconds
+    pub(crate) ttl: u32,
+}
+
+/// Puts an app under the firewall.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub(crate) struct SandboxRequest {
+    pub(crate) pid: u8,
+    /// the name of the app, which its rules are kept under
+    pub(crate) name: xous_ipc::String<64>,
+    /// set by the server: whether the app was sandboxed
+    pub(crate) sandboxed: bool,
+}
+
+/// New firewall rules for a process, in the form they are stored in: a line of text each.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub(crate) struct FirewallRulesUpdate {
+    pub(crate) process: xous_ipc::String<64>,
+    pub(crate) rules: xous_ipc::String<2048>,
+    /// set by the server: whether the caller may change the rules
+    pub(crate) allowed: bool,
+    /// set by the server: whether the rules were saved, and so are in force
+    pub(crate) saved: bool,
+}
+
+/// Every firewall rule, a line each, prefixed by the process it belongs to and a tab.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
+pub(crate) struct FirewallRuleList {
+    pub(crate) text: xous_ipc::String<3072>,
+}
+
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, Default)]
+pub(crate) struct IpSettingsUpdate {
+    pub(crate) settings: IpSettings,
+    /// set by the server: whether the caller may change the settings
+    pub(crate) allowed: bool,
+    /// set by the server: whether the settings were saved to the PDDB
+    pub(crate) saved: bool,
+}
+
 /// These opcodes are reserved for private SIDs shared from a DNS server to
 /// reconfigure DNS on IP change/update.
 #[derive(num_derive::FromPrimitive, num_derive::ToPrimitive, Debug)]
@@ -420,7 +633,7 @@ pub enum NetError {
     // Ok = 0,
     Unaddressable = 1,
     SocketInUse = 2,
-    // AccessDenied = 3,
+    AccessDenied = 3,
     Invalid = 4,
     // Finished = 5,
     LibraryError = 6,
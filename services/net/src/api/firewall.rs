@@ -0,0 +1,192 @@
+//! Rules of the outbound firewall, which decides what network access each process gets.
+//!
+//! The net service keeps the rules in the PDDB, one key per process. The kernel does not expose process
+//! names, so a process is known by the names of the servers it has registered with xous-names. An app
+//! started by the app loader could register any name it likes, so it is only known by the name the app
+//! loader gives it, `app:<name>`. The key is one of those names, and its value holds the rules of the
+//! process as lines of text:
+//!
+//! ```text
+//! <allow|deny> <connect|listen|udp|send|*> <destination> <ports>
+//! ```
+//!
+//! * `<destination>` is `*`, an address or CIDR block such as `10.0.0.0/8`, or a host name. A host name may
+//!   start with `*.` to match its subdomains, and is matched against the lookups made by the DNS service.
+//! * `<ports>` is `*`, a port, or an inclusive range such as `8000-8080`.
+//!
+//! The first rule that matches an access decides it. `listen` and `udp` rules are checked against the
+//! address and port being bound, since that's all the net service is told when a socket is opened;
+//! `send` rules are checked against the destination of every UDP datagram, which covers connected UDP
+//! sockets too, as those are connected on the client's side.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirewallAccess {
+    /// opening a TCP connection
+    Connect,
+    /// listening for TCP connections
+    Listen,
+    /// binding a UDP socket
+    Udp,
+    /// sending a UDP datagram
+    UdpSend,
+}
+impl FirewallAccess {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            FirewallAccess::Connect => "connect",
+            FirewallAccess::Listen => "listen",
+            FirewallAccess::Udp => "udp",
+            FirewallAccess::UdpSend => "send",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FirewallDest {
+    Any,
+    /// an address and the length of the prefix to match
+    Net(IpAddr, u8),
+    /// a host name, in lower case
+    Host(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirewallRule {
+    pub allow: bool,
+    /// `None` matches every kind of access
+    pub access: Option<FirewallAccess>,
+    pub dest: FirewallDest,
+    /// An inclusive range. `None` matches every port.
+    pub ports: Option<(u16, u16)>,
+}
+impl FirewallRule {
+    /// Whether the rule covers `access` to `addr` and `port`. `hosts` are the names `addr` was looked up
+    /// under.
+    pub fn matches(&self, access: FirewallAccess, addr: IpAddr, port: u16, hosts: &[String]) -> bool {
+        if self.access.map(|a| a != access).unwrap_or(false) {
+            return false;
+        }
+        if let Some((low, high)) = self.ports {
+            if port < low || port > high {
+                return false;
+            }
+        }
+        match &self.dest {
+            FirewallDest::Any => true,
+            FirewallDest::Net(net, prefix_len) => in_net(addr, *net, *prefix_len),
+            FirewallDest::Host(pattern) => hosts.iter().any(|host| host_matches(pattern, host)),
+        }
+    }
+}
+
+fn in_net(addr: IpAddr, net: IpAddr, prefix_len: u8) -> bool {
+    match (addr, net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) => {
+            let mask = if prefix_len == 0 { 0 } else { u32::MAX << (32 - prefix_len.min(32) as u32) };
+            u32::from(addr) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(net)) => {
+            let mask = if prefix_len == 0 { 0 } else { u128::MAX << (128 - prefix_len.min(128) as u32) };
+            u128::from(addr) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.ends_with(&format!(".{}", domain)),
+        None => pattern == host,
+    }
+}
+
+impl FromStr for FirewallRule {
+    type Err = xous::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let allow = match tokens.next() {
+            Some("allow") => true,
+            Some("deny") => false,
+            _ => return Err(xous::Error::InvalidString),
+        };
+        let access = match tokens.next() {
+            Some("*") => None,
+            Some("connect") => Some(FirewallAccess::Connect),
+            Some("listen") => Some(FirewallAccess::Listen),
+            Some("udp") => Some(FirewallAccess::Udp),
+            Some("send") => Some(FirewallAccess::UdpSend),
+            _ => return Err(xous::Error::InvalidString),
+        };
+        let dest = match tokens.next() {
+            Some("*") => FirewallDest::Any,
+            Some(dest) => match dest.split_once('/') {
+                Some((addr, prefix_len)) => {
+                    let addr = addr.parse::<IpAddr>().or(Err(xous::Error::InvalidString))?;
+                    let prefix_len = prefix_len.parse::<u8>().or(Err(xous::Error::InvalidString))?;
+                    if prefix_len > full_prefix(addr) {
+                        return Err(xous::Error::InvalidString);
+                    }
+                    FirewallDest::Net(addr, prefix_len)
+                }
+                None => match dest.parse::<IpAddr>() {
+                    Ok(addr) => FirewallDest::Net(addr, full_prefix(addr)),
+                    Err(_) => FirewallDest::Host(dest.to_ascii_lowercase()),
+                },
+            },
+            None => return Err(xous::Error::InvalidString),
+        };
+        let ports = match tokens.next() {
+            Some("*") => None,
+            Some(ports) => {
+                let (low, high) = ports.split_once('-').unwrap_or((ports, ports));
+                let low = low.parse::<u16>().or(Err(xous::Error::InvalidString))?;
+                let high = high.parse::<u16>().or(Err(xous::Error::InvalidString))?;
+                if low > high {
+                    return Err(xous::Error::InvalidString);
+                }
+                Some((low, high))
+            }
+            None => return Err(xous::Error::InvalidString),
+        };
+        if tokens.next().is_some() {
+            return Err(xous::Error::InvalidString);
+        }
+        Ok(FirewallRule { allow, access, dest, ports })
+    }
+}
+
+fn full_prefix(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl fmt::Display for FirewallRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ",
+            if self.allow { "allow" } else { "deny" },
+            self.access.map(|a| a.as_str()).unwrap_or("*")
+        )?;
+        match &self.dest {
+            FirewallDest::Any => write!(f, "* ")?,
+            FirewallDest::Net(addr, prefix_len) if *prefix_len == full_prefix(*addr) => {
+                write!(f, "{} ", addr)?
+            }
+            FirewallDest::Net(addr, prefix_len) => write!(f, "{}/{} ", addr, prefix_len)?,
+            FirewallDest::Host(host) => write!(f, "{} ", host)?,
+        }
+        match self.ports {
+            None => write!(f, "*"),
+            Some((low, high)) if low == high => write!(f, "{}", low),
+            Some((low, high)) => write!(f, "{}-{}", low, high),
+        }
+    }
+}
@@ -19,6 +19,8 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use rkyv::{Archive, Deserialize, Serialize};
 use smoltcp::wire::IpAddress;
 
+use super::Ipv4Settings;
+
 #[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone)]
 pub enum XousServerId {
     /// A SID that is shared directly with the Net crate; a private, single-use SID for best security
@@ -42,6 +44,28 @@ pub(crate) enum NetMemResponse {
     AlreadyUsed,
 }
 
+/// Where the user wants the IPv4 settings of the interface to come from.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum Ipv4Mode {
+    /// Use the lease obtained by the DHCP client in the EC
+    Ec,
+    /// Run a DHCP client in the net service
+    Dhcp,
+    /// Use fixed settings
+    Static(Ipv4Settings),
+}
+
+/// Where the IPv4 settings in effect came from.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Ipv4Source {
+    /// No IPv4 address is configured
+    #[default]
+    None,
+    Ec,
+    Dhcp,
+    Static,
+}
+
 #[derive(Archive, Serialize, Deserialize, Copy, Clone)]
 pub enum NetIpAddr {
     Ipv4([u8; 4]),
@@ -108,3 +132,44 @@ pub(crate) enum TcpMgmtCode {
     Flush(bool),
     CloseListener,
 }
+
+/// The kind of a socket listed by `Netstat`, and for TCP, the state of its connection.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum SocketState {
+    Udp,
+    Tcp(TcpState),
+}
+
+/// The states of a TCP connection, as in RFC 793.
+#[derive(Debug, Archive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+impl From<smoltcp::socket::tcp::State> for TcpState {
+    fn from(other: smoltcp::socket::tcp::State) -> TcpState {
+        use smoltcp::socket::tcp::State;
+        match other {
+            State::Closed => TcpState::Closed,
+            State::Listen => TcpState::Listen,
+            State::SynSent => TcpState::SynSent,
+            State::SynReceived => TcpState::SynReceived,
+            State::Established => TcpState::Established,
+            State::FinWait1 => TcpState::FinWait1,
+            State::FinWait2 => TcpState::FinWait2,
+            State::CloseWait => TcpState::CloseWait,
+            State::Closing => TcpState::Closing,
+            State::LastAck => TcpState::LastAck,
+            State::TimeWait => TcpState::TimeWait,
+        }
+    }
+}
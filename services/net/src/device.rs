@@ -12,6 +12,7 @@ use smoltcp::wire::{
     Ipv4Packet, Ipv4Repr, /* IpProtocol, TcpPacket, TcpRepr, IpAddress, UdpPacket, UdpRepr */
 };
 
+use crate::netstat::Traffic;
 use crate::{IPV4_ADDRESS, MAC_ADDRESS_LSB, MAC_ADDRESS_MSB};
 
 pub struct NetPhy {
@@ -22,10 +23,16 @@ pub struct NetPhy {
     loopback_conn: xous::CID,
     // tracks the length (and count) of the loopback packets pending
     loopback_pending: Arc<Mutex<VecDeque<u16>>>,
+    // every frame in and out is counted here, for netstat
+    traffic: Arc<Mutex<Traffic>>,
 }
 
 impl<'a> NetPhy {
-    pub fn new(xns: &xous_names::XousNames, loopback_conn: xous::CID) -> NetPhy {
+    pub fn new(
+        xns: &xous_names::XousNames,
+        loopback_conn: xous::CID,
+        traffic: Arc<Mutex<Traffic>>,
+    ) -> NetPhy {
         NetPhy {
             rx_buffer: [0; NET_MTU],
             tx_buffer: [0; NET_MTU],
@@ -33,6 +40,7 @@ impl<'a> NetPhy {
             rx_avail: None,
             loopback_conn,
             loopback_pending: Arc::new(Mutex::new(VecDeque::new())),
+            traffic,
         }
     }
 
@@ -43,6 +51,7 @@ impl<'a> NetPhy {
             self.rx_avail = Some(len);
             None
         } else {
+            self.traffic.lock().unwrap().iface.rx_dropped += 1;
             Some(len)
         }
     }
@@ -63,6 +72,7 @@ impl phy::Device for NetPhy {
             self.com
                 .wlan_fetch_loopback_packet(&mut self.rx_buffer[..rx_len as usize])
                 .expect("Couldn't call wlan_fetch_packet in device adapter");
+            self.traffic.lock().unwrap().count_rx(&self.rx_buffer[..rx_len as usize]);
 
             Some((
                 NetPhyRxToken { buf: &mut self.rx_buffer[..rx_len as usize] },
@@ -71,6 +81,7 @@ impl phy::Device for NetPhy {
                     com: &self.com,
                     loopback_conn: self.loopback_conn,
                     loopback_count: self.loopback_pending.clone(),
+                    traffic: self.traffic.clone(),
                     caps: csum_copy,
                 },
             ))
@@ -80,6 +91,7 @@ impl phy::Device for NetPhy {
                 self.com
                     .wlan_fetch_packet(&mut self.rx_buffer[..rx_len as usize])
                     .expect("Couldn't call wlan_fetch_packet in device adapter");
+                self.traffic.lock().unwrap().count_rx(&self.rx_buffer[..rx_len as usize]);
 
                 Some((
                     NetPhyRxToken { buf: &mut self.rx_buffer[..rx_len as usize] },
@@ -88,6 +100,7 @@ impl phy::Device for NetPhy {
                         com: &self.com,
                         loopback_conn: self.loopback_conn,
                         loopback_count: self.loopback_pending.clone(),
+                        traffic: self.traffic.clone(),
                         caps: csum_copy,
                     },
                 ))
@@ -106,6 +119,7 @@ impl phy::Device for NetPhy {
             com: &self.com,
             loopback_conn: self.loopback_conn,
             loopback_count: self.loopback_pending.clone(),
+            traffic: self.traffic.clone(),
             caps: csum_copy,
         })
     }
@@ -139,6 +153,7 @@ pub struct NetPhyTxToken<'a> {
     com: &'a Com,
     loopback_conn: xous::CID,
     loopback_count: Arc<Mutex<VecDeque<u16>>>,
+    traffic: Arc<Mutex<Traffic>>,
     caps: ChecksumCapabilities,
 }
 impl<'a> NetPhyTxToken<'a> {
@@ -199,6 +214,7 @@ impl<'a> phy::TxToken for NetPhyTxToken<'a> {
     {
         let result = f(&mut self.buf[..len]);
         log::debug!("txlen: {}", len);
+        self.traffic.lock().unwrap().count_tx(&self.buf[..len]);
 
         {
             // this is a hack to make loopbacks work on smoltcp. Work-around taken from Redox, but tracking
@@ -397,7 +413,10 @@ impl<'a> phy::TxToken for NetPhyTxToken<'a> {
         }
         // forward the packet on if it's not a loopback (loopback will call return early and exit before
         // getting to this line)
-        self.com.wlan_send_packet(&self.buf[..len]).expect("driver error sending WLAN packet");
+        if let Err(e) = self.com.wlan_send_packet(&self.buf[..len]) {
+            log::warn!("driver error sending WLAN packet: {:?}", e);
+            self.traffic.lock().unwrap().iface.tx_errors += 1;
+        }
 
         result
     }
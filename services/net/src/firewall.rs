@@ -0,0 +1,568 @@
+//! The outbound firewall, which checks the sockets processes open against their rules in the PDDB
+//! (see `api::firewall` for the format of those).
+//!
+//! Processes without rules keep the historic behaviour of unrestricted access, unless the app loader
+//! has put them in the sandbox. Any access of a sandboxed process, or of a process with rules, that no
+//! rule covers is put to the user in a modal. The request waits while the modal is up, and goes back on
+//! the main loop's queue once the user has answered, by which time their answer is a rule. A process
+//! only ever has one question before the user: its other accesses that no rule covers are refused until
+//! that one is answered, and asked about when next tried.
+//!
+//! The rules are ours alone: they are read out of the PDDB once it is mounted, and from then on only
+//! change at the user's say-so, through a prompt or through shellchat. The PDDB doesn't stop another
+//! process from writing the dict, but such a change is never read back until the next boot.
+//!
+//! UDP datagrams are checked one by one, so the verdicts are cached per destination until anything they
+//! may depend on changes.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use locales::t;
+use num_traits::*;
+use xous::{Message, MessageEnvelope};
+
+use crate::api::*;
+use crate::std_glue::{parse_address, respond_with_error};
+
+/// The servers registered by the processes we take instructions from
+const SERVER_NAME_APP_LOADER: &str = "_App Loader_";
+const SERVER_NAME_DNS: &str = "_DNS Resolver Middleware_";
+const SERVER_NAME_SHELLCHAT: &str = "_Shell chat application_";
+const SERVER_NAME_STATUS: &str = "_Status_";
+
+/// PDDB dict holding the rules
+const FIREWALL_DICT: &str = "net.firewall";
+
+/// Lookups are kept at least this long, since a connection follows its lookup, and TTLs can be 0
+const MIN_RESOLVED_MS: u64 = 60_000;
+/// the most addresses to remember names for
+const MAX_RESOLVED: usize = 256;
+/// the most verdicts to cache
+const MAX_VERDICTS: usize = 256;
+/// the most requests to hold for one prompt
+const MAX_HELD: usize = 16;
+
+/// The answers offered by the prompt, in order
+const CHOICE_ALLOW_DEST: usize = 0;
+const CHOICE_ALLOW_ALL: usize = 1;
+const CHOICE_DENY_ALL: usize = 2;
+
+/// An access waiting on the user
+struct Pending {
+    pid: xous::PID,
+    access: FirewallAccess,
+    addr: IpAddr,
+    port: u16,
+    /// the requests that asked for it, to be handled once it is decided
+    held: Vec<MessageEnvelope>,
+}
+
+pub(crate) struct Firewall {
+    xns: xous_names::XousNames,
+    /// questions for the prompt thread, by prompt
+    prompts: Sender<(usize, String)>,
+    rules: HashMap<String, Vec<FirewallRule>>,
+    /// whether `rules` holds what is in the PDDB; rules made before it was mounted are only in memory
+    loaded: bool,
+    /// sandboxed processes, and the names the app loader gave them
+    sandboxed: HashMap<xous::PID, Option<String>>,
+    /// decisions for sandboxed processes that have no name to keep them under, until the process exits
+    anonymous: HashMap<xous::PID, Vec<FirewallRule>>,
+    /// the server names of processes, as looked up in xous-names
+    names: HashMap<xous::PID, Vec<String>>,
+    /// names that addresses were looked up under, and when each lookup expires
+    resolved: HashMap<IpAddr, Vec<(String, u64)>>,
+    /// the accesses being asked about, by prompt; there is at most one per process
+    pending: HashMap<usize, Pending>,
+    next_prompt: usize,
+    /// whether an access was allowed, by process, access, address and port
+    verdicts: HashMap<(xous::PID, FirewallAccess, IpAddr, u16), bool>,
+}
+
+impl Firewall {
+    pub fn new(net_conn: xous::CID) -> Self {
+        let (prompts, questions) = channel();
+        thread::spawn(move || prompt_thread(net_conn, questions));
+        Firewall {
+            xns: xous_names::XousNames::new().unwrap(),
+            prompts,
+            rules: HashMap::new(),
+            loaded: false,
+            sandboxed: HashMap::new(),
+            anonymous: HashMap::new(),
+            names: HashMap::new(),
+            resolved: HashMap::new(),
+            pending: HashMap::new(),
+            next_prompt: 0,
+            verdicts: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, pddb: &pddb::Pddb, poller: &pddb::PddbMountPoller) {
+        if self.loaded || !poller.is_mounted_nonblocking() {
+            return;
+        }
+        let mut rules = load_rules(pddb);
+        // decisions made before the PDDB was mounted are saved now
+        for (name, unsaved) in self.rules.drain() {
+            let saved = rules.entry(name.clone()).or_default();
+            saved.extend(unsaved);
+            if let Err(e) = store_rules(pddb, &name, saved) {
+                log::warn!("couldn't save firewall rules of {}: {:?}", name, e);
+            }
+        }
+        log::info!("{} process(es) have firewall rules", rules.len());
+        self.rules = rules;
+        self.loaded = true;
+    }
+
+    /// Whether `pid` registered the server called `name`
+    fn owns(&self, pid: Option<xous::PID>, name: &str) -> bool {
+        match (pid, self.xns.server_info(name)) {
+            (Some(pid), Ok(Some(record))) => record.owner == Some(pid.get()),
+            _ => false,
+        }
+    }
+
+    /// Whether `pid` is one of the processes that show the user what every process is doing: shellchat
+    /// and the status bar.
+    pub fn shows_all(&self, pid: Option<xous::PID>) -> bool {
+        self.owns(pid, SERVER_NAME_SHELLCHAT) || self.owns(pid, SERVER_NAME_STATUS)
+    }
+
+    /// The names a process's rules are kept under. A sandboxed process could register any server name,
+    /// so it only has the name the app loader gave it.
+    fn names_of(&mut self, pid: xous::PID) -> Vec<String> {
+        if let Some(name) = self.sandboxed.get(&pid) {
+            return name.iter().cloned().collect();
+        }
+        if let Some(names) = self.names.get(&pid) {
+            return names.clone();
+        }
+        let names: Vec<String> = match self.xns.list() {
+            Ok(servers) => servers
+                .iter()
+                .filter(|server| server.owner == Some(pid.get()))
+                .map(|server| server.name.to_str().to_string())
+                .collect(),
+            Err(e) => {
+                log::warn!("couldn't list servers: {:?}", e);
+                Vec::new()
+            }
+        };
+        // a process may register its servers after it first uses the network, so an empty answer is
+        // asked again next time
+        if !names.is_empty() {
+            self.names.insert(pid, names.clone());
+        }
+        names
+    }
+
+    fn hosts_of(&self, addr: IpAddr, now: u64) -> Vec<String> {
+        match self.resolved.get(&addr) {
+            Some(hosts) => {
+                hosts.iter().filter(|(_, expiry)| *expiry > now).map(|(host, _)| host.clone()).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Checks the socket request in `msg`. Returns the request if it may go ahead; otherwise it is either
+    /// refused, or held until the user has been asked about it.
+    pub fn check(
+        &mut self,
+        msg: MessageEnvelope,
+        access: FirewallAccess,
+        pddb: &pddb::Pddb,
+        poller: &pddb::PddbMountPoller,
+        now: u64,
+    ) -> Option<MessageEnvelope> {
+        let pid = match msg.sender.pid() {
+            Some(pid) if pid.get() as u32 != xous::process::id() => pid,
+            _ => return Some(msg),
+        };
+        // requests that don't parse are left to their handlers to refuse
+        let (addr, port) = match request_endpoint(&msg, access) {
+            Some(endpoint) => endpoint,
+            None => return Some(msg),
+        };
+        if let Some(&allow) = self.verdicts.get(&(pid, access, addr, port)) {
+            return enforce(msg, allow);
+        }
+        self.load(pddb, poller);
+        let names = self.names_of(pid);
+        let hosts = self.hosts_of(addr, now);
+        let mut restricted = self.sandboxed.contains_key(&pid);
+        let mut rules = Vec::new();
+        if let Some(anonymous) = self.anonymous.get(&pid) {
+            rules.extend(anonymous.iter());
+        }
+        for name in names.iter() {
+            if let Some(named) = self.rules.get(name) {
+                restricted = true;
+                rules.extend(named.iter());
+            }
+        }
+        if !restricted {
+            return Some(msg);
+        }
+        match rules.iter().find(|rule| rule.matches(access, addr, port, &hosts)).map(|rule| rule.allow) {
+            Some(allow) => {
+                if !allow {
+                    log::info!(
+                        "firewall denied {:?} to {}:{} for {:?} ({:?})",
+                        access,
+                        addr,
+                        port,
+                        pid,
+                        names
+                    );
+                }
+                if self.verdicts.len() >= MAX_VERDICTS {
+                    self.verdicts.clear();
+                }
+                self.verdicts.insert((pid, access, addr, port), allow);
+                enforce(msg, allow)
+            }
+            None => {
+                self.prompt(Pending { pid, access, addr, port, held: vec![msg] }, &names, &hosts);
+                None
+            }
+        }
+    }
+
+    fn prompt(&mut self, mut request: Pending, names: &[String], hosts: &[String]) {
+        // a process has one question before the user at a time; the same question can wait on it, but
+        // anything else is refused until it has been answered
+        if let Some(pending) = self.pending.values_mut().find(|pending| pending.pid == request.pid) {
+            if pending.access == request.access
+                && pending.addr == request.addr
+                && pending.port == request.port
+                && pending.held.len() + request.held.len() <= MAX_HELD
+            {
+                pending.held.append(&mut request.held);
+            } else {
+                log::info!(
+                    "firewall refused {:?} to {}:{} for {:?} while it waits on the user",
+                    request.access,
+                    request.addr,
+                    request.port,
+                    request.pid
+                );
+                for msg in request.held {
+                    respond_with_error(msg, NetError::AccessDenied);
+                }
+            }
+            return;
+        }
+        let (pid, access, addr, port) = (request.pid, request.access, request.addr, request.port);
+        let id = self.next_prompt;
+        self.next_prompt = self.next_prompt.wrapping_add(1);
+
+        let process = names.first().cloned().unwrap_or_else(|| format!("PID {}", pid));
+        let dest = match hosts.first() {
+            Some(host) => format!("{} ({}) port {}", host, addr, port),
+            None => format!("{} port {}", addr, port),
+        };
+        let question = format!(
+            "{}\n{}\n{} {}",
+            t!("net.firewall_prompt", locales::LANG),
+            process,
+            access.as_str(),
+            dest
+        );
+        if self.prompts.send((id, question)).is_err() {
+            log::warn!("the firewall prompt is gone, refusing {:?}", pid);
+            for msg in request.held {
+                respond_with_error(msg, NetError::AccessDenied);
+            }
+            return;
+        }
+        self.pending.insert(id, request);
+    }
+
+    /// Records the user's answer to prompt `id`, and sends the requests that waited on it back to the
+    /// main loop through `requeue`.
+    pub fn decide(
+        &mut self,
+        id: usize,
+        choice: usize,
+        pddb: &pddb::Pddb,
+        poller: &pddb::PddbMountPoller,
+        requeue: &Sender<MessageEnvelope>,
+        now: u64,
+    ) {
+        let pending = match self.pending.remove(&id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let rule = match choice {
+            CHOICE_ALLOW_DEST => {
+                let dest = match self.hosts_of(pending.addr, now).first() {
+                    Some(host) => FirewallDest::Host(host.clone()),
+                    None => FirewallDest::Net(pending.addr, if pending.addr.is_ipv4() { 32 } else { 128 }),
+                };
+                Some(FirewallRule {
+                    allow: true,
+                    access: Some(pending.access),
+                    dest,
+                    // an ephemeral port is different every time
+                    ports: if pending.port == 0 { None } else { Some((pending.port, pending.port)) },
+                })
+            }
+            CHOICE_ALLOW_ALL => {
+                Some(FirewallRule { allow: true, access: None, dest: FirewallDest::Any, ports: None })
+            }
+            CHOICE_DENY_ALL => {
+                Some(FirewallRule { allow: false, access: None, dest: FirewallDest::Any, ports: None })
+            }
+            _ => None,
+        };
+        let rule = match rule {
+            Some(rule) => rule,
+            None => {
+                for msg in pending.held {
+                    respond_with_error(msg, NetError::AccessDenied);
+                }
+                return;
+            }
+        };
+        log::info!("firewall rule for {:?}: {}", pending.pid, rule);
+        self.verdicts.clear();
+        self.load(pddb, poller);
+        match self.names_of(pending.pid).first() {
+            Some(name) => {
+                let rules = self.rules.entry(name.clone()).or_default();
+                rules.push(rule);
+                if self.loaded {
+                    if let Err(e) = store_rules(pddb, name, rules) {
+                        log::warn!("couldn't save firewall rules of {}: {:?}", name, e);
+                    }
+                }
+            }
+            None => self.anonymous.entry(pending.pid).or_default().push(rule),
+        }
+        for msg in pending.held {
+            requeue.send(msg).ok();
+        }
+    }
+
+    /// Whether `pid` is shellchat, which changes settings on the user's behalf. A sandboxed process could
+    /// register shellchat's server name before shellchat does, so it never counts.
+    pub fn acts_for_user(&self, pid: Option<xous::PID>) -> bool {
+        self.owns(pid, SERVER_NAME_SHELLCHAT)
+            && !pid.map(|pid| self.sandboxed.contains_key(&pid)).unwrap_or(true)
+    }
+
+    /// Replaces the rules of a process, if `sender` is shellchat acting for the user. Fills in whether the
+    /// change was allowed and saved; rules can't be changed before the PDDB is mounted, as they would be
+    /// merged with the saved ones instead of replacing them.
+    pub fn update(
+        &mut self,
+        sender: Option<xous::PID>,
+        update: &mut FirewallRulesUpdate,
+        pddb: &pddb::Pddb,
+        poller: &pddb::PddbMountPoller,
+    ) {
+        update.allowed = self.acts_for_user(sender);
+        update.saved = false;
+        if !update.allowed {
+            log::warn!("{:?} may not change firewall rules", sender);
+            return;
+        }
+        let process = update.process.to_str().to_string();
+        let rules = match update
+            .rules
+            .to_str()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.parse::<FirewallRule>())
+            .collect::<Result<Vec<FirewallRule>, _>>()
+        {
+            Ok(rules) => rules,
+            Err(_) => {
+                log::warn!("bad firewall rules for {}: {}", process, update.rules.to_str());
+                return;
+            }
+        };
+        self.load(pddb, poller);
+        if !self.loaded {
+            log::warn!("firewall rules can't be changed until the PDDB is mounted");
+            return;
+        }
+        if let Err(e) = store_rules(pddb, &process, &rules) {
+            log::warn!("couldn't save firewall rules of {}: {:?}", process, e);
+            return;
+        }
+        log::info!("{} firewall rule(s) for {}", rules.len(), process);
+        if rules.is_empty() {
+            self.rules.remove(&process);
+        } else {
+            self.rules.insert(process, rules);
+        }
+        self.verdicts.clear();
+        update.saved = true;
+    }
+
+    /// The rules of every process that has any, sorted by process.
+    pub fn list(
+        &mut self,
+        pddb: &pddb::Pddb,
+        poller: &pddb::PddbMountPoller,
+    ) -> Vec<(&String, &Vec<FirewallRule>)> {
+        self.load(pddb, poller);
+        let mut rules: Vec<(&String, &Vec<FirewallRule>)> = self.rules.iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        rules
+    }
+
+    /// Puts `pid` in the sandbox, if `sender` is the app loader. The rules of the process are kept under
+    /// `app:<name>`, or only until it exits if `name` is empty.
+    pub fn sandbox(&mut self, sender: Option<xous::PID>, pid: xous::PID, name: &str) -> bool {
+        if !self.owns(sender, SERVER_NAME_APP_LOADER) {
+            log::warn!("{:?} may not sandbox processes", sender);
+            return false;
+        }
+        log::info!("sandboxing {:?} as {:?}", pid, name);
+        // the PID may have been in use by a process whose exit we missed
+        self.names.remove(&pid);
+        self.anonymous.remove(&pid);
+        self.sandboxed.insert(pid, if name.is_empty() { None } else { Some(format!("app:{}", name)) });
+        self.verdicts.retain(|(verdict_pid, _, _, _), _| *verdict_pid != pid);
+        true
+    }
+
+    /// Notes the addresses of a lookup made by the DNS service.
+    pub fn resolved(&mut self, sender: Option<xous::PID>, host: &ResolvedHost, now: u64) {
+        if !self.owns(sender, SERVER_NAME_DNS) {
+            log::warn!("ignoring lookup results from {:?}", sender);
+            return;
+        }
+        let name = host.name.to_str().trim_end_matches('.').to_ascii_lowercase();
+        let expiry = now + (host.ttl as u64 * 1000).max(MIN_RESOLVED_MS);
+        // rules naming hosts may match differently now
+        self.verdicts.clear();
+        self.resolved.retain(|_, hosts| {
+            hosts.retain(|(_, host_expiry)| *host_expiry > now);
+            !hosts.is_empty()
+        });
+        for addr in host.addrs.iter().flatten() {
+            let addr = IpAddr::from(*addr);
+            if self.resolved.len() >= MAX_RESOLVED && !self.resolved.contains_key(&addr) {
+                log::debug!("too many lookups to remember {} for {}", addr, name);
+                continue;
+            }
+            let hosts = self.resolved.entry(addr).or_default();
+            hosts.retain(|(host, _)| *host != name);
+            hosts.push((name.clone(), expiry));
+        }
+    }
+
+    /// Forgets about a process that has exited.
+    pub fn forget(&mut self, pid: xous::PID) {
+        self.names.remove(&pid);
+        self.sandboxed.remove(&pid);
+        self.anonymous.remove(&pid);
+        self.verdicts.retain(|(verdict_pid, _, _, _), _| *verdict_pid != pid);
+        // its requests go unanswered, as there's no one left to answer
+        self.pending.retain(|_, pending| pending.pid != pid);
+    }
+}
+
+/// Puts questions to the user one at a time, for as long as the net server runs, and sends each answer
+/// back to the main loop as a `FirewallDecision`.
+fn prompt_thread(net_conn: xous::CID, questions: Receiver<(usize, String)>) {
+    let xns = xous_names::XousNames::new().unwrap();
+    let modals = modals::Modals::new(&xns).expect("can't connect to modals");
+    for (id, question) in questions {
+        // a prompt that can't be shown is taken as a refusal, and asked again next time
+        let choice = modals
+            .add_list(vec![
+                t!("net.firewall_allow_dest", locales::LANG),
+                t!("net.firewall_allow_all", locales::LANG),
+                t!("net.firewall_deny_all", locales::LANG),
+            ])
+            .and_then(|_| modals.get_radiobutton(&question))
+            .and_then(|_| modals.get_radio_index())
+            .unwrap_or(usize::MAX);
+        xous::send_message(
+            net_conn,
+            Message::new_scalar(Opcode::FirewallDecision.to_usize().unwrap(), id, choice, 0, 0),
+        )
+        .ok();
+    }
+}
+
+/// Lets `msg` through, or refuses it.
+fn enforce(msg: MessageEnvelope, allow: bool) -> Option<MessageEnvelope> {
+    if allow {
+        Some(msg)
+    } else {
+        respond_with_error(msg, NetError::AccessDenied);
+        None
+    }
+}
+
+/// The address and port a socket request is for: the remote end for a connection, and the local end
+/// otherwise.
+fn request_endpoint(msg: &MessageEnvelope, access: FirewallAccess) -> Option<(IpAddr, u16)> {
+    let body = msg.body.memory_message()?;
+    let bytes = unsafe { body.buf.as_slice::<u8>() };
+    let port = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]);
+    // a connect request has a timeout between the port and the address
+    let addr_offset = if access == FirewallAccess::Connect { 10 } else { 2 };
+    let addr = parse_address(bytes.get(addr_offset..)?)?;
+    Some((ipaddress_to_ipaddr(addr), port))
+}
+
+/// The rules of every process that has any. Lines that don't parse are skipped.
+fn load_rules(pddb: &pddb::Pddb) -> HashMap<String, Vec<FirewallRule>> {
+    let mut all_rules = HashMap::new();
+    let keys = match pddb.list_keys(FIREWALL_DICT, None) {
+        Ok(keys) => keys,
+        // the dict doesn't exist until a rule is added
+        Err(_) => return all_rules,
+    };
+    for key in keys {
+        let mut data = Vec::new();
+        match pddb.get(FIREWALL_DICT, &key, None, false, false, None, None::<fn()>) {
+            Ok(mut record) => {
+                if let Err(e) = record.read_to_end(&mut data) {
+                    log::warn!("couldn't read firewall rules of {}: {:?}", key, e);
+                    continue;
+                }
+            }
+            Err(e) => {
+                log::warn!("couldn't open firewall rules of {}: {:?}", key, e);
+                continue;
+            }
+        }
+        let mut rules = Vec::new();
+        for line in String::from_utf8_lossy(&data).lines().filter(|line| !line.trim().is_empty()) {
+            match line.parse::<FirewallRule>() {
+                Ok(rule) => rules.push(rule),
+                Err(_) => log::warn!("ignoring bad firewall rule of {}: {}", key, line),
+            }
+        }
+        all_rules.insert(key, rules);
+    }
+    all_rules
+}
+
+/// Replaces the rules of `process`, in a transaction so that losing power part-way can't leave the
+/// process without any. With no rules, its key is removed.
+fn store_rules(pddb: &pddb::Pddb, process: &str, rules: &[FirewallRule]) -> std::io::Result<()> {
+    let mut txn = pddb.transaction(None);
+    if rules.is_empty() {
+        txn.delete(FIREWALL_DICT, process);
+    } else {
+        let text: String = rules.iter().map(|rule| format!("{}\n", rule)).collect();
+        txn.write(FIREWALL_DICT, process, text.as_bytes());
+    }
+    txn.commit()?;
+    pddb.sync()
+}
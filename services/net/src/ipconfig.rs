@@ -0,0 +1,606 @@
+//! Addressing of the interface: IPv4 settings from the EC, from our own DHCP client or from the
+//! user's static settings, plus IPv6 link-local and SLAAC addresses. The global SLAAC address has a
+//! stable-privacy interface identifier (RFC 7217), so that it can't be used to follow the device from
+//! one network to the next the way one built from the MAC address could.
+
+use core::sync::atomic::Ordering;
+use std::io::{Read, Write};
+
+use com::api::Ipv4Conf;
+use sha2::{Digest, Sha256};
+use smoltcp::iface::{Interface, SocketHandle, SocketSet};
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::{dhcpv4, raw};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{Icmpv6Packet, Icmpv6Repr};
+use smoltcp::wire::{
+    IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Address, Ipv6Address, Ipv6Packet, Ipv6Repr,
+    NdiscPrefixInfoFlags, NdiscRepr, RawHardwareAddress,
+};
+
+use crate::api::*;
+use crate::IPV4_ADDRESS;
+
+/// Length of the `IpSettings` record kept in the PDDB
+pub(crate) const IPSETTINGS_LEN: usize = 23;
+const IPSETTINGS_VERSION: u8 = 1;
+/// Length of the secret that stable-privacy addresses are derived from
+pub(crate) const STABLE_SECRET_LEN: usize = 32;
+
+impl IpSettings {
+    pub(crate) fn to_bytes(&self) -> [u8; IPSETTINGS_LEN] {
+        let mut data = [0u8; IPSETTINGS_LEN];
+        data[0] = IPSETTINGS_VERSION;
+        data[2] = self.slaac as u8;
+        match self.ipv4 {
+            Ipv4Mode::Ec => data[1] = 0,
+            Ipv4Mode::Dhcp => data[1] = 1,
+            Ipv4Mode::Static(settings) => {
+                data[1] = 2;
+                data[3] = settings.prefix_len;
+                data[4..8].copy_from_slice(&settings.addr);
+                for (i, addr) in [settings.gateway, settings.dns[0], settings.dns[1]].iter().enumerate() {
+                    if let Some(addr) = addr {
+                        data[8 + i * 5] = 1;
+                        data[9 + i * 5..13 + i * 5].copy_from_slice(addr);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() != IPSETTINGS_LEN || data[0] != IPSETTINGS_VERSION {
+            return None;
+        }
+        let optional_addr = |offset: usize| match data[offset] {
+            0 => Some(None),
+            1 => Some(Some([data[offset + 1], data[offset + 2], data[offset + 3], data[offset + 4]])),
+            _ => None,
+        };
+        let ipv4 = match data[1] {
+            0 => Ipv4Mode::Ec,
+            1 => Ipv4Mode::Dhcp,
+            2 => Ipv4Mode::Static(Ipv4Settings {
+                addr: [data[4], data[5], data[6], data[7]],
+                prefix_len: data[3],
+                gateway: optional_addr(8)?,
+                dns: [optional_addr(13)?, optional_addr(18)?],
+            }),
+            _ => return None,
+        };
+        let settings = IpSettings { ipv4, slaac: data[2] != 0 };
+        if settings.is_valid() { Some(settings) } else { None }
+    }
+}
+
+impl From<&Ipv4Conf> for Ipv4Settings {
+    fn from(conf: &Ipv4Conf) -> Self {
+        let nonzero = |addr: [u8; 4]| if addr != [0, 0, 0, 0] { Some(addr) } else { None };
+        let mask = u32::from_be_bytes(conf.mask);
+        Ipv4Settings {
+            addr: conf.addr,
+            // the EC has been known to report an empty mask; /24 is what we always assumed before
+            prefix_len: if mask != 0 && mask.leading_ones() == mask.count_ones() {
+                mask.count_ones() as u8
+            } else {
+                24
+            },
+            gateway: nonzero(conf.gtwy),
+            dns: [nonzero(conf.dns1), nonzero(conf.dns2)],
+        }
+    }
+}
+
+impl From<&dhcpv4::Config<'_>> for Ipv4Settings {
+    fn from(lease: &dhcpv4::Config) -> Self {
+        let mut dns = [None; 2];
+        for (slot, server) in dns.iter_mut().zip(lease.dns_servers.iter()) {
+            *slot = Some(server.0);
+        }
+        Ipv4Settings {
+            addr: lease.address.address().0,
+            prefix_len: lease.address.prefix_len(),
+            gateway: lease.router.map(|router| router.0),
+            dns,
+        }
+    }
+}
+
+/// Reads the user's settings out of the PDDB. Missing or unreadable settings give the defaults.
+pub(crate) fn load_settings(pddb: &pddb::Pddb) -> IpSettings {
+    let mut data = Vec::new();
+    match pddb.get(IPCONFIG_DICT_NAME, IPCONFIG_KEY_NAME, None, false, false, None, None::<fn()>) {
+        Ok(mut key) => match key.read_to_end(&mut data) {
+            Ok(_) => IpSettings::from_bytes(&data).unwrap_or_else(|| {
+                log::warn!("IP settings in the PDDB are corrupt, using the defaults");
+                IpSettings::default()
+            }),
+            Err(e) => {
+                log::warn!("couldn't read IP settings: {:?}", e);
+                IpSettings::default()
+            }
+        },
+        Err(_) => IpSettings::default(),
+    }
+}
+
+pub(crate) fn store_settings(pddb: &pddb::Pddb, settings: &IpSettings) -> std::io::Result<()> {
+    let mut key = pddb.get(
+        IPCONFIG_DICT_NAME,
+        IPCONFIG_KEY_NAME,
+        None,
+        true,
+        true,
+        Some(IPSETTINGS_LEN),
+        None::<fn()>,
+    )?;
+    key.write_all(&settings.to_bytes())?;
+    pddb.sync()
+}
+
+/// Reads the secret that stable-privacy addresses are derived from out of the PDDB. If there is none
+/// yet, `fresh` is stored and used from then on.
+pub(crate) fn load_secret(pddb: &pddb::Pddb, fresh: &[u8; STABLE_SECRET_LEN]) -> [u8; STABLE_SECRET_LEN] {
+    let mut data = Vec::new();
+    if let Ok(mut key) =
+        pddb.get(IPCONFIG_DICT_NAME, IPCONFIG_SECRET_KEY_NAME, None, false, false, None, None::<fn()>)
+    {
+        if key.read_to_end(&mut data).is_ok() && data.len() == STABLE_SECRET_LEN {
+            let mut secret = [0u8; STABLE_SECRET_LEN];
+            secret.copy_from_slice(&data);
+            return secret;
+        }
+        log::warn!("IPv6 address secret in the PDDB is corrupt, replacing it");
+        pddb.delete_key(IPCONFIG_DICT_NAME, IPCONFIG_SECRET_KEY_NAME, None).ok();
+    }
+    let stored = pddb
+        .get(
+            IPCONFIG_DICT_NAME,
+            IPCONFIG_SECRET_KEY_NAME,
+            None,
+            true,
+            true,
+            Some(STABLE_SECRET_LEN),
+            None::<fn()>,
+        )
+        .and_then(|mut key| key.write_all(fresh))
+        .and_then(|_| pddb.sync());
+    if let Err(e) = stored {
+        // the addresses are still stable until the next boot
+        log::warn!("couldn't save IPv6 address secret: {:?}", e);
+    }
+    *fresh
+}
+
+/// Modified EUI-64 interface identifier derived from a MAC address (RFC 4291 appendix A). Only used
+/// for the link-local address, which never leaves the network.
+pub(crate) fn eui64(mac: &[u8; 6]) -> [u8; 8] {
+    [mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+}
+
+/// Whether an interface identifier is reserved (RFC 5453): the subnet-router anycast identifier, the
+/// ones of the reserved IPv6 interface identifier range, and the subnet anycast identifiers.
+fn reserved_interface_id(iid: &[u8; 8]) -> bool {
+    let iid = u64::from_be_bytes(*iid);
+    iid == 0 || (0x0200_5eff_fe00_0000..=0x0200_5eff_fe00_5213).contains(&iid) || iid >= 0xfdff_ffff_ffff_ff80
+}
+
+/// Stable-privacy interface identifier for addresses under `prefix` (RFC 7217): the first 64 bits of
+/// SHA-256 over the prefix, the interface, the DAD counter and `secret`. The network ID is left out.
+pub(crate) fn stable_interface_id(
+    prefix: &[u8; 16],
+    mac: &[u8; 6],
+    secret: &[u8; STABLE_SECRET_LEN],
+) -> [u8; 8] {
+    // we don't do duplicate address detection, so the DAD counter only steps past reserved identifiers
+    let mut dad_counter = 0u8;
+    loop {
+        let mut hasher = Sha256::new();
+        hasher.update(&prefix[..8]);
+        hasher.update(mac);
+        hasher.update([dad_counter]);
+        hasher.update(secret);
+        let mut iid = [0u8; 8];
+        iid.copy_from_slice(&hasher.finalize()[..8]);
+        if !reserved_interface_id(&iid) {
+            return iid;
+        }
+        dad_counter = dad_counter.wrapping_add(1);
+    }
+}
+
+/// Builds an address out of the first 64 bits of `prefix` and a stable-privacy interface identifier.
+pub(crate) fn slaac_address(prefix: &[u8; 16], mac: &[u8; 6], secret: &[u8; STABLE_SECRET_LEN]) -> [u8; 16] {
+    let mut addr = [0u8; 16];
+    addr[..8].copy_from_slice(&prefix[..8]);
+    addr[8..].copy_from_slice(&stable_interface_id(prefix, mac, secret));
+    addr
+}
+
+pub(crate) fn link_local_address(mac: &[u8; 6]) -> [u8; 16] {
+    let mut addr = [0u8; 16];
+    addr[0] = 0xfe;
+    addr[1] = 0x80;
+    addr[8..].copy_from_slice(&eui64(mac));
+    addr
+}
+
+/// Address and default router learnt from router advertisements.
+#[derive(Debug, Default)]
+pub(crate) struct Slaac {
+    pub(crate) global: Option<(Ipv6Settings, Instant)>,
+    pub(crate) router: Option<(Ipv6Address, Instant)>,
+}
+impl Slaac {
+    /// Takes in an IPv6 packet carrying ICMPv6. Returns true if the addressing changed.
+    pub(crate) fn process(
+        &mut self,
+        packet: &[u8],
+        mac: &[u8; 6],
+        secret: &[u8; STABLE_SECRET_LEN],
+        now: Instant,
+    ) -> bool {
+        let packet = match Ipv6Packet::new_checked(packet) {
+            Ok(packet) => packet,
+            Err(_) => return false,
+        };
+        let (src, dst) = (IpAddress::Ipv6(packet.src_addr()), IpAddress::Ipv6(packet.dst_addr()));
+        // a router advertisement must come from a link-local address, and not have been forwarded
+        if packet.next_header() != IpProtocol::Icmpv6
+            || packet.hop_limit() != 255
+            || !packet.src_addr().is_link_local()
+        {
+            return false;
+        }
+        let icmp = match Icmpv6Packet::new_checked(packet.payload()) {
+            Ok(icmp) => icmp,
+            Err(_) => return false,
+        };
+        let (router_lifetime, prefix_info) =
+            match Icmpv6Repr::parse(&src, &dst, &icmp, &ChecksumCapabilities::default()) {
+                Ok(Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert { router_lifetime, prefix_info, .. })) => {
+                    (router_lifetime, prefix_info)
+                }
+                _ => return false,
+            };
+        let mut changed = false;
+        let router = packet.src_addr();
+        if router_lifetime == Duration::ZERO {
+            if self.router.map(|(r, _)| r) == Some(router) {
+                self.router = None;
+                changed = true;
+            }
+        } else {
+            changed |= self.router.map(|(r, _)| r) != Some(router);
+            self.router = Some((router, now + router_lifetime));
+        }
+        if let Some(info) = prefix_info {
+            // only /64 prefixes can be combined with a 64-bit interface identifier
+            if info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+                && info.prefix_len == 64
+                && !info.prefix.is_link_local()
+            {
+                let global =
+                    Ipv6Settings { addr: slaac_address(&info.prefix.0, mac, secret), prefix_len: 64 };
+                if info.valid_lifetime == Duration::ZERO {
+                    if self.global.map(|(g, _)| g) == Some(global) {
+                        self.global = None;
+                        changed = true;
+                    }
+                } else {
+                    changed |= self.global.map(|(g, _)| g) != Some(global);
+                    self.global = Some((global, now + info.valid_lifetime));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Drops whatever has outlived its advertised lifetime. Returns true if the addressing changed.
+    pub(crate) fn expire(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        if self.global.map(|(_, until)| until <= now).unwrap_or(false) {
+            self.global = None;
+            changed = true;
+        }
+        if self.router.map(|(_, until)| until <= now).unwrap_or(false) {
+            self.router = None;
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Builds a router solicitation from `src`, as an IPv6 packet for a raw socket.
+pub(crate) fn router_solicitation(src: &[u8; 16], mac: &[u8; 6]) -> Vec<u8> {
+    let src = Ipv6Address(*src);
+    let dst = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+    let icmp_repr =
+        Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit { lladdr: Some(RawHardwareAddress::from_bytes(mac)) });
+    let ip_repr = Ipv6Repr {
+        src_addr: src,
+        dst_addr: dst,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 255,
+    };
+    let mut data = vec![0u8; ip_repr.buffer_len() + icmp_repr.buffer_len()];
+    let mut packet = Ipv6Packet::new_unchecked(&mut data[..]);
+    ip_repr.emit(&mut packet);
+    icmp_repr.emit(
+        &IpAddress::Ipv6(src),
+        &IpAddress::Ipv6(dst),
+        &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+        &ChecksumCapabilities::default(),
+    );
+    data
+}
+
+/// The addressing state of the interface.
+pub(crate) struct IpConfig {
+    pub(crate) settings: IpSettings,
+    /// set once `settings` have been read out of the PDDB
+    pub(crate) loaded: bool,
+    pub(crate) mac: [u8; 6],
+    /// what the global IPv6 address is derived from; a random one is used until it is read out of
+    /// the PDDB
+    secret: [u8; STABLE_SECRET_LEN],
+    pub(crate) source: Ipv4Source,
+    pub(crate) ipv4: Option<Ipv4Settings>,
+    /// the last settings reported by the EC, kept in case the user switches back to them
+    pub(crate) ec: Option<Ipv4Settings>,
+    pub(crate) slaac: Slaac,
+    /// our DHCP client, present only when the settings call for it
+    dhcp: Option<SocketHandle>,
+    /// raw socket receiving ICMPv6, for router advertisements
+    icmpv6: SocketHandle,
+    /// the DNS servers last announced to the DNS hooks
+    dns_announced: Option<[Option<[u8; 4]>; 2]>,
+}
+impl IpConfig {
+    pub(crate) fn new(mac: [u8; 6], secret: [u8; STABLE_SECRET_LEN], sockets: &mut SocketSet) -> Self {
+        let icmpv6_rx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 2048]);
+        let icmpv6_tx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY], vec![0; 256]);
+        let icmpv6 = sockets.add(raw::Socket::new(
+            IpVersion::Ipv6,
+            IpProtocol::Icmpv6,
+            icmpv6_rx_buffer,
+            icmpv6_tx_buffer,
+        ));
+        IpConfig {
+            settings: IpSettings::default(),
+            loaded: false,
+            mac,
+            secret,
+            source: Ipv4Source::None,
+            ipv4: None,
+            ec: None,
+            slaac: Slaac::default(),
+            dhcp: None,
+            icmpv6,
+            dns_announced: None,
+        }
+    }
+
+    /// Reads the user's settings out of the PDDB, once it is mounted. Returns true if they were
+    /// read by this call.
+    pub(crate) fn load(&mut self, pddb: &pddb::Pddb, poller: &pddb::PddbMountPoller) -> bool {
+        if self.loaded || !poller.is_mounted_nonblocking() {
+            return false;
+        }
+        self.settings = load_settings(pddb);
+        self.secret = load_secret(pddb, &self.secret);
+        // an address formed before the secret was read moves to the stable one
+        if let Some((global, _)) = self.slaac.global.as_mut() {
+            global.addr = slaac_address(&global.addr, &self.mac, &self.secret);
+        }
+        self.loaded = true;
+        log::info!("IP settings: {:?}", self.settings);
+        self.select();
+        true
+    }
+
+    /// Changes the settings, and picks the IPv4 settings to use accordingly.
+    pub(crate) fn set_settings(&mut self, settings: IpSettings) {
+        self.settings = settings;
+        self.loaded = true;
+        self.select();
+    }
+
+    /// Records the settings reported by the EC, and picks the IPv4 settings to use accordingly.
+    pub(crate) fn set_ec(&mut self, ec: Option<Ipv4Settings>) {
+        self.ec = ec;
+        self.select();
+    }
+
+    /// Picks the IPv4 settings to use according to `settings`.
+    fn select(&mut self) {
+        match self.settings.ipv4 {
+            Ipv4Mode::Ec => {
+                self.source = if self.ec.is_some() { Ipv4Source::Ec } else { Ipv4Source::None };
+                self.ipv4 = self.ec;
+            }
+            Ipv4Mode::Static(settings) => {
+                self.source = Ipv4Source::Static;
+                self.ipv4 = Some(settings);
+            }
+            Ipv4Mode::Dhcp => {
+                // keep the lease we have, if any
+                if self.source != Ipv4Source::Dhcp {
+                    self.source = Ipv4Source::None;
+                    self.ipv4 = None;
+                }
+            }
+        }
+        if !self.settings.slaac {
+            self.slaac.global = None;
+            self.slaac.router = None;
+        }
+    }
+
+    /// Forgets everything learnt from the network, keeping static settings.
+    pub(crate) fn reset(&mut self, sockets: &mut SocketSet) {
+        self.ec = None;
+        self.slaac = Slaac::default();
+        if self.source == Ipv4Source::Dhcp {
+            self.source = Ipv4Source::None;
+            self.ipv4 = None;
+        }
+        if let Some(handle) = self.dhcp {
+            sockets.get_mut::<dhcpv4::Socket>(handle).reset();
+        }
+        self.select();
+    }
+
+    /// Called when the link comes up: restarts our DHCP client, and asks routers to advertise
+    /// themselves rather than waiting on their next unsolicited advertisement.
+    pub(crate) fn link_up(&mut self, sockets: &mut SocketSet) {
+        if let Some(handle) = self.dhcp {
+            sockets.get_mut::<dhcpv4::Socket>(handle).reset();
+        }
+        if self.settings.slaac {
+            let socket = sockets.get_mut::<raw::Socket>(self.icmpv6);
+            if let Err(e) = socket.send_slice(&router_solicitation(&self.link_local(), &self.mac)) {
+                log::warn!("couldn't send router solicitation: {:?}", e);
+            }
+        }
+    }
+
+    /// Handles what the sockets received during the last poll of the interface. Returns true if
+    /// the addressing changed.
+    pub(crate) fn poll(&mut self, sockets: &mut SocketSet, now: Instant) -> bool {
+        let mut changed = false;
+        if let Some(handle) = self.dhcp {
+            match sockets.get_mut::<dhcpv4::Socket>(handle).poll() {
+                Some(dhcpv4::Event::Configured(lease)) => {
+                    log::info!("DHCP lease acquired: {} via {:?}", lease.address, lease.router);
+                    self.source = Ipv4Source::Dhcp;
+                    self.ipv4 = Some(Ipv4Settings::from(&lease));
+                    changed = true;
+                }
+                Some(dhcpv4::Event::Deconfigured) => {
+                    log::info!("DHCP lease lost");
+                    self.source = Ipv4Source::None;
+                    self.ipv4 = None;
+                    changed = true;
+                }
+                None => {}
+            }
+        }
+        let socket = sockets.get_mut::<raw::Socket>(self.icmpv6);
+        while let Ok(packet) = socket.recv() {
+            if self.settings.slaac {
+                changed |= self.slaac.process(packet, &self.mac, &self.secret, now);
+            }
+        }
+        changed | self.slaac.expire(now)
+    }
+
+    pub(crate) fn link_local(&self) -> [u8; 16] { link_local_address(&self.mac) }
+
+    /// The address to originate IPv6 traffic from: the global one if we have it.
+    pub(crate) fn ipv6_source(&self) -> IpAddress {
+        match self.slaac.global {
+            Some((global, _)) => IpAddress::Ipv6(Ipv6Address(global.addr)),
+            None => IpAddress::Ipv6(Ipv6Address(self.link_local())),
+        }
+    }
+
+    /// Whether packets should be taken in from the EC. With the EC as the source of IPv4 settings,
+    /// this waits until it has a lease; otherwise our own DHCP client needs the traffic to get one.
+    pub(crate) fn accepts_rx(&self) -> bool { self.ipv4.is_some() || self.settings.ipv4 != Ipv4Mode::Ec }
+
+    /// Brings the interface, our DHCP client and the DNS hooks in line with the current addressing.
+    pub(crate) fn commit(
+        &mut self,
+        iface: &mut Interface,
+        sockets: &mut SocketSet,
+        dns_allclear_hook: &mut XousScalarEndpoint,
+        dns_ipv4_hook: &mut XousScalarEndpoint,
+    ) {
+        match (self.settings.ipv4, self.dhcp) {
+            (Ipv4Mode::Dhcp, None) => self.dhcp = Some(sockets.add(dhcpv4::Socket::new())),
+            (Ipv4Mode::Dhcp, Some(_)) => {}
+            (_, Some(handle)) => {
+                sockets.remove(handle);
+                self.dhcp = None;
+            }
+            (_, None) => {}
+        }
+        self.apply(iface);
+        // update a static variable that tracks this, useful for e.g. UDP bind address checking
+        IPV4_ADDRESS
+            .store(self.ipv4.map(|ipv4| u32::from_be_bytes(ipv4.addr)).unwrap_or(0), Ordering::SeqCst);
+
+        let dns = self.ipv4.map(|ipv4| ipv4.dns);
+        if dns != self.dns_announced {
+            dns_allclear_hook.notify();
+            for server in dns.iter().flatten().flatten() {
+                dns_ipv4_hook.notify_custom_args([Some(u32::from_be_bytes(*server)), None, None, None]);
+            }
+            self.dns_announced = dns;
+        }
+    }
+
+    /// Pushes the current addressing into `iface`.
+    pub(crate) fn apply(&self, iface: &mut Interface) {
+        let ipv4 = self.ipv4.filter(|ipv4| {
+            if ipv4.addr == [127, 0, 0, 1] {
+                log::warn!("Attempt to update the loopback interface! Ignoring.");
+                false
+            } else {
+                true
+            }
+        });
+        // note: ARP cache is stale. Maybe that's ok?
+        iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
+            if let Some(ipv4) = ipv4 {
+                ip_addrs.push(IpCidr::new(IpAddress::Ipv4(Ipv4Address(ipv4.addr)), ipv4.prefix_len)).unwrap();
+            }
+            // ...and the loopback interface
+            ip_addrs.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8)).unwrap();
+            ip_addrs.push(IpCidr::new(IpAddress::Ipv6(Ipv6Address(self.link_local())), 64)).unwrap();
+            if let Some((global, _)) = self.slaac.global {
+                ip_addrs
+                    .push(IpCidr::new(IpAddress::Ipv6(Ipv6Address(global.addr)), global.prefix_len))
+                    .unwrap();
+            }
+        });
+        // reset the default routes, in case they have changed
+        iface.routes_mut().remove_default_ipv4_route();
+        if let Some(gateway) = ipv4.and_then(|ipv4| ipv4.gateway) {
+            iface.routes_mut().add_default_ipv4_route(Ipv4Address(gateway)).unwrap();
+        }
+        iface.routes_mut().remove_default_ipv6_route();
+        if let Some((router, _)) = self.slaac.router {
+            iface.routes_mut().add_default_ipv6_route(router).unwrap();
+        }
+    }
+
+    /// The IPv4 settings in the format of the EC, for `GetIpv4Config`.
+    pub(crate) fn ipv4_conf(&self) -> Option<Ipv4Conf> {
+        self.ipv4.map(|ipv4| Ipv4Conf {
+            dhcp: com_rs::DhcpState::Bound,
+            mac: self.mac,
+            addr: ipv4.addr,
+            gtwy: ipv4.gateway.unwrap_or_default(),
+            mask: (u32::MAX.checked_shl(32 - ipv4.prefix_len as u32).unwrap_or(0)).to_be_bytes(),
+            dns1: ipv4.dns[0].unwrap_or_default(),
+            dns2: ipv4.dns[1].unwrap_or_default(),
+        })
+    }
+
+    pub(crate) fn net_config(&self) -> NetConfig {
+        NetConfig {
+            mac: self.mac,
+            ipv4_source: self.source,
+            ipv4: self.ipv4,
+            ipv6_link_local: Some(self.link_local()),
+            ipv6_global: self.slaac.global.map(|(global, _)| global),
+            ipv6_router: self.slaac.router.map(|(router, _)| router.0),
+        }
+    }
+}
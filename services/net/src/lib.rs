@@ -91,6 +91,138 @@ impl NetManager {
         }
     }
 
+    /// Returns the addresses of the interface, and where they came from.
+    pub fn get_net_config(&self) -> NetConfig {
+        let mut buf = Buffer::into_buf(NetConfig::default()).expect("Couldn't convert to memory structure");
+        buf.lend_mut(self.netconn.conn(), Opcode::GetNetConfig.to_u32().unwrap())
+            .expect("Couldn't execute GetNetConfig opcode");
+        buf.to_original().expect("couldn't restore config structure")
+    }
+
+    /// Lists the open sockets, with the traffic counters of each and of the interface. Only shellchat and
+    /// the status bar see the sockets of every process; anyone else sees just their own.
+    pub fn netstat(&self) -> Netstat {
+        let mut buf = Buffer::into_buf(Netstat::default()).expect("Couldn't convert to memory structure");
+        buf.lend_mut(self.netconn.conn(), Opcode::Netstat.to_u32().unwrap())
+            .expect("Couldn't execute Netstat opcode");
+        buf.to_original().expect("couldn't restore netstat structure")
+    }
+
+    /// Puts process `pid` under the firewall, keeping its rules under `app:<name>`. Only the app loader
+    /// may do this.
+    pub fn sandbox_process(&self, pid: xous::PID, name: &str) -> Result<(), xous::Error> {
+        let request =
+            SandboxRequest { pid: pid.get(), name: xous_ipc::String::from_str(name), sandboxed: false };
+        let mut buf = Buffer::into_buf(request).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.netconn.conn(), Opcode::SandboxProcess.to_u32().unwrap())?;
+        let request = buf.to_original::<SandboxRequest, _>().or(Err(xous::Error::InternalError))?;
+        if request.sandboxed { Ok(()) } else { Err(xous::Error::AccessDenied) }
+    }
+
+    /// The firewall rules of every process that has any, sorted by process.
+    pub fn firewall_rules(&self) -> Vec<(String, Vec<FirewallRule>)> {
+        let mut buf = Buffer::into_buf(FirewallRuleList { text: xous_ipc::String::new() })
+            .expect("Couldn't convert to memory structure");
+        buf.lend_mut(self.netconn.conn(), Opcode::ListFirewallRules.to_u32().unwrap())
+            .expect("Couldn't execute ListFirewallRules opcode");
+        let list = buf.to_original::<FirewallRuleList, _>().expect("couldn't restore rule list");
+        let mut rules: Vec<(String, Vec<FirewallRule>)> = Vec::new();
+        for (process, rule) in list.text.to_str().lines().filter_map(|line| line.split_once('\t')) {
+            let rule = match rule.parse::<FirewallRule>() {
+                Ok(rule) => rule,
+                Err(_) => continue,
+            };
+            match rules.last_mut() {
+                Some((last, process_rules)) if last == process => process_rules.push(rule),
+                _ => rules.push((process.to_string(), vec![rule])),
+            }
+        }
+        rules
+    }
+
+    /// Replaces the firewall rules of `process`, which is named by one of the servers it registers. With
+    /// no rules, the process goes back to unrestricted access, unless it was sandboxed. Only shellchat
+    /// may change the rules.
+    pub fn set_firewall_rules(&self, process: &str, rules: &[FirewallRule]) -> Result<(), xous::Error> {
+        let text: String = rules.iter().map(|rule| format!("{}\n", rule)).collect();
+        let update = FirewallRulesUpdate {
+            process: xous_ipc::String::from_str(process),
+            rules: xous_ipc::String::from_str(&text),
+            allowed: false,
+            saved: false,
+        };
+        // the strings would be cut short otherwise
+        if update.process.len() != process.len() || update.rules.len() != text.len() {
+            return Err(xous::Error::OutOfMemory);
+        }
+        let mut buf = Buffer::into_buf(update).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.netconn.conn(), Opcode::SetFirewallRules.to_u32().unwrap())?;
+        let update = buf.to_original::<FirewallRulesUpdate, _>().or(Err(xous::Error::InternalError))?;
+        if !update.allowed {
+            Err(xous::Error::AccessDenied)
+        } else if !update.saved {
+            Err(xous::Error::InternalError)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn get_ip_settings(&self) -> IpSettings {
+        let mut buf = Buffer::into_buf(IpSettings::default()).expect("Couldn't convert to memory structure");
+        buf.lend_mut(self.netconn.conn(), Opcode::GetIpSettings.to_u32().unwrap())
+            .expect("Couldn't execute GetIpSettings opcode");
+        buf.to_original().expect("couldn't restore settings structure")
+    }
+
+    /// Replaces the IP settings, which take effect at once. Returns `true` if they were also saved to
+    /// the PDDB, so they apply again after a reboot; otherwise they last until then. Only shellchat,
+    /// acting for the user, may change them.
+    pub fn set_ip_settings(&self, settings: IpSettings) -> Result<bool, xous::Error> {
+        if !settings.is_valid() {
+            return Err(xous::Error::BadAddress);
+        }
+        let mut buf = Buffer::into_buf(IpSettingsUpdate { settings, allowed: false, saved: false })
+            .or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.netconn.conn(), Opcode::SetIpSettings.to_u32().unwrap())?;
+        let update = buf.to_original::<IpSettingsUpdate, _>().or(Err(xous::Error::InternalError))?;
+        if !update.allowed {
+            return Err(xous::Error::AccessDenied);
+        }
+        Ok(update.saved)
+    }
+
+    /// Joins the interface to an IPv4 multicast group, so UDP sockets bound to the right port receive
+    /// the traffic sent to it. Each successful join should be paired with `leave_multicast_group()`.
+    pub fn join_multicast_group(&self, group: std::net::Ipv4Addr) -> Result<(), xous::Error> {
+        match send_message(
+            self.netconn.conn(),
+            Message::new_blocking_scalar(
+                Opcode::JoinMulticastGroup.to_usize().unwrap(),
+                u32::from(group) as usize,
+                0,
+                0,
+                0,
+            ),
+        )? {
+            xous::Result::Scalar1(0) => Ok(()),
+            _ => Err(xous::Error::OutOfMemory),
+        }
+    }
+
+    pub fn leave_multicast_group(&self, group: std::net::Ipv4Addr) -> Result<(), xous::Error> {
+        send_message(
+            self.netconn.conn(),
+            Message::new_blocking_scalar(
+                Opcode::LeaveMulticastGroup.to_usize().unwrap(),
+                u32::from(group) as usize,
+                0,
+                0,
+                0,
+            ),
+        )
+        .map(|_| ())
+    }
+
     pub fn reset(&self) {
         send_message(
             self.netconn.conn(),
@@ -16,6 +16,10 @@ use std_tcplistener::*;
 
 mod connection_manager;
 mod device;
+mod firewall;
+mod ipconfig;
+use ipconfig::IpConfig;
+mod netstat;
 
 #[cfg(test)]
 mod tests;
@@ -26,7 +30,7 @@ use std::cmp::Ordering as CmpOrdering;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::sync::mpsc::{channel, Sender};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use byteorder::{ByteOrder, NetworkEndian};
@@ -35,7 +39,7 @@ use smoltcp::iface::{Config, Interface, SocketSet};
 use smoltcp::phy::{Device, Tracer};
 use smoltcp::socket::{icmp, tcp, udp};
 use smoltcp::time::{Duration, Instant};
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpEndpoint, Ipv4Address};
 use smoltcp::wire::{Icmpv4Packet, Icmpv4Repr, Icmpv6Packet, Icmpv6Repr};
 use xous::{msg_blocking_scalar_unpack, msg_scalar_unpack, try_send_message, Message, CID, SID};
 use xous_ipc::Buffer;
@@ -142,6 +146,9 @@ fn main() -> ! {
     let net_sid = xns.register_name(api::SERVER_NAME_NET, None).expect("can't register server");
     let net_conn = xous::connect(net_sid).unwrap();
     log::trace!("registered with NS -- {:?}", net_sid);
+    // the firewall forgets about processes once they exit
+    xous::set_peer_exit_notification(net_sid, Opcode::PeerExited.to_usize().unwrap())
+        .expect("couldn't register for peer exit notifications");
 
     // bring the EC into a sane state for the network -- that is, reset the EC
     let mut llio = llio::Llio::new(&xns);
@@ -164,7 +171,6 @@ fn main() -> ! {
     com_int_list.clear();
     com.ints_get_active(&mut com_int_list).ok();
     log::debug!("COM pending interrupts after enabling: {:?}", com_int_list);
-    let mut net_config: Option<Ipv4Conf> = None;
 
     // ----------- build the device
     let mut config_valid = true;
@@ -194,6 +200,7 @@ fn main() -> ! {
     MAC_ADDRESS_LSB.store(u32::from_be_bytes(hw_config.mac[2..6].try_into().unwrap()), Ordering::SeqCst);
     MAC_ADDRESS_MSB.store(u16::from_be_bytes(hw_config.mac[0..2].try_into().unwrap()), Ordering::SeqCst);
     let mac_unchecked: HardwareAddress = EthernetAddress(hw_config.mac).into();
+    let mut mac = hw_config.mac;
     let mut config = if !mac_unchecked.is_unicast() {
         Config::new(mac_unchecked)
     } else {
@@ -207,11 +214,14 @@ fn main() -> ! {
             "We had a bogus MAC address from the EC, filling in a temporary fake one to avoid panics: {:x?}",
             fake_mac
         );
+        mac = fake_mac;
         Config::new(EthernetAddress(fake_mac).into())
     };
     config.random_seed = trng.get_u64().unwrap();
 
-    let device = device::NetPhy::new(&xns, net_cid);
+    // traffic counters for netstat, shared with the device that does the counting
+    let traffic = Arc::new(Mutex::new(netstat::Traffic::default()));
+    let device = device::NetPhy::new(&xns, net_cid, traffic.clone());
     let mut device = Tracer::new(device, |_timestamp, _printer| {
         log::trace!("{}", _printer);
     });
@@ -231,6 +241,19 @@ fn main() -> ! {
         icmp_socket.bind(icmp::Endpoint::Ident(PING_IDENT)).expect("couldn't bind to icmp socket");
     }
 
+    // the addressing of the interface. The user's settings are read out of the PDDB once it mounts;
+    // until then, the IPv6 link-local address is all we have.
+    let mut stable_secret = [0u8; ipconfig::STABLE_SECRET_LEN];
+    trng.fill_bytes_via_next(&mut stable_secret);
+    let mut ip_config = IpConfig::new(mac, stable_secret, &mut sockets);
+    ip_config.apply(&mut iface);
+    let pddb = pddb::Pddb::new();
+    let pddb_poller = pddb::PddbMountPoller::new();
+    // multicast groups joined on behalf of UDP sockets, with the number of outstanding joins
+    let mut multicast_groups = HashMap::<[u8; 4], usize>::new();
+
+    let mut firewall = firewall::Firewall::new(net_conn);
+
     // ------------- libstd variant -----------
     // Each process keeps track of its own sockets. These are kept in a Vec. When a handle
     // is destroyed, it is turned into a `None`.
@@ -314,6 +337,8 @@ fn main() -> ! {
     let mut cid_to_disconnect: Option<CID> = None;
 
     let (core_tx, core_rx) = channel();
+    // requests held by the firewall go back on the queue once they're decided
+    let requeue_tx = core_tx.clone();
     thread::spawn({
         let parent_conn = net_conn.clone();
         move || {
@@ -353,6 +378,19 @@ fn main() -> ! {
                             &mut device,
                             Instant::from_millis(timer.elapsed_ms() as i64),
                         );
+                        // anything learnt from router advertisements was for the old MAC address
+                        ip_config.mac = hw_config.mac;
+                        ip_config.slaac = Default::default();
+                        ip_config.apply(&mut iface);
+                        for group in multicast_groups.keys() {
+                            iface
+                                .join_multicast_group(
+                                    &mut device,
+                                    Ipv4Address(*group),
+                                    Instant::from_millis(timer.elapsed_ms() as i64),
+                                )
+                                .ok();
+                        }
                         config_valid = true;
                     } else {
                         // else, config_valid stays false, and we try again next time around
@@ -501,9 +539,7 @@ fn main() -> ! {
                             icmp_repr.emit(&mut icmp_packet, &device_caps.checksum);
                         }
                         IpAddress::Ipv6(_) => {
-                            // not sure if this is a valid thing to do, to just assign the source some number
-                            // like this??
-                            let src_ipv6 = IpAddress::v6(0xfdaa, 0, 0, 0, 0, 0, 0, 1);
+                            let src_ipv6 = ip_config.ipv6_source();
                             let icmp_repr = Icmpv6Repr::EchoRequest {
                                 ident: PING_IDENT,
                                 seq_no: seq,
@@ -600,7 +636,54 @@ fn main() -> ! {
                 xous::return_scalar(msg.sender, 1).expect("couldn't ack unhook");
             }),
 
+            Some(Opcode::JoinMulticastGroup) => msg_blocking_scalar_unpack!(msg, group, _, _, _, {
+                let group = (group as u32).to_be_bytes();
+                let timestamp = Instant::from_millis(timer.elapsed_ms() as i64);
+                let count = multicast_groups.entry(group).or_insert(0);
+                let result = if *count == 0 {
+                    iface.join_multicast_group(&mut device, Ipv4Address(group), timestamp)
+                } else {
+                    Ok(false)
+                };
+                match result {
+                    Ok(_) => {
+                        *count += 1;
+                        xous::return_scalar(msg.sender, 0).unwrap();
+                    }
+                    Err(e) => {
+                        log::warn!("couldn't join multicast group {:?}: {:?}", group, e);
+                        if *count == 0 {
+                            multicast_groups.remove(&group);
+                        }
+                        xous::return_scalar(msg.sender, 1).unwrap();
+                    }
+                }
+            }),
+            Some(Opcode::LeaveMulticastGroup) => msg_blocking_scalar_unpack!(msg, group, _, _, _, {
+                let group = (group as u32).to_be_bytes();
+                match multicast_groups.get_mut(&group) {
+                    Some(count) if *count > 1 => *count -= 1,
+                    Some(_) => {
+                        multicast_groups.remove(&group);
+                        let timestamp = Instant::from_millis(timer.elapsed_ms() as i64);
+                        iface.leave_multicast_group(&mut device, Ipv4Address(group), timestamp).ok();
+                    }
+                    None => log::warn!("not a member of multicast group {:?}", group),
+                }
+                xous::return_scalar(msg.sender, 0).unwrap();
+            }),
+
             Some(Opcode::StdTcpConnect) => {
+                let msg = match firewall.check(
+                    msg,
+                    FirewallAccess::Connect,
+                    &pddb,
+                    &pddb_poller,
+                    timer.elapsed_ms(),
+                ) {
+                    Some(msg) => msg,
+                    None => continue,
+                };
                 // Pick a random local port using the system's TRNG
                 let local_port = (trng.get_u32().unwrap() % 16384 + 49152) as u16;
                 let pid = msg.sender.pid();
@@ -826,6 +909,16 @@ fn main() -> ! {
             }
 
             Some(Opcode::StdTcpListen) => {
+                let msg = match firewall.check(
+                    msg,
+                    FirewallAccess::Listen,
+                    &pddb,
+                    &pddb_poller,
+                    timer.elapsed_ms(),
+                ) {
+                    Some(msg) => msg,
+                    None => continue,
+                };
                 let pid = msg.sender.pid();
 
                 std_tcp_listen(msg, &mut iface, &mut sockets, process_sockets.entry(pid).or_default(), &trng);
@@ -988,6 +1081,11 @@ fn main() -> ! {
 
             Some(Opcode::StdUdpBind) => {
                 log::debug!("StdUdpBind");
+                let msg =
+                    match firewall.check(msg, FirewallAccess::Udp, &pddb, &pddb_poller, timer.elapsed_ms()) {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
                 let pid = msg.sender.pid();
                 std_udp_bind(msg, &mut iface, &mut sockets, process_sockets.entry(pid).or_default());
             }
@@ -1007,6 +1105,16 @@ fn main() -> ! {
 
             Some(Opcode::StdUdpTx) => {
                 log::debug!("StdUdpTx");
+                let msg = match firewall.check(
+                    msg,
+                    FirewallAccess::UdpSend,
+                    &pddb,
+                    &pddb_poller,
+                    timer.elapsed_ms(),
+                ) {
+                    Some(msg) => msg,
+                    None => continue,
+                };
                 let pid = msg.sender.pid();
                 std_udp_tx(msg, &mut iface, &mut sockets, process_sockets.entry(pid).or_default());
                 xous::try_send_message(
@@ -1081,10 +1189,8 @@ fn main() -> ! {
                                     log::warn!("Battery is critical! TODO: go into SHIP mode");
                                 }
                                 ComIntSources::WlanIpConfigUpdate => {
-                                    // right now the WLAN implementation only does IPV4. So IPV6 compatibility
-                                    // ends here. if IPV6 gets added to
-                                    // the EC/COM bus, ideally this is one of a couple spots in Xous that
-                                    // needs a tweak.
+                                    // the EC only does IPv4, and its settings are only used if the user's
+                                    // settings call for them. IPv6 addressing is handled by `ip_config`.
                                     let config = match com.wlan_get_config() {
                                         Ok(config) => config,
                                         Err(e) => {
@@ -1102,67 +1208,29 @@ fn main() -> ! {
                                         std::net::IpAddr::from(config.addr),
                                         xous::BOOKEND_END
                                     );
-                                    net_config = Some(config);
-                                    // update a static variable that tracks this, useful for e.g. UDP bind
-                                    // address checking
-                                    IPV4_ADDRESS.store(u32::from_be_bytes(config.addr), Ordering::SeqCst);
-
-                                    if config.addr != [127, 0, 0, 1] {
-                                        // note: ARP cache is stale. Maybe that's ok?
-                                        iface.update_ip_addrs(|ip_addrs| {
-                                            ip_addrs.clear();
-                                            ip_addrs
-                                                .push(IpCidr::new(
-                                                    IpAddress::v4(
-                                                        config.addr[0],
-                                                        config.addr[1],
-                                                        config.addr[2],
-                                                        config.addr[3],
-                                                    ),
-                                                    24,
-                                                ))
-                                                .unwrap();
-                                            // ...and the loopback interface
-                                            ip_addrs
-                                                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
-                                                .unwrap();
-                                        });
-                                    } else {
-                                        log::warn!("Attempt to update the loopback interface! Ignoring.");
-                                    }
-                                    // reset the default route, in case it has changed
-                                    iface.routes_mut().remove_default_ipv4_route();
-                                    iface
-                                        .routes_mut()
-                                        .add_default_ipv4_route(Ipv4Address::new(
-                                            config.gtwy[0],
-                                            config.gtwy[1],
-                                            config.gtwy[2],
-                                            config.gtwy[3],
-                                        ))
-                                        .unwrap();
-
-                                    dns_allclear_hook.notify();
-                                    dns_ipv4_hook.notify_custom_args([
-                                        Some(u32::from_be_bytes(config.dns1)),
-                                        None,
-                                        None,
-                                        None,
-                                    ]);
-                                    // the current implementation always returns 0.0.0.0 as the second dns,
-                                    // ignore this if that's what we've got; otherwise, pass it on.
-                                    if config.dns2 != [0, 0, 0, 0] {
-                                        dns_ipv4_hook.notify_custom_args([
-                                            Some(u32::from_be_bytes(config.dns2)),
-                                            None,
-                                            None,
-                                            None,
-                                        ]);
+                                    ip_config.load(&pddb, &pddb_poller);
+                                    ip_config.set_ec(Some(Ipv4Settings::from(&config)));
+                                    ip_config.commit(
+                                        &mut iface,
+                                        &mut sockets,
+                                        &mut dns_allclear_hook,
+                                        &mut dns_ipv4_hook,
+                                    );
+                                }
+                                ComIntSources::Connect => {
+                                    if ip_config.load(&pddb, &pddb_poller) {
+                                        ip_config.commit(
+                                            &mut iface,
+                                            &mut sockets,
+                                            &mut dns_allclear_hook,
+                                            &mut dns_ipv4_hook,
+                                        );
                                     }
+                                    ip_config.link_up(&mut sockets);
                                 }
                                 ComIntSources::WlanRxReady => {
                                     activity_interval.store(0, Ordering::Relaxed); // reset the activity interval to 0
-                                    if let Some(_config) = net_config {
+                                    if ip_config.accepts_rx() {
                                         if let Some(rxlen) = maybe_rxlen {
                                             match device.get_mut().push_rx_avail(rxlen) {
                                                 None => {} /* log::info!("pushed {} bytes avail to iface", */
@@ -1261,7 +1329,15 @@ fn main() -> ! {
                 log::trace!("NetPump");
                 let now = timer.elapsed_ms();
                 let timestamp = Instant::from_millis(now as i64);
-                if !iface.poll(timestamp, &mut device, &mut sockets) {
+                // pick up the endpoints of new sockets, so their first packets are counted
+                let flows = sockets.iter().filter_map(|(_, socket)| netstat::socket_flow(socket));
+                traffic.lock().unwrap().sync(flows);
+                let readiness_changed = iface.poll(timestamp, &mut device, &mut sockets);
+                // leases and router advertisements can also come and go with the passage of time
+                if ip_config.poll(&mut sockets, timestamp) {
+                    ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
+                }
+                if !readiness_changed {
                     // nothing to do, continue on.
                     log::debug!("No change to socket readiness");
                     continue;
@@ -1749,9 +1825,8 @@ fn main() -> ! {
                                 }
 
                                 IpAddress::Ipv6(_) => {
-                                    // NOTE: actually not sure what src_ipv6 should be. This is just from an
-                                    // example.
-                                    let src_ipv6 = IpAddress::v6(0xfdaa, 0, 0, 0, 0, 0, 0, 1);
+                                    // the reply is addressed to whichever address we sent the request from
+                                    let src_ipv6 = ip_config.ipv6_source();
                                     let icmp_packet = Icmpv6Packet::new_checked(&payload).unwrap();
                                     let icmp_repr = Icmpv6Repr::parse(
                                         &remote_addr,
@@ -1878,9 +1953,141 @@ fn main() -> ! {
             Some(Opcode::GetIpv4Config) => {
                 let mut buffer =
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
-                let ser = if let Some(config) = net_config { Some(config.encode_u16()) } else { None };
+                let ser =
+                    if let Some(config) = ip_config.ipv4_conf() { Some(config.encode_u16()) } else { None };
                 buffer.replace(ser).expect("couldn't return config");
             }
+            Some(Opcode::GetNetConfig) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                buffer.replace(ip_config.net_config()).expect("couldn't return config");
+            }
+            Some(Opcode::Netstat) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut owners = HashMap::new();
+                for (pid, handles) in process_sockets.iter() {
+                    for handle in handles.iter().flatten() {
+                        owners.insert(*handle, pid.map(|pid| pid.get()));
+                    }
+                }
+                // the endpoints of other processes are only shown to the user
+                let sender = msg.sender.pid();
+                let shows_all = firewall.shows_all(sender);
+                let traffic = traffic.lock().unwrap();
+                let mut stats = Netstat::default();
+                stats.iface = traffic.iface;
+                let infos = sockets
+                    .iter()
+                    .filter(|(handle, _)| {
+                        shows_all || owners.get(handle).copied().flatten() == sender.map(|pid| pid.get())
+                    })
+                    .filter_map(|(handle, socket)| {
+                        netstat::socket_info(socket, owners.get(&handle).copied().flatten(), &traffic)
+                    });
+                for info in infos {
+                    if let Some(slot) = stats.sockets.get_mut(stats.socket_count as usize) {
+                        *slot = Some(info);
+                    }
+                    stats.socket_count += 1;
+                }
+                buffer.replace(stats).expect("couldn't return netstat");
+            }
+            Some(Opcode::SandboxProcess) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut request = buffer.to_original::<SandboxRequest, _>().unwrap();
+                request.sandboxed = match xous::PID::new(request.pid) {
+                    Some(pid) => firewall.sandbox(msg.sender.pid(), pid, request.name.to_str()),
+                    None => false,
+                };
+                buffer.replace(request).expect("couldn't return result");
+            }
+            Some(Opcode::SetFirewallRules) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut update = buffer.to_original::<FirewallRulesUpdate, _>().unwrap();
+                firewall.update(msg.sender.pid(), &mut update, &pddb, &pddb_poller);
+                buffer.replace(update).expect("couldn't return result");
+            }
+            Some(Opcode::ListFirewallRules) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                use std::fmt::Write;
+                let mut list = FirewallRuleList { text: xous_ipc::String::new() };
+                'list: for (process, rules) in firewall.list(&pddb, &pddb_poller) {
+                    for rule in rules.iter() {
+                        // the list is cut short if it doesn't fit
+                        if write!(list.text, "{}\t{}\n", process, rule).is_err() {
+                            break 'list;
+                        }
+                    }
+                }
+                buffer.replace(list).expect("couldn't return rule list");
+            }
+            Some(Opcode::FirewallDecision) => msg_scalar_unpack!(msg, id, choice, _, _, {
+                // only our own prompt threads get to answer
+                if msg.sender.pid().map(|pid| pid.get() as u32) != Some(xous::process::id()) {
+                    log::warn!("ignoring firewall decision from {:?}", msg.sender.pid());
+                    continue;
+                }
+                firewall.decide(id, choice, &pddb, &pddb_poller, &requeue_tx, timer.elapsed_ms());
+            }),
+            Some(Opcode::FirewallResolved) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                match buffer.to_original::<ResolvedHost, _>() {
+                    Ok(host) => firewall.resolved(msg.sender.pid(), &host, timer.elapsed_ms()),
+                    Err(e) => log::warn!("bad lookup results: {:?}", e),
+                }
+            }
+            Some(Opcode::PeerExited) => msg_scalar_unpack!(msg, pid, _, _, _, {
+                if let Some(pid) = xous::PID::new(pid as u8) {
+                    // anyone can send this, so check with the kernel that the process really is gone
+                    if xous::process_info(pid) == Err(xous::Error::ProcessNotFound) {
+                        firewall.forget(pid);
+                    }
+                }
+            }),
+            Some(Opcode::GetIpSettings) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                ip_config.load(&pddb, &pddb_poller);
+                buffer.replace(ip_config.settings).expect("couldn't return settings");
+            }
+            Some(Opcode::SetIpSettings) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut update = buffer.to_original::<IpSettingsUpdate, _>().unwrap();
+                update.allowed = firewall.acts_for_user(msg.sender.pid());
+                if !update.allowed {
+                    log::warn!("{:?} may not change IP settings", msg.sender.pid());
+                    update.saved = false;
+                    buffer.replace(update).expect("couldn't return result");
+                    continue;
+                }
+                if !update.settings.is_valid() {
+                    log::warn!("Ignoring invalid IP settings: {:?}", update.settings);
+                    update.saved = false;
+                    buffer.replace(update).expect("couldn't return result");
+                    continue;
+                }
+                update.saved = if pddb_poller.is_mounted_nonblocking() {
+                    match ipconfig::store_settings(&pddb, &update.settings) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            log::warn!("Couldn't save IP settings: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    false
+                };
+                log::info!("IP settings changed: {:?}", update.settings);
+                ip_config.set_settings(update.settings);
+                ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
+                ip_config.link_up(&mut sockets);
+                buffer.replace(update).expect("couldn't return result");
+            }
             Some(Opcode::SubscribeWifiStats) => {
                 msg.forward(cm_cid, connection_manager::ConnectionManagerOpcode::SubscribeWifiStats as _)
                     .expect("couldn't forward subscription request");
@@ -1925,8 +2132,6 @@ fn main() -> ! {
                 };
             }),
             Some(Opcode::Reset) => {
-                // reset the DHCP address
-                IPV4_ADDRESS.store(0, Ordering::SeqCst);
                 // ack any pending ints
                 com_int_list.clear();
                 com.ints_get_active(&mut com_int_list).ok();
@@ -1936,9 +2141,9 @@ fn main() -> ! {
                 com.ints_enable(&com_int_list);
                 com_int_list.clear();
 
-                // note: ARP cache isn't reset
-                iface.routes_mut().remove_default_ipv4_route();
-                dns_allclear_hook.notify();
+                // forget the DHCP address and router advertisements. note: ARP cache isn't reset
+                ip_config.reset(&mut sockets);
+                ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
 
                 match try_send_message(
                     cm_cid,
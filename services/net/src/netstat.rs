@@ -0,0 +1,162 @@
+//! Traffic accounting for `Netstat`.
+//!
+//! Frames are counted by the device as they go past, and attributed to sockets by the addresses and
+//! ports in their transport headers. Only the flows of open sockets are tracked, so that traffic to
+//! ports nobody listens on can't grow the table; the set of flows is brought up to date from the
+//! `SocketSet` before each poll of the interface.
+
+use std::collections::HashMap;
+
+use smoltcp::socket::{tcp, Socket};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpAddress, IpEndpoint, IpProtocol, Ipv4Packet, Ipv6Packet, TcpPacket,
+    UdpPacket,
+};
+
+use crate::api::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Flow {
+    /// TCP is counted per connection
+    Tcp { local_port: u16, remote: IpEndpoint },
+    /// UDP is counted per local port, whoever is at the other end
+    Udp { local_port: u16 },
+}
+
+/// The flow a socket's traffic is counted under, if it has one yet.
+pub(crate) fn socket_flow(socket: &Socket) -> Option<Flow> {
+    match socket {
+        Socket::Tcp(tcp) => match (tcp.local_endpoint(), tcp.remote_endpoint()) {
+            (Some(local), Some(remote)) => Some(Flow::Tcp { local_port: local.port, remote }),
+            _ => None,
+        },
+        Socket::Udp(udp) if udp.is_open() => Some(Flow::Udp { local_port: udp.endpoint().port }),
+        _ => None,
+    }
+}
+
+/// Describes a socket for `Netstat`. Sockets other than TCP and UDP aren't listed.
+pub(crate) fn socket_info(socket: &Socket, pid: Option<u8>, traffic: &Traffic) -> Option<SocketInfo> {
+    let traffic = socket_flow(socket).map(|flow| traffic.flow(&flow)).unwrap_or_default();
+    match socket {
+        Socket::Tcp(tcp) => {
+            let local = tcp.local_endpoint();
+            let remote = tcp.remote_endpoint();
+            Some(SocketInfo {
+                pid,
+                state: SocketState::Tcp(tcp.state().into()),
+                local_addr: local.map(|ep| NetIpAddr::from(ep.addr)),
+                local_port: local.map(|ep| ep.port).unwrap_or_else(|| listen_port(tcp)),
+                remote_addr: remote.map(|ep| NetIpAddr::from(ep.addr)),
+                remote_port: remote.map(|ep| ep.port).unwrap_or(0),
+                traffic,
+            })
+        }
+        Socket::Udp(udp) => {
+            let endpoint = udp.endpoint();
+            Some(SocketInfo {
+                pid,
+                state: SocketState::Udp,
+                local_addr: endpoint.addr.map(NetIpAddr::from),
+                local_port: endpoint.port,
+                remote_addr: None,
+                remote_port: 0,
+                traffic,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A listening socket has no local endpoint until a connection comes in, only the port it listens on.
+fn listen_port(tcp: &tcp::Socket) -> u16 { tcp.listen_endpoint().port }
+
+#[derive(Default)]
+pub(crate) struct Traffic {
+    pub(crate) iface: InterfaceStats,
+    flows: HashMap<Flow, TrafficCounters>,
+}
+impl Traffic {
+    /// Tracks the given flows from now on, and forgets the counters of any others.
+    pub fn sync(&mut self, flows: impl Iterator<Item = Flow>) {
+        let mut synced = HashMap::new();
+        for flow in flows {
+            let counters = self.flows.get(&flow).copied().unwrap_or_default();
+            synced.insert(flow, counters);
+        }
+        self.flows = synced;
+    }
+
+    pub fn flow(&self, flow: &Flow) -> TrafficCounters { self.flows.get(flow).copied().unwrap_or_default() }
+
+    pub fn count_rx(&mut self, frame: &[u8]) {
+        let bytes = frame.len() as u64;
+        self.iface.traffic.rx_packets += 1;
+        self.iface.traffic.rx_bytes += bytes;
+        if EthernetFrame::new_checked(frame).is_err() {
+            self.iface.rx_errors += 1;
+            return;
+        }
+        if let Some(counters) = frame_flow(frame, false).and_then(|flow| self.flows.get_mut(&flow)) {
+            counters.rx_packets += 1;
+            counters.rx_bytes += bytes;
+        }
+    }
+
+    pub fn count_tx(&mut self, frame: &[u8]) {
+        let bytes = frame.len() as u64;
+        self.iface.traffic.tx_packets += 1;
+        self.iface.traffic.tx_bytes += bytes;
+        if let Some(counters) = frame_flow(frame, true).and_then(|flow| self.flows.get_mut(&flow)) {
+            counters.tx_packets += 1;
+            counters.tx_bytes += bytes;
+        }
+    }
+}
+
+/// Works out the flow of a TCP or UDP frame. The local end of an `outbound` frame is its source, and
+/// of an inbound one its destination. IPv6 extension headers aren't followed.
+fn frame_flow(frame: &[u8], outbound: bool) -> Option<Flow> {
+    let frame = EthernetFrame::new_checked(frame).ok()?;
+    let (protocol, src, dst, payload) = match frame.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+            (
+                packet.next_header(),
+                IpAddress::Ipv4(packet.src_addr()),
+                IpAddress::Ipv4(packet.dst_addr()),
+                packet.payload(),
+            )
+        }
+        EthernetProtocol::Ipv6 => {
+            let packet = Ipv6Packet::new_checked(frame.payload()).ok()?;
+            (
+                packet.next_header(),
+                IpAddress::Ipv6(packet.src_addr()),
+                IpAddress::Ipv6(packet.dst_addr()),
+                packet.payload(),
+            )
+        }
+        _ => return None,
+    };
+    let (src_port, dst_port) = match protocol {
+        IpProtocol::Tcp => {
+            let segment = TcpPacket::new_checked(payload).ok()?;
+            (segment.src_port(), segment.dst_port())
+        }
+        IpProtocol::Udp => {
+            let datagram = UdpPacket::new_checked(payload).ok()?;
+            (datagram.src_port(), datagram.dst_port())
+        }
+        _ => return None,
+    };
+    let (local_port, remote) = if outbound {
+        (src_port, IpEndpoint::new(dst, dst_port))
+    } else {
+        (dst_port, IpEndpoint::new(src, src_port))
+    };
+    match protocol {
+        IpProtocol::Tcp => Some(Flow::Tcp { local_port, remote }),
+        _ => Some(Flow::Udp { local_port }),
+    }
+}
@@ -172,6 +172,20 @@ impl DnsServerManager {
     pub fn get_random(&self) -> Option<IpAddr> {
         if let Some(&addr) = self.servers.lock().unwrap().iter().next() { Some(addr) } else { None }
     }
+
+    /// Tells the firewall which addresses `name` was resolved to, so that its rules can name hosts.
+    pub fn report_lookup(&self, name: &str, addrs: &[IpAddr], ttl: u32) {
+        let mut host = ResolvedHost { name: xous_ipc::String::from_str(name), addrs: [None; 8], ttl };
+        for (slot, addr) in host.addrs.iter_mut().zip(addrs.iter()) {
+            *slot = Some(NetIpAddr::from(*addr));
+        }
+        match Buffer::into_buf(host) {
+            Ok(buf) => {
+                buf.send(self.net.conn(), Opcode::FirewallResolved.to_u32().unwrap()).ok();
+            }
+            Err(e) => log::warn!("couldn't report lookup of {}: {:?}", name, e),
+        }
+    }
 }
 
 impl Drop for DnsServerManager {
@@ -20,4 +20,7 @@ impl DnsServerManager {
 
     /// Always returns 1.1.1.1
     pub fn get_random(&self) -> Option<IpAddr> { Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))) }
+
+    /// Fake function: there's no firewall in hosted mode
+    pub fn report_lookup(&self, _name: &str, _addrs: &[IpAddr], _ttl: u32) {}
 }
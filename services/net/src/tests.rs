@@ -818,3 +818,520 @@ fn connect_timeout_valid() {
     let addr = listener.local_addr().unwrap();
     TcpStream::connect_timeout(&addr, Duration::from_secs(2)).unwrap();
 }
+
+const TEST_MAC: [u8; 6] = [0x02, 0x12, 0x34, 0x56, 0x78, 0x9a];
+const TEST_SECRET: [u8; crate::ipconfig::STABLE_SECRET_LEN] = [0x5a; crate::ipconfig::STABLE_SECRET_LEN];
+
+/// Builds a router advertisement from fe80::1, as it would come out of a raw ICMPv6 socket.
+fn router_advert(router_lifetime_secs: u64, prefix_lifetime_secs: u32) -> Vec<u8> {
+    use smoltcp::phy::ChecksumCapabilities;
+    use smoltcp::time::Duration;
+    use smoltcp::wire::*;
+
+    let src = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    let dst = Ipv6Address::LINK_LOCAL_ALL_NODES;
+    let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+        hop_limit: 64,
+        flags: NdiscRouterFlags::empty(),
+        router_lifetime: Duration::from_secs(router_lifetime_secs),
+        reachable_time: Duration::ZERO,
+        retrans_time: Duration::ZERO,
+        lladdr: None,
+        mtu: None,
+        prefix_info: Some(NdiscPrefixInformation {
+            prefix_len: 64,
+            flags: NdiscPrefixInfoFlags::ADDRCONF | NdiscPrefixInfoFlags::ON_LINK,
+            valid_lifetime: Duration::from_secs(prefix_lifetime_secs as u64),
+            preferred_lifetime: Duration::from_secs(prefix_lifetime_secs as u64),
+            prefix: Ipv6Address::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 0),
+        }),
+    });
+    let ip_repr = Ipv6Repr {
+        src_addr: src,
+        dst_addr: dst,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 255,
+    };
+    let mut data = vec![0u8; ip_repr.buffer_len() + icmp_repr.buffer_len()];
+    let mut packet = Ipv6Packet::new_unchecked(&mut data[..]);
+    ip_repr.emit(&mut packet);
+    icmp_repr.emit(
+        &IpAddress::Ipv6(src),
+        &IpAddress::Ipv6(dst),
+        &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+        &ChecksumCapabilities::default(),
+    );
+    data
+}
+
+#[test]
+fn ipv6_link_local_from_mac() {
+    assert_eq!(
+        Ipv6Addr::from(crate::ipconfig::link_local_address(&TEST_MAC)),
+        "fe80::12:34ff:fe56:789a".parse::<Ipv6Addr>().unwrap()
+    );
+}
+
+#[test]
+fn slaac_from_router_advert() {
+    use smoltcp::time::{Duration, Instant};
+
+    let mut slaac = crate::ipconfig::Slaac::default();
+    let now = Instant::from_secs(10);
+    assert!(slaac.process(&router_advert(1800, 3600), &TEST_MAC, &TEST_SECRET, now));
+    let (global, _) = slaac.global.unwrap();
+    // the address is under the advertised prefix, but doesn't give the MAC address away
+    assert_eq!(Ipv6Addr::from(global.addr).segments()[..4], [0x2001, 0xdb8, 0, 1]);
+    assert_ne!(Ipv6Addr::from(global.addr), "2001:db8:0:1:12:34ff:fe56:789a".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(global.addr, crate::ipconfig::slaac_address(&global.addr, &TEST_MAC, &TEST_SECRET));
+    assert_eq!(global.prefix_len, 64);
+    assert_eq!(Ipv6Addr::from(slaac.router.unwrap().0.0), "fe80::1".parse::<Ipv6Addr>().unwrap());
+    // a repeated advertisement only refreshes the lifetimes
+    assert!(!slaac.process(&router_advert(1800, 3600), &TEST_MAC, &TEST_SECRET, now));
+    // the router outlives its lifetime before the address does
+    assert!(slaac.expire(now + Duration::from_secs(1800)));
+    assert!(slaac.router.is_none() && slaac.global.is_some());
+    assert!(slaac.expire(now + Duration::from_secs(3600)));
+    assert!(slaac.global.is_none());
+    // a forwarded advertisement is ignored
+    let mut forwarded = router_advert(1800, 3600);
+    forwarded[7] = 254;
+    assert!(!slaac.process(&forwarded, &TEST_MAC, &TEST_SECRET, now));
+}
+
+#[test]
+fn stable_privacy_addresses() {
+    use crate::ipconfig::stable_interface_id;
+
+    let home: [u8; 16] = "2001:db8:0:1::".parse::<Ipv6Addr>().unwrap().octets();
+    let away: [u8; 16] = "2001:db8:0:2::".parse::<Ipv6Addr>().unwrap().octets();
+    let iid = stable_interface_id(&home, &TEST_MAC, &TEST_SECRET);
+    // the same on every visit to a network...
+    assert_eq!(stable_interface_id(&home, &TEST_MAC, &TEST_SECRET), iid);
+    // ...but different from one network to the next, and from one device to the next
+    assert_ne!(stable_interface_id(&away, &TEST_MAC, &TEST_SECRET), iid);
+    assert_ne!(stable_interface_id(&home, &TEST_MAC, &[0xa5; 32]), iid);
+    // only the prefix of the address it is given counts
+    let mut host = home;
+    host[15] = 1;
+    assert_eq!(stable_interface_id(&host, &TEST_MAC, &TEST_SECRET), iid);
+}
+
+#[test]
+fn ip_settings_round_trip() {
+    use crate::api::{IpSettings, Ipv4Mode, Ipv4Settings};
+
+    let static_settings = Ipv4Settings {
+        addr: [192, 168, 1, 20],
+        prefix_len: 24,
+        gateway: Some([192, 168, 1, 1]),
+        dns: [Some([9, 9, 9, 9]), None],
+    };
+    for settings in [
+        IpSettings::default(),
+        IpSettings { ipv4: Ipv4Mode::Dhcp, slaac: false },
+        IpSettings { ipv4: Ipv4Mode::Static(static_settings), slaac: true },
+    ]
+    .iter()
+    {
+        assert_eq!(IpSettings::from_bytes(&settings.to_bytes()), Some(*settings));
+    }
+    // versions we don't know and invalid settings are rejected
+    let mut data = IpSettings::default().to_bytes();
+    data[0] = 0;
+    assert_eq!(IpSettings::from_bytes(&data), None);
+    let mut data = IpSettings { ipv4: Ipv4Mode::Static(static_settings), slaac: true }.to_bytes();
+    data[3] = 33;
+    assert_eq!(IpSettings::from_bytes(&data), None);
+}
+
+#[test]
+fn ipv4_settings_validity() {
+    use crate::api::Ipv4Settings;
+
+    let mut settings =
+        Ipv4Settings { addr: [10, 0, 0, 2], prefix_len: 8, gateway: Some([10, 0, 0, 1]), dns: [None; 2] };
+    assert!(settings.is_valid());
+    settings.gateway = Some([192, 168, 0, 1]);
+    assert!(!settings.is_valid());
+    settings.gateway = None;
+    settings.prefix_len = 0;
+    assert!(!settings.is_valid());
+    settings.prefix_len = 32;
+    settings.addr = [224, 0, 0, 1];
+    assert!(!settings.is_valid());
+}
+
+/// Answers the DHCP discover or request in `packet`, as it would come out of a raw UDP socket, with an
+/// offer or acknowledgement of 192.168.69.100/24 from a server at 192.168.69.1.
+fn dhcp_reply(packet: &[u8]) -> Option<Vec<u8>> {
+    use smoltcp::phy::ChecksumCapabilities;
+    use smoltcp::wire::*;
+
+    let ip = Ipv4Packet::new_checked(packet).ok()?;
+    let udp = UdpPacket::new_checked(ip.payload()).ok()?;
+    // the server's own replies come through the socket too
+    if udp.dst_port() != 67 {
+        return None;
+    }
+    let request = DhcpRepr::parse(&DhcpPacket::new_checked(udp.payload()).ok()?).ok()?;
+    let message_type = match request.message_type {
+        DhcpMessageType::Discover => DhcpMessageType::Offer,
+        DhcpMessageType::Request => DhcpMessageType::Ack,
+        _ => return None,
+    };
+    let server = Ipv4Address::new(192, 168, 69, 1);
+    let reply = DhcpRepr {
+        message_type,
+        transaction_id: request.transaction_id,
+        secs: 0,
+        client_hardware_address: request.client_hardware_address,
+        client_ip: Ipv4Address::UNSPECIFIED,
+        your_ip: Ipv4Address::new(192, 168, 69, 100),
+        server_ip: server,
+        router: Some(server),
+        subnet_mask: Some(Ipv4Address::new(255, 255, 255, 0)),
+        relay_agent_ip: Ipv4Address::UNSPECIFIED,
+        broadcast: false,
+        requested_ip: None,
+        client_identifier: None,
+        server_identifier: Some(server),
+        parameter_request_list: None,
+        dns_servers: None,
+        max_size: None,
+        lease_duration: Some(3600),
+        renew_duration: None,
+        rebind_duration: None,
+        additional_options: &[],
+    };
+    let udp_repr = UdpRepr { src_port: 67, dst_port: 68 };
+    let ip_repr = Ipv4Repr {
+        src_addr: server,
+        dst_addr: Ipv4Address::BROADCAST,
+        next_header: IpProtocol::Udp,
+        payload_len: udp_repr.header_len() + reply.buffer_len(),
+        hop_limit: 64,
+    };
+    let mut data = vec![0u8; ip_repr.buffer_len() + ip_repr.payload_len];
+    let mut packet = Ipv4Packet::new_unchecked(&mut data[..]);
+    ip_repr.emit(&mut packet, &ChecksumCapabilities::default());
+    udp_repr.emit(
+        &mut UdpPacket::new_unchecked(packet.payload_mut()),
+        &IpAddress::Ipv4(server),
+        &IpAddress::Ipv4(Ipv4Address::BROADCAST),
+        reply.buffer_len(),
+        |payload| reply.emit(&mut DhcpPacket::new_unchecked(payload)).unwrap(),
+        &ChecksumCapabilities::default(),
+    );
+    Some(data)
+}
+
+#[test]
+fn dhcp_lease_on_loopback() {
+    use smoltcp::iface::{Config, Interface, SocketSet};
+    use smoltcp::phy::{Loopback, Medium};
+    use smoltcp::socket::raw;
+    use smoltcp::time::Instant;
+    use smoltcp::wire::{EthernetAddress, IpAddress, IpProtocol, IpVersion};
+
+    use crate::api::*;
+    use crate::ipconfig::IpConfig;
+
+    let mut device = Loopback::new(Medium::Ethernet);
+    let mut iface = Interface::new(Config::new(EthernetAddress(TEST_MAC).into()), &mut device, Instant::ZERO);
+    let mut sockets = SocketSet::new(vec![]);
+    // the DHCP server sits on the same interface, and sees every UDP packet
+    let server = sockets.add(raw::Socket::new(
+        IpVersion::Ipv4,
+        IpProtocol::Udp,
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 4096]),
+        raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 4096]),
+    ));
+    let mut ip_config = IpConfig::new(TEST_MAC, TEST_SECRET, &mut sockets);
+    let mut dns_allclear_hook = XousScalarEndpoint::new();
+    let mut dns_ipv4_hook = XousScalarEndpoint::new();
+    ip_config.set_settings(IpSettings { ipv4: Ipv4Mode::Dhcp, slaac: false });
+    ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
+    ip_config.link_up(&mut sockets);
+    // our DHCP client needs the traffic before it has a lease
+    assert!(ip_config.accepts_rx());
+
+    for step in 0..100 {
+        let now = Instant::from_millis(step * 10);
+        iface.poll(now, &mut device, &mut sockets);
+        let socket = sockets.get_mut::<raw::Socket>(server);
+        let mut replies = Vec::new();
+        while let Ok(packet) = socket.recv() {
+            replies.extend(dhcp_reply(packet));
+        }
+        for reply in replies {
+            socket.send_slice(&reply).unwrap();
+        }
+        if ip_config.poll(&mut sockets, now) {
+            ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
+        }
+        if ip_config.ipv4.is_some() {
+            break;
+        }
+    }
+
+    assert_eq!(ip_config.source, Ipv4Source::Dhcp);
+    let ipv4 = ip_config.ipv4.expect("no DHCP lease");
+    assert_eq!(ipv4.addr, [192, 168, 69, 100]);
+    assert_eq!(ipv4.prefix_len, 24);
+    assert_eq!(ipv4.gateway, Some([192, 168, 69, 1]));
+    assert_eq!(ipv4.dns, [None, None]);
+    assert!(iface.has_ip_addr(IpAddress::v4(192, 168, 69, 100)));
+    assert_eq!(ip_config.ipv4_conf().unwrap().mask, [255, 255, 255, 0]);
+
+    // switching away from DHCP drops the client, and the lease with it
+    ip_config.set_settings(IpSettings { ipv4: Ipv4Mode::Ec, slaac: false });
+    ip_config.commit(&mut iface, &mut sockets, &mut dns_allclear_hook, &mut dns_ipv4_hook);
+    assert_eq!(ip_config.source, Ipv4Source::None);
+    assert!(!iface.has_ip_addr(IpAddress::v4(192, 168, 69, 100)));
+}
+
+#[test]
+fn ipv4_settings_from_ec() {
+    use crate::api::Ipv4Settings;
+
+    let mut conf = com::api::Ipv4Conf {
+        dhcp: com_rs::DhcpState::Bound,
+        mac: TEST_MAC,
+        addr: [192, 168, 4, 7],
+        gtwy: [192, 168, 4, 1],
+        mask: [255, 255, 252, 0],
+        dns1: [192, 168, 4, 1],
+        dns2: [0, 0, 0, 0],
+    };
+    let settings = Ipv4Settings::from(&conf);
+    assert_eq!(settings.prefix_len, 22);
+    assert_eq!(settings.gateway, Some([192, 168, 4, 1]));
+    assert_eq!(settings.dns, [Some([192, 168, 4, 1]), None]);
+    // an empty mask falls back to a /24
+    conf.mask = [0; 4];
+    assert_eq!(Ipv4Settings::from(&conf).prefix_len, 24);
+}
+
+#[test]
+fn firewall_rule_round_trip() {
+    use crate::api::{FirewallAccess, FirewallDest, FirewallRule};
+
+    for line in [
+        "allow connect 10.0.0.0/8 443",
+        "deny * * *",
+        "allow udp 0.0.0.0 *",
+        "allow send 192.168.1.1 53",
+        "deny listen fe80::/10 8000-8080",
+        "allow connect *.example.com 80-443",
+        "allow connect 2001:db8::1 22",
+    ] {
+        let rule =
+            line.parse::<FirewallRule>().unwrap_or_else(|e| panic!("{:?} didn't parse: {:?}", line, e));
+        assert_eq!(rule.to_string(), line);
+    }
+    // host names are kept in lower case
+    let rule: FirewallRule = "allow connect Example.COM 80".parse().unwrap();
+    assert_eq!(rule.dest, FirewallDest::Host("example.com".to_string()));
+    assert_eq!(rule.access, Some(FirewallAccess::Connect));
+    // a whole address is printed without its prefix length
+    let rule: FirewallRule = "allow * 10.1.2.3/32 *".parse().unwrap();
+    assert_eq!(rule.to_string(), "allow * 10.1.2.3 *");
+    assert_eq!(rule.dest, FirewallDest::Net("10.1.2.3".parse().unwrap(), 32));
+}
+
+#[test]
+fn firewall_rule_parse_errors() {
+    use crate::api::FirewallRule;
+
+    for line in [
+        "",
+        "allow",
+        "allow connect",
+        "allow connect *",
+        "permit connect * *",
+        "allow bind * *",
+        "allow connect 10.0.0.0/33 *",
+        "allow connect ::/129 *",
+        "allow connect 10.0.0.0/x *",
+        "allow connect * 443-80",
+        "allow connect * 65536",
+        "allow connect * 80 extra",
+    ] {
+        assert!(line.parse::<FirewallRule>().is_err(), "parsed {:?}", line);
+    }
+}
+
+#[test]
+fn firewall_rule_matching() {
+    use crate::api::{FirewallAccess, FirewallRule};
+
+    let v4: IpAddr = "192.168.1.20".parse().unwrap();
+    let v6: IpAddr = "2001:db8::20".parse().unwrap();
+    let matches = |rule: &str, access, addr, port, hosts: &[&str]| {
+        let hosts: Vec<String> = hosts.iter().map(|host| host.to_string()).collect();
+        rule.parse::<FirewallRule>().unwrap().matches(access, addr, port, &hosts)
+    };
+    let connect = FirewallAccess::Connect;
+
+    // /0 covers every address of its family, and only that family
+    assert!(matches("allow * 0.0.0.0/0 *", connect, v4, 80, &[]));
+    assert!(!matches("allow * 0.0.0.0/0 *", connect, v6, 80, &[]));
+    assert!(matches("allow * ::/0 *", connect, v6, 80, &[]));
+    assert!(!matches("allow * ::/0 *", connect, v4, 80, &[]));
+    // a v4 rule never matches a v6 address, even one mapping the same v4 address
+    assert!(!matches("allow * 192.168.1.20 *", connect, "::ffff:192.168.1.20".parse().unwrap(), 80, &[]));
+    // /32 and /128 are single addresses
+    assert!(matches("allow * 192.168.1.20/32 *", connect, v4, 80, &[]));
+    assert!(!matches("allow * 192.168.1.21/32 *", connect, v4, 80, &[]));
+    assert!(matches("allow * 2001:db8::20/128 *", connect, v6, 80, &[]));
+    assert!(!matches("allow * 2001:db8::21/128 *", connect, v6, 80, &[]));
+    // the host bits of a block don't matter
+    assert!(matches("allow * 192.168.1.99/24 *", connect, v4, 80, &[]));
+    assert!(!matches("allow * 192.168.2.0/24 *", connect, v4, 80, &[]));
+    assert!(matches("allow * 2001:db8::/32 *", connect, v6, 80, &[]));
+
+    // ports and access kinds
+    assert!(matches("allow connect * 80-443", connect, v4, 80, &[]));
+    assert!(matches("allow connect * 80-443", connect, v4, 443, &[]));
+    assert!(!matches("allow connect * 80-443", connect, v4, 444, &[]));
+    assert!(!matches("allow connect * 80-443", connect, v4, 79, &[]));
+    assert!(!matches("allow connect * *", FirewallAccess::Listen, v4, 80, &[]));
+    assert!(!matches("allow udp * *", FirewallAccess::UdpSend, v4, 53, &[]));
+    assert!(matches("allow send * 53", FirewallAccess::UdpSend, v4, 53, &[]));
+
+    // host names, with and without a wildcard
+    assert!(matches("allow * example.com *", connect, v4, 80, &["example.com"]));
+    assert!(!matches("allow * example.com *", connect, v4, 80, &["www.example.com"]));
+    assert!(matches("allow * *.example.com *", connect, v4, 80, &["www.example.com"]));
+    assert!(matches("allow * *.example.com *", connect, v4, 80, &["a.b.example.com"]));
+    assert!(!matches("allow * *.example.com *", connect, v4, 80, &["example.com"]));
+    assert!(!matches("allow * *.example.com *", connect, v4, 80, &["badexample.com"]));
+    assert!(matches("allow * *.example.com *", connect, v4, 80, &["other.org", "www.example.com"]));
+    // a host rule needs the address to have been looked up under that name
+    assert!(!matches("allow * example.com *", connect, v4, 80, &[]));
+}
+
+/// An Ethernet frame holding an empty TCP segment or UDP datagram, as `count_rx` and `count_tx` see it.
+fn test_frame(src: IpAddr, dst: IpAddr, tcp: bool, src_port: u16, dst_port: u16) -> Vec<u8> {
+    let mut transport = vec![0u8; if tcp { 20 } else { 8 }];
+    transport[0..2].copy_from_slice(&src_port.to_be_bytes());
+    transport[2..4].copy_from_slice(&dst_port.to_be_bytes());
+    if tcp {
+        // data offset, in words
+        transport[12] = 5 << 4;
+    } else {
+        transport[4..6].copy_from_slice(&8u16.to_be_bytes());
+    }
+    let protocol = if tcp { 6 } else { 17 };
+    // the MAC addresses aren't looked at
+    let mut frame = vec![0u8; 12];
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            frame.extend_from_slice(&[0x08, 0x00, 0x45, 0]);
+            frame.extend_from_slice(&(20 + transport.len() as u16).to_be_bytes());
+            frame.extend_from_slice(&[0, 0, 0, 0, 64, protocol, 0, 0]);
+            frame.extend_from_slice(&src.octets());
+            frame.extend_from_slice(&dst.octets());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            frame.extend_from_slice(&[0x86, 0xdd, 0x60, 0, 0, 0]);
+            frame.extend_from_slice(&(transport.len() as u16).to_be_bytes());
+            frame.extend_from_slice(&[protocol, 64]);
+            frame.extend_from_slice(&src.octets());
+            frame.extend_from_slice(&dst.octets());
+        }
+        _ => panic!("mixed address families"),
+    }
+    frame.extend_from_slice(&transport);
+    frame
+}
+
+#[test]
+fn netstat_tcp_attribution() {
+    use smoltcp::wire::{IpAddress, IpEndpoint};
+
+    use crate::netstat::{Flow, Traffic};
+
+    let local: IpAddr = "10.0.0.2".parse().unwrap();
+    let remote: IpAddr = "93.184.216.34".parse().unwrap();
+    let flow = Flow::Tcp { local_port: 49152, remote: IpEndpoint::new(IpAddress::v4(93, 184, 216, 34), 443) };
+    let mut traffic = Traffic::default();
+    traffic.sync(vec![flow].into_iter());
+
+    let outbound = test_frame(local, remote, true, 49152, 443);
+    let inbound = test_frame(remote, local, true, 443, 49152);
+    traffic.count_tx(&outbound);
+    traffic.count_rx(&inbound);
+    traffic.count_rx(&inbound);
+    let counters = traffic.flow(&flow);
+    assert_eq!((counters.tx_packets, counters.tx_bytes), (1, outbound.len() as u64));
+    assert_eq!((counters.rx_packets, counters.rx_bytes), (2, 2 * inbound.len() as u64));
+
+    // the same ports the other way around, or another remote port, are another connection
+    traffic.count_rx(&test_frame(remote, local, true, 49152, 443));
+    traffic.count_rx(&test_frame(remote, local, true, 444, 49152));
+    traffic.count_tx(&test_frame(local, remote, true, 443, 49152));
+    let counters = traffic.flow(&flow);
+    assert_eq!((counters.rx_packets, counters.tx_packets), (2, 1));
+    // but the interface counts everything
+    assert_eq!(traffic.iface.traffic.rx_packets, 4);
+    assert_eq!(traffic.iface.traffic.tx_packets, 2);
+    assert_eq!(traffic.iface.rx_errors, 0);
+
+    // frames too short to be Ethernet are errors
+    traffic.count_rx(&[0u8; 10]);
+    assert_eq!(traffic.iface.rx_errors, 1);
+    assert_eq!(traffic.iface.traffic.rx_packets, 5);
+}
+
+#[test]
+fn netstat_udp_attribution() {
+    use crate::netstat::{Flow, Traffic};
+
+    let local: IpAddr = "fe80::2".parse().unwrap();
+    let flow = Flow::Udp { local_port: 5353 };
+    let mut traffic = Traffic::default();
+    traffic.sync(vec![flow].into_iter());
+
+    // UDP is counted by local port, whoever is at the other end
+    traffic.count_rx(&test_frame("fe80::10".parse().unwrap(), local, false, 5353, 5353));
+    traffic.count_rx(&test_frame("2001:db8::1".parse().unwrap(), local, false, 1234, 5353));
+    traffic.count_tx(&test_frame(local, "ff02::fb".parse().unwrap(), false, 5353, 5353));
+    // a remote port of 5353 isn't ours
+    traffic.count_rx(&test_frame("fe80::10".parse().unwrap(), local, false, 5353, 5354));
+    traffic.count_tx(&test_frame(local, "fe80::10".parse().unwrap(), false, 5354, 5353));
+    let counters = traffic.flow(&flow);
+    assert_eq!((counters.rx_packets, counters.tx_packets), (2, 1));
+    assert_eq!(traffic.flow(&Flow::Udp { local_port: 5354 }).rx_packets, 0);
+
+    // TCP to the same port isn't UDP
+    traffic.count_rx(&test_frame("fe80::10".parse().unwrap(), local, true, 80, 5353));
+    assert_eq!(traffic.flow(&flow).rx_packets, 2);
+}
+
+#[test]
+fn netstat_sync() {
+    use crate::netstat::{Flow, Traffic};
+
+    let local: IpAddr = "10.0.0.2".parse().unwrap();
+    let remote: IpAddr = "10.0.0.1".parse().unwrap();
+    let a = Flow::Udp { local_port: 1000 };
+    let b = Flow::Udp { local_port: 2000 };
+    let mut traffic = Traffic::default();
+    traffic.sync(vec![a, b].into_iter());
+    traffic.count_rx(&test_frame(remote, local, false, 53, 1000));
+    traffic.count_rx(&test_frame(remote, local, false, 53, 2000));
+
+    // flows that are still open keep their counters
+    traffic.sync(vec![b].into_iter());
+    assert_eq!(traffic.flow(&a).rx_packets, 0);
+    assert_eq!(traffic.flow(&b).rx_packets, 1);
+    // and closed ones aren't counted any more, nor remembered if they open again
+    traffic.count_rx(&test_frame(remote, local, false, 53, 1000));
+    traffic.sync(vec![a, b].into_iter());
+    assert_eq!(traffic.flow(&a).rx_packets, 0);
+    assert_eq!(traffic.flow(&b).rx_packets, 1);
+    assert_eq!(traffic.iface.traffic.rx_packets, 3);
+}
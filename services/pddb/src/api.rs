@@ -30,8 +30,6 @@ pub(crate) const DICT_NAME_LEN: usize = 127 - 4 - 4 - 4 - 4; // u32: flags, age,
 pub(crate) const KEY_NAME_LEN: usize = 127 - 8 - 8 - 8 - 4 - 4; // u64: vaddr/len/resvd, u32: flags, age = 95
 #[allow(dead_code)]
 pub(crate) const PASSWORD_LEN: usize = 72; // this is actually set by bcrypt
-#[allow(dead_code)]
-pub(crate) const PDDB_MAGIC: [u8; 4] = [0x50, 0x44, 0x44, 0x42];
 /// migrateable version pairs
 /// PDDB_MIGRATE_1:
 ///   00.00.01.01 - xous 0.9.7 release (original base release)
@@ -39,8 +37,6 @@ pub(crate) const PDDB_MAGIC: [u8; 4] = [0x50, 0x44, 0x44, 0x42];
 #[allow(dead_code)]
 pub(crate) const PDDB_MIGRATE_1: (u32, u32) = (0x00_00_01_01, 0x00_00_02_01);
 #[allow(dead_code)]
-pub(crate) const PDDB_VERSION: u32 = 0x00_00_02_01;
-#[allow(dead_code)]
 // PDDB_A_LEN may be shorter than xous::PDDB_LEN, to speed up testing.
 #[allow(dead_code)]
 #[cfg(not(any(feature = "pddbtest", feature = "autobasis", feature = "ci", feature = "smalldb")))]
@@ -102,9 +98,6 @@ pub(crate) const FAST_REKEY_CHANCE: u32 = 26;
 
 #[allow(dead_code)]
 pub const PDDB_DEFAULT_SYSTEM_BASIS: &'static str = ".System";
-// this isn't an "official" basis, but it is used for the AAD for encrypting the FastSpace structure
-#[allow(dead_code)]
-pub(crate) const PDDB_FAST_SPACE_SYSTEM_BASIS: &'static str = ".FastSpace";
 
 #[allow(dead_code)]
 // TODO: add hardware acceleration for BCRYPT so we can hit the OWASP target without excessive UX delay
@@ -164,6 +157,7 @@ pub(crate) enum Opcode {
     BasisTesting = 26,
 
     ListBasisStd = 26,
+    /// Create a basis, prompting for its password
     CreateBasisStd = 27,
 
     ListDictStd = 28,
@@ -248,6 +242,47 @@ pub(crate) enum Opcode {
     /// Prune the cache. Used mainly for diagnostics.
     Prune = 56,
 
+    /// Save the log server's ring buffer and crash report after a process panicked (arg1 = PID,
+    /// arg2 = crash report ID). Private: only accepted from the PDDB's own thread that receives the
+    /// log server's panic notifications.
+    SaveLog = 57,
+
+    /// Create a dict that keeps prior versions of its keys, or change how many it keeps
+    CreateDictVersioned = 58,
+    /// List the versions kept of a key
+    ListKeyVersions = 59,
+    /// Compact a dictionary's key index and small pool storage
+    CompactDict = 60,
+    /// Delete a key, overwriting its record on disk before returning
+    DeleteKeyParanoid = 61,
+    /// Unlock a basis, prompting for its password
+    OpenBasisStd = 62,
+    /// Lock a basis
+    CloseBasisStd = 63,
+    /// Send changes to keys in a dict, and to the list of mounted bases, to a caller-supplied server
+    Subscribe = 64,
+    /// Stop sending changes to a subscribed server (blocking scalar carrying its SID)
+    Unsubscribe = 65,
+    /// Apply a set of writes and deletes to keys in one basis, all or nothing
+    Transaction = 66,
+    /// Report the space used by a dict, or by a basis, and the free space in the PDDB
+    Usage = 67,
+    /// Set or clear the limit on the space used by a dict
+    SetQuota = 68,
+    /// Start exporting a basis to an encrypted archive
+    ExportBasis = 69,
+    /// Read the next part of the archive being exported
+    ExportRead = 70,
+    /// Start importing an encrypted archive as a new basis
+    ImportBasis = 71,
+    /// Write the next record of the archive being imported
+    ImportWrite = 72,
+    /// Abandon the export or import in progress - blocking scalar
+    ArchiveAbort = 73,
+    /// Turn saving the log on (arg1 = 1) or off (arg1 = 0), following the user's `save_log` preference -
+    /// blocking scalar. Only accepted from the status process.
+    SetLogSaving = 74,
+
     /// This key type could not be decoded
     InvalidOpcode = u32::MAX as _,
 }
@@ -285,6 +320,7 @@ pub enum PddbRequestCode {
     Uninit = 10,
     DuplicateEntry = 11,
     BulkRead = 12,
+    QuotaExceeded = 13,
 }
 // enum def is in rkyv_enum module
 impl BasisRetentionPolicy {
@@ -332,9 +368,422 @@ pub struct PddbKeyRequest {
     pub alloc_hint: Option<u64>, /* this is a usize but for IPC we must have defined memory sizes, so we
                                   * pick the big option. */
     pub cb_sid: Option<[u32; 4]>,
+    /// Open this prior version of the key, as numbered by `ListKeyVersions`, for reading only
+    pub version: Option<u32>,
+    pub result: PddbRequestCode,
+}
+
+/// The most prior versions of each key a dict can be asked to keep
+#[allow(dead_code)]
+pub const MAX_KEY_VERSIONS: usize = 16;
+/// Separates a key's name from a version number in the names of the keys that hold its prior versions.
+/// It can't be typed, which keeps version keys apart from the keys that applications create.
+#[allow(dead_code)]
+pub(crate) const VERSION_SEPARATOR: char = '\u{1}';
+/// The name of the key holding version `version` of `key`
+#[allow(dead_code)]
+pub(crate) fn version_key_name(key: &str, version: u32) -> String {
+    format!("{}{}{}", key, VERSION_SEPARATOR, version)
+}
+
+/// A structure for configuring version retention on a dict, and for listing the versions of a key
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct PddbVersionRequest {
+    pub basis_specified: bool,
+    pub basis: xous_ipc::String<BASIS_NAME_LEN>,
+    pub dict: xous_ipc::String<DICT_NAME_LEN>,
+    pub key: xous_ipc::String<KEY_NAME_LEN>,
+    /// number of versions the dict should keep, for `CreateDictVersioned`
+    pub keep: u32,
+    /// version numbers of the key, oldest first, for `ListKeyVersions`. Only the first `count` are valid.
+    pub versions: [u32; MAX_KEY_VERSIONS],
+    pub count: u32,
     pub result: PddbRequestCode,
 }
 
+/// A request to have `PddbChange` records sent to a caller-supplied server
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct PddbSubscription {
+    /// the server that receives the changes
+    pub sid: [u32; 4],
+    /// when `false`, only bases being mounted and unmounted are reported
+    pub dict_specified: bool,
+    pub dict: xous_ipc::String<DICT_NAME_LEN>,
+    /// when `true`, only changes within the named basis are reported
+    pub basis_specified: bool,
+    pub basis: xous_ipc::String<BASIS_NAME_LEN>,
+    pub result: PddbRequestCode,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PddbChangeKind {
+    KeyCreated,
+    KeyUpdated,
+    KeyDeleted,
+    BasisMounted,
+    BasisUnmounted,
+    /// Changes were dropped because the subscriber's queue was full; anything it watches may have
+    /// changed since. The other fields are empty.
+    ChangesDropped,
+}
+
+/// The opcode of the messages carrying a `PddbChange`. It is fixed, rather than chosen by the
+/// subscriber, so that a subscription can't be used to make the PDDB send a server a message it
+/// doesn't expect from the PDDB; it is well clear of the opcodes servers number from zero.
+pub const PDDB_CHANGE_OPCODE: u32 = 0x7064_6462; // "pddb"
+
+/// A change sent to the servers that subscribed with `Pddb::subscribe()`. The record is sent as a
+/// non-blocking memory message with `PDDB_CHANGE_OPCODE`; restore it with
+/// `Buffer::from_memory_message()` and `to_original::<PddbChange, _>()`.
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Copy, Clone)]
+pub struct PddbChange {
+    pub kind: PddbChangeKind,
+    /// the basis the change happened in, or the basis that was mounted or unmounted
+    pub basis: xous_ipc::String<BASIS_NAME_LEN>,
+    /// empty for basis changes
+    pub dict: xous_ipc::String<DICT_NAME_LEN>,
+    /// empty for basis changes
+    pub key: xous_ipc::String<KEY_NAME_LEN>,
+}
+
+/// The largest encoded transaction that can be committed: it is sent in one memory message, and is
+/// written to disk in full before any of its keys are touched.
+#[allow(dead_code)]
+pub const MAX_TRANSACTION_LEN: usize = 64 * 1024;
+/// Size of the header at the top of a `Transaction` memory message: a `PddbRetcode` as a u32, followed
+/// by the length of the encoded transaction as a u32.
+#[allow(dead_code)]
+pub(crate) const TXN_HEADER_LEN: usize = 8;
+/// Transactions are logged to this dict of the basis they change, before their keys are touched. Like
+/// the names of version keys, it can't be typed, so it is kept apart from the dicts apps create.
+#[allow(dead_code)]
+pub(crate) const TXN_DICT: &str = "\u{1}txn";
+#[allow(dead_code)]
+pub(crate) const TXN_LOG_KEY: &str = "log";
+/// A dict being compacted is copied to a new slot under this name, so that the copy isn't found in
+/// place of the original before the move has been logged.
+#[allow(dead_code)]
+pub(crate) const COMPACT_COPY_DICT: &str = "\u{1}compact";
+/// Logs, in `TXN_DICT`, the move of a compacted copy into the place of the original dict
+#[allow(dead_code)]
+pub(crate) const COMPACT_LOG_KEY: &str = "compact";
+const TXN_VERSION: u8 = 1;
+
+/// Appends `name` to `out` as a length byte followed by its utf-8
+#[allow(dead_code)]
+pub(crate) fn put_name(out: &mut Vec<u8>, name: &str) {
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// Reads the little-endian integers and names written by the encoders in this file
+#[allow(dead_code)]
+pub(crate) struct ByteReader<'a> {
+    pub data: &'a [u8],
+}
+#[allow(dead_code)]
+impl<'a> ByteReader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Some(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> { self.take(1).map(|b| b[0]) }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    pub(crate) fn name(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        std::str::from_utf8(self.take(len)?).ok().map(|s| s.to_string())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool { self.data.is_empty() }
+}
+
+/// A change staged in a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TxnOp {
+    Write { dict: String, key: String, data: Vec<u8> },
+    Delete { dict: String, key: String },
+}
+impl TxnOp {
+    #[allow(dead_code)]
+    pub(crate) fn dict(&self) -> &str {
+        match self {
+            TxnOp::Write { dict, .. } | TxnOp::Delete { dict, .. } => dict,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn key(&self) -> &str {
+        match self {
+            TxnOp::Write { key, .. } | TxnOp::Delete { key, .. } => key,
+        }
+    }
+}
+
+/// The operations of a transaction, in the order they are applied. The same encoding is used to send
+/// a transaction to the server, and to log it to disk.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct Transaction {
+    /// `None` for the most recently opened basis
+    pub basis: Option<String>,
+    pub ops: Vec<TxnOp>,
+}
+impl Transaction {
+    /// The encoding is: a version byte; the basis name; a u32 count of operations; then for each
+    /// operation a tag byte (0 write, 1 delete), the dict and key names, and for writes the u32 length
+    /// of the data followed by the data. Names are a length byte followed by utf-8, and an empty basis
+    /// name stands for `None`. All integers are little-endian.
+    #[allow(dead_code)]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = vec![TXN_VERSION];
+        put_name(&mut out, self.basis.as_deref().unwrap_or(""));
+        out.extend_from_slice(&(self.ops.len() as u32).to_le_bytes());
+        for op in self.ops.iter() {
+            match op {
+                TxnOp::Write { dict, key, data } => {
+                    out.push(0);
+                    put_name(&mut out, dict);
+                    put_name(&mut out, key);
+                    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    out.extend_from_slice(data);
+                }
+                TxnOp::Delete { dict, key } => {
+                    out.push(1);
+                    put_name(&mut out, dict);
+                    put_name(&mut out, key);
+                }
+            }
+        }
+        out
+    }
+
+    /// Returns `None` if `data` is not exactly one encoded transaction.
+    #[allow(dead_code)]
+    pub(crate) fn decode(data: &[u8]) -> Option<Transaction> {
+        let mut r = ByteReader { data };
+        if r.u8()? != TXN_VERSION {
+            return None;
+        }
+        let basis = r.name()?;
+        let count = r.u32()?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let op = match r.u8()? {
+                0 => {
+                    let dict = r.name()?;
+                    let key = r.name()?;
+                    let len = r.u32()? as usize;
+                    TxnOp::Write { dict, key, data: r.take(len)?.to_vec() }
+                }
+                1 => TxnOp::Delete { dict: r.name()?, key: r.name()? },
+                _ => return None,
+            };
+            ops.push(op);
+        }
+        if !r.is_empty() {
+            return None;
+        }
+        Some(Transaction { basis: if basis.is_empty() { None } else { Some(basis) }, ops })
+    }
+}
+
+/// The quotas of the dicts in a basis are kept in this dict of the basis, with the name of each dict as
+/// a key holding its quota as a little-endian u64.
+#[allow(dead_code)]
+pub(crate) const QUOTA_DICT: &str = "\u{1}quota";
+
+/// Space used by the keys of a dict, or of all the dicts in a basis. Byte counts of flash pages are in
+/// units of the data the pages hold, so they can be compared with the lengths of keys.
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PddbUsage {
+    /// number of keys, not counting the prior versions kept of keys
+    pub key_count: u32,
+    /// total length of the keys, including the prior versions kept of keys. This is what quotas limit.
+    pub used: u64,
+    /// space reserved for the keys to grow into
+    pub reserved: u64,
+    /// flash pages holding key data
+    pub allocated: u64,
+    /// flash pages holding the key descriptors
+    pub index: u64,
+}
+impl PddbUsage {
+    /// Space in the pages holding key data that isn't holding any
+    pub fn fragmentation(&self) -> u64 { self.allocated.saturating_sub(self.used) }
+
+    #[allow(dead_code)]
+    pub(crate) fn add(&mut self, other: &PddbUsage) {
+        self.key_count += other.key_count;
+        self.used += other.used;
+        self.reserved += other.reserved;
+        self.allocated += other.allocated;
+        self.index += other.index;
+    }
+}
+
+/// Space used by one dict in a basis, as returned by `Pddb::usage()`
+#[derive(Debug, Clone)]
+pub struct DictUsage {
+    pub name: String,
+    pub usage: PddbUsage,
+    /// the limit on `usage.used`, if the dict has one
+    pub quota: Option<u64>,
+}
+
+/// Space used by a basis and by each of its dicts, as returned by `Pddb::usage()`
+#[derive(Debug, Clone)]
+pub struct BasisUsage {
+    pub name: String,
+    /// totals over every dict of the basis, including the ones the PDDB keeps for itself
+    pub total: PddbUsage,
+    /// the dicts of the basis, sorted by name
+    pub dicts: Vec<DictUsage>,
+    /// free space in the PDDB, which is shared by every basis. The pages of bases that aren't open can't
+    /// be told apart from free pages, so they are counted as free.
+    pub free: u64,
+}
+
+/// The error inside the `OutOfMemory` error returned for a write that would take a dict over its quota.
+/// Test for it with `is_quota_exceeded()`.
+#[derive(Debug)]
+pub struct QuotaExceeded;
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dictionary quota exceeded")
+    }
+}
+impl std::error::Error for QuotaExceeded {}
+impl QuotaExceeded {
+    #[allow(dead_code)]
+    pub(crate) fn error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::OutOfMemory, QuotaExceeded)
+    }
+}
+/// Returns `true` if `e` was returned because a write would have taken a dict over its quota
+pub fn is_quota_exceeded(e: &std::io::Error) -> bool {
+    e.get_ref().map_or(false, |inner| inner.is::<QuotaExceeded>())
+}
+
+/// A request for the space used by a dict or a basis, or to set the quota of a dict
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct PddbUsageRequest {
+    /// set to the name of the basis that was selected, on return
+    pub basis_specified: bool,
+    pub basis: xous_ipc::String<BASIS_NAME_LEN>,
+    /// when `false`, the usage of the whole basis is returned
+    pub dict_specified: bool,
+    pub dict: xous_ipc::String<DICT_NAME_LEN>,
+    pub usage: PddbUsage,
+    /// the quota of the dict; for `SetQuota`, `None` clears it
+    pub quota: Option<u64>,
+    pub free: u64,
+    pub result: PddbRequestCode,
+}
+
+/// Size of the header at the top of the memory messages that carry a basis archive: a `PddbRetcode` as a
+/// u32, followed by the length of the payload as a u32.
+#[allow(dead_code)]
+pub(crate) const ARCHIVE_MSG_HEADER_LEN: usize = 8;
+/// A basis archive starts with this many bytes of plaintext header: a magic number, a u32 version, the
+/// salt of the archive key and the prefix of the nonces of its records.
+pub(crate) const ARCHIVE_HEADER_LEN: usize = 32;
+pub(crate) const ARCHIVE_MAGIC: [u8; 4] = *b"PDBX";
+#[allow(dead_code)]
+pub(crate) const ARCHIVE_VERSION: u32 = 1;
+/// Key data is split into records of at most this many bytes
+#[allow(dead_code)]
+pub(crate) const ARCHIVE_DATA_CHUNK: usize = 2048;
+/// The largest sealed record in an archive, which must fit in one page along with the message header
+#[allow(dead_code)]
+pub(crate) const ARCHIVE_MAX_RECORD: usize = 2048 + 256;
+
+/// Asks for a basis, or some of its dicts, to be exported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExportRequest {
+    /// `None` for the most recently opened basis
+    pub basis: Option<String>,
+    /// `None` for every dict
+    pub dicts: Option<Vec<String>>,
+}
+impl ExportRequest {
+    /// The encoding is the basis name, then a u32 count of dicts followed by their names, with names
+    /// encoded as for a `Transaction`. An empty basis name stands for `None`, and a count of `u32::MAX`
+    /// for every dict.
+    #[allow(dead_code)]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_name(&mut out, self.basis.as_deref().unwrap_or(""));
+        match &self.dicts {
+            Some(dicts) => {
+                out.extend_from_slice(&(dicts.len() as u32).to_le_bytes());
+                for dict in dicts.iter() {
+                    put_name(&mut out, dict);
+                }
+            }
+            None => out.extend_from_slice(&u32::MAX.to_le_bytes()),
+        }
+        out
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn decode(data: &[u8]) -> Option<ExportRequest> {
+        let mut r = ByteReader { data };
+        let basis = r.name()?;
+        let dicts = match r.u32()? {
+            u32::MAX => None,
+            count => {
+                let mut dicts = Vec::new();
+                for _ in 0..count {
+                    dicts.push(r.name()?);
+                }
+                Some(dicts)
+            }
+        };
+        if !r.is_empty() {
+            return None;
+        }
+        Some(ExportRequest { basis: if basis.is_empty() { None } else { Some(basis) }, dicts })
+    }
+}
+
+/// Asks for an archive to be imported as a new basis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ImportRequest {
+    /// the name of the basis to create
+    pub name: String,
+    /// the header of the archive, followed by its first sealed record
+    pub start: Vec<u8>,
+}
+impl ImportRequest {
+    /// The encoding is the name, encoded as for a `Transaction`, followed by `start`.
+    #[allow(dead_code)]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_name(&mut out, &self.name);
+        out.extend_from_slice(&self.start);
+        out
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn decode(data: &[u8]) -> Option<ImportRequest> {
+        let mut r = ByteReader { data };
+        let name = r.name()?;
+        Some(ImportRequest { name, start: r.data.to_vec() })
+    }
+}
+
 pub(crate) const MAX_PDDBKLISTLEN: usize = 4064;
 /// A structure for requesting a token to access a particular key/value pair
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
@@ -376,8 +825,17 @@ pub(crate) enum PddbRetcode {
     UnexpectedEof = 4,
     InternalError = 5,
     DiskFull = 6,
+    QuotaExceeded = 7,
+    InvalidArchive = 8,
+    AlreadyExists = 9,
 }
 
+/// Tags of the senres messages that carry the basis name of `CreateBasisStd`, `OpenBasisStd` and
+/// `CloseBasisStd`. A failed request comes back with a `PddbRetcode` in the offset of the message.
+pub(crate) const CREATE_BASIS_STD_TAG: [u8; 4] = *b"NuBQ";
+pub(crate) const OPEN_BASIS_STD_TAG: [u8; 4] = *b"OpBQ";
+pub(crate) const CLOSE_BASIS_STD_TAG: [u8; 4] = *b"ClBQ";
+
 pub(crate) const PDDB_BUF_DATA_LEN: usize = 4072;
 /// PddbBuf is a C-representation of a page of memory that's used
 /// to shuttle data for streaming channels. It must be exactly one
@@ -1,3 +1,5 @@
+mod layout;
+pub use layout::*;
 mod basis;
 pub use basis::*;
 mod dictionary;
@@ -12,6 +14,9 @@ mod types;
 pub use types::*;
 mod bcrypt;
 
+mod archive;
+pub(crate) use archive::*;
+
 // local to the backend
 mod murmur3;
 pub(crate) use murmur3::*;
@@ -0,0 +1,397 @@
+//! Encrypted archives of a single basis, for moving it to another device.
+//!
+//! An archive starts with a plaintext header: the magic number, a u32 version, a random 16-byte salt
+//! and a random 8-byte nonce prefix. The archive key is derived from a password and the salt by
+//! `PddbOs::archive_derive_key()`, so unlike the key of a basis it doesn't depend on the device. The
+//! header is followed by records, each a u32 length and an AES-256-GCM-SIV ciphertext. Every record is
+//! sealed with the header as its AAD, and with a nonce made of the prefix and the index of the record,
+//! so records can't be reordered, dropped, or spliced in from another archive. The `End` record that
+//! closes every archive catches truncation.
+//!
+//! The records are a `Basis` naming the basis that was exported, then for each dict a `Dict`
+//! followed by its keys. Each key is a `Key` giving its name and length, followed by its data split
+//! into `Data` records. Only the current contents of keys are archived: prior versions are left out.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Result};
+
+use aes_gcm_siv::aead::{Aead, KeyInit, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use zeroize::Zeroize;
+
+use super::*;
+use crate::api::*;
+
+const RECORD_BASIS: u8 = 0;
+const RECORD_DICT: u8 = 1;
+const RECORD_KEY: u8 = 2;
+const RECORD_DATA: u8 = 3;
+const RECORD_END: u8 = 4;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Record {
+    Basis { name: String },
+    Dict { name: String, versions: u8 },
+    Key { name: String, len: u64 },
+    Data(Vec<u8>),
+    End { keys: u32 },
+}
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Record::Basis { name } => {
+                out.push(RECORD_BASIS);
+                put_name(&mut out, name);
+            }
+            Record::Dict { name, versions } => {
+                out.push(RECORD_DICT);
+                put_name(&mut out, name);
+                out.push(*versions);
+            }
+            Record::Key { name, len } => {
+                out.push(RECORD_KEY);
+                put_name(&mut out, name);
+                out.extend_from_slice(&len.to_le_bytes());
+            }
+            Record::Data(data) => {
+                out.push(RECORD_DATA);
+                out.extend_from_slice(data);
+            }
+            Record::End { keys } => {
+                out.push(RECORD_END);
+                out.extend_from_slice(&keys.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    fn decode(data: &[u8]) -> Option<Record> {
+        let mut r = ByteReader { data };
+        let record = match r.u8()? {
+            RECORD_BASIS => Record::Basis { name: r.name()? },
+            RECORD_DICT => Record::Dict { name: r.name()?, versions: r.u8()? },
+            RECORD_KEY => Record::Key { name: r.name()?, len: r.u64()? },
+            RECORD_DATA => Record::Data(r.take(r.data.len())?.to_vec()),
+            RECORD_END => Record::End { keys: r.u32()? },
+            _ => return None,
+        };
+        if !r.is_empty() {
+            return None;
+        }
+        Some(record)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid archive: {}", reason))
+}
+
+/// Seals and opens the records of one archive, in order.
+struct ArchiveCipher {
+    cipher: Aes256GcmSiv,
+    header: [u8; ARCHIVE_HEADER_LEN],
+    /// index of the next record
+    index: u32,
+}
+impl ArchiveCipher {
+    fn new(hw: &PddbOs, header: [u8; ARCHIVE_HEADER_LEN], password: &str) -> Self {
+        let salt: [u8; 16] = header[8..24].try_into().unwrap();
+        let mut key = hw.archive_derive_key(&salt, password);
+        let cipher = Aes256GcmSiv::new(&key.into());
+        key.zeroize();
+        ArchiveCipher { cipher, header, index: 0 }
+    }
+
+    fn nonce(&self) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&self.header[24..]);
+        nonce[8..].copy_from_slice(&self.index.to_le_bytes());
+        nonce
+    }
+
+    /// Returns the sealed `record`, without its length
+    fn seal(&mut self, record: Record) -> Vec<u8> {
+        let mut plaintext = record.encode();
+        if let Record::Data(mut data) = record {
+            data.zeroize();
+        }
+        let nonce = self.nonce();
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { aad: &self.header, msg: &plaintext })
+            .expect("couldn't seal archive record");
+        plaintext.zeroize();
+        self.index += 1;
+        sealed
+    }
+
+    /// Returns `None` if `sealed` is not the next record of the archive
+    fn open(&mut self, sealed: &[u8]) -> Option<Record> {
+        let nonce = self.nonce();
+        let mut plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { aad: &self.header, msg: sealed })
+            .ok()?;
+        let record = Record::decode(&plaintext);
+        plaintext.zeroize();
+        if record.is_some() {
+            self.index += 1;
+        }
+        record
+    }
+}
+
+enum Pending {
+    Basis,
+    Dict(String, u8),
+    /// a key, and its dict
+    Key(String, String),
+}
+
+/// Writes out a basis as an archive, one chunk at a time, so that the archive never has to be held in
+/// memory all at once.
+pub(crate) struct BasisExport {
+    cipher: ArchiveCipher,
+    basis: String,
+    /// the header, until it has been read
+    header: Option<[u8; ARCHIVE_HEADER_LEN]>,
+    pending: VecDeque<Pending>,
+    /// the key being exported: its dict, its name, its length, and how much of it has been exported
+    current: Option<(String, String, u64, u64)>,
+    keys: u32,
+    done: bool,
+}
+impl BasisExport {
+    /// Lists the keys to export from `basis_name`, or from the most recently opened basis, limited to
+    /// `dicts` if given. Keys created after this are left out of the archive.
+    pub(crate) fn new(
+        hw: &mut PddbOs,
+        cache: &mut BasisCache,
+        basis_name: Option<&str>,
+        dicts: Option<&[String]>,
+        password: &str,
+    ) -> Result<BasisExport> {
+        let basis = match basis_name {
+            Some(name) if cache.basis_contains(name) => name.to_string(),
+            None if cache.basis_latest().is_some() => cache.basis_latest().unwrap().to_string(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    "Requested basis not found, or PDDB not mounted.",
+                ));
+            }
+        };
+        let mut names: Vec<String> = match dicts {
+            Some(dicts) if dicts.iter().any(|d| d.contains(VERSION_SEPARATOR)) => {
+                return Err(Error::new(ErrorKind::InvalidInput, "invalid dict name"));
+            }
+            Some(dicts) => dicts.to_vec(),
+            None => cache.dict_list(hw, Some(&basis)).into_iter().collect(),
+        };
+        names.sort();
+        names.dedup();
+        let mut pending = VecDeque::new();
+        pending.push_back(Pending::Basis);
+        for dict in names {
+            let versions = cache.dict_attributes(hw, &dict, Some(&basis))?.flags.versions();
+            let (keys, _, _) = cache.key_list(hw, &dict, Some(&basis))?;
+            pending.push_back(Pending::Dict(dict.clone(), versions));
+            for key in keys.into_iter().filter(|k| !k.contains(VERSION_SEPARATOR)) {
+                pending.push_back(Pending::Key(dict.clone(), key));
+            }
+        }
+
+        let mut header = [0u8; ARCHIVE_HEADER_LEN];
+        header[..4].copy_from_slice(&ARCHIVE_MAGIC);
+        header[4..8].copy_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+        hw.trng_slice(&mut header[8..]);
+        Ok(BasisExport {
+            cipher: ArchiveCipher::new(hw, header, password),
+            basis,
+            header: Some(header),
+            pending,
+            current: None,
+            keys: 0,
+            done: false,
+        })
+    }
+
+    /// Returns the next part of the archive: the header, then one length-prefixed record at a time.
+    /// Returns `None` once the whole archive has been returned.
+    pub(crate) fn next_chunk(&mut self, hw: &mut PddbOs, cache: &mut BasisCache) -> Result<Option<Vec<u8>>> {
+        if let Some(header) = self.header.take() {
+            return Ok(Some(header.to_vec()));
+        }
+        if self.done {
+            return Ok(None);
+        }
+        if !cache.basis_contains(&self.basis) {
+            return Err(Error::new(ErrorKind::NotFound, "basis was closed during export"));
+        }
+        let record = loop {
+            if let Some((dict, key, len, exported)) = &mut self.current {
+                let mut data = vec![0u8; ((*len - *exported) as usize).min(ARCHIVE_DATA_CHUNK)];
+                let read =
+                    cache.key_read(hw, dict, key, &mut data, Some(*exported as usize), Some(&self.basis))?;
+                if read == 0 {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "key was truncated during export"));
+                }
+                data.truncate(read);
+                *exported += read as u64;
+                if *exported >= *len {
+                    self.current = None;
+                }
+                break Record::Data(data);
+            }
+            match self.pending.pop_front() {
+                Some(Pending::Basis) => break Record::Basis { name: self.basis.clone() },
+                Some(Pending::Dict(name, versions)) => break Record::Dict { name, versions },
+                Some(Pending::Key(dict, name)) => {
+                    let len = match cache.key_attributes(hw, &dict, &name, Some(&self.basis)) {
+                        Ok(attr) => attr.len as u64,
+                        // keys deleted since the export started are left out
+                        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                        Err(e) => return Err(e),
+                    };
+                    self.keys += 1;
+                    if len > 0 {
+                        self.current = Some((dict, name.clone(), len, 0));
+                    }
+                    break Record::Key { name, len };
+                }
+                None => {
+                    self.done = true;
+                    break Record::End { keys: self.keys };
+                }
+            }
+        };
+        let sealed = self.cipher.seal(record);
+        let mut chunk = (sealed.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(&sealed);
+        Ok(Some(chunk))
+    }
+}
+
+/// Loads an archive into a newly created basis, one record at a time.
+pub(crate) struct BasisImport {
+    cipher: ArchiveCipher,
+    /// the basis the archive is loaded into
+    basis: String,
+    /// the name of the basis that was exported
+    source: String,
+    dict: Option<String>,
+    /// the key being imported: its name, its length, and how much of it has been written
+    current: Option<(String, u64, u64)>,
+    keys: u32,
+    done: bool,
+}
+impl BasisImport {
+    /// Checks `password` against the archive that begins with `start`, which holds the header of the
+    /// archive followed by its first record. The archive will be imported into the basis `basis_name`,
+    /// which must be created and mounted before any records are applied.
+    ///
+    /// Returns `PermissionDenied` if the password is wrong, and `InvalidData` if `start` isn't the
+    /// beginning of an archive.
+    pub(crate) fn new(hw: &PddbOs, basis_name: &str, start: &[u8], password: &str) -> Result<BasisImport> {
+        if start.len() < ARCHIVE_HEADER_LEN || start[..4] != ARCHIVE_MAGIC {
+            return Err(invalid("bad header"));
+        }
+        let header: [u8; ARCHIVE_HEADER_LEN] = start[..ARCHIVE_HEADER_LEN].try_into().unwrap();
+        if u32::from_le_bytes(header[4..8].try_into().unwrap()) != ARCHIVE_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let mut cipher = ArchiveCipher::new(hw, header, password);
+        let source = match cipher.open(&start[ARCHIVE_HEADER_LEN..]) {
+            Some(Record::Basis { name }) => name,
+            Some(_) => return Err(invalid("missing basis record")),
+            None => return Err(Error::new(ErrorKind::PermissionDenied, "wrong password for archive")),
+        };
+        Ok(BasisImport {
+            cipher,
+            basis: basis_name.to_string(),
+            source,
+            dict: None,
+            current: None,
+            keys: 0,
+            done: false,
+        })
+    }
+
+    pub(crate) fn basis(&self) -> &str { &self.basis }
+
+    pub(crate) fn source(&self) -> &str { &self.source }
+
+    /// `true` once the `End` record has been applied, and the basis has been synced
+    pub(crate) fn finished(&self) -> bool { self.done }
+
+    /// Applies the next sealed record of the archive, without its length.
+    pub(crate) fn apply(&mut self, hw: &mut PddbOs, cache: &mut BasisCache, sealed: &[u8]) -> Result<()> {
+        if self.done {
+            return Err(invalid("records past the end"));
+        }
+        let record = self.cipher.open(sealed).ok_or_else(|| invalid("damaged record"))?;
+        let basis = Some(self.basis.as_str());
+        match (record, &mut self.current) {
+            (Record::Data(mut data), Some((key, len, written))) => {
+                if *written + data.len() as u64 > *len {
+                    data.zeroize();
+                    return Err(invalid("key data is too long"));
+                }
+                let dict = self.dict.as_deref().unwrap();
+                let result =
+                    cache.key_update(hw, dict, key, &data, Some(*written as usize), None, basis, false);
+                *written += data.len() as u64;
+                data.zeroize();
+                result?;
+                if *written == *len {
+                    self.current = None;
+                }
+            }
+            (_, Some(_)) => return Err(invalid("key data is missing")),
+            (Record::Dict { name, versions }, None) => {
+                if !valid_name(&name, DICT_NAME_LEN) {
+                    return Err(invalid("bad dict name"));
+                }
+                cache.dict_add(hw, &name, basis)?;
+                cache.dict_set_versions(hw, &name, versions, basis)?;
+                self.dict = Some(name);
+            }
+            (Record::Key { name, len }, None) => {
+                let dict = self.dict.as_deref().ok_or_else(|| invalid("key outside of a dict"))?;
+                if !valid_name(&name, KEY_NAME_LEN) {
+                    return Err(invalid("bad key name"));
+                }
+                let empty: [u8; 0] = [];
+                let hint = if len > 0 { Some(len as usize) } else { None };
+                cache.key_update(hw, dict, &name, &empty, None, hint, basis, len == 0)?;
+                self.keys += 1;
+                if len > 0 {
+                    self.current = Some((name, len, 0));
+                }
+            }
+            (Record::End { keys }, None) => {
+                if keys != self.keys {
+                    return Err(invalid("wrong number of keys"));
+                }
+                cache.sync(hw, basis, false)?;
+                self.done = true;
+            }
+            (Record::Basis { .. }, None) | (Record::Data(_), None) => {
+                return Err(invalid("unexpected record"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn valid_name(name: &str, max_len: usize) -> bool {
+    !name.is_empty() && name.len() < max_len && !name.contains(VERSION_SEPARATOR)
+}
+
+/// An export or import in progress
+pub(crate) enum ArchiveSession {
+    Export(BasisExport),
+    Import(BasisImport),
+}
@@ -158,8 +158,6 @@ pub(crate) const SMALL_POOL_STRIDE: u64 = 0xFE_0000;
 /// if we made this larger than a VPAGE_SIZE, we don't get much gain in terms of write reduction,
 /// and it greatly complicates the implementation. So, SMALL_CAPACITY should be less than VPAGE_SIZE.
 pub(crate) const SMALL_CAPACITY: usize = VPAGE_SIZE;
-pub(crate) const LARGE_POOL_START: u64 = 0x0000_FE00_0000_0000;
-pub(crate) const KEY_MAXCOUNT: usize = 131_071; // 2^17 - 1
 /// This is a size limit on the biggest file you can create. It's currently 32GiB. No, this is not
 /// web scale, but it's big enough to hold a typical blu-ray movie as a single file. You can adjust
 /// this constant up or down, and the trade-off is, you get more or less total number of large files
@@ -190,19 +188,15 @@ pub(crate) const KEY_MAXCOUNT: usize = 131_071; // 2^17 - 1
 /// you access to the 32GiB file size limit.
 pub(crate) const LARGE_FILE_MAX_SIZE: u64 = 0x0000_0008_0000_0000;
 
-/// The chosen "stride" of a dict/key entry. Drives a lot of key parameters in the database's characteristics.
-/// This is chosen such that 32 of these entries fit evenly into a VPAGE.
-pub(crate) const DK_STRIDE: usize = 127;
-//// DK_STRIDES per VPAGE
-pub(crate) const DK_PER_VPAGE: usize = VPAGE_SIZE / DK_STRIDE; // should be 32 - use this for computing modulus on dictionary indices
-/// size of a dictionary region in virtual memory
-pub(crate) const DICT_VSIZE: u64 = 0xFE_0000;
-/// maximum number of dictionaries in a system
-pub(crate) const DICT_MAXCOUNT: usize = 16383;
+/// seed for the checksum appended to transaction logs
+const TXN_LOG_SEED: u32 = 0x7478_6e31;
 /// default alloc hint, if none is given (needs to be non-zero)
 /// this would be the typical "minimum space" reserved for a key
 /// users are of course allowed to specify something smaller, but it should be non-zero
 pub(crate) const DEFAULT_ALLOC_HINT: usize = 8;
+/// maximum number of decrypted vpages cached per large key. Caches are also trimmed by `cache_prune()`
+/// along with the small key data, so this just keeps one big key from crowding out everything else.
+pub(crate) const LARGE_CACHE_PAGES: usize = 16;
 
 /// This is the format of the Basis as stored on disk
 #[derive(PartialEq, Debug, Default)]
@@ -265,6 +259,12 @@ pub(crate) struct BasisCache {
     pub(crate) tt: ticktimer_server::Ticktimer,
     /// data cache - stores the most recently decrypted pages of data
     data_cache: PlaintextCache,
+    /// changes to keys and to the list of mounted bases, waiting to be sent to subscribers
+    changes: Vec<PddbChange>,
+    /// bases mounted since the last check for transactions that didn't finish
+    unrecovered: Vec<String>,
+    /// quotas of the dicts in each basis, by basis name. Loaded from `QUOTA_DICT` when first needed.
+    quotas: HashMap<String, HashMap<String, u64>>,
 }
 impl BasisCache {
     pub(crate) fn new() -> Self {
@@ -272,6 +272,19 @@ impl BasisCache {
             cache: Vec::new(),
             tt: ticktimer_server::Ticktimer::new().unwrap(),
             data_cache: PlaintextCache { data: None, tag: None },
+            changes: Vec::new(),
+            unrecovered: Vec::new(),
+            quotas: HashMap::new(),
+        }
+    }
+
+    /// Returns the changes recorded since the last call, oldest first.
+    pub(crate) fn take_changes(&mut self) -> Vec<PddbChange> { std::mem::take(&mut self.changes) }
+
+    fn record_change(&mut self, kind: PddbChangeKind, basis: &str, dict: &str, key: &str) {
+        // version keys and transaction logs are the PDDB's own records
+        if !dict.contains(VERSION_SEPARATOR) && !key.contains(VERSION_SEPARATOR) {
+            self.changes.push(PddbChange::new(kind, basis, dict, key));
         }
     }
 
@@ -373,6 +386,123 @@ impl BasisCache {
         }
     }
 
+    /// Sets the number of prior versions that `dict` keeps of each of its keys. Versions past a lowered
+    /// limit are dropped the next time a key is written.
+    pub(crate) fn dict_set_versions(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        versions: u8,
+        basis_name: Option<&str>,
+    ) -> Result<()> {
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let basis = &mut self.cache[basis_index];
+            if !basis.ensure_dict_in_cache(hw, dict) {
+                return Err(Error::new(ErrorKind::NotFound, "dictionary not found"));
+            }
+            let dict_entry = basis.dicts.get_mut(dict).expect("Entry was assured, but not there!");
+            if dict_entry.flags.versions() != versions {
+                dict_entry.flags.set_versions(versions);
+                dict_entry.clean = false;
+                basis.dict_sync(hw, dict, false)?;
+            }
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))
+        }
+    }
+
+    /// Compacts `dict` in the basis selected by `basis_name`. A compacted copy is written to a new slot
+    /// first, and the move of the copy into place is logged to `TXN_DICT` before the original is wiped.
+    /// If power is lost before the log is complete, `txn_recover()` discards the copy, and if it is lost
+    /// after, `txn_recover()` finishes the move; either way no keys are lost. See
+    /// `BasisCacheEntry::dict_compact`.
+    pub(crate) fn dict_compact(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        basis_name: Option<&str>,
+    ) -> Result<()> {
+        let basis_index = self
+            .select_basis(basis_name)
+            .ok_or(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))?;
+        let basis = self.cache[basis_index].name.clone();
+        // a move left behind by an earlier compaction must be finished first
+        self.unrecovered.push(basis.clone());
+        self.txn_recover(hw);
+        if let Some(dict_entry) = self.cache[basis_index].dicts.get(dict) {
+            // the copy's small pool and key index, and the log of the move
+            let pages = dict_entry.small_pool.len() + 1 + dict_entry.key_count as usize / DK_PER_VPAGE + 4;
+            if !hw.ensure_fast_space_alloc(pages, &self.cache) {
+                return Err(Error::new(ErrorKind::OutOfMemory, "No free space to compact dict"));
+            }
+        }
+        let (old, new) = self.cache[basis_index].dict_compact(hw, dict)?;
+
+        let mut log = Vec::new();
+        put_name(&mut log, dict);
+        log.extend_from_slice(&old.get().to_le_bytes());
+        log.extend_from_slice(&new.get().to_le_bytes());
+        let checksum = murmur3_32(&log, TXN_LOG_SEED);
+        log.extend_from_slice(&checksum.to_le_bytes());
+        let logged = self
+            .key_update(hw, TXN_DICT, COMPACT_LOG_KEY, &log, None, None, Some(&basis), true)
+            .and_then(|_| self.sync(hw, Some(&basis), false));
+        if let Err(e) = logged {
+            // depending on how much of the log made it to disk, this finishes the move or discards the copy
+            self.unrecovered.push(basis);
+            self.txn_recover(hw);
+            return Err(e);
+        }
+        // the move is committed: from here on, an error leaves the log in place to be finished later
+        self.cache[basis_index].dict_compact_finish(hw, dict, old, new)?;
+        self.key_remove(hw, TXN_DICT, COMPACT_LOG_KEY, Some(&basis), false)
+    }
+
+    /// Finishes the move of a compacted dict that was logged in `basis`, or discards the copy of a
+    /// compaction that didn't get as far as logging the move.
+    fn compact_recover(&mut self, hw: &mut PddbOs, basis: &str) {
+        let basis_index = match self.select_basis(Some(basis)) {
+            Some(index) => index,
+            None => return,
+        };
+        let log = match self.key_attributes(hw, TXN_DICT, COMPACT_LOG_KEY, Some(basis)) {
+            Ok(attr) => {
+                let mut log = vec![0u8; attr.len];
+                match self.key_read(hw, TXN_DICT, COMPACT_LOG_KEY, &mut log, None, Some(basis)) {
+                    Ok(read) if read == attr.len => Some(log),
+                    _ => Some(Vec::new()),
+                }
+            }
+            Err(_) => None,
+        };
+        let moved = log.as_ref().filter(|log| log.len() >= 4).and_then(|log| {
+            let (body, checksum) = log.split_at(log.len() - 4);
+            if murmur3_32(body, TXN_LOG_SEED).to_le_bytes() != checksum {
+                return None;
+            }
+            let mut reader = ByteReader { data: body };
+            let dict = reader.name()?;
+            let old = NonZeroU32::new(reader.u32()?)?;
+            let new = NonZeroU32::new(reader.u32()?)?;
+            if reader.is_empty() { Some((dict, old, new)) } else { None }
+        });
+        if let Some((dict, old, new)) = moved {
+            log::info!("finishing the compaction of {} in {}", dict, basis);
+            if let Err(e) = self.cache[basis_index].dict_compact_finish(hw, &dict, old, new) {
+                log::error!("couldn't finish the compaction of {} in {}: {:?}", dict, basis, e);
+                return;
+            }
+        } else {
+            // power was lost before the move was logged, so the original is intact
+            self.cache[basis_index].dict_compact_discard(hw);
+        }
+        if log.is_some() {
+            self.key_remove(hw, TXN_DICT, COMPACT_LOG_KEY, Some(basis), false)
+                .unwrap_or_else(|e| log::error!("couldn't remove the compaction log in {}: {:?}", basis, e));
+        }
+    }
+
     /// Returns a list of all the known dictionaries, across all the basis. A HashSet is returned
     /// because you can have the same-named dictionary in multiple basis, and what we're asking for
     /// is the union of all the dictionary names, without duplicates.
@@ -383,7 +513,8 @@ impl BasisCache {
                 let basis = &mut self.cache[basis_index];
                 basis.populate_caches(hw);
                 for (key, dcache) in basis.dicts.iter() {
-                    if dcache.flags.valid() {
+                    // dicts with unprintable names hold the PDDB's own records, such as transaction logs
+                    if dcache.flags.valid() && !key.contains(VERSION_SEPARATOR) {
                         dict_set.insert(String::from(key));
                     }
                 }
@@ -392,7 +523,7 @@ impl BasisCache {
             for basis in self.cache.iter_mut() {
                 basis.populate_caches(hw);
                 for (key, dcache) in basis.dicts.iter() {
-                    if dcache.flags.valid() {
+                    if dcache.flags.valid() && !key.contains(VERSION_SEPARATOR) {
                         dict_set.insert(String::from(key));
                     }
                 }
@@ -416,20 +547,20 @@ impl BasisCache {
                 let basis = &mut self.cache[basis_index];
                 basis.populate_caches(hw);
                 if let Some(dcache) = basis.dicts.get_mut(dict) {
-                    dcache.key_list(hw, &basis.v2p_map, &basis.cipher, &mut merge_list);
+                    let hidden = dcache.key_list(hw, &basis.v2p_map, &basis.cipher, &mut merge_list);
                     found_dict = true;
-                    key_count += dcache.key_count;
-                    found_key_count += dcache.found_key_count;
+                    key_count += dcache.key_count.saturating_sub(hidden);
+                    found_key_count += dcache.found_key_count.saturating_sub(hidden);
                 }
             }
         } else {
             for basis in self.cache.iter_mut() {
                 basis.populate_caches(hw);
                 if let Some(dcache) = basis.dicts.get_mut(dict) {
-                    dcache.key_list(hw, &basis.v2p_map, &basis.cipher, &mut merge_list);
+                    let hidden = dcache.key_list(hw, &basis.v2p_map, &basis.cipher, &mut merge_list);
                     found_dict = true;
-                    key_count += dcache.key_count;
-                    found_key_count += dcache.found_key_count;
+                    key_count += dcache.key_count.saturating_sub(hidden);
+                    found_key_count += dcache.found_key_count.saturating_sub(hidden);
                 }
             }
         }
@@ -453,6 +584,8 @@ impl BasisCache {
     ) -> Result<()> {
         if let Some(basis_index) = self.select_basis(basis_name) {
             log::debug!("deleting dict {}", dict);
+            let name = self.cache[basis_index].name.clone();
+            let keys = self.key_list(hw, dict, Some(&name)).map(|(keys, _, _)| keys).unwrap_or_default();
             let basis = &mut self.cache[basis_index];
 
             basis.age = basis.age.saturating_add(1);
@@ -460,6 +593,9 @@ impl BasisCache {
             basis.dict_delete(hw, dict, paranoid)?;
             basis.basis_sync(hw);
             basis.pt_sync(hw);
+            for key in keys.iter() {
+                self.record_change(PddbChangeKind::KeyDeleted, &name, dict, key);
+            }
             Ok(())
         } else {
             Err(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))
@@ -539,7 +675,16 @@ impl BasisCache {
                                 // logic.
                                 return Ok(0);
                             }
-                            // large pool fetch
+                            // large pool fetch, through the key's page cache
+                            let cache = match kcache
+                                .data
+                                .get_or_insert_with(|| KeyCacheData::Large(KeyLargeData::new()))
+                            {
+                                KeyCacheData::Large(cache) => cache,
+                                _ => panic!(
+                                    "Key allocated to large area but its cache data was not of the large type"
+                                ),
+                            };
                             let mut abs_cursor = offset.unwrap_or(0) as u64;
                             let mut blocks_read = 0;
                             let mut bytes_read = 0;
@@ -551,9 +696,13 @@ impl BasisCache {
                                 {
                                     let block_start_pos = (abs_cursor % VPAGE_SIZE as u64) as usize;
                                     assert!(pp.valid(), "v2p returned an invalid page");
-                                    let pt_data = hw
-                                        .data_decrypt_page(&basis.cipher, &basis.aad, pp)
-                                        .expect("Decryption auth error");
+                                    if cache.get(start_vpage_addr).is_none() {
+                                        let page = hw
+                                            .data_decrypt_page(&basis.cipher, &basis.aad, pp)
+                                            .expect("Decryption auth error");
+                                        cache.insert(start_vpage_addr, page);
+                                    }
+                                    let pt_data = cache.get(start_vpage_addr).expect("page was just cached");
                                     if blocks_read != 0 {
                                         assert!(
                                             block_start_pos == 0,
@@ -652,11 +801,27 @@ impl BasisCache {
                 basis.age = basis.age.saturating_add(1);
                 basis.clean = false;
                 if dict_entry.ensure_key_entry(hw, &mut basis.v2p_map, &basis.cipher, key) {
-                    if !paranoid {
-                        dict_entry.key_remove(hw, &mut basis.v2p_map, &basis.cipher, key, false);
-                    } else {
-                        // this implementation is still in progress
-                        dict_entry.key_erase(key);
+                    // a key's prior versions go with it. They are looked up first, because the lookup
+                    // prunes removed keys from the cache before they are synced.
+                    let versions = dict_entry.key_versions(hw, &basis.v2p_map, &basis.cipher, key);
+                    let mut names = vec![key.to_string()];
+                    names.extend(versions.iter().map(|&version| version_key_name(key, version)));
+                    for name in names.iter() {
+                        if !dict_entry.ensure_key_entry(hw, &mut basis.v2p_map, &basis.cipher, name) {
+                            continue;
+                        }
+                        if !paranoid {
+                            dict_entry.key_remove(hw, &mut basis.v2p_map, &basis.cipher, name, false);
+                        } else if !dict_entry.key_erase(hw, &mut basis.v2p_map, &basis.cipher, name) {
+                            return Err(Error::new(
+                                ErrorKind::OutOfMemory,
+                                "Ran out of memory syncing small pool",
+                            ));
+                        }
+                    }
+                    if paranoid {
+                        // the most recently decrypted page may have held the key
+                        self.data_cache.scrub();
                     }
                     assert!(dict_entry.clean == false, "dictionary entry should have been marked unclean");
 
@@ -674,6 +839,8 @@ impl BasisCache {
                     basis.basis_sync(hw);
                     // finally, sync the page tables.
                     basis.pt_sync(hw);
+                    let name = basis.name.clone();
+                    self.record_change(PddbChangeKind::KeyDeleted, &name, dict, key);
                     return Ok(());
                 } else {
                     return Err(Error::new(ErrorKind::NotFound, "key not found"));
@@ -686,6 +853,44 @@ impl BasisCache {
         }
     }
 
+    /// Returns the physical pages that hold a key's data on disk: its small pool page, or the pages of
+    /// its large pool reservation. Used by the hosted tests to check what actually went to the disk.
+    #[cfg(not(target_os = "xous"))]
+    pub(crate) fn dbg_key_pages(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        key: &str,
+        basis_name: Option<&str>,
+    ) -> Vec<PhysPage> {
+        let mut pages = Vec::<PhysPage>::new();
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let basis = &mut self.cache[basis_index];
+            if !basis.ensure_dict_in_cache(hw, dict) {
+                return pages;
+            }
+            if let Some(dict_entry) = basis.dicts.get_mut(dict) {
+                if !dict_entry.ensure_key_entry(hw, &mut basis.v2p_map, &basis.cipher, key) {
+                    return pages;
+                }
+                let kcache = dict_entry.keys.get(key).expect("Entry was assured, but not there!");
+                let vpages = match small_storage_index_from_key(kcache, dict_entry.index) {
+                    Some(pool_index) => {
+                        let pool_vaddr = small_storage_base_vaddr_from_indices(dict_entry.index, pool_index);
+                        vec![VirtAddr::new(pool_vaddr).unwrap()]
+                    }
+                    None => kcache.large_pool_vpages(),
+                };
+                for vpage in vpages {
+                    if let Some(pp) = basis.v2p_map.get(&vpage) {
+                        pages.push(*pp);
+                    }
+                }
+            }
+        }
+        pages
+    }
+
     pub(crate) fn key_list_remove(
         &mut self,
         hw: &mut PddbOs,
@@ -701,13 +906,22 @@ impl BasisCache {
             if let Some(dict_entry) = basis.dicts.get_mut(dict) {
                 basis.age = basis.age.saturating_add(1);
                 basis.clean = false;
-                for key in key_list {
+                // prior versions of the keys go with them
+                let mut version_keys = Vec::new();
+                for key in key_list.iter() {
+                    for version in dict_entry.key_versions(hw, &basis.v2p_map, &basis.cipher, key) {
+                        version_keys.push(version_key_name(key, version));
+                    }
+                }
+                let mut removed = Vec::new();
+                for key in key_list.into_iter().chain(version_keys.into_iter()) {
                     if dict_entry.ensure_key_entry(hw, &mut basis.v2p_map, &basis.cipher, &key) {
                         dict_entry.key_remove(hw, &mut basis.v2p_map, &basis.cipher, &key, false);
                         assert!(
                             dict_entry.clean == false,
                             "dictionary entry should have been marked unclean"
                         );
+                        removed.push(key);
                     }
                 }
                 // sync the key pools to disk
@@ -721,6 +935,10 @@ impl BasisCache {
                 basis.basis_sync(hw);
                 // finally, sync the page tables.
                 basis.pt_sync(hw);
+                let name = basis.name.clone();
+                for key in removed.iter() {
+                    self.record_change(PddbChangeKind::KeyDeleted, &name, dict, key);
+                }
                 Ok(())
             } else {
                 Err(Error::new(ErrorKind::NotFound, "dictionary not found"))
@@ -730,8 +948,369 @@ impl BasisCache {
         }
     }
 
+    /// Returns the numbers of the prior versions kept of `key`, oldest first.
+    pub(crate) fn key_versions(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        key: &str,
+        basis_name: Option<&str>,
+    ) -> Result<Vec<u32>> {
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let basis = &mut self.cache[basis_index];
+            if !basis.ensure_dict_in_cache(hw, dict) {
+                return Err(Error::new(ErrorKind::NotFound, "dictionary not found"));
+            }
+            let dict_entry = basis.dicts.get_mut(dict).expect("Entry was assured, but not there!");
+            Ok(dict_entry.key_versions(hw, &basis.v2p_map, &basis.cipher, key))
+        } else {
+            Err(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))
+        }
+    }
+
+    /// If `dict` keeps prior versions of its keys, copies the current contents of `key` into a new
+    /// version and drops the oldest versions past the dict's limit. This is meant to be called before
+    /// the first write through a handle to the key, so that each version is a complete value.
+    pub(crate) fn key_snapshot(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        key: &str,
+        basis_name: Option<&str>,
+    ) -> Result<()> {
+        let keep = self.dict_attributes(hw, dict, basis_name)?.flags.versions() as usize;
+        if keep == 0 {
+            return Ok(());
+        }
+        if version_key_name(key, u32::MAX).len() > KEY_NAME_LEN - 1 {
+            log::warn!("name of {}:{} is too long to keep versions of it", dict, key);
+            return Ok(());
+        }
+        let len = match self.key_attributes(hw, dict, key, basis_name) {
+            Ok(attr) => attr.len,
+            // nothing to keep for a key that doesn't exist yet
+            Err(_) => return Ok(()),
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        let versions = self.key_versions(hw, dict, key, basis_name)?;
+        // wrapping around would file the new version under the number of the oldest one
+        let next = match versions.last() {
+            Some(newest) => newest
+                .checked_add(1)
+                .ok_or(Error::new(ErrorKind::Other, "the version numbers of this key are used up"))?,
+            None => 1,
+        };
+        let version = version_key_name(key, next);
+        // copied a page at a time, so that large keys don't have to fit in RAM
+        let mut page = vec![0u8; VPAGE_SIZE];
+        let mut offset = 0;
+        while offset < len {
+            let chunk = VPAGE_SIZE.min(len - offset);
+            let copied =
+                self.key_read(hw, dict, key, &mut page[..chunk], Some(offset), basis_name).and_then(|read| {
+                    self.key_update(
+                        hw,
+                        dict,
+                        &version,
+                        &page[..read],
+                        Some(offset),
+                        Some(len),
+                        basis_name,
+                        offset == 0,
+                    )
+                    .map(|_| read)
+                });
+            match copied {
+                Ok(0) => break,
+                Ok(read) => offset += read,
+                Err(e) => {
+                    // don't leave a partial copy behind as if it were a version
+                    self.key_remove(hw, dict, &version, basis_name, false).ok();
+                    return Err(e);
+                }
+            }
+        }
+
+        let excess = (versions.len() + 1).saturating_sub(keep);
+        if excess > 0 {
+            let expired = versions[..excess].iter().map(|&v| version_key_name(key, v)).collect();
+            self.key_list_remove(hw, dict, expired, basis_name)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the writes and deletes of `txn` to the basis it names, all or nothing. The transaction is
+    /// first logged to `TXN_DICT` in that basis; if power is lost before the log is complete, none of its
+    /// keys were touched, and if it is lost after, `txn_recover()` finishes the transaction the next
+    /// time the basis is mounted.
+    pub(crate) fn txn_commit(&mut self, hw: &mut PddbOs, txn: &Transaction) -> Result<()> {
+        let basis_index = self
+            .select_basis(txn.basis.as_deref())
+            .ok_or(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))?;
+        let basis = self.cache[basis_index].name.clone();
+        // a log left behind by an earlier transaction must be finished before it can be replaced
+        self.unrecovered.push(basis.clone());
+        self.txn_recover(hw);
+        if self.key_attributes(hw, TXN_DICT, TXN_LOG_KEY, Some(&basis)).is_ok() {
+            return Err(Error::new(ErrorKind::Other, "an earlier transaction couldn't be finished"));
+        }
+        let mut pages_needed = 2;
+        for op in txn.ops.iter() {
+            if op.dict().is_empty()
+                || op.dict().len() > DICT_NAME_LEN - 1
+                || op.dict().contains(VERSION_SEPARATOR)
+                || op.key().is_empty()
+                || op.key().len() > KEY_NAME_LEN - 1
+                || op.key().contains(VERSION_SEPARATOR)
+            {
+                return Err(Error::new(ErrorKind::InvalidInput, "invalid dict or key name in transaction"));
+            }
+            if let TxnOp::Write { data, .. } = op {
+                pages_needed += 2 + data.len() / VPAGE_SIZE;
+            }
+        }
+        // the final length of each key written or deleted, by dict, to check against the dicts' quotas
+        let mut lens: HashMap<&str, Vec<(&str, Option<u64>)>> = HashMap::new();
+        for op in txn.ops.iter() {
+            let len = match op {
+                TxnOp::Write { data, .. } => Some(data.len() as u64),
+                TxnOp::Delete { .. } => None,
+            };
+            let keys = lens.entry(op.dict()).or_default();
+            keys.retain(|(key, _)| *key != op.key());
+            keys.push((op.key(), len));
+        }
+        for (dict, keys) in lens.iter() {
+            self.quota_check(hw, basis_index, dict, keys)?;
+        }
+        let mut log = Transaction { basis: Some(basis.clone()), ops: txn.ops.clone() }.encode();
+        pages_needed += 2 + log.len() / VPAGE_SIZE;
+        // make sure the whole transaction fits before it is logged, because a logged transaction can only
+        // be finished, not undone
+        if !hw.ensure_fast_space_alloc(pages_needed, &self.cache) {
+            return Err(Error::new(ErrorKind::OutOfMemory, "No free space for the transaction"));
+        }
+        // the checksum catches a log that was only partially written when power was lost
+        let checksum = murmur3_32(&log, TXN_LOG_SEED);
+        log.extend_from_slice(&checksum.to_le_bytes());
+        self.key_update(hw, TXN_DICT, TXN_LOG_KEY, &log, None, None, Some(&basis), true)?;
+        self.sync(hw, Some(&basis), false)?;
+        // the transaction is committed: from here on, an error leaves the log in place to be finished later
+        self.txn_apply(hw, &basis, &txn.ops)?;
+        self.key_remove(hw, TXN_DICT, TXN_LOG_KEY, Some(&basis), false)
+    }
+
+    /// Finishes the transactions and dict compactions that were logged, but not completely applied, in
+    /// the bases mounted since the last call.
+    pub(crate) fn txn_recover(&mut self, hw: &mut PddbOs) {
+        for basis in std::mem::take(&mut self.unrecovered) {
+            if !self.basis_contains(&basis) {
+                continue;
+            }
+            self.compact_recover(hw, &basis);
+            let len = match self.key_attributes(hw, TXN_DICT, TXN_LOG_KEY, Some(&basis)) {
+                Ok(attr) => attr.len,
+                Err(_) => continue,
+            };
+            let mut log = vec![0u8; len];
+            let txn = match self.key_read(hw, TXN_DICT, TXN_LOG_KEY, &mut log, None, Some(&basis)) {
+                Ok(read) if read == len && len >= 4 => {
+                    let (body, checksum) = log.split_at(len - 4);
+                    if murmur3_32(body, TXN_LOG_SEED).to_le_bytes() == checksum {
+                        Transaction::decode(body).filter(|txn| txn.basis.as_deref() == Some(basis.as_str()))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            if let Some(txn) = txn {
+                log::info!("finishing a transaction of {} operations in {}", txn.ops.len(), basis);
+                if let Err(e) = self.txn_apply(hw, &basis, &txn.ops) {
+                    log::error!("couldn't finish the transaction in {}: {:?}", basis, e);
+                    continue;
+                }
+            } else {
+                // power was lost while the log was being written, so none of the keys were touched
+                log::warn!("discarding an incomplete transaction log in {}", basis);
+            }
+            self.key_remove(hw, TXN_DICT, TXN_LOG_KEY, Some(&basis), false)
+                .unwrap_or_else(|e| log::error!("couldn't remove the transaction log in {}: {:?}", basis, e));
+        }
+    }
+
+    /// Applies `ops` to `basis`. Applying the same operations twice has the same result as applying them
+    /// once, so a transaction can be finished after an interruption at any point.
+    fn txn_apply(&mut self, hw: &mut PddbOs, basis: &str, ops: &[TxnOp]) -> Result<()> {
+        for op in ops.iter() {
+            match op {
+                TxnOp::Write { dict, key, data } => {
+                    // quotas were checked against the outcome of the whole transaction when it was
+                    // committed; part-way through, a dict may be over its quota
+                    self.key_write(hw, dict, key, data, None, None, Some(basis), true)?;
+                }
+                TxnOp::Delete { dict, key } => match self.key_remove(hw, dict, key, Some(basis), false) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                    _ => (),
+                },
+            }
+        }
+        self.sync(hw, Some(basis), false)
+    }
+
+    /// Returns the space used by `dict` in the basis selected by `basis_name`, and the name of that basis.
+    pub(crate) fn dict_usage(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        basis_name: Option<&str>,
+    ) -> Result<(String, PddbUsage)> {
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let basis = &mut self.cache[basis_index];
+            if !basis.ensure_dict_in_cache(hw, dict) {
+                return Err(Error::new(ErrorKind::NotFound, "dictionary not found"));
+            }
+            let dict_entry = basis.dicts.get_mut(dict).expect("Entry was assured, but not there!");
+            let usage = dict_entry.usage(hw, &basis.v2p_map, &basis.cipher);
+            Ok((basis.name.clone(), usage))
+        } else {
+            Err(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))
+        }
+    }
+
+    /// Returns the space used by all the dicts in the basis selected by `basis_name`, and the name of that
+    /// basis.
+    pub(crate) fn basis_usage(
+        &mut self,
+        hw: &mut PddbOs,
+        basis_name: Option<&str>,
+    ) -> Result<(String, PddbUsage)> {
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let basis = &mut self.cache[basis_index];
+            basis.populate_caches(hw);
+            let mut total = PddbUsage::default();
+            for dict_entry in basis.dicts.values_mut().filter(|d| d.flags.valid()) {
+                total.add(&dict_entry.usage(hw, &basis.v2p_map, &basis.cipher));
+            }
+            Ok((basis.name.clone(), total))
+        } else {
+            Err(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))
+        }
+    }
+
+    /// Returns the number of bytes of free space in the data region. Pages of bases that aren't open are
+    /// counted as free, because there is no way to tell them apart from free pages.
+    pub(crate) fn free_space(&self, hw: &PddbOs) -> u64 {
+        let mapped: usize = self.cache.iter().map(|basis| basis.v2p_map.len()).sum();
+        hw.data_pages().saturating_sub(mapped) as u64 * VPAGE_SIZE as u64
+    }
+
+    /// Returns the quota of `dict` in the basis named `basis`, if it has one.
+    pub(crate) fn dict_quota(&mut self, hw: &mut PddbOs, dict: &str, basis: &str) -> Option<u64> {
+        if !self.quotas.contains_key(basis) {
+            // look up just the quota dict: this runs on the first write to each basis, so it can't afford to
+            // populate the caches of the whole basis
+            let mut dicts = BTreeSet::new();
+            if let Some(basis_index) = self.select_basis(Some(basis)) {
+                let entry = &mut self.cache[basis_index];
+                if entry.ensure_dict_in_cache(hw, QUOTA_DICT) {
+                    let dict_entry =
+                        entry.dicts.get_mut(QUOTA_DICT).expect("Entry was assured, but not there!");
+                    dict_entry.key_list(hw, &entry.v2p_map, &entry.cipher, &mut dicts);
+                }
+            }
+            let mut quotas = HashMap::new();
+            for name in dicts {
+                let mut quota = [0u8; 8];
+                if let Ok(8) = self.key_read(hw, QUOTA_DICT, &name, &mut quota, None, Some(basis)) {
+                    quotas.insert(name, u64::from_le_bytes(quota));
+                }
+            }
+            self.quotas.insert(basis.to_string(), quotas);
+        }
+        self.quotas[basis].get(dict).copied()
+    }
+
+    /// Limits the total length of the keys of `dict` in the basis selected by `basis_name`, counting the
+    /// prior versions kept of them, to `quota` bytes; `None` removes the limit. A dict that is already over
+    /// a new quota keeps its keys, but can't grow until it is back under.
+    pub(crate) fn dict_set_quota(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        quota: Option<u64>,
+        basis_name: Option<&str>,
+    ) -> Result<()> {
+        let basis_index = self
+            .select_basis(basis_name)
+            .ok_or(Error::new(ErrorKind::NotFound, "Requested basis not found, or PDDB not mounted."))?;
+        let basis = self.cache[basis_index].name.clone();
+        if dict.is_empty() || dict.len() > DICT_NAME_LEN - 1 || dict.contains(VERSION_SEPARATOR) {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid dict name"));
+        }
+        // make sure the quotas already set are loaded before the cached copy is updated
+        self.dict_quota(hw, dict, &basis);
+        match quota {
+            Some(quota) => {
+                self.key_update(hw, QUOTA_DICT, dict, &quota.to_le_bytes(), None, None, Some(&basis), true)?
+            }
+            None => match self.key_remove(hw, QUOTA_DICT, dict, Some(&basis), false) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => (),
+            },
+        }
+        let quotas = self.quotas.get_mut(&basis).expect("quotas were loaded, but not there!");
+        match quota {
+            Some(quota) => quotas.insert(dict.to_string(), quota),
+            None => quotas.remove(dict),
+        };
+        Ok(())
+    }
+
+    /// Returns a `QuotaExceeded` error if `dict` has a quota in the basis at `basis_index`, and giving the
+    /// keys in `lens` the lengths paired with them (`None` for keys that are deleted) would grow the dict
+    /// past it.
+    fn quota_check(
+        &mut self,
+        hw: &mut PddbOs,
+        basis_index: usize,
+        dict: &str,
+        lens: &[(&str, Option<u64>)],
+    ) -> Result<()> {
+        let name = self.cache[basis_index].name.clone();
+        let quota = match self.dict_quota(hw, dict, &name) {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let lens: HashMap<&str, Option<u64>> = lens.iter().cloned().collect();
+        let mut current = 0;
+        let mut after: u64 = lens.values().map(|len| len.unwrap_or(0)).sum();
+        let basis = &mut self.cache[basis_index];
+        if basis.ensure_dict_in_cache(hw, dict) {
+            let dict_entry = basis.dicts.get_mut(dict).expect("Entry was assured, but not there!");
+            if dict_entry.keys.len() < dict_entry.key_count as usize {
+                dict_entry.fill(hw, &basis.v2p_map, &basis.cipher, false);
+            }
+            for (key, kcache) in dict_entry.keys.iter().filter(|(_, kcache)| kcache.flags.valid()) {
+                current += kcache.len;
+                if !lens.contains_key(key.as_str()) {
+                    after += kcache.len;
+                }
+            }
+        }
+        if after > quota && after > current {
+            log::warn!("{}:{} would use {} bytes, over its quota of {}", name, dict, after, quota);
+            Err(QuotaExceeded::error())
+        } else {
+            Ok(())
+        }
+    }
+
     /// Updates a key in a dictionary; if it doesn't exist, creates it. User can specify a basis,
-    /// or rely upon the auto-basis select algorithm.
+    /// or rely upon the auto-basis select algorithm. Fails with `QuotaExceeded` if the update would take
+    /// the dictionary over its quota.
     pub(crate) fn key_update(
         &mut self,
         hw: &mut PddbOs,
@@ -742,6 +1321,33 @@ impl BasisCache {
         alloc_hint: Option<usize>,
         basis_name: Option<&str>,
         truncate: bool,
+    ) -> Result<()> {
+        if let Some(basis_index) = self.select_basis(basis_name) {
+            let name = self.cache[basis_index].name.clone();
+            if self.dict_quota(hw, dict, &name).is_some() {
+                // writes past the end of a key extend it; other writes keep its length, unless truncating
+                let end = (offset.unwrap_or(0) + data.len()) as u64;
+                let len = match self.key_attributes(hw, dict, key, Some(&name)) {
+                    Ok(attr) if !truncate => end.max(attr.len as u64),
+                    _ => end,
+                };
+                self.quota_check(hw, basis_index, dict, &[(key, Some(len))])?;
+            }
+        }
+        self.key_write(hw, dict, key, data, offset, alloc_hint, basis_name, truncate)
+    }
+
+    /// `key_update()`, without the quota check.
+    fn key_write(
+        &mut self,
+        hw: &mut PddbOs,
+        dict: &str,
+        key: &str,
+        data: &[u8],
+        offset: Option<usize>,
+        alloc_hint: Option<usize>,
+        basis_name: Option<&str>,
+        truncate: bool,
     ) -> Result<()> {
         // we have to estimate how many pages are needed *before* we do anything, because we can't
         // mutate the page table to allocate data while we're accessing the page table. This huge gob of code
@@ -872,6 +1478,7 @@ impl BasisCache {
 
             // now do the sync
             if let Some(dict_entry) = basis.dicts.get_mut(dict) {
+                let existed = dict_entry.ensure_key_entry(hw, &mut basis.v2p_map, &basis.cipher, key);
                 let updated_ptr = dict_entry.key_update(
                     hw,
                     &mut basis.v2p_map,
@@ -898,6 +1505,9 @@ impl BasisCache {
                 basis.basis_sync(hw);
                 // finally, sync the page tables.
                 basis.pt_sync(hw);
+                let kind = if existed { PddbChangeKind::KeyUpdated } else { PddbChangeKind::KeyCreated };
+                let name = basis.name.clone();
+                self.record_change(kind, &name, dict, key);
             } else {
                 return Err(Error::new(
                     ErrorKind::NotFound,
@@ -1133,13 +1743,19 @@ impl BasisCache {
         }
     }
 
-    pub(crate) fn basis_add(&mut self, basis: BasisCacheEntry) { self.cache.push(basis); }
+    pub(crate) fn basis_add(&mut self, basis: BasisCacheEntry) {
+        self.changes.push(PddbChange::new(PddbChangeKind::BasisMounted, &basis.name, "", ""));
+        self.unrecovered.push(basis.name.clone());
+        self.cache.push(basis);
+    }
 
     pub(crate) fn basis_unmount(&mut self, hw: &mut PddbOs, basis_name: &str) -> Result<()> {
         if let Some(basis_index) = self.select_basis(Some(basis_name)) {
             let basis = &mut self.cache[basis_index];
             basis.sync(hw, false)?;
             self.cache.retain(|x| x.name != basis_name);
+            self.quotas.remove(basis_name);
+            self.changes.push(PddbChange::new(PddbChangeKind::BasisUnmounted, basis_name, "", ""));
             Ok(())
         } else {
             Err(Error::new(ErrorKind::NotFound, "Basis not found"))
@@ -1175,6 +1791,15 @@ impl BasisCache {
         }
     }
 
+    /// Deletes a mounted basis and forgets it without syncing it first, as when an import into it fails.
+    pub(crate) fn basis_discard(&mut self, hw: &mut PddbOs, basis_name: &str) -> Result<()> {
+        self.basis_delete(hw, basis_name)?;
+        self.cache.retain(|x| x.name != basis_name);
+        self.quotas.remove(basis_name);
+        self.changes.push(PddbChange::new(PddbChangeKind::BasisUnmounted, basis_name, "", ""));
+        Ok(())
+    }
+
     pub(crate) fn sync(&mut self, hw: &mut PddbOs, basis_name: Option<&str>, cleanup: bool) -> Result<()> {
         if cleanup {
             log::info!("calling sync with cleanup!");
@@ -1700,11 +2325,17 @@ impl BasisCacheEntry {
     /// dictionary index for this to work.
     pub(crate) fn dict_decrypt(&self, hw: &mut PddbOs, pp: &PhysPage) -> Option<Dictionary> {
         if let Some(data) = hw.data_decrypt_page(&self.cipher, &self.aad, &pp) {
-            let mut dict = Dictionary::default();
-            for (&src, dst) in data[size_of::<JournalType>()..].iter().zip(dict.deref_mut().iter_mut()) {
-                *dst = src;
-            }
-            Some(dict)
+            let record = DictRecord::decode(&data[size_of::<JournalType>()..]);
+            let mut name = DictName::default();
+            name.len = record.name.len() as u8;
+            name.data[..record.name.len()].copy_from_slice(record.name);
+            Some(Dictionary {
+                flags: record.flags,
+                age: record.age,
+                num_keys: record.num_keys,
+                free_key_index: record.free_key_index,
+                name,
+            })
         } else {
             None
         }
@@ -1953,12 +2584,136 @@ impl BasisCacheEntry {
         }
     }
 
-    /// Runs through the dictionary listing in a basis and compacts them. Call when the
-    /// the dictionary space becomes sufficiently fragmented that accesses are becoming
+    /// Writes a compacted copy of the named dictionary to an empty slot: its key descriptors are
+    /// renumbered to be contiguous, and its small pool data is repacked into as few pages as possible.
+    /// The copy is named `COMPACT_COPY_DICT` until `dict_compact_finish()` moves it into the place of
+    /// the original, which is left untouched until then. Returns the slots of the original and the copy.
+    /// On error, the copy is discarded again.
+    ///
+    /// Call when the dictionary space becomes sufficiently fragmented that accesses are becoming
     /// inefficient.
-    #[allow(dead_code)]
-    pub(crate) fn dict_compact(&self, _basis_name: Option<&str>) -> Result<()> {
-        unimplemented!();
+    pub(crate) fn dict_compact(&mut self, hw: &mut PddbOs, name: &str) -> Result<(NonZeroU32, NonZeroU32)> {
+        if !self.ensure_dict_in_cache(hw, name) {
+            return Err(Error::new(ErrorKind::NotFound, "Dictionary not found"));
+        }
+        // flush any pending writes, so that fill() sees exactly what is on disk
+        let dcache = self.dicts.get_mut(name).expect("entry was ensured, but somehow missing");
+        if !dcache.sync_small_pool(hw, &mut self.v2p_map, &self.cipher) {
+            return Err(Error::new(ErrorKind::OutOfMemory, "Ran out of memory syncing small pool"));
+        }
+        self.dict_sync(hw, name, false)?;
+        self.pt_sync(hw);
+
+        // the original is read back from disk the next time it's needed
+        let mut dcache = self.dicts.remove(name).expect("entry was ensured, but somehow missing");
+        dcache.fill(hw, &self.v2p_map, &self.cipher, false);
+        let old_index = dcache.index;
+        let new_index = NonZeroU32::new(self.dict_get_free_offset(hw)).unwrap();
+        // pages left in the slot by an earlier crash would be read back as part of the copy
+        self.dict_slot_erase(hw, new_index);
+        self.pt_sync(hw);
+        dcache.compact(hw, &self.v2p_map, &self.cipher, new_index)?;
+
+        // count the copy before writing it: too high a count only makes searches for a dict run longer,
+        // while too low a count hides dicts
+        self.dicts.insert(COMPACT_COPY_DICT.to_string(), dcache);
+        self.num_dicts += 1;
+        self.clean = false;
+        self.basis_sync(hw);
+        let dcache = self.dicts.get_mut(COMPACT_COPY_DICT).expect("copy was just inserted");
+        let written = if dcache.sync_small_pool(hw, &mut self.v2p_map, &self.cipher) {
+            self.dict_sync(hw, COMPACT_COPY_DICT, false)
+        } else {
+            Err(Error::new(ErrorKind::OutOfMemory, "Ran out of memory syncing small pool"))
+        };
+        if let Err(e) = written {
+            self.dict_compact_discard(hw);
+            return Err(e);
+        }
+        self.pt_sync(hw);
+        Ok((old_index, new_index))
+    }
+
+    /// Replaces the dictionary `name` in slot `old` with the compacted copy that `dict_compact()` wrote
+    /// to slot `new`. Only call this once the move has been logged: the original is wiped first, and
+    /// then the copy is renamed. Finishing a move again has no effect, so a move that was interrupted
+    /// at any point can be finished later.
+    pub(crate) fn dict_compact_finish(
+        &mut self,
+        hw: &mut PddbOs,
+        name: &str,
+        old: NonZeroU32,
+        new: NonZeroU32,
+    ) -> Result<()> {
+        self.dicts.remove(name);
+        self.dicts.remove(COMPACT_COPY_DICT);
+        self.dict_slot_erase(hw, old);
+        self.pt_sync(hw);
+        self.basis_sync(hw);
+
+        let copy_vaddr = VirtAddr::new(new.get() as u64 * DICT_VSIZE).unwrap();
+        let copy = match self.v2p_map.get(&copy_vaddr).and_then(|pp| self.dict_decrypt(hw, pp)) {
+            Some(dict) if dict.flags.valid() => dict,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "compacted copy is missing")),
+        };
+        let copy_name = std::str::from_utf8(&copy.name.data[..copy.name.len as usize]).unwrap_or("");
+        if copy_name == name {
+            // the copy was renamed already
+            return Ok(());
+        } else if copy_name != COMPACT_COPY_DICT {
+            return Err(Error::new(ErrorKind::InvalidData, "compacted copy is missing"));
+        }
+        // renaming rewrites the first page of the copy, which holds its record along with its first keys
+        let mut dcache = DictCacheEntry::new(copy, new.get() as usize, &self.aad);
+        dcache.fill(hw, &self.v2p_map, &self.cipher, false);
+        dcache.clean = false;
+        self.dicts.insert(name.to_string(), dcache);
+        self.dict_sync(hw, name, false)?;
+        self.pt_sync(hw);
+        Ok(())
+    }
+
+    /// Removes the copy left behind by a compaction whose move wasn't logged, if there is one. The large
+    /// keys of the copy are still those of the original, so only the copy's own pages are freed.
+    pub(crate) fn dict_compact_discard(&mut self, hw: &mut PddbOs) {
+        let index = match self.dicts.remove(COMPACT_COPY_DICT) {
+            Some(dcache) => Some(dcache.index),
+            None => {
+                self.dict_deep_search(hw, COMPACT_COPY_DICT).and_then(|(index, _)| NonZeroU32::new(index))
+            }
+        };
+        if let Some(index) = index {
+            log::warn!("discarding the copy of a dictionary whose compaction didn't finish");
+            self.dict_slot_erase(hw, index);
+            self.pt_sync(hw);
+            self.basis_sync(hw);
+        }
+    }
+
+    /// Wipes and frees every page of the key index and small pool of the dictionary slot `index`. Large
+    /// key data is left alone. Doesn't touch the cache; call `pt_sync()` afterwards.
+    fn dict_slot_erase(&mut self, hw: &mut PddbOs, index: NonZeroU32) {
+        let index_base = index.get() as u64 * DICT_VSIZE;
+        let small_base = (index.get() - 1) as u64 * SMALL_POOL_STRIDE + SMALL_POOL_START;
+        let held_dict = match self.v2p_map.get(&VirtAddr::new(index_base).unwrap()) {
+            Some(pp) => self.dict_decrypt(hw, pp).map_or(false, |dict| dict.flags.valid()),
+            None => false,
+        };
+        for (vaddr, pp) in self.v2p_map.iter_mut() {
+            if pp.valid()
+                && ((index_base..index_base + DICT_VSIZE).contains(&vaddr.get())
+                    || (small_base..small_base + SMALL_POOL_STRIDE).contains(&vaddr.get()))
+            {
+                let mut random = [0u8; PAGE_SIZE];
+                hw.trng_slice(&mut random);
+                hw.patch_data(&random, pp.page_number() * PAGE_SIZE as u32);
+                hw.fast_space_free(pp);
+            }
+        }
+        if held_dict {
+            self.num_dicts -= 1;
+            self.clean = false;
+        }
     }
 
     /// Syncs *only* the basis header to disk.
@@ -6,7 +6,6 @@ use std::io::{Error, ErrorKind, Result};
 use std::num::NonZeroU32;
 
 use aes_gcm_siv::Aes256GcmSiv;
-use bitfield::bitfield;
 #[cfg(feature = "perfcounter")]
 use perflib::{PERFMETA_ENDBLOCK, PERFMETA_NONE, PERFMETA_STARTBLOCK};
 
@@ -15,13 +14,6 @@ use crate::api::*;
 #[cfg(feature = "perfcounter")]
 use crate::FILE_ID_SERVICES_PDDB_SRC_DICTIONARY;
 
-bitfield! {
-    #[derive(Copy, Clone, PartialEq, Eq)]
-    pub struct DictFlags(u32);
-    impl Debug;
-    pub valid, set_valid: 0;
-}
-
 /// RAM based copy of the dictionary structures on disk. Most of the methods on this function operate on
 /// keys within the Dictionary. Operations on the Dictionary itself originate from the containing Basis
 /// structure.
@@ -59,8 +51,8 @@ pub(crate) struct DictCacheEntry {
     /// got space. Thus if a key was found allocated to the Nth index position, but the previous N-1
     /// positions are empty, the only way we could have gotten there was if we had allocated lots of
     /// small data, filled up the pool to the Nth position, and then deleted all of that prior data. This
-    /// situation could create pathologies in the memory usage overhead of the small_pool, which until we
-    /// have a "defrag" operation for the small pool, we may just have to live with.
+    /// situation could create pathologies in the memory usage overhead of the small_pool, which are
+    /// cleaned up by `compact()`.
     pub(crate) small_pool: Vec<KeySmallPool>,
     /// free space of each small pool element. It's a collection of free space along with the Vec index of
     /// the small_pool. We don't keep the KeySmallPool itself in the small_pool_free directly because
@@ -193,27 +185,21 @@ impl DictCacheEntry {
                     assert!(cache_pp.page_number() == pp.page_number(), "cache inconsistency error");
                     let cache =
                         index_cache.data.as_ref().expect("Cache should be full, it was already checked...");
-                    let mut keydesc = KeyDescriptor::default();
                     let start = size_of::<JournalType>() + (try_entry % DK_PER_VPAGE) * DK_STRIDE;
-                    for (&src, dst) in
-                        cache[start..start + DK_STRIDE].iter().zip(keydesc.deref_mut().iter_mut())
-                    {
-                        *dst = src;
-                    }
-                    if keydesc.flags.valid() {
+                    let keydesc = KeyRecord::decode(&cache[start..start + DK_STRIDE]);
+                    if keydesc.valid() {
                         let kcache = KeyCacheEntry {
                             start: keydesc.start,
                             len: keydesc.len,
                             reserved: keydesc.reserved,
-                            flags: keydesc.flags,
+                            flags: KeyFlags(keydesc.flags),
                             age: keydesc.age,
                             descriptor_index: NonZeroU32::new(try_entry as u32).unwrap(),
                             clean: true,
                             data: None,
                             atime: self.created.elapsed().as_millis() as u64,
                         };
-                        let kname = std::str::from_utf8(&keydesc.name.data[..keydesc.name.len as usize])
-                            .expect("key is not valid utf-8");
+                        let kname = std::str::from_utf8(keydesc.name).expect("key is not valid utf-8");
                         let key_exists_and_valid = if let Some(kcache) = self.keys.get(kname) {
                             kcache.flags.valid()
                         } else {
@@ -255,13 +241,14 @@ impl DictCacheEntry {
 
     /// merges the list of keys in this dict cache entry into a merge_list.
     /// The `merge_list` is used because keys are presented as a union across all open basis.
+    /// Returns the number of keys left out because they hold prior versions of other keys.
     pub(crate) fn key_list(
         &mut self,
         hw: &mut PddbOs,
         v2p_map: &HashMap<VirtAddr, PhysPage>,
         cipher: &Aes256GcmSiv,
         merge_list: &mut BTreeSet<String>,
-    ) {
+    ) -> u32 {
         #[cfg(feature = "perfcounter")]
         hw.perf_entry(FILE_ID_SERVICES_PDDB_SRC_DICTIONARY, PERFMETA_STARTBLOCK, 0, std::line!());
         // ensure that the key cache is filled
@@ -270,13 +257,39 @@ impl DictCacheEntry {
         }
         #[cfg(feature = "perfcounter")]
         hw.perf_entry(FILE_ID_SERVICES_PDDB_SRC_DICTIONARY, PERFMETA_NONE, 0, std::line!());
-        for (key, kcache) in self.keys.iter() {
-            if kcache.flags.valid() {
+        let mut hidden = 0;
+        for (key, _) in self.keys.iter().filter(|(_, kcache)| kcache.flags.valid()) {
+            // prior versions of keys are only reachable through `key_versions()`
+            if key.contains(VERSION_SEPARATOR) {
+                hidden += 1;
+            } else {
                 merge_list.insert(key.to_string());
             }
         }
         #[cfg(feature = "perfcounter")]
         hw.perf_entry(FILE_ID_SERVICES_PDDB_SRC_DICTIONARY, PERFMETA_ENDBLOCK, 0, std::line!());
+        hidden
+    }
+
+    /// Returns the numbers of the prior versions kept of key `name`, oldest first.
+    pub(crate) fn key_versions(
+        &mut self,
+        hw: &mut PddbOs,
+        v2p_map: &HashMap<VirtAddr, PhysPage>,
+        cipher: &Aes256GcmSiv,
+        name: &str,
+    ) -> Vec<u32> {
+        if self.keys.len() < self.key_count as usize {
+            self.fill(hw, v2p_map, cipher, false);
+        }
+        let mut versions: Vec<u32> = self
+            .keys
+            .iter()
+            .filter(|(_, kcache)| kcache.flags.valid())
+            .filter_map(|(key, _)| key.strip_prefix(name)?.strip_prefix(VERSION_SEPARATOR)?.parse().ok())
+            .collect();
+        versions.sort_unstable();
+        versions
     }
 
     /// Simply ensures we have the description of a key in cache. Only tries to load small key data.
@@ -351,21 +364,17 @@ impl DictCacheEntry {
                         .expect("dictionary PP should be in existence");
                     assert!(pp.valid(), "v2p returned an invalid page");
                     assert!(cache_pp.page_number() == pp.page_number(), "cache inconsistency error");
-                    let mut keydesc = KeyDescriptor::default();
                     let start = size_of::<JournalType>() + (try_entry % DK_PER_VPAGE) * DK_STRIDE;
-                    // there is 1 byte of extra padding that causes the slices of keydesc.deref_mut() to be
-                    // 128 bytes long instead of 127 bytes...
-                    keydesc.deref_mut()[..DK_STRIDE].copy_from_slice(&cache[start..start + DK_STRIDE]);
-                    let kname = std::str::from_utf8(&keydesc.name.data[..keydesc.name.len as usize])
-                        .expect("key is not valid utf-8");
-                    if keydesc.flags.valid() {
+                    let keydesc = KeyRecord::decode(&cache[start..start + DK_STRIDE]);
+                    let kname = std::str::from_utf8(keydesc.name).expect("key is not valid utf-8");
+                    if keydesc.valid() {
                         if kname == name_str {
                             log::debug!("found {} at entry {}/{}", name_str, try_entry, start);
                             let kcache = KeyCacheEntry {
                                 start: keydesc.start,
                                 len: keydesc.len,
                                 reserved: keydesc.reserved,
-                                flags: keydesc.flags,
+                                flags: KeyFlags(keydesc.flags),
                                 age: keydesc.age,
                                 descriptor_index: NonZeroU32::new(try_entry as u32).unwrap(),
                                 clean: true,
@@ -418,8 +427,7 @@ impl DictCacheEntry {
                     // allocate a large amount of small data, and then delete all but the
                     // most recently allocated one, leaving an orphan at a high index, which is then
                     // subsequently treated as read-only so none of the subsequent
-                    // write/update ops would have occassion to move it. This would
-                    // need to be remedied with a "defrag" operation, but for now, we don't have that.
+                    // write/update ops would have occassion to move it. `compact()` remedies this.
                     let ksp = KeySmallPool::new();
                     self.small_pool.push(ksp);
                 }
@@ -755,166 +763,186 @@ impl DictCacheEntry {
                 self.small_pool[pool_index].clean = false;
                 // note: there is no need to update small_pool_free because the reserved size did not change.
             } else {
-                // it's a large key
-                if let Some(_kcd) = &kcache.data {
-                    unimplemented!("caching is not yet implemented for large data sets");
-                } else {
-                    kcache.age = kcache.age.saturating_add(1);
-                    kcache.clean = false;
-                    /* // this was for debugging a patching bug -- OK to remove
-                    if data.len() == 4 {
-                        use std::convert::TryInto;
-                        log::info!("patching checksum: {:x} at offset {}", u32::from_le_bytes(data.try_into().unwrap()), offset);
-                    }*/
-                    // 1. handle unaligned start offsets
-                    let mut written: usize = 0;
-                    if ((kcache.start + offset as u64 + written as u64) % VPAGE_SIZE as u64) != 0 {
-                        let start_vpage_addr =
-                            ((kcache.start + offset as u64) / VPAGE_SIZE as u64) * VPAGE_SIZE as u64;
-                        let pp = v2p_map
-                            .get(&VirtAddr::new(start_vpage_addr).unwrap())
-                            .expect("large key data allocation missing");
-                        assert!(pp.valid(), "v2p returned an invalid page");
-                        let mut pt_data = match hw.data_decrypt_page(&cipher, &self.aad, pp) {
-                            Some(data) => data,
-                            None => {
-                                // this case is triggered by the following circumstance:
-                                //  - we reserved data that includes this current page
-                                //  - up until now, we've only written data into the previous page (so this
-                                //    page is not initialized -- it's garbage)
-                                //  - we just issued an update that causes the data to touch this page for the
-                                //    first time
-                                // in response to this, we allocate a fresh page of 0's.
-                                log::debug!(
-                                    "Reserved and uninitialized page encountered updating large block: {} {:x}..{}->{}; update @{}..{}",
-                                    name,
-                                    kcache.start,
-                                    kcache.len,
-                                    kcache.reserved,
-                                    offset,
-                                    data.len()
-                                );
-                                let mut d = vec![0u8; VPAGE_SIZE + size_of::<JournalType>()];
-                                for (&src, dst) in (hw.trng_u32() % JOURNAL_RAND_RANGE)
-                                    .to_le_bytes()
-                                    .iter()
-                                    .zip(d[..size_of::<JournalType>()].iter_mut())
-                                {
-                                    *dst = src;
-                                }
-                                d
-                            }
-                        };
-                        if offset > 0 {
-                            log::trace!(
-                                "patching offset {}, total length {}, data length {}",
-                                offset % VPAGE_SIZE,
+                // it's a large key. Pages are patched through the key's page cache, so that updating a page
+                // that was recently read or written doesn't require decrypting it again.
+                let mut cache = match kcache.data.take() {
+                    Some(KeyCacheData::Large(cache)) => cache,
+                    Some(KeyCacheData::Small(_)) => {
+                        panic!("Key allocated to large area but its cache data was not of the large type");
+                    }
+                    None => KeyLargeData::new(),
+                };
+                // closures capture all of `self` in this edition, so hand them just the AAD
+                let aad = &self.aad;
+                kcache.age = kcache.age.saturating_add(1);
+                kcache.clean = false;
+                /* // this was for debugging a patching bug -- OK to remove
+                if data.len() == 4 {
+                    use std::convert::TryInto;
+                    log::info!("patching checksum: {:x} at offset {}", u32::from_le_bytes(data.try_into().unwrap()), offset);
+                }*/
+                // 1. handle unaligned start offsets
+                let mut written: usize = 0;
+                if ((kcache.start + offset as u64 + written as u64) % VPAGE_SIZE as u64) != 0 {
+                    let start_vpage_addr =
+                        ((kcache.start + offset as u64) / VPAGE_SIZE as u64) * VPAGE_SIZE as u64;
+                    let pp = v2p_map
+                        .get(&VirtAddr::new(start_vpage_addr).unwrap())
+                        .expect("large key data allocation missing");
+                    assert!(pp.valid(), "v2p returned an invalid page");
+                    let mut pt_data = match cache
+                        .take(start_vpage_addr)
+                        .or_else(|| hw.data_decrypt_page(&cipher, aad, pp))
+                    {
+                        Some(data) => data,
+                        None => {
+                            // this case is triggered by the following circumstance:
+                            //  - we reserved data that includes this current page
+                            //  - up until now, we've only written data into the previous page (so this page
+                            //    is not initialized -- it's garbage)
+                            //  - we just issued an update that causes the data to touch this page for the
+                            //    first time
+                            // in response to this, we allocate a fresh page of 0's.
+                            log::debug!(
+                                "Reserved and uninitialized page encountered updating large block: {} {:x}..{}->{}; update @{}..{}",
+                                name,
+                                kcache.start,
                                 kcache.len,
+                                kcache.reserved,
+                                offset,
                                 data.len()
                             );
+                            let mut d = vec![0u8; VPAGE_SIZE + size_of::<JournalType>()];
+                            for (&src, dst) in (hw.trng_u32() % JOURNAL_RAND_RANGE)
+                                .to_le_bytes()
+                                .iter()
+                                .zip(d[..size_of::<JournalType>()].iter_mut())
+                            {
+                                *dst = src;
+                            }
+                            d
                         }
-                        for (&src, dst) in data[written..]
+                    };
+                    if offset > 0 {
+                        log::trace!(
+                            "patching offset {}, total length {}, data length {}",
+                            offset % VPAGE_SIZE,
+                            kcache.len,
+                            data.len()
+                        );
+                    }
+                    for (&src, dst) in data[written..]
+                        .iter()
+                        .zip(pt_data[size_of::<JournalType>() + (offset % VPAGE_SIZE)..].iter_mut())
+                    {
+                        *dst = src;
+                        written += 1;
+                    }
+                    if written < data.len() {
+                        assert!(
+                            (kcache.start + offset as u64 + written as u64) % VPAGE_SIZE as u64 == 0,
+                            "alignment algorithm failed"
+                        );
+                    }
+                    hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut pt_data, &pp);
+                    cache.insert(start_vpage_addr, pt_data);
+                }
+                // 2. do the rest
+                while written < data.len() {
+                    let vpage_addr = ((kcache.start + written as u64 + offset as u64) / VPAGE_SIZE as u64)
+                        * VPAGE_SIZE as u64;
+                    let pp = v2p_map
+                        .get(&VirtAddr::new(vpage_addr).unwrap())
+                        .expect("large key data allocation missing");
+                    assert!(pp.valid(), "v2p returned an invalid page");
+                    if data.len() - written >= VPAGE_SIZE {
+                        // overwrite whole pages without decryption
+                        let mut block = [0u8; VPAGE_SIZE + size_of::<JournalType>()];
+                        for (&src, dst) in (hw.trng_u32() % JOURNAL_RAND_RANGE)
+                            .to_le_bytes()
                             .iter()
-                            .zip(pt_data[size_of::<JournalType>() + (offset % VPAGE_SIZE)..].iter_mut())
+                            .zip(block[..size_of::<JournalType>()].iter_mut())
+                        {
+                            *dst = src;
+                        }
+                        for (&src, dst) in
+                            data[written..].iter().zip(block[size_of::<JournalType>()..].iter_mut())
                         {
                             *dst = src;
                             written += 1;
                         }
-                        if written < data.len() {
-                            assert!(
-                                (kcache.start + offset as u64 + written as u64) % VPAGE_SIZE as u64 == 0,
-                                "alignment algorithm failed"
-                            );
+                        hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut block, pp);
+                        // only refresh pages that were already cached: a long streaming write would
+                        // otherwise push out all the pages that are being worked on.
+                        if cache.take(vpage_addr).is_some() {
+                            cache.insert(vpage_addr, block.to_vec());
                         }
-                        hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut pt_data, &pp);
-                    }
-                    // 2. do the rest
-                    while written < data.len() {
-                        let vpage_addr = ((kcache.start + written as u64 + offset as u64)
-                            / VPAGE_SIZE as u64)
-                            * VPAGE_SIZE as u64;
-                        let pp = v2p_map
-                            .get(&VirtAddr::new(vpage_addr).unwrap())
-                            .expect("large key data allocation missing");
-                        assert!(pp.valid(), "v2p returned an invalid page");
-                        if data.len() - written >= VPAGE_SIZE {
-                            // overwrite whole pages without decryption
-                            let mut block = [0u8; VPAGE_SIZE + size_of::<JournalType>()];
+                    } else {
+                        // handle partial trailing pages
+                        if let Some(mut pt_data) =
+                            cache.take(vpage_addr).or_else(|| hw.data_decrypt_page(&cipher, aad, pp))
+                        {
+                            for (&src, dst) in
+                                data[written..].iter().zip(pt_data[size_of::<JournalType>()..].iter_mut())
+                            {
+                                *dst = src;
+                                written += 1;
+                            }
+                            hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut pt_data, pp);
+                            cache.insert(vpage_addr, pt_data);
+                        } else {
+                            // page didn't exist, initialize it with 0's and merge the tail end.
+                            let mut pt_data = [0u8; VPAGE_SIZE + size_of::<JournalType>()];
                             for (&src, dst) in (hw.trng_u32() % JOURNAL_RAND_RANGE)
                                 .to_le_bytes()
                                 .iter()
-                                .zip(block[..size_of::<JournalType>()].iter_mut())
+                                .zip(pt_data[..size_of::<JournalType>()].iter_mut())
                             {
                                 *dst = src;
                             }
                             for (&src, dst) in
-                                data[written..].iter().zip(block[size_of::<JournalType>()..].iter_mut())
+                                data[written..].iter().zip(pt_data[size_of::<JournalType>()..].iter_mut())
                             {
                                 *dst = src;
                                 written += 1;
                             }
-                            hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut block, pp);
-                        } else {
-                            // handle partial trailing pages
-                            if let Some(pt_data) = hw.data_decrypt_page(&cipher, &self.aad, pp).as_mut() {
-                                for (&src, dst) in
-                                    data[written..].iter().zip(pt_data[size_of::<JournalType>()..].iter_mut())
-                                {
-                                    *dst = src;
-                                    written += 1;
-                                }
-                                hw.data_encrypt_and_patch_page(cipher, &self.aad, pt_data, pp);
-                            } else {
-                                // page didn't exist, initialize it with 0's and merge the tail end.
-                                let mut pt_data = [0u8; VPAGE_SIZE + size_of::<JournalType>()];
-                                for (&src, dst) in (hw.trng_u32() % JOURNAL_RAND_RANGE)
-                                    .to_le_bytes()
-                                    .iter()
-                                    .zip(pt_data[..size_of::<JournalType>()].iter_mut())
-                                {
-                                    *dst = src;
-                                }
-                                for (&src, dst) in
-                                    data[written..].iter().zip(pt_data[size_of::<JournalType>()..].iter_mut())
-                                {
-                                    *dst = src;
-                                    written += 1;
-                                }
-                                hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut pt_data, pp);
-                            }
+                            hw.data_encrypt_and_patch_page(cipher, &self.aad, &mut pt_data, pp);
+                            cache.insert(vpage_addr, pt_data.to_vec());
                         }
                     }
-                    log::trace!("data written: {}, data requested to write: {}", written, data.len());
-                    assert!(
-                        written == data.len(),
-                        "algorithm problem -- didn't write all the data we thought we would"
-                    );
-                    // 3. truncate or extend
-                    // check if we grew the length; extend the length by exactly enough if so.
-                    if kcache.len < (data.len() + offset) as u64 {
-                        kcache.len = (data.len() + offset) as u64;
-                    } else if truncate {
-                        // discard all whole pages after written+offset, and reset the reserved field to the
-                        // smaller size.
-                        log::trace!("PageAligned VA components: {}, {}", written, offset);
-                        let vpage_end_offset = PageAlignedVa::from((written + offset) as u64);
-                        if (vpage_end_offset.as_u64() - kcache.start) > kcache.reserved {
-                            for vpage in (vpage_end_offset.as_u64()..kcache.start + kcache.reserved)
-                                .step_by(VPAGE_SIZE)
-                            {
-                                if let Some(pp) = v2p_map.get_mut(&VirtAddr::new(vpage).unwrap()) {
-                                    assert!(pp.valid(), "v2p returned an invalid page");
-                                    log::trace!("fast_space_free key_update {} before", pp.journal());
-                                    hw.fast_space_free(pp);
-                                    assert!(pp.valid() == false, "pp is still marked as valid!");
-                                }
+                }
+                log::trace!("data written: {}, data requested to write: {}", written, data.len());
+                assert!(
+                    written == data.len(),
+                    "algorithm problem -- didn't write all the data we thought we would"
+                );
+                // 3. truncate or extend
+                // check if we grew the length; extend the length by exactly enough if so.
+                if kcache.len < (data.len() + offset) as u64 {
+                    kcache.len = (data.len() + offset) as u64;
+                } else if truncate {
+                    // discard all whole pages after written+offset, and reset the reserved field to the
+                    // smaller size.
+                    log::trace!("PageAligned VA components: {}, {}", written, offset);
+                    let vpage_end_offset = PageAlignedVa::from((written + offset) as u64);
+                    if (vpage_end_offset.as_u64() - kcache.start) > kcache.reserved {
+                        for vpage in
+                            (vpage_end_offset.as_u64()..kcache.start + kcache.reserved).step_by(VPAGE_SIZE)
+                        {
+                            cache.take(vpage);
+                            if let Some(pp) = v2p_map.get_mut(&VirtAddr::new(vpage).unwrap()) {
+                                assert!(pp.valid(), "v2p returned an invalid page");
+                                log::trace!("fast_space_free key_update {} before", pp.journal());
+                                hw.fast_space_free(pp);
+                                assert!(pp.valid() == false, "pp is still marked as valid!");
                             }
-                            kcache.reserved = vpage_end_offset.as_u64() - kcache.start;
-                            kcache.clean = false;
-                            kcache.len = (data.len() + offset) as u64;
                         }
+                        kcache.reserved = vpage_end_offset.as_u64() - kcache.start;
+                        kcache.clean = false;
+                        kcache.len = (data.len() + offset) as u64;
                     }
                 }
+                kcache.data = Some(KeyCacheData::Large(cache));
             }
         } else {
             // key does not exist (or was previously erased) -- create one or replace the erased one.
@@ -1024,7 +1052,7 @@ impl DictCacheEntry {
                     age: 0,
                     descriptor_index,
                     clean: false,
-                    data: None, // no caching implemented yet for large keys
+                    data: None, // the page cache is filled in as the key is written and read
                     atime: self.created.elapsed().as_millis() as u64,
                 };
                 self.keys.insert(name.to_string(), kcache);
@@ -1072,8 +1100,9 @@ impl DictCacheEntry {
     }
 
     /// Used to remove a key from the dictionary. If you call it with a non-existent key,
-    /// the routine has no effect, and does not report an error. Small keys are not immediately
-    /// overwritten in paranoid mode, but large keys are.
+    /// the routine has no effect, and does not report an error. Large keys are always overwritten
+    /// with noise on disk. In paranoid mode, any copy of the key's data cached in RAM is zeroed as
+    /// well; small keys are only gone from the disk once their pool is synced, see `key_erase()`.
     pub fn key_remove(
         &mut self,
         hw: &mut PddbOs,
@@ -1083,12 +1112,6 @@ impl DictCacheEntry {
         paranoid: bool,
     ) {
         log::debug!("removing key {}", name_str);
-        if paranoid {
-            // large records are paranoid-erased, by default, because of the pool-reuse problem.
-            unimplemented!(
-                "Paranoid erase for small records not yet implemented. Calling sync after an update, however, effectively does a paranoid erase."
-            );
-        }
         // this call will check the disk to see if there's key data that's not in cache.
         if self.ensure_key_entry(hw, v2p_map, cipher, name_str) {
             let name = String::from(name_str);
@@ -1108,6 +1131,9 @@ impl DictCacheEntry {
                 kcache.flags.set_valid(false);
                 kcache.clean = false;
 
+                if paranoid {
+                    kcache.scrub_data();
+                }
                 if let Some(small_index) = small_storage_index_from_key(kcache, self.index) {
                     // handle the small pool case
                     let ksp = &mut self.small_pool[small_index];
@@ -1125,6 +1151,7 @@ impl DictCacheEntry {
                     need_rebuild = true;
                 } else {
                     // handle the large pool case
+                    kcache.data = None; // cached pages would be of data that is about to be overwritten
                     // mark the entry as invalid and dirty; virtual space is one huge memory leak...
                     // ...but we remove the virtual pages from the page pool, effectively reclaiming the
                     // physical space.
@@ -1172,12 +1199,20 @@ impl DictCacheEntry {
         // if there's no key....we're done!
     }
 
-    /// used to remove a key from the dictionary, syncing 0's to the disk in the key's place
-    /// sort of less relevant now that the large keys have a paranoid mode; probably this routine should
-    /// actually be a higher-level function that catches the paranoid request and does an "update" of 0's
-    /// to the key then does a disk sync and then calls remove
-    pub fn key_erase(&mut self, _name: &str) {
-        unimplemented!();
+    /// Removes a key and overwrites it on disk right away. A small key's pool is re-encrypted without it
+    /// under a fresh nonce, over the same physical page, so neither the key's plaintext nor the
+    /// ciphertext it was stored as survive; large keys are overwritten with noise by `key_remove()`.
+    /// Copies of the data cached in RAM are zeroed. Returns `false` if there wasn't enough FastSpace to
+    /// sync the small pool. Follow up with `dict_sync` and `pt_sync` to wipe the key's descriptor.
+    pub fn key_erase(
+        &mut self,
+        hw: &mut PddbOs,
+        v2p_map: &mut HashMap<VirtAddr, PhysPage>,
+        cipher: &Aes256GcmSiv,
+        name: &str,
+    ) -> bool {
+        self.key_remove(hw, v2p_map, cipher, name, true);
+        self.sync_small_pool(hw, v2p_map, cipher)
     }
 
     /// estimates the amount of space needed to sync the dict cache. Pass this to ensure_fast_space_alloc()
@@ -1349,6 +1384,91 @@ impl DictCacheEntry {
     /// No data cache to flush yet...large pool caches not implemented!
     pub(crate) fn sync_large_pool(&self) {}
 
+    /// Moves this dictionary to the empty slot `index`, renumbering its key descriptors so they occupy
+    /// indices 1..=N, and repacking the small pool data first-fit into as few pools as possible. Large
+    /// key data is not moved; only its descriptor is renumbered.
+    ///
+    /// Nothing is written to disk, and the pages of the old slot are left as they are, so the copy on
+    /// disk stays intact until the caller has synced the new one and wiped the old slot.
+    ///
+    /// ASSUMES: the dictionary has been sync'd and `fill()`ed prior to calling this. On return, every
+    /// key and small pool is dirty.
+    pub(crate) fn compact(
+        &mut self,
+        hw: &mut PddbOs,
+        v2p_map: &HashMap<VirtAddr, PhysPage>,
+        cipher: &Aes256GcmSiv,
+        index: NonZeroU32,
+    ) -> Result<()> {
+        // visit keys in their current index order, so the relative order of descriptors is preserved
+        let mut key_list: Vec<(u32, String)> = self
+            .keys
+            .iter()
+            .filter(|(_name, kcache)| kcache.flags.valid())
+            .map(|(name, kcache)| (kcache.descriptor_index.get(), name.to_string()))
+            .collect();
+        key_list.sort();
+
+        // all the small key data has to be in RAM before it is repacked
+        let mut data_cache = PlaintextCache { data: None, tag: None };
+        for (_index, name) in key_list.iter() {
+            let kcache = self.keys.get(name).expect("key list is derived from the cache");
+            if small_storage_index_from_key(&kcache, self.index).is_some() && kcache.data.is_none() {
+                self.refill_small_key(hw, v2p_map, cipher, &mut data_cache, name);
+                if self.keys.get(name).expect("key list is derived from the cache").data.is_none() {
+                    log::error!("compaction aborted: couldn't read back small key {}", name);
+                    return Err(Error::new(ErrorKind::InvalidData, "small key data is unreadable"));
+                }
+            }
+        }
+
+        // repack the small pool, and renumber the descriptors
+        let mut small_pool = Vec::<KeySmallPool>::new();
+        for (new_index, (_old_index, name)) in key_list.iter().enumerate() {
+            let kcache = self.keys.get_mut(name).expect("key list is derived from the cache");
+            if small_storage_index_from_key(&kcache, self.index).is_some() {
+                let pool_index = match small_pool.iter().position(|ksp| ksp.avail as u64 >= kcache.reserved) {
+                    Some(pool_index) => pool_index,
+                    None => {
+                        small_pool.push(KeySmallPool::new());
+                        small_pool.len() - 1
+                    }
+                };
+                let ksp = &mut small_pool[pool_index];
+                ksp.contents.push(name.to_string());
+                ksp.avail -= kcache.reserved as u16;
+                // the offset within the pool is assigned by sync_small_pool()
+                kcache.start = small_storage_base_vaddr_from_indices(index, pool_index);
+            }
+            kcache.descriptor_index = NonZeroU32::new(new_index as u32 + 1).unwrap();
+            kcache.clean = false;
+        }
+
+        let key_pages = 1 + key_list.len() / DK_PER_VPAGE;
+        let old_key_pages = 1 + (self.last_disk_key_index + 1) as usize / DK_PER_VPAGE;
+        log::info!(
+            "compacted {} keys: {}->{} index pages, {}->{} small pools",
+            key_list.len(),
+            old_key_pages,
+            key_pages,
+            self.small_pool.len(),
+            small_pool.len()
+        );
+
+        self.index = index;
+        self.small_pool = small_pool;
+        self.rebuild_free_pool();
+        let next_free = key_list.len() as u32 + 1;
+        self.free_keys.clear();
+        self.free_keys
+            .push(Reverse(FreeKeyRange { start: next_free, run: KEY_MAXCOUNT as u32 - 1 - next_free }));
+        self.last_disk_key_index = next_free;
+        self.key_count = key_list.len() as u32;
+        self.found_key_count = key_list.len() as u32;
+        self.clean = false;
+        Ok(())
+    }
+
     /// Finds the next available slot to store the key metadata (not the data itself). It also
     /// does bookkeeping to bound brute-force searches for keys within the dictionary's index space.
     pub(crate) fn get_free_key_index(&mut self) -> Option<NonZeroU32> {
@@ -1474,6 +1594,49 @@ impl DictCacheEntry {
         }
     }
 
+    /// Tallies the space used by the keys of this dict, and by the pages mapped for it in `v2p_map`.
+    pub(crate) fn usage(
+        &mut self,
+        hw: &mut PddbOs,
+        v2p_map: &HashMap<VirtAddr, PhysPage>,
+        cipher: &Aes256GcmSiv,
+    ) -> PddbUsage {
+        if self.keys.len() < self.key_count as usize {
+            self.fill(hw, v2p_map, cipher, false);
+        }
+        let mut usage = PddbUsage::default();
+        let mut data_pages = 0;
+        for (name, kcache) in self.keys.iter().filter(|(_, kcache)| kcache.flags.valid()) {
+            if !name.contains(VERSION_SEPARATOR) {
+                usage.key_count += 1;
+            }
+            usage.used += kcache.len;
+            usage.reserved += kcache.reserved;
+            if small_storage_index_from_key(kcache, self.index).is_none() {
+                // large keys are allocated whole pages; count the ones that have been written
+                for vpage in (kcache.start..kcache.start + kcache.reserved).step_by(VPAGE_SIZE) {
+                    if v2p_map.contains_key(&VirtAddr::new(vpage).unwrap()) {
+                        data_pages += 1;
+                    }
+                }
+            }
+        }
+        // the descriptors and the small pool of a dict each have a region of virtual memory to themselves
+        let index_base = self.index.get() as u64 * DICT_VSIZE;
+        let small_base = (self.index.get() - 1) as u64 * SMALL_POOL_STRIDE + SMALL_POOL_START;
+        let mut index_pages = 0;
+        for vaddr in v2p_map.keys() {
+            if (index_base..index_base + DICT_VSIZE).contains(&vaddr.get()) {
+                index_pages += 1;
+            } else if (small_base..small_base + SMALL_POOL_STRIDE).contains(&vaddr.get()) {
+                data_pages += 1;
+            }
+        }
+        usage.allocated = data_pages * VPAGE_SIZE as u64;
+        usage.index = index_pages * VPAGE_SIZE as u64;
+        usage
+    }
+
     /// removes a key cache entry from a dictionary cache, returning the amount of space liberated with
     /// the eviction. 0 means the key was either 0-sized, or it could not be evicted (possibly
     /// because it was dirty; you need to call sync before evicting anything from the cache)
@@ -1483,17 +1646,30 @@ impl DictCacheEntry {
             if kcache.flags.valid() && !kcache.clean {
                 return 0;
             }
-            if let Some(KeyCacheData::Small(_)) = kcache.data {
-                // can only prune small records
-                let pruned = kcache.size();
-                kcache.data.take(); // this effectively frees up the key cache data
-                // mark the key's pool as unclean, so it is processed for filling
-                let pool_index = small_storage_index_from_key(&kcache, self.index).expect("index missing");
-                self.small_pool[pool_index].evicted = true;
-                log::debug!("pruned {} bytes from key {} / evicted ksp index: {}", pruned, key, pool_index);
-                pruned
-            } else {
-                0
+            match kcache.data {
+                Some(KeyCacheData::Small(_)) => {
+                    let pruned = kcache.size();
+                    kcache.data.take(); // this effectively frees up the key cache data
+                    // mark the key's pool as unclean, so it is processed for filling
+                    let pool_index =
+                        small_storage_index_from_key(&kcache, self.index).expect("index missing");
+                    self.small_pool[pool_index].evicted = true;
+                    log::debug!(
+                        "pruned {} bytes from key {} / evicted ksp index: {}",
+                        pruned,
+                        key,
+                        pool_index
+                    );
+                    pruned
+                }
+                Some(KeyCacheData::Large(_)) => {
+                    // cached large key pages are never dirty, so they can simply be dropped
+                    let pruned = kcache.size();
+                    kcache.data.take();
+                    log::debug!("pruned {} bytes of cached pages from large key {}", pruned, key);
+                    pruned
+                }
+                None => 0,
             }
         } else {
             0
@@ -1721,6 +1897,15 @@ impl PlaintextCache {
             self.tag = None;
         }
     }
+
+    /// Zeroes and drops the cached page
+    pub fn scrub(&mut self) {
+        if let Some(data) = self.data.as_mut() {
+            data.iter_mut().for_each(|b| *b = 0);
+        }
+        self.data = None;
+        self.tag = None;
+    }
 }
 
 #[cfg(test)]
@@ -7,37 +7,6 @@ use aes_gcm_siv::{Nonce, Tag};
 use super::PAGE_SIZE;
 use crate::*;
 
-/// Each free_pool entry takes about 4 bytes, so give-or-take we have about 1000 free_pool
-/// entries per page of storage for the free_pool, or 4k * 1000 ~ 4MiB per page, when PhysAddr is a u32
-pub(crate) const FASTSPACE_PAGES: usize = 2;
-
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-#[repr(u8)]
-pub enum SpaceState {
-    /// pages that are completely un-spoken for
-    Free = 0,
-    /// pages that are in the process of being used, but the journal has yet to be committed
-    /// in other words, these are pages that might be in the RAM cache.
-    MaybeUsed = 1,
-    /// pages that are confirm plus chop fully used
-    Used = 2,
-    /// pages that are no longer used and need to be erased
-    Dirty = 3,
-}
-impl From<u8> for SpaceState {
-    fn from(arg: u8) -> Self {
-        match arg & 0x3 {
-            0 => SpaceState::Free,
-            1 => SpaceState::MaybeUsed,
-            2 => SpaceState::Used,
-            _ => SpaceState::Dirty,
-        }
-    }
-}
-impl From<SpaceState> for u8 {
-    fn from(arg: SpaceState) -> Self { arg as u8 }
-}
-
 pub(crate) const FASTSPACE_FREE_POOL_LEN: usize = ((PAGE_SIZE * FASTSPACE_PAGES)
     - (size_of::<Nonce>() + size_of::<Tag>()))
     / core::mem::size_of::<PhysPage>();
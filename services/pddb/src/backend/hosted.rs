@@ -4,6 +4,7 @@ use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::io::SeekFrom;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Once;
 
 use crate::api::*;
@@ -63,6 +64,25 @@ fn flashmem() -> &'static mut FlashSingleton {
     }
 }
 
+/// Number of writes that complete before a simulated power loss. `POWER_ON` when no loss is pending,
+/// and `POWER_LOST` once it has happened.
+static WRITES_UNTIL_POWER_LOSS: AtomicUsize = AtomicUsize::new(POWER_ON);
+const POWER_ON: usize = usize::MAX;
+const POWER_LOST: usize = usize::MAX - 1;
+
+/// Returns `false` if the power has been lost, and the write must be dropped. Power is lost between
+/// writes: the PDDB rewrites some of its records in place, and doesn't survive a torn write of those.
+fn powered() -> bool {
+    let prev = WRITES_UNTIL_POWER_LOSS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+            POWER_ON | POWER_LOST => None,
+            0 => Some(POWER_LOST),
+            n => Some(n - 1),
+        })
+        .unwrap_or_else(|n| n);
+    prev != POWER_LOST && prev != 0
+}
+
 #[derive(Copy, Clone)]
 pub struct KeyExport {
     pub basis_name: [u8; 64],
@@ -91,6 +111,13 @@ impl EmuStorage {
         }
     }
 
+    /// Overwrites the whole image, e.g. to rewind the disk to an earlier copy.
+    pub fn restore(&mut self, image: &[u8]) {
+        flashmem().memory.copy_from_slice(image);
+        flashmem().disk.seek(SeekFrom::Start(0)).expect("couldn't seek PDDB");
+        flashmem().disk.write_all(image).expect("couldn't write PDDB");
+    }
+
     pub fn dump_fs(&self, name: &Option<String>) {
         let defaultname = String::from("pddb");
         let rootname = name.as_ref().unwrap_or(&defaultname);
@@ -117,6 +144,15 @@ pub struct HostedSpinor {}
 impl HostedSpinor {
     pub fn new() -> Self { HostedSpinor {} }
 
+    /// Simulates losing power after the next `writes` writes: every write after them is dropped.
+    /// `None` restores power.
+    pub fn power_loss_after(&self, writes: Option<usize>) {
+        WRITES_UNTIL_POWER_LOSS.store(writes.unwrap_or(POWER_ON), Ordering::SeqCst);
+    }
+
+    /// Returns `true` if power was lost since it was last restored.
+    pub fn power_lost(&self) -> bool { WRITES_UNTIL_POWER_LOSS.load(Ordering::SeqCst) == POWER_LOST }
+
     pub fn patch(
         &self,
         _region: &[u8],
@@ -125,6 +161,9 @@ impl HostedSpinor {
         offset: u32,
     ) -> Result<(), xous::Error> {
         // println!("patch at {:x}+{}", offset, data.len());
+        if !powered() {
+            return Ok(());
+        }
         for (&src, dst) in data
             .iter()
             .zip(flashmem().memory.as_mut_slice()[offset as usize..offset as usize + data.len()].iter_mut())
@@ -137,6 +176,9 @@ impl HostedSpinor {
     }
 
     pub fn bulk_erase(&self, start: u32, len: u32) -> Result<(), xous::Error> {
+        if !powered() {
+            return Ok(());
+        }
         for b in flashmem().memory.as_mut_slice()
             [(start - xous::PDDB_LOC) as usize..(start - xous::PDDB_LOC + len) as usize]
             .iter_mut()
@@ -38,22 +38,11 @@ use zeroize::Zeroize;
 #[cfg(feature = "migration1")]
 use crate::backend::migration1to2::*;
 
-/// Implementation-specific PDDB structures: for Precursor/Xous OS pair
-pub(crate) const MBBB_PAGES: usize = 10;
-pub(crate) const FSCB_PAGES: usize = 16;
-
-/// size of a physical page
-pub const PAGE_SIZE: usize = spinor::SPINOR_ERASE_SIZE as usize;
-/// size of a virtual page -- after the AES encryption and journaling overhead is subtracted
-pub const VPAGE_SIZE: usize = PAGE_SIZE - size_of::<Nonce>() - size_of::<Tag>() - size_of::<JournalType>();
-
-/// length of the ciphertext in an AES-GCM-SIV page with key commitments
-/// equal to the total plaintext to be encrypted, including the journal number
-/// does not include the MAC overhead
-pub const KCOM_CT_LEN: usize = 4004;
-
-pub(crate) const WRAPPED_AES_KEYSIZE: usize = AES_KEYSIZE + 8;
-const SCD_VERSION: u32 = 2;
+// the layout in `layout.rs` is restated without reference to the hardware or the cipher; hold it to them
+const _: () = assert!(PAGE_SIZE == spinor::SPINOR_ERASE_SIZE as usize);
+const _: () = assert!(
+    NONCE_LEN == size_of::<Nonce>() && TAG_LEN == size_of::<Tag>() && JOURNAL_LEN == size_of::<JournalType>()
+);
 
 #[cfg(all(feature = "pddbtest", feature = "autobasis"))]
 pub const BASIS_TEST_ROOTNAME: &'static str = "test";
@@ -331,6 +320,33 @@ impl PddbOs {
         self.pddb_mr.dump_keys(&export, &name);
     }
 
+    /// The whole PDDB region as it would be on the FLASH, for tests that check what made it to disk
+    #[allow(dead_code)]
+    #[cfg(not(target_os = "xous"))]
+    pub fn dbg_raw_image(&self) -> &[u8] { unsafe { self.pddb_mr.as_slice() } }
+
+    /// The raw, still encrypted contents of a data page
+    #[allow(dead_code)]
+    #[cfg(not(target_os = "xous"))]
+    pub fn dbg_data_page(&self, page: &PhysPage) -> Vec<u8> {
+        let base = self.data_phys_base.as_usize() + page.page_number() as usize * PAGE_SIZE;
+        unsafe { self.pddb_mr.as_slice()[base..base + PAGE_SIZE].to_vec() }
+    }
+
+    /// Simulates losing power after the next `writes` writes to the disk; `None` restores power.
+    #[allow(dead_code)]
+    #[cfg(not(target_os = "xous"))]
+    pub fn dbg_power_loss_after(&self, writes: Option<usize>) { self.spinor.power_loss_after(writes) }
+
+    #[allow(dead_code)]
+    #[cfg(not(target_os = "xous"))]
+    pub fn dbg_power_lost(&self) -> bool { self.spinor.power_lost() }
+
+    /// Rewinds the whole PDDB region to `image`, an earlier copy of `dbg_raw_image()`
+    #[allow(dead_code)]
+    #[cfg(not(target_os = "xous"))]
+    pub fn dbg_restore_image(&mut self, image: &[u8]) { self.pddb_mr.restore(image) }
+
     #[allow(dead_code)]
     #[cfg(any(feature = "precursor", feature = "renode"))]
     pub fn dbg_dump(&self, _name: Option<String>) {
@@ -735,15 +751,12 @@ impl PddbOs {
         }
     }
 
-    /// maps a StaticCryptoData structure into the key area of the PDDB.
-    fn static_crypto_data_get(&self) -> &StaticCryptoData {
-        let scd_ptr = unsafe {
-            self.pddb_mr.as_slice::<u8>()
+    /// decodes the StaticCryptoData structure in the key area of the PDDB.
+    fn static_crypto_data_get(&self) -> ScdRecord<'_> {
+        ScdRecord::decode(unsafe {
+            &self.pddb_mr.as_slice::<u8>()
                 [self.key_phys_base.as_usize()..self.key_phys_base.as_usize() + PAGE_SIZE]
-                .as_ptr() as *const StaticCryptoData
-        };
-        let scd: &StaticCryptoData = unsafe { scd_ptr.as_ref().unwrap() };
-        scd
+        })
     }
 
     #[cfg(feature = "migration1")]
@@ -804,15 +817,15 @@ impl PddbOs {
             // due to interpedencies with other crypto crates.
             let mut new_crypto_keys = StaticCryptoData::default();
             new_crypto_keys.version = scd.version;
-            new_crypto_keys.system_key_pt.copy_from_slice(&scd.system_key_pt);
-            new_crypto_keys.system_key.copy_from_slice(&scd.system_key);
-            new_crypto_keys.salt_base.copy_from_slice(&scd.salt_base);
+            new_crypto_keys.system_key_pt.copy_from_slice(scd.system_key_pt);
+            new_crypto_keys.system_key.copy_from_slice(scd.system_key);
+            new_crypto_keys.salt_base.copy_from_slice(scd.salt_base);
 
             // now try to populate our keys, and prep a migration if necessary
             let mut syskey_pt = [0u8; 32];
             let mut syskey = [0u8; 32];
             let mut keys_updated = false;
-            match self.rootkeys.unwrap_key(&scd.system_key_pt, AES_KEYSIZE) {
+            match self.rootkeys.unwrap_key(scd.system_key_pt, AES_KEYSIZE) {
                 Ok(skpt) => syskey_pt.copy_from_slice(&skpt),
                 Err(e) => match e {
                     KeywrapError::UpgradeToNew((key, upgrade)) => {
@@ -828,7 +841,7 @@ impl PddbOs {
                     }
                 },
             }
-            match self.rootkeys.unwrap_key(&scd.system_key, AES_KEYSIZE) {
+            match self.rootkeys.unwrap_key(scd.system_key, AES_KEYSIZE) {
                 Ok(sk) => syskey.copy_from_slice(&sk),
                 Err(e) => match e {
                     KeywrapError::UpgradeToNew((key, upgrade)) => {
@@ -1424,6 +1437,9 @@ impl PddbOs {
     /// returns a count of the number of pages in the fspace cache
     pub fn fast_space_len(&self) -> usize { self.fspace_cache.len() }
 
+    /// returns the number of pages in the data region, which holds the pages of every basis
+    pub(crate) fn data_pages(&self) -> usize { (PDDB_A_LEN - self.data_phys_base.as_usize()) / PAGE_SIZE }
+
     /// Normally, the fspace_log_next_addr is just incremented, but when it hits the end of the
     /// page, it's set to None. This function will do a modestly expensive scan of the FSCB area
     /// to try and either find another partially filled page, or a completely empty page.
@@ -1757,8 +1773,6 @@ impl PddbOs {
         page: &PhysPage,
     ) -> Option<Vec<u8>> {
         use aes::cipher::KeyInit;
-        const KCOM_NONCE_LEN: usize = 32;
-        const KCOM_LEN: usize = 32;
         const MAC_LEN: usize = 16;
         let ct_slice = unsafe {
             &self.pddb_mr.as_slice()[self.data_phys_base.as_usize() + page.page_number() as usize * PAGE_SIZE
@@ -2024,8 +2038,10 @@ impl PddbOs {
                 .expect("Internal error wrapping our encryption key");
             let wrapped_key_pt =
                 self.rootkeys.wrap_key(&system_keys.pt).expect("Internal error wrapping our encryption key");
+            let scd = self.static_crypto_data_get();
             let mut crypto_keys = StaticCryptoData::default();
-            crypto_keys.deref_mut().copy_from_slice(&self.static_crypto_data_get().deref());
+            crypto_keys.version = scd.version;
+            crypto_keys.salt_base.copy_from_slice(scd.salt_base);
             assert!(wrapped_key_pt.len() == 40);
             assert!(wrapped_key.len() == 40);
             crypto_keys.system_key_pt.copy_from_slice(&wrapped_key_pt);
@@ -2316,8 +2332,8 @@ impl PddbOs {
             self.tt.sleep_ms(100).unwrap();
         }
         let basis_root = BasisRoot {
-            magic: api::PDDB_MAGIC,
-            version: api::PDDB_VERSION,
+            magic: PDDB_MAGIC,
+            version: PDDB_VERSION,
             name: BasisRootName::try_from_str(PDDB_DEFAULT_SYSTEM_BASIS).unwrap(),
             age: 0,
             num_dictionaries: 0,
@@ -2940,6 +2956,23 @@ impl PddbOs {
         BasisKeys { pt: okm_pt, data: okm_data }
     }
 
+    /// Derives the key of a basis archive from its password and the random salt in the archive's header.
+    /// Unlike `basis_derive_key()`, the device's own salt is left out, so that the archive can be opened
+    /// on another device.
+    pub(crate) fn archive_derive_key(&self, salt: &[u8; 16], password: &str) -> [u8; 32] {
+        let mut hashed_password: [u8; 24] = [0; 24];
+        let start_time = self.timestamp_now();
+        bcrypt(BCRYPT_COST, salt, password, &mut hashed_password);
+        let elapsed = self.timestamp_now() - start_time;
+        log::info!("derived archive bcrypt password in {}ms", elapsed);
+
+        let hk = hkdf::Hkdf::<sha2::Sha256>::new(Some(salt), &hashed_password);
+        let mut okm = [0u8; 32];
+        hk.expand(b"pddb archive key", &mut okm).expect("invalid length specified for HKDF");
+        hashed_password.zeroize();
+        okm
+    }
+
     pub(crate) fn reset_dont_ask_init(&self) { self.rootkeys.do_reset_dont_ask_init(); }
 
     pub(crate) fn checksums(&self, modals: Option<&Modals>) -> root_keys::api::Checksums {
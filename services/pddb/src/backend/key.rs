@@ -1,5 +1,7 @@
+use core::mem::size_of;
 use core::ops::{Deref, DerefMut};
 use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
 use std::io::{Error, ErrorKind, Result};
 use std::num::NonZeroU32;
 
@@ -143,12 +145,24 @@ impl KeyCacheEntry {
             None => 0,
             Some(kcd) => match kcd {
                 KeyCacheData::Small(ksd) => ksd.data.len(),
-                KeyCacheData::Large(kld) => kld.data.len(),
+                KeyCacheData::Large(kld) => kld.size(),
             },
         };
         core::mem::size_of::<KeyCacheEntry>() + data_size
     }
 
+    /// Zeroes any copy of the key's data held in RAM, and drops it
+    pub(crate) fn scrub_data(&mut self) {
+        match self.data.as_mut() {
+            Some(KeyCacheData::Small(ksd)) => ksd.data.iter_mut().for_each(|b| *b = 0),
+            Some(KeyCacheData::Large(kld)) => {
+                kld.pages.values_mut().for_each(|page| page.iter_mut().for_each(|b| *b = 0))
+            }
+            None => {}
+        }
+        self.data = None;
+    }
+
     pub(crate) fn atime(&self) -> u64 { self.atime }
 
     pub(crate) fn set_atime(&mut self, atime: u64) { self.atime = atime; }
@@ -157,7 +171,6 @@ impl KeyCacheEntry {
 pub(crate) enum KeyCacheData {
     Small(KeySmallData),
     // the "Medium" type has a region reserved for it, but we haven't coded a handler for it.
-    #[allow(dead_code)] // Large data caching isn't implemented, so of course, we don't ever create this type
     Large(KeyLargeData),
 }
 /// Small data is optimized for low overhead, and always represent a complete copy of the data.
@@ -165,14 +178,57 @@ pub(crate) struct KeySmallData {
     pub clean: bool,
     pub(crate) data: Vec<u8>,
 }
-/// This can hold just a portion of a large key's data. For now, we now essentially manually
-/// encode a sub-slice in parts, but, later on we could get more clever and start to cache
-/// multiple disjoint portions of a large key's data...
-#[allow(dead_code)] // large key caching is not yet implemented
+/// Large data holds decrypted copies of just some of a large key's vpages, up to `LARGE_CACHE_PAGES` of
+/// them. Writes go to disk and to the cached copy at the same time, so cached pages are never dirty and
+/// can be dropped at any time.
 pub(crate) struct KeyLargeData {
-    pub clean: bool,
-    pub(crate) start: u64,
-    pub(crate) data: Vec<u8>,
+    /// plaintext of each cached vpage, including its journal number, indexed by the vpage's virtual address
+    pub(crate) pages: HashMap<u64, Vec<u8>>,
+    /// virtual addresses of the cached vpages, least recently used first
+    pub(crate) lru: VecDeque<u64>,
+}
+impl KeyLargeData {
+    pub(crate) fn new() -> KeyLargeData {
+        KeyLargeData { pages: HashMap::new(), lru: VecDeque::with_capacity(LARGE_CACHE_PAGES) }
+    }
+
+    /// returns the plaintext of the vpage at `vaddr` if it's cached, and marks it as recently used
+    pub(crate) fn get(&mut self, vaddr: u64) -> Option<&Vec<u8>> {
+        if self.pages.contains_key(&vaddr) {
+            self.touch(vaddr);
+        }
+        self.pages.get(&vaddr)
+    }
+
+    /// removes the plaintext of the vpage at `vaddr` from the cache, so it can be patched and written out.
+    /// Hand it back with `insert()` afterwards.
+    pub(crate) fn take(&mut self, vaddr: u64) -> Option<Vec<u8>> {
+        self.lru.retain(|&v| v != vaddr);
+        self.pages.remove(&vaddr)
+    }
+
+    /// caches `page` as the plaintext of the vpage at `vaddr`, evicting the least recently used page if
+    /// the cache is full
+    pub(crate) fn insert(&mut self, vaddr: u64, page: Vec<u8>) {
+        if self.pages.insert(vaddr, page).is_some() {
+            self.touch(vaddr);
+            return;
+        }
+        self.lru.push_back(vaddr);
+        while self.lru.len() > LARGE_CACHE_PAGES {
+            if let Some(oldest) = self.lru.pop_front() {
+                self.pages.remove(&oldest);
+            }
+        }
+    }
+
+    /// amount of plaintext held in the cache
+    pub(crate) fn size(&self) -> usize { self.pages.len() * (VPAGE_SIZE + size_of::<JournalType>()) }
+
+    fn touch(&mut self, vaddr: u64) {
+        self.lru.retain(|&v| v != vaddr);
+        self.lru.push_back(vaddr);
+    }
 }
 
 /// A storage pool for data that is strictly smaller than one VPAGE. These element are serialized
@@ -0,0 +1,332 @@
+//! The on-flash layout of the PDDB: region sizes, record strides, the `PhysPage` bitfield, and decoders
+//! for the records that are read back out of flash.
+//!
+//! Nothing in here depends on the rest of the crate besides `murmur3`, so tools that read PDDB images
+//! (`tools/src/bin/pddb-inspect`) include this file and `murmur3.rs` with `#[path]` instead of restating
+//! them. The backend still writes records through its `#[repr(C)]` structs, but reads them through the
+//! decoders here, so a change to a record's layout has to be made in one place for both to follow it.
+
+use core::cmp::Ordering;
+use core::convert::TryInto;
+use core::hash::{Hash, Hasher};
+
+use bitfield::bitfield;
+
+use super::murmur3::murmur3_32;
+
+/// Implementation-specific PDDB structures: for Precursor/Xous OS pair
+pub(crate) const MBBB_PAGES: usize = 10;
+pub(crate) const FSCB_PAGES: usize = 16;
+
+/// size of a physical page; must match `spinor::SPINOR_ERASE_SIZE`
+pub const PAGE_SIZE: usize = 4096;
+/// size of an AES-GCM-SIV nonce, as stored at the start of each page
+pub(crate) const NONCE_LEN: usize = 12;
+/// size of an AES-GCM-SIV tag, as stored at the end of each page
+pub(crate) const TAG_LEN: usize = 16;
+/// size of the `JournalType` that prefixes the plaintext of each page
+pub(crate) const JOURNAL_LEN: usize = 4;
+/// size of a virtual page -- after the AES encryption and journaling overhead is subtracted
+pub const VPAGE_SIZE: usize = PAGE_SIZE - NONCE_LEN - TAG_LEN - JOURNAL_LEN;
+
+/// length of the ciphertext in an AES-GCM-SIV page with key commitments
+/// equal to the total plaintext to be encrypted, including the journal number
+/// does not include the MAC overhead
+pub const KCOM_CT_LEN: usize = 4004;
+/// length of the key commitment nonce that follows the ciphertext in a key-committed page
+pub(crate) const KCOM_NONCE_LEN: usize = 32;
+/// length of the key commitment that follows the commitment nonce
+pub(crate) const KCOM_LEN: usize = 32;
+
+/// for the life of me, I can't figure out how to query the AES crate to give me the length of a 256-bit key.
+/// I mean, we know what it is, it's well-defined and never changes. But it'd just be nice to you know,
+/// derive it from a const or something with symbolic meaning, but the KeySize Trait is buried in some sort
+/// of a NewBlock trait and I can't figure out how to access it. Looking at the example code on the AES crate
+/// on docs.rs, they just pull the number 16 out of their ass instead of referring to a trait.
+/// So, maybe that's just what you're supposed to do. ¯\_(ツ)_/¯ Oddly enough, a BLOCK_SIZE constant /is/
+/// defined, but maybe that's because it's constant regardless of the key size so it's easy to do.
+pub(crate) const AES_KEYSIZE: usize = 32;
+pub(crate) const WRAPPED_AES_KEYSIZE: usize = AES_KEYSIZE + 8;
+/// version of the `StaticCryptoData` record
+pub(crate) const SCD_VERSION: u32 = 2;
+
+/// size of a `Pte` in the page table
+pub(crate) const PTE_LEN: usize = 16;
+
+/// Each free_pool entry takes about 4 bytes, so give-or-take we have about 1000 free_pool
+/// entries per page of storage for the free_pool, or 4k * 1000 ~ 4MiB per page, when PhysAddr is a u32
+pub(crate) const FASTSPACE_PAGES: usize = 2;
+
+pub(crate) const PDDB_MAGIC: [u8; 4] = [0x50, 0x44, 0x44, 0x42];
+pub(crate) const PDDB_VERSION: u32 = 0x00_00_02_01;
+// this isn't an "official" basis, but it is used for the AAD for encrypting the FastSpace structure
+pub(crate) const PDDB_FAST_SPACE_SYSTEM_BASIS: &'static str = ".FastSpace";
+
+pub(crate) const LARGE_POOL_START: u64 = 0x0000_FE00_0000_0000;
+pub(crate) const KEY_MAXCOUNT: usize = 131_071; // 2^17 - 1
+/// The chosen "stride" of a dict/key entry. Drives a lot of key parameters in the database's characteristics.
+/// This is chosen such that 32 of these entries fit evenly into a VPAGE.
+pub(crate) const DK_STRIDE: usize = 127;
+//// DK_STRIDES per VPAGE
+pub(crate) const DK_PER_VPAGE: usize = VPAGE_SIZE / DK_STRIDE; // should be 32 - use this for computing modulus on dictionary indices
+/// size of a dictionary region in virtual memory
+pub(crate) const DICT_VSIZE: u64 = 0xFE_0000;
+/// maximum number of dictionaries in a system
+pub(crate) const DICT_MAXCOUNT: usize = 16383;
+
+fn le_u32(bytes: &[u8]) -> u32 { u32::from_le_bytes(bytes[..4].try_into().unwrap()) }
+
+fn le_u64(bytes: &[u8]) -> u64 { u64::from_le_bytes(bytes[..8].try_into().unwrap()) }
+
+/// Decodes the `{len: u8, data: [u8]}` name records that end the basis root, dictionary and key records.
+/// A length that runs past the end of the record is clamped to it; the bytes are not checked for utf-8.
+pub(crate) fn record_name(record: &[u8]) -> &[u8] {
+    let len = record[0] as usize;
+    &record[1..(1 + len).min(record.len())]
+}
+
+/// `StaticCryptoData` from hw.rs: the page between the page table and the MBBB
+pub(crate) struct ScdRecord<'a> {
+    pub(crate) version: u32,
+    /// system basis page table key, wrapped with the User0 root key
+    pub(crate) system_key_pt: &'a [u8],
+    /// system basis data key, wrapped with the User0 root key
+    pub(crate) system_key: &'a [u8],
+    /// salt pool; the first 32 bytes are reserved for the HKDF
+    pub(crate) salt_base: &'a [u8],
+}
+impl<'a> ScdRecord<'a> {
+    pub(crate) fn decode(page: &'a [u8]) -> ScdRecord<'a> {
+        let (version, rest) = page[..PAGE_SIZE].split_at(4);
+        let (system_key_pt, rest) = rest.split_at(WRAPPED_AES_KEYSIZE);
+        let (system_key, salt_base) = rest.split_at(WRAPPED_AES_KEYSIZE);
+        ScdRecord { version: le_u32(version), system_key_pt, system_key, salt_base }
+    }
+}
+
+/// `Pte` from pagetable.rs, once decrypted
+pub(crate) struct PteRecord {
+    /// virtual page number; multiply by `VPAGE_SIZE` for the address
+    pub(crate) vpage: u64,
+    /// `PtFlags`
+    pub(crate) flags: u8,
+    pub(crate) nonce: u32,
+    pub(crate) checksum: u32,
+}
+impl PteRecord {
+    /// Returns `None` if the checksum doesn't match, which is how entries that belong to other bases (or to
+    /// nobody) are told apart from those of the basis whose key decrypted `block`.
+    pub(crate) fn decode(block: &[u8]) -> Option<PteRecord> {
+        if block.len() != PTE_LEN {
+            return None;
+        }
+        let nonce = le_u32(&block[8..12]);
+        let checksum = le_u32(&block[12..16]);
+        if murmur3_32(&block[..12], nonce) != checksum {
+            return None;
+        }
+        let mut vpage = [0u8; 8];
+        vpage[..7].copy_from_slice(&block[..7]);
+        Some(PteRecord { vpage: u64::from_le_bytes(vpage), flags: block[7], nonce, checksum })
+    }
+
+    pub(crate) fn vaddr(&self) -> u64 { self.vpage * VPAGE_SIZE as u64 }
+}
+
+bitfield! {
+    #[derive(Copy, Clone, PartialEq, Eq)]
+    pub struct DictFlags(u32);
+    impl Debug;
+    pub valid, set_valid: 0;
+    /// number of prior versions kept of each key; 0 disables versioning
+    pub u8, versions, set_versions: 15, 8;
+}
+
+/// `Dictionary` from dictionary.rs: the first `DK_STRIDE` bytes of a dictionary's virtual region
+pub(crate) struct DictRecord<'a> {
+    pub(crate) flags: DictFlags,
+    pub(crate) age: u32,
+    pub(crate) num_keys: u32,
+    pub(crate) free_key_index: u32,
+    pub(crate) name: &'a [u8],
+}
+impl<'a> DictRecord<'a> {
+    pub(crate) fn decode(record: &'a [u8]) -> DictRecord<'a> {
+        let record = &record[..DK_STRIDE];
+        DictRecord {
+            flags: DictFlags(le_u32(record)),
+            age: le_u32(&record[4..]),
+            num_keys: le_u32(&record[8..]),
+            free_key_index: le_u32(&record[12..]),
+            name: record_name(&record[16..]),
+        }
+    }
+}
+
+/// `KeyDescriptor` from key.rs: one `DK_STRIDE` entry in a dictionary's descriptor pages
+pub(crate) struct KeyRecord<'a> {
+    pub(crate) start: u64,
+    pub(crate) len: u64,
+    pub(crate) reserved: u64,
+    /// `KeyFlags` from api.rs
+    pub(crate) flags: u32,
+    pub(crate) age: u32,
+    pub(crate) name: &'a [u8],
+}
+impl<'a> KeyRecord<'a> {
+    pub(crate) fn decode(record: &'a [u8]) -> KeyRecord<'a> {
+        let record = &record[..DK_STRIDE];
+        KeyRecord {
+            start: le_u64(record),
+            len: le_u64(&record[8..]),
+            reserved: le_u64(&record[16..]),
+            flags: le_u32(&record[24..]),
+            age: le_u32(&record[28..]),
+            name: record_name(&record[32..]),
+        }
+    }
+
+    /// `KeyFlags::valid()`
+    pub(crate) fn valid(&self) -> bool { self.flags & 1 != 0 }
+
+    /// `KeyFlags::unresolved()`
+    pub(crate) fn unresolved(&self) -> bool { self.flags & 2 != 0 }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[repr(u8)]
+pub enum SpaceState {
+    /// pages that are completely un-spoken for
+    Free = 0,
+    /// pages that are in the process of being used, but the journal has yet to be committed
+    /// in other words, these are pages that might be in the RAM cache.
+    MaybeUsed = 1,
+    /// pages that are confirm plus chop fully used
+    Used = 2,
+    /// pages that are no longer used and need to be erased
+    Dirty = 3,
+}
+impl From<u8> for SpaceState {
+    fn from(arg: u8) -> Self {
+        match arg & 0x3 {
+            0 => SpaceState::Free,
+            1 => SpaceState::MaybeUsed,
+            2 => SpaceState::Used,
+            _ => SpaceState::Dirty,
+        }
+    }
+}
+impl From<SpaceState> for u8 {
+    fn from(arg: SpaceState) -> Self { arg as u8 }
+}
+
+/// This has to be manually synchronized with the bit range of the `journal` field below. It doesn't look like
+/// there is a good way to automatically derive this.
+pub(crate) const PHYS_PAGE_JOURNAL_MAX: u8 = 15;
+/// We should be able to change this to a u64 and everything should "just work", but
+/// we'd end up using 2x the amount of data for overhead and bookkeeping.
+#[cfg(not(feature = "u64_pa"))]
+pub type PhysAddr = u32;
+#[cfg(feature = "u64_pa")]
+pub type PhysAddr = u64;
+const BITFIELD_PAGE_WIDTH: usize = core::mem::size_of::<PhysAddr>() * 8 - 12; // "12" should be log2(PAGE_SIZE) but https://github.com/rust-lang/rust/issues/70887
+// Physical page information, coded as a bitfield, because space is a premium!
+bitfield! {
+    #[derive(Copy, Clone, Eq)]
+    pub struct PhysPage(PhysAddr);
+    impl Debug;
+    pub page_number, set_page_number: BITFIELD_PAGE_WIDTH - 1, 0;
+    // this is only used by the page table mechanism
+    pub clean, set_clean: BITFIELD_PAGE_WIDTH + 0;
+    // when set, indicates that the record contents are valid and should be used
+    // when cleared, the record contents are invalid and should be ignored.
+    // valid is used by both FastSpace and the page table mechanism. Note that we rely upon the mapping of 0->not valid.
+    pub valid, set_valid: BITFIELD_PAGE_WIDTH + 1;
+    // these are only used by the FastSpace mechanism; they have no meaning in other contexts
+    pub u8, from into SpaceState, space_state, set_space_state: BITFIELD_PAGE_WIDTH + 3, BITFIELD_PAGE_WIDTH + 2;
+    // 4 bits for a journal revision. Intended for the FastSpace mechanism
+    pub u8, journal, set_journal: BITFIELD_PAGE_WIDTH + 7, BITFIELD_PAGE_WIDTH + 4;
+}
+// hashes should only key off of the page number, not the metadata
+impl Hash for PhysPage {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.page_number().hash(state); }
+}
+impl PartialEq for PhysPage {
+    fn eq(&self, other: &Self) -> bool { self.page_number() == other.page_number() }
+}
+impl Ord for PhysPage {
+    fn cmp(&self, other: &Self) -> Ordering { self.page_number().cmp(&other.page_number()) }
+}
+impl PartialOrd for PhysPage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    /// This test exists because nothing in the bitfield spec explicitly requires that a true maps to a 1.
+    /// In fact a lot of code would work just fine if you mapped true to 0 and false to 1: if you're just
+    /// using the generated getter and setter, it wouldn't matter.
+    /// However, in our application, we fully expect a true to be a 1. This test exists to ensure this
+    /// seemingly obvious but not explicitly stated fact always remains true.
+    #[test]
+    fn test_bitfield_bool() {
+        bitfield! {
+            pub struct Test(u8);
+            impl Debug;
+            pub test, set_test: 1;
+        }
+        let mut t = Test(0);
+        t.set_test(true);
+        assert!(t.0 == 0x2, "polarity of boolean bit is not as expected");
+        assert!(t.test() == true, "bool getter did not work as expected");
+    }
+    #[test]
+    fn test_journal_range() {
+        let pp = PhysPage(u32::MAX);
+        println!("pp.journal(): {}", pp.journal());
+        assert!(pp.journal() == PHYS_PAGE_JOURNAL_MAX, "PHYS_PAGE_JOURNAL_MAX is incorrect");
+    }
+    /// The decoders are shared with tools that read PDDB images, so pin down where they find each field.
+    #[test]
+    fn test_record_decoders() {
+        let mut pte = [0u8; PTE_LEN];
+        pte[..7].copy_from_slice(&0x12_3456u64.to_le_bytes()[..7]);
+        pte[7] = 0b11;
+        pte[8..12].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+        let checksum = murmur3_32(&pte[..12], 0xdead_beef);
+        pte[12..].copy_from_slice(&checksum.to_le_bytes());
+        let record = PteRecord::decode(&pte).expect("PTE checksum did not verify");
+        assert!(record.vaddr() == 0x12_3456 * VPAGE_SIZE as u64);
+        assert!(record.flags == 0b11 && record.nonce == 0xdead_beef);
+        pte[0] ^= 1;
+        assert!(PteRecord::decode(&pte).is_none(), "corrupted PTE was accepted");
+
+        let mut desc = [0u8; DK_STRIDE];
+        desc[..8].copy_from_slice(&0xFE_0000u64.to_le_bytes());
+        desc[8..16].copy_from_slice(&100u64.to_le_bytes());
+        desc[16..24].copy_from_slice(&128u64.to_le_bytes());
+        desc[24..28].copy_from_slice(&0b11u32.to_le_bytes());
+        desc[28..32].copy_from_slice(&7u32.to_le_bytes());
+        desc[32] = 3;
+        desc[33..36].copy_from_slice(b"key");
+        let key = KeyRecord::decode(&desc);
+        assert!(key.start == 0xFE_0000 && key.len == 100 && key.reserved == 128 && key.age == 7);
+        assert!(key.valid() && key.unresolved());
+        assert!(key.name == b"key");
+
+        let mut dict = [0u8; DK_STRIDE];
+        let mut flags = DictFlags(0);
+        flags.set_valid(true);
+        flags.set_versions(4);
+        dict[..4].copy_from_slice(&flags.0.to_le_bytes());
+        dict[4..8].copy_from_slice(&2u32.to_le_bytes());
+        dict[8..12].copy_from_slice(&5u32.to_le_bytes());
+        dict[12..16].copy_from_slice(&6u32.to_le_bytes());
+        dict[16] = 0xFF; // names that overrun the record are clamped to it
+        let dict = DictRecord::decode(&dict);
+        assert!(dict.flags.valid() && dict.flags.versions() == 4);
+        assert!(dict.age == 2 && dict.num_keys == 5 && dict.free_key_index == 6);
+        assert!(dict.name.len() == DK_STRIDE - 17);
+    }
+}
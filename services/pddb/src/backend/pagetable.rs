@@ -7,7 +7,7 @@ use std::rc::Rc;
 use aes_gcm_siv::{Nonce, Tag};
 use bitflags::bitflags;
 
-use super::{murmur3_32, TrngPool, VirtAddr, PAGE_SIZE, VPAGE_SIZE};
+use super::{murmur3_32, PteRecord, TrngPool, VirtAddr, PAGE_SIZE, PTE_LEN, VPAGE_SIZE};
 
 bitflags! {
     /// flags used by the page table
@@ -56,6 +56,8 @@ pub(crate) struct Pte {
     /// classifier checksum is computed on all of the bits prior, so checksum(pddb_addr, flags, nonce)
     checksum: [u8; 4],
 }
+// `PTE_LEN` is what tools reading the page table out of an image go by
+const _: () = assert!(size_of::<Pte>() == PTE_LEN);
 impl Pte {
     pub fn new(va: VirtAddr, flags: PtFlags, entropy: Rc<RefCell<TrngPool>>) -> Self {
         let nonce_u32 = entropy.borrow_mut().get_u32();
@@ -101,20 +103,13 @@ impl Pte {
     pub fn flags(&self) -> PtFlags { self.flags }
 
     pub fn try_from_slice(slice: &[u8]) -> Option<Self> {
-        if slice.len() == size_of::<Pte>() {
-            let mut maybe_pt = Pte::default();
-            for (&src, dst) in slice.iter().zip(maybe_pt.deref_mut().iter_mut()) {
-                *dst = src;
-            }
-            let nonce_u32 = u32::from_le_bytes(maybe_pt.nonce);
-            if u32::from_le_bytes(maybe_pt.checksum) == murmur3_32(&slice[..12], nonce_u32) {
-                Some(maybe_pt)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let record = PteRecord::decode(slice)?;
+        Some(Pte {
+            pddb_addr: record.vpage.to_le_bytes()[..7].try_into().unwrap(),
+            flags: PtFlags::from_bits_truncate(record.flags),
+            nonce: record.nonce.to_le_bytes(),
+            checksum: record.checksum.to_le_bytes(),
+        })
     }
 
     /// Normally you should be using pt_patch_mapping(), which generates a new nonce every
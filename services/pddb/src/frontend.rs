@@ -1,2 +1,6 @@
 pub mod pddbkey;
 pub use pddbkey::*;
+pub mod transaction;
+pub use transaction::*;
+pub mod archive;
+pub use archive::*;
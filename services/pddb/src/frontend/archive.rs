@@ -0,0 +1,83 @@
+use std::io::{Error, ErrorKind, Read, Result};
+
+use num_traits::*;
+use xous::CID;
+use xous_ipc::Buffer;
+
+use crate::*;
+
+/// Sends `payload` to the server with `opcode`, and returns the payload of the reply.
+pub(crate) fn archive_request(conn: CID, opcode: Opcode, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = Buffer::new((ARCHIVE_MSG_HEADER_LEN + payload.len()).max(4096));
+    buf[4..ARCHIVE_MSG_HEADER_LEN].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf[ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN + payload.len()].copy_from_slice(payload);
+    buf.lend_mut(conn, opcode.to_u32().unwrap())
+        .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+    let code = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+    match FromPrimitive::from_u32(code) {
+        Some(PddbRetcode::Ok) => match buf.get(ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN + len) {
+            Some(reply) => Ok(reply.to_vec()),
+            None => Err(Error::new(ErrorKind::Other, "Internal error")),
+        },
+        Some(PddbRetcode::BasisLost) => Err(Error::new(ErrorKind::NotFound, "Basis or dictionary not found")),
+        Some(PddbRetcode::AccessDenied) => {
+            Err(Error::new(ErrorKind::PermissionDenied, "Access denied, or no export or import in progress"))
+        }
+        Some(PddbRetcode::InvalidArchive) => Err(Error::new(ErrorKind::InvalidData, "Archive is damaged")),
+        Some(PddbRetcode::UnexpectedEof) => Err(Error::new(ErrorKind::UnexpectedEof, "Archive is truncated")),
+        Some(PddbRetcode::AlreadyExists) => Err(Error::new(ErrorKind::AlreadyExists, "Basis already exists")),
+        Some(PddbRetcode::DiskFull) => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+        Some(PddbRetcode::QuotaExceeded) => Err(QuotaExceeded::error()),
+        _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+    }
+}
+
+/// Abandons the export or import in progress. A partly imported basis is deleted.
+pub(crate) fn archive_abort(conn: CID) {
+    xous::send_message(
+        conn,
+        xous::Message::new_blocking_scalar(Opcode::ArchiveAbort.to_usize().unwrap(), 0, 0, 0, 0),
+    )
+    .ok();
+}
+
+/// An encrypted archive of a basis, read out of the PDDB as it is written. Created by
+/// `Pddb::export_basis()`.
+///
+/// Reading to the end finishes the export; dropping it before then abandons the export.
+pub struct PddbExport {
+    pub(crate) conn: CID,
+    /// the part of the archive last returned by the server, and how much of it has been read
+    pub(crate) chunk: Vec<u8>,
+    pub(crate) pos: usize,
+    pub(crate) done: bool,
+}
+impl Read for PddbExport {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.chunk.len() && !self.done {
+            self.chunk = match archive_request(self.conn, Opcode::ExportRead, &[]) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.done = true;
+                    self.chunk.clear();
+                    self.pos = 0;
+                    return Err(e);
+                }
+            };
+            self.pos = 0;
+            self.done = self.chunk.is_empty();
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+impl Drop for PddbExport {
+    fn drop(&mut self) {
+        if !self.done {
+            archive_abort(self.conn);
+        }
+    }
+}
@@ -166,6 +166,7 @@ impl<'a> Write for PddbKey<'a> {
                     PddbRetcode::AccessDenied => {
                         Err(Error::new(ErrorKind::PermissionDenied, "Access denied"))
                     }
+                    PddbRetcode::QuotaExceeded => Err(QuotaExceeded::error()),
                     _ => Err(Error::new(ErrorKind::Other, "Unhandled error code in PddbKey Write")),
                 }
             }
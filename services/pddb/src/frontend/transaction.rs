@@ -0,0 +1,105 @@
+use std::io::{Error, ErrorKind, Result};
+
+use num_traits::*;
+use xous::CID;
+use xous_ipc::Buffer;
+
+use crate::*;
+
+/// Stages writes and deletes of keys in one basis, to be applied all at once by `commit()`. Created by
+/// `Pddb::transaction()`.
+///
+/// Each write replaces the entire contents of its key, creating the key (and its dict) if needed.
+/// Deleting a key that doesn't exist is not an error. Operations are applied in the order they were
+/// staged. Versions of keys in versioned dicts are not kept for writes made in a transaction.
+pub struct PddbTransaction {
+    pub(crate) conn: CID,
+    pub(crate) txn: Transaction,
+    /// the first invalid operation staged, reported by `commit()`
+    pub(crate) error: Option<Error>,
+}
+impl PddbTransaction {
+    /// Stages replacing the contents of `key` in `dict` with `data`.
+    pub fn write(&mut self, dict: &str, key: &str, data: &[u8]) -> &mut Self {
+        if self.check_names(dict, key) {
+            self.txn.ops.push(TxnOp::Write {
+                dict: dict.to_string(),
+                key: key.to_string(),
+                data: data.to_vec(),
+            });
+        }
+        self
+    }
+
+    /// Stages deleting `key` in `dict`.
+    pub fn delete(&mut self, dict: &str, key: &str) -> &mut Self {
+        if self.check_names(dict, key) {
+            self.txn.ops.push(TxnOp::Delete { dict: dict.to_string(), key: key.to_string() });
+        }
+        self
+    }
+
+    /// The number of operations staged
+    pub fn len(&self) -> usize { self.txn.ops.len() }
+
+    pub fn is_empty(&self) -> bool { self.txn.ops.is_empty() }
+
+    /// Applies the staged operations. Either all of them take effect, or, if power is lost or the
+    /// device suspends part-way through, none of them do until the basis is mounted again, at which
+    /// point the PDDB finishes the transaction. The staged operations are cleared on success.
+    ///
+    /// The encoded transaction is limited to `MAX_TRANSACTION_LEN` bytes. Returns `InvalidInput` if a
+    /// staged name was invalid or the transaction is too large, `NotFound` if the basis isn't mounted,
+    /// and `OutOfMemory` if the basis doesn't have room for the transaction or it would leave a dict
+    /// over its quota; in these cases nothing was changed. Any other error means the transaction was
+    /// logged but not finished, and it will be finished when the basis is next mounted.
+    pub fn commit(&mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.txn.ops.is_empty() {
+            return Ok(());
+        }
+        let encoded = self.txn.encode();
+        if encoded.len() > MAX_TRANSACTION_LEN {
+            return Err(Error::new(ErrorKind::InvalidInput, "transaction is too large"));
+        }
+        let mut buf = Buffer::new(TXN_HEADER_LEN + encoded.len());
+        buf[4..TXN_HEADER_LEN].copy_from_slice(&(encoded.len() as u32).to_le_bytes());
+        buf[TXN_HEADER_LEN..TXN_HEADER_LEN + encoded.len()].copy_from_slice(&encoded);
+        let result = buf.lend_mut(self.conn, Opcode::Transaction.to_u32().unwrap());
+        let code = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        // the buffer holds the data of every key in the transaction
+        buf.volatile_clear();
+        result.or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        match FromPrimitive::from_u32(code) {
+            Some(PddbRetcode::Ok) => {
+                self.txn.ops.clear();
+                Ok(())
+            }
+            Some(PddbRetcode::BasisLost) => Err(Error::new(ErrorKind::NotFound, "Basis not found")),
+            Some(PddbRetcode::DiskFull) => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+            Some(PddbRetcode::QuotaExceeded) => Err(QuotaExceeded::error()),
+            Some(PddbRetcode::AccessDenied) => {
+                Err(Error::new(ErrorKind::InvalidInput, "Invalid dict or key name in transaction"))
+            }
+            _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+        }
+    }
+
+    fn check_names(&mut self, dict: &str, key: &str) -> bool {
+        let valid = !dict.is_empty()
+            && dict.len() < DICT_NAME_LEN
+            && !dict.contains(VERSION_SEPARATOR)
+            && !key.is_empty()
+            && key.len() < KEY_NAME_LEN
+            && !key.contains(VERSION_SEPARATOR);
+        if !valid && self.error.is_none() {
+            self.error = Some(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid dict or key name: {}:{}", dict, key),
+            ));
+        }
+        valid
+    }
+}
@@ -3,6 +3,8 @@
 pub mod api;
 pub use api::*;
 pub mod frontend;
+#[path = "libstd/senres.rs"]
+mod senres;
 use core::sync::atomic::{AtomicU32, Ordering};
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind, Result};
@@ -27,6 +29,7 @@ use rkyv::{
     ser::{serializers::WriteSerializer, Serializer},
     AlignedVec, Deserialize,
 };
+use senres::{Senres, SenresMut};
 
 #[derive(num_derive::FromPrimitive, num_derive::ToPrimitive, Debug)]
 pub enum CbOp {
@@ -318,6 +321,77 @@ impl Pddb {
         ret
     }
 
+    /// Asks for a `PddbChange` to be sent to the server `sid` with `PDDB_CHANGE_OPCODE` whenever a key in
+    /// `dict_name` is created, updated or deleted, and whenever a basis is mounted or unmounted. With a
+    /// `dict_name` of `None`, only the basis changes are sent. With a `basis_name`, only changes within that
+    /// basis are sent. A server can subscribe to several dicts; it is sent each change once.
+    ///
+    /// The server must already be running. Changes are sent as non-blocking messages after the request
+    /// that caused them has returned, so a process is also told about its own changes. Each write to a key
+    /// is reported as a separate update. Changes that arrive while the server's queue is full are dropped,
+    /// and replaced by a single `PddbChangeKind::ChangesDropped` once it has room. A server that can't be
+    /// sent a change for any other reason is dropped, and has to subscribe again.
+    pub fn subscribe(&self, sid: SID, dict_name: Option<&str>, basis_name: Option<&str>) -> Result<()> {
+        let dict = if let Some(dname) = dict_name {
+            if dname.len() > DICT_NAME_LEN - 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "dictionary name too long"));
+            }
+            xous_ipc::String::<DICT_NAME_LEN>::from_str(dname)
+        } else {
+            xous_ipc::String::<DICT_NAME_LEN>::new()
+        };
+        let basis = if let Some(bname) = basis_name {
+            if bname.len() > BASIS_NAME_LEN - 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+            }
+            xous_ipc::String::<BASIS_NAME_LEN>::from_str(bname)
+        } else {
+            xous_ipc::String::<BASIS_NAME_LEN>::new()
+        };
+        let request = PddbSubscription {
+            sid: sid.to_array(),
+            dict_specified: dict_name.is_some(),
+            dict,
+            basis_specified: basis_name.is_some(),
+            basis,
+            result: PddbRequestCode::Uninit,
+        };
+        let mut buf =
+            Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        buf.lend_mut(self.conn, Opcode::Subscribe.to_u32().unwrap())
+            .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        let response = buf.to_original::<PddbSubscription, _>().unwrap();
+        match response.result {
+            PddbRequestCode::NoErr => Ok(()),
+            _ => Err(Error::new(ErrorKind::Other, "Couldn't connect to the subscribing server")),
+        }
+    }
+
+    /// Stops sending changes to the server `sid`, for all of its subscriptions.
+    pub fn unsubscribe(&self, sid: SID) -> Result<()> {
+        let s = sid.to_array();
+        let response = send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                Opcode::Unsubscribe.to_usize().unwrap(),
+                s[0] as usize,
+                s[1] as usize,
+                s[2] as usize,
+                s[3] as usize,
+            ),
+        )
+        .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        if let xous::Result::Scalar1(rcode) = response {
+            match FromPrimitive::from_u8(rcode as u8) {
+                Some(PddbRetcode::Ok) => Ok(()),
+                Some(PddbRetcode::AccessDenied) => Err(Error::new(ErrorKind::NotFound, "Not subscribed")),
+                _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+            }
+        } else {
+            Err(Error::new(ErrorKind::Other, "Xous internal error"))
+        }
+    }
+
     /// returns the latest basis that is opened -- this is where all new values are being sent by default
     /// if the PDDB is not mounted, returns None
     pub fn latest_basis(&self) -> Option<String> {
@@ -425,6 +499,45 @@ impl Pddb {
         }
     }
 
+    /// Creates a basis as libstd does, with `CreateBasisStd`. The PDDB prompts for the password.
+    pub fn create_basis_std(&self, basis_name: &str) -> Result<()> {
+        self.basis_request_std(Opcode::CreateBasisStd, CREATE_BASIS_STD_TAG, basis_name)
+    }
+
+    /// Unlocks a basis as libstd does, with `OpenBasisStd`. The PDDB prompts for the password.
+    pub fn unlock_basis_std(&self, basis_name: &str) -> Result<()> {
+        self.basis_request_std(Opcode::OpenBasisStd, OPEN_BASIS_STD_TAG, basis_name)
+    }
+
+    /// Locks a basis as libstd does, with `CloseBasisStd`. Files opened within the basis stop working.
+    pub fn lock_basis_std(&self, basis_name: &str) -> Result<()> {
+        self.basis_request_std(Opcode::CloseBasisStd, CLOSE_BASIS_STD_TAG, basis_name)
+    }
+
+    /// Sends `basis_name` in a senres message tagged `tag`, the way libstd makes its basis requests.
+    fn basis_request_std(&self, opcode: Opcode, tag: [u8; 4], basis_name: &str) -> Result<()> {
+        if basis_name.len() > BASIS_NAME_LEN - 1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+        }
+        let mut request = senres::Stack::<4096>::new();
+        request.writer(tag).ok_or(Error::new(ErrorKind::Other, "Xous internal error"))?.append(basis_name);
+        // Safety: the request is page-aligned and a whole number of pages long
+        let buf = unsafe { xous::MemoryRange::new(request.as_mut_ptr() as usize, request.len()) }
+            .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        let msg = xous::MemoryMessage { id: opcode.to_usize().unwrap(), buf, offset: None, valid: None };
+        match send_message(self.conn, Message::MutableBorrow(msg)) {
+            Ok(xous::Result::MemoryReturned(None, _)) => Ok(()),
+            Ok(xous::Result::MemoryReturned(Some(code), _)) => match FromPrimitive::from_usize(code.get()) {
+                Some(PddbRetcode::AccessDenied) => {
+                    Err(Error::new(ErrorKind::PermissionDenied, "Basis name or password refused"))
+                }
+                Some(PddbRetcode::BasisLost) => Err(Error::new(ErrorKind::NotFound, "Basis not found")),
+                _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+            },
+            _ => Err(Error::new(ErrorKind::Other, "Xous internal error")),
+        }
+    }
+
     pub fn delete_basis(&self, basis_name: &str) -> Result<()> {
         if basis_name.len() > BASIS_NAME_LEN - 1 {
             return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
@@ -466,6 +579,30 @@ impl Pddb {
         create_key: bool,
         alloc_hint: Option<usize>,
         key_changed_cb: Option<impl Fn() + 'static + Send>,
+    ) -> Result<PddbKey> {
+        self.key_request(
+            dict_name,
+            key_name,
+            None,
+            basis_name,
+            create_dict,
+            create_key,
+            alloc_hint,
+            key_changed_cb,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn key_request(
+        &self,
+        dict_name: &str,
+        key_name: &str,
+        version: Option<u32>,
+        basis_name: Option<&str>,
+        create_dict: bool,
+        create_key: bool,
+        alloc_hint: Option<usize>,
+        key_changed_cb: Option<impl Fn() + 'static + Send>,
     ) -> Result<PddbKey> {
         if key_name.len() > (KEY_NAME_LEN - 1) {
             return Err(Error::new(ErrorKind::InvalidInput, "key name too long"));
@@ -501,6 +638,7 @@ impl Pddb {
             result: PddbRequestCode::Uninit,
             cb_sid,
             alloc_hint: if let Some(a) = alloc_hint { Some(a as u64) } else { None },
+            version,
         };
         let mut buf =
             Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
@@ -540,6 +678,7 @@ impl Pddb {
                 Err(Error::new(ErrorKind::PermissionDenied, "Dict/Key access denied"))
             }
             PddbRequestCode::NoFreeSpace => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+            PddbRequestCode::QuotaExceeded => Err(QuotaExceeded::error()),
             PddbRequestCode::NotMounted => Err(Error::new(ErrorKind::ConnectionReset, "PDDB was unmounted")),
             PddbRequestCode::NotFound => {
                 Err(Error::new(ErrorKind::NotFound, "Dictionary or key was not found"))
@@ -550,6 +689,29 @@ impl Pddb {
 
     /// deletes a key within the dictionary
     pub fn delete_key(&self, dict_name: &str, key_name: &str, basis_name: Option<&str>) -> Result<()> {
+        self.delete_key_inner(dict_name, key_name, basis_name, Opcode::DeleteKey)
+    }
+
+    /// deletes a key within the dictionary, and overwrites its record on disk before returning, so
+    /// that neither its plaintext nor its ciphertext can be recovered from the FLASH afterwards. Prior
+    /// versions of the key are erased the same way. Use this for passwords and key material: it is
+    /// slower than `delete_key()`, because it re-encrypts the key's small pool page on the spot.
+    pub fn delete_key_paranoid(
+        &self,
+        dict_name: &str,
+        key_name: &str,
+        basis_name: Option<&str>,
+    ) -> Result<()> {
+        self.delete_key_inner(dict_name, key_name, basis_name, Opcode::DeleteKeyParanoid)
+    }
+
+    fn delete_key_inner(
+        &self,
+        dict_name: &str,
+        key_name: &str,
+        basis_name: Option<&str>,
+        op: Opcode,
+    ) -> Result<()> {
         if key_name.len() > (KEY_NAME_LEN - 1) {
             return Err(Error::new(ErrorKind::InvalidInput, "key name too long"));
         }
@@ -580,10 +742,11 @@ impl Pddb {
             result: PddbRequestCode::Uninit,
             cb_sid,
             alloc_hint: None,
+            version: None,
         };
         let mut buf =
             Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
-        buf.lend_mut(self.conn, Opcode::DeleteKey.to_u32().unwrap())
+        buf.lend_mut(self.conn, op.to_u32().unwrap())
             .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
 
         let response = buf.to_original::<PddbKeyRequest, _>().unwrap();
@@ -592,10 +755,29 @@ impl Pddb {
             PddbRequestCode::NotFound => {
                 Err(Error::new(ErrorKind::NotFound, "Dictionary or key was not found"))
             }
+            PddbRequestCode::NoFreeSpace => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
             _ => Err(Error::new(ErrorKind::Other, "Internal error")),
         }
     }
 
+    /// Starts a transaction: a set of writes and deletes of keys that are applied all together, or not at
+    /// all, even if power is lost part-way through. The keys can be in any dicts of one basis; if
+    /// `basis_name` is `None`, the most recently opened basis is used. Stage operations with
+    /// `write()` and `delete()`, then apply them with `commit()`.
+    pub fn transaction(&self, basis_name: Option<&str>) -> PddbTransaction {
+        let error = match basis_name {
+            Some(bname) if bname.len() > BASIS_NAME_LEN - 1 => {
+                Some(Error::new(ErrorKind::InvalidInput, "basis name too long"))
+            }
+            _ => None,
+        };
+        PddbTransaction {
+            conn: self.conn,
+            txn: Transaction { basis: basis_name.map(|b| b.to_string()), ops: Vec::new() },
+            error,
+        }
+    }
+
     /// deletes a list of keys from a dictionary. The list of keys must be less than MAX_PDDB_DELETE_LEN
     /// characters long.
     pub fn delete_key_list(
@@ -690,6 +872,7 @@ impl Pddb {
             result: PddbRequestCode::Uninit,
             cb_sid,
             alloc_hint: None,
+            version: None,
         };
         let mut buf =
             Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
@@ -706,6 +889,342 @@ impl Pddb {
         }
     }
 
+    /// Compacts a dictionary, so its keys are stored contiguously. Deleting many keys leaves holes in a
+    /// dictionary's key index and small-key storage, which make lookups and syncs slower and hold on to
+    /// pages that could otherwise be reused; compaction closes those holes. Key data and attributes are
+    /// unchanged. If `basis_name` is `None`, the dictionary in the most recently opened basis is
+    /// compacted.
+    pub fn compact(&self, dict_name: &str, basis_name: Option<&str>) -> Result<()> {
+        if dict_name.len() > (DICT_NAME_LEN - 1) {
+            return Err(Error::new(ErrorKind::InvalidInput, "dictionary name too long"));
+        }
+        let bname = if let Some(bname) = basis_name {
+            if bname.len() > BASIS_NAME_LEN - 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+            }
+            xous_ipc::String::<BASIS_NAME_LEN>::from_str(bname)
+        } else {
+            xous_ipc::String::<BASIS_NAME_LEN>::new()
+        };
+        let request = PddbKeyRequest {
+            basis_specified: basis_name.is_some(),
+            basis: bname,
+            dict: xous_ipc::String::<DICT_NAME_LEN>::from_str(dict_name),
+            key: xous_ipc::String::<KEY_NAME_LEN>::new(),
+            create_dict: false,
+            create_key: false,
+            token: None,
+            result: PddbRequestCode::Uninit,
+            cb_sid: None,
+            alloc_hint: None,
+            version: None,
+        };
+        let mut buf =
+            Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        buf.lend_mut(self.conn, Opcode::CompactDict.to_u32().unwrap())
+            .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+
+        let response = buf.to_original::<PddbKeyRequest, _>().unwrap();
+        match response.result {
+            PddbRequestCode::NoErr => Ok(()),
+            PddbRequestCode::NotFound => Err(Error::new(ErrorKind::NotFound, "Dictionary was not found")),
+            PddbRequestCode::NoFreeSpace => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+            _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+        }
+    }
+
+    /// Reports the space used by a basis and by each of its dictionaries, along with the free space left
+    /// in the PDDB. If `basis_name` is `None`, the most recently opened basis is reported.
+    pub fn usage(&self, basis_name: Option<&str>) -> Result<BasisUsage> {
+        let response = self.usage_request(Opcode::Usage, None, basis_name, None)?;
+        let name = response.basis.as_str().unwrap().to_string();
+        let mut dicts = Vec::new();
+        let mut names = self.list_dict(Some(&name))?;
+        names.sort();
+        for dict in names.iter() {
+            let dict_response = self.usage_request(Opcode::Usage, Some(dict), Some(&name), None)?;
+            dicts.push(DictUsage {
+                name: dict.to_string(),
+                usage: dict_response.usage,
+                quota: dict_response.quota,
+            });
+        }
+        Ok(BasisUsage { name, total: response.usage, dicts, free: response.free })
+    }
+
+    /// Limits the total length of the keys in a dictionary, including the prior versions it keeps of
+    /// them, to `quota` bytes. A write that would take the dictionary over its quota fails with an
+    /// `OutOfMemory` error for which `is_quota_exceeded()` is `true`; if the dictionary is already over
+    /// a new quota, its keys are kept, but they can only shrink until it is back under. `None` removes the
+    /// quota. Quotas are kept per basis: if `basis_name` is `None`, the quota applies to the dictionary in
+    /// the most recently opened basis.
+    pub fn set_quota(&self, dict_name: &str, quota: Option<u64>, basis_name: Option<&str>) -> Result<()> {
+        self.usage_request(Opcode::SetQuota, Some(dict_name), basis_name, quota).map(|_| ())
+    }
+
+    /// Exports a basis to an encrypted archive that can be loaded on another device with `import_basis()`.
+    /// If `basis_name` is `None`, the most recently opened basis is exported, and if `dicts` is given,
+    /// only those dictionaries are. Only the current contents of each key are exported; prior versions and
+    /// quotas are left behind. The user is asked for a password for the archive. It doesn't depend on the
+    /// device, so the archive is only as strong as the password.
+    ///
+    /// The archive is produced as it is read from the returned `PddbExport`. Only one export or import
+    /// runs at a time, and starting another abandons the one in progress.
+    pub fn export_basis(&self, basis_name: Option<&str>, dicts: Option<&[&str]>) -> Result<PddbExport> {
+        if let Some(bname) = basis_name {
+            if bname.len() > BASIS_NAME_LEN - 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+            }
+        }
+        if let Some(dicts) = dicts {
+            if dicts.iter().any(|d| d.is_empty() || d.len() > DICT_NAME_LEN - 1) {
+                return Err(Error::new(ErrorKind::InvalidInput, "invalid dictionary name"));
+            }
+        }
+        let req = ExportRequest {
+            basis: basis_name.map(|b| b.to_string()),
+            dicts: dicts.map(|dicts| dicts.iter().map(|d| d.to_string()).collect()),
+        };
+        archive_request(self.conn, Opcode::ExportBasis, &req.encode())?;
+        Ok(PddbExport { conn: self.conn, chunk: Vec::new(), pos: 0, done: false })
+    }
+
+    /// Loads an archive made by `export_basis()` as a new basis called `basis_name`, which is left open.
+    /// The user is asked for the password of the archive, and then for a password for the new basis.
+    ///
+    /// Returns `PermissionDenied` if the user gave up on the archive's password, `AlreadyExists` if the
+    /// basis already exists, and `InvalidData` or `UnexpectedEof` if the archive is damaged or
+    /// truncated. If the import fails part-way through, the new basis is deleted.
+    pub fn import_basis<R: std::io::Read>(&self, basis_name: &str, archive: &mut R) -> Result<()> {
+        if basis_name.is_empty() || basis_name.len() > BASIS_NAME_LEN - 1 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid basis name"));
+        }
+        let mut start = vec![0u8; ARCHIVE_HEADER_LEN];
+        archive.read_exact(&mut start)?;
+        if start[..4] != ARCHIVE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a PDDB archive"));
+        }
+        match Self::archive_record(archive)? {
+            Some(record) => start.extend_from_slice(&record),
+            None => return Err(Error::new(ErrorKind::UnexpectedEof, "Archive is truncated")),
+        }
+        let req = ImportRequest { name: basis_name.to_string(), start };
+        archive_request(self.conn, Opcode::ImportBasis, &req.encode())?;
+        loop {
+            let record = match Self::archive_record(archive) {
+                Ok(Some(record)) => record,
+                // an empty record tells the server that the archive has ended
+                Ok(None) => Vec::new(),
+                Err(e) => {
+                    archive_abort(self.conn);
+                    return Err(e);
+                }
+            };
+            archive_request(self.conn, Opcode::ImportWrite, &record)?;
+            if record.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Exports a basis with `export_basis()` into a new file at `path`, and returns the length of the
+    /// archive. In hosted mode `path` is a file on the host. On Xous it is a key, named as with `std::fs`,
+    /// and it should not be in the basis being exported. A partly written file is removed.
+    pub fn export_basis_to_file<P: AsRef<std::path::Path>>(
+        &self,
+        basis_name: Option<&str>,
+        dicts: Option<&[&str]>,
+        path: P,
+    ) -> Result<u64> {
+        let mut export = self.export_basis(basis_name, dicts)?;
+        let mut file = std::fs::File::create(path.as_ref())?;
+        std::io::copy(&mut export, &mut file).map_err(|e| {
+            drop(file);
+            std::fs::remove_file(path.as_ref()).ok();
+            e
+        })
+    }
+
+    /// Loads the archive in the file at `path` with `import_basis()`. `path` is named as for
+    /// `export_basis_to_file()`.
+    pub fn import_basis_from_file<P: AsRef<std::path::Path>>(&self, basis_name: &str, path: P) -> Result<()> {
+        let mut file = std::fs::File::open(path)?;
+        self.import_basis(basis_name, &mut file)
+    }
+
+    /// Reads the next sealed record from an archive, or `None` at the end of the archive.
+    fn archive_record<R: std::io::Read>(archive: &mut R) -> Result<Option<Vec<u8>>> {
+        let mut len = [0u8; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match archive.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "Archive is truncated")),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 || len > ARCHIVE_MAX_RECORD {
+            return Err(Error::new(ErrorKind::InvalidData, "Archive is damaged"));
+        }
+        let mut record = vec![0u8; len];
+        archive.read_exact(&mut record)?;
+        Ok(Some(record))
+    }
+
+    /// Creates a dictionary that keeps up to `versions` prior values of each of its keys, or changes
+    /// how many an existing dictionary keeps. `versions` is capped at `MAX_KEY_VERSIONS`, and 0 turns
+    /// versioning off. A version is kept each time a key is first written through a newly opened handle,
+    /// so a bad write can be undone by reading the value back with `get_version()`. Versions count
+    /// against the basis' space like any other key, and are removed along with their key.
+    pub fn create_dict_versioned(
+        &self,
+        dict_name: &str,
+        basis_name: Option<&str>,
+        versions: usize,
+    ) -> Result<()> {
+        let mut buf = self.version_request(
+            Opcode::CreateDictVersioned,
+            dict_name,
+            "",
+            basis_name,
+            versions.min(MAX_KEY_VERSIONS) as u32,
+        )?;
+        let response = buf.to_original::<PddbVersionRequest, _>().unwrap();
+        match response.result {
+            PddbRequestCode::NoErr => Ok(()),
+            PddbRequestCode::NoFreeSpace => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+            PddbRequestCode::NotMounted => Err(Error::new(ErrorKind::NotFound, "Basis not found")),
+            _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+        }
+    }
+
+    /// Lists the prior versions kept of a key, oldest first. The list is empty if the key's dictionary
+    /// does not keep versions.
+    pub fn list_versions(
+        &self,
+        dict_name: &str,
+        key_name: &str,
+        basis_name: Option<&str>,
+    ) -> Result<Vec<u32>> {
+        let mut buf = self.version_request(Opcode::ListKeyVersions, dict_name, key_name, basis_name, 0)?;
+        let response = buf.to_original::<PddbVersionRequest, _>().unwrap();
+        match response.result {
+            PddbRequestCode::NoErr => Ok(response.versions[..response.count as usize].to_vec()),
+            PddbRequestCode::NotFound => {
+                Err(Error::new(ErrorKind::NotFound, "Dictionary or key was not found"))
+            }
+            _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+        }
+    }
+
+    /// Reads back a prior version of a key, as numbered by `list_versions()`
+    pub fn get_version(
+        &self,
+        dict_name: &str,
+        key_name: &str,
+        version: u32,
+        basis_name: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        use std::io::Read;
+        let mut key = self.key_request(
+            dict_name,
+            key_name,
+            Some(version),
+            basis_name,
+            false,
+            false,
+            None,
+            None::<fn()>,
+        )?;
+        let mut data = Vec::new();
+        key.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn usage_request(
+        &self,
+        op: Opcode,
+        dict_name: Option<&str>,
+        basis_name: Option<&str>,
+        quota: Option<u64>,
+    ) -> Result<PddbUsageRequest> {
+        if dict_name.map_or(false, |dict| dict.len() > DICT_NAME_LEN - 1) {
+            return Err(Error::new(ErrorKind::InvalidInput, "dictionary name too long"));
+        }
+        if basis_name.map_or(false, |bname| bname.len() > BASIS_NAME_LEN - 1) {
+            return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+        }
+        let request = PddbUsageRequest {
+            basis_specified: basis_name.is_some(),
+            basis: xous_ipc::String::<BASIS_NAME_LEN>::from_str(basis_name.unwrap_or("")),
+            dict_specified: dict_name.is_some(),
+            dict: xous_ipc::String::<DICT_NAME_LEN>::from_str(dict_name.unwrap_or("")),
+            usage: PddbUsage::default(),
+            quota,
+            free: 0,
+            result: PddbRequestCode::Uninit,
+        };
+        let mut buf =
+            Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        buf.lend_mut(self.conn, op.to_u32().unwrap())
+            .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        let response = buf.to_original::<PddbUsageRequest, _>().unwrap();
+        match response.result {
+            PddbRequestCode::NoErr => Ok(response),
+            PddbRequestCode::NotFound => {
+                Err(Error::new(ErrorKind::NotFound, "Dictionary or basis was not found"))
+            }
+            PddbRequestCode::NotMounted => Err(Error::new(ErrorKind::NotFound, "Basis not found")),
+            PddbRequestCode::AccessDenied => {
+                Err(Error::new(ErrorKind::InvalidInput, "Invalid dictionary name"))
+            }
+            PddbRequestCode::NoFreeSpace => Err(Error::new(ErrorKind::OutOfMemory, "No more space on disk")),
+            _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+        }
+    }
+
+    fn version_request(
+        &self,
+        op: Opcode,
+        dict_name: &str,
+        key_name: &str,
+        basis_name: Option<&str>,
+        keep: u32,
+    ) -> Result<Buffer<'static>> {
+        if version_key_name(key_name, u32::MAX).len() > (KEY_NAME_LEN - 1) {
+            return Err(Error::new(ErrorKind::InvalidInput, "key name too long to keep versions of"));
+        }
+        if dict_name.len() > (DICT_NAME_LEN - 1) {
+            return Err(Error::new(ErrorKind::InvalidInput, "dictionary name too long"));
+        }
+        let bname = if let Some(bname) = basis_name {
+            if bname.len() > BASIS_NAME_LEN - 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "basis name too long"));
+            }
+            xous_ipc::String::<BASIS_NAME_LEN>::from_str(bname)
+        } else {
+            xous_ipc::String::<BASIS_NAME_LEN>::new()
+        };
+        let request = PddbVersionRequest {
+            basis_specified: basis_name.is_some(),
+            basis: bname,
+            dict: xous_ipc::String::<DICT_NAME_LEN>::from_str(dict_name),
+            key: xous_ipc::String::<KEY_NAME_LEN>::from_str(key_name),
+            keep,
+            versions: [0; MAX_KEY_VERSIONS],
+            count: 0,
+            result: PddbRequestCode::Uninit,
+        };
+        let mut buf =
+            Buffer::into_buf(request).or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        buf.lend_mut(self.conn, op.to_u32().unwrap())
+            .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        Ok(buf)
+    }
+
     pub fn sync(&self) -> Result<()> {
         let response = send_message(
             self.conn,
@@ -942,6 +1461,28 @@ impl Pddb {
         Ok(dict_list)
     }
 
+    /// Tells the PDDB whether the user wants the log saved to the system basis. The PDDB can't read the
+    /// user's preferences itself, so this is called by `userprefs`, which keeps them. Only the status
+    /// process may call this.
+    pub fn set_log_saving(&self, enabled: bool) -> Result<()> {
+        let response = send_message(
+            self.conn,
+            Message::new_blocking_scalar(Opcode::SetLogSaving.to_usize().unwrap(), enabled as usize, 0, 0, 0),
+        )
+        .or(Err(Error::new(ErrorKind::Other, "Xous internal error")))?;
+        if let xous::Result::Scalar1(rcode) = response {
+            match FromPrimitive::from_u8(rcode as u8) {
+                Some(PddbRetcode::Ok) => Ok(()),
+                Some(PddbRetcode::AccessDenied) => {
+                    Err(Error::new(ErrorKind::PermissionDenied, "Only the status process may set this"))
+                }
+                _ => Err(Error::new(ErrorKind::Other, "Internal error")),
+            }
+        } else {
+            Err(Error::new(ErrorKind::Other, "Xous internal error"))
+        }
+    }
+
     /// Public function to query efuse security state. Replicated here to avoid exposing RootKeys full API to
     /// the world.
     pub fn is_efuse_secured(&self) -> bool {
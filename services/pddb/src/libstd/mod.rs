@@ -1,4 +1,4 @@
-mod senres;
+pub(crate) mod senres;
 mod utils;
 
 use senres::{Senres, SenresMut};
@@ -248,6 +248,60 @@ pub(crate) fn list_basis(
     Ok(())
 }
 
+/// Reads the basis name out of a `CreateBasisStd` or `OpenBasisStd` request tagged with `tag`.
+/// The password is prompted for by the PDDB itself, and never passes through the caller.
+pub(crate) fn basis_name(mem: &mut xous::MemoryMessage, tag: [u8; 4]) -> Result<String, crate::PddbRetcode> {
+    // Safety: the memory message must be aligned, and we test for validity here
+    let backing = unsafe { senres::Message::from_mut_slice(mem.buf.as_slice_mut()) }
+        .or(Err(crate::PddbRetcode::InternalError))?;
+    let reader = backing.reader(tag).ok_or(crate::PddbRetcode::InternalError)?;
+    let name = reader.try_get_ref_from::<str>().or(Err(crate::PddbRetcode::InternalError))?;
+    if name.is_empty() || name.contains(utils::MAIN_SEP) {
+        log::error!("invalid basis name {}", name);
+        return Err(crate::PddbRetcode::AccessDenied);
+    }
+    Ok(name.to_owned())
+}
+
+/// Translates the result of a basis create or unlock into the libstd return codes
+pub(crate) fn basis_result(code: crate::PddbRequestCode) -> Result<(), crate::PddbRetcode> {
+    match code {
+        crate::PddbRequestCode::NoErr => Ok(()),
+        crate::PddbRequestCode::AccessDenied | crate::PddbRequestCode::DuplicateEntry => {
+            Err(crate::PddbRetcode::AccessDenied)
+        }
+        crate::PddbRequestCode::NotFound => Err(crate::PddbRetcode::BasisLost),
+        _ => Err(crate::PddbRetcode::InternalError),
+    }
+}
+
+/// Locks the basis named in a `CloseBasisStd` request. Any file handles opened explicitly
+/// within that basis stop working.
+pub(crate) fn close_basis(
+    mem: &mut xous::MemoryMessage,
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    all_fds: &mut std::collections::HashMap<Option<xous::PID>, Vec<Option<FileHandle>>>,
+) -> Result<(), crate::PddbRetcode> {
+    let name = basis_name(mem, crate::CLOSE_BASIS_STD_TAG)?;
+    basis_cache.basis_unmount(pddb_os, &name).map_err(|e| {
+        log::error!("unable to lock basis {}: {:?}", name, e);
+        match e.kind() {
+            std::io::ErrorKind::NotFound => crate::PddbRetcode::BasisLost,
+            _ => crate::PddbRetcode::InternalError,
+        }
+    })?;
+
+    for fds in all_fds.values_mut() {
+        for fd in fds.iter_mut().filter_map(|f| f.as_mut()) {
+            if fd.basis.as_deref() == Some(name.as_str()) {
+                fd.deleted = true;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Also called `KeyRequest` for non-libstd calls
 pub(crate) fn open_key(
     mem: &mut xous::MemoryMessage,
@@ -306,6 +360,12 @@ pub(crate) fn open_key(
             crate::PddbRetcode::AccessDenied
         })?;
 
+    // Prior versions of keys are kept under names that can't be opened, so that they can't be changed
+    if requested_dict.contains(crate::VERSION_SEPARATOR) || requested_key.contains(crate::VERSION_SEPARATOR) {
+        log::error!("invalid dict or key name");
+        return Err(crate::PddbRetcode::AccessDenied);
+    }
+
     let mut writer = backing.writer(*b"KyOR").ok_or(crate::PddbRetcode::InternalError)?;
 
     // Behavior for opening files:
@@ -375,7 +435,8 @@ pub(crate) fn open_key(
             );
             return Err(crate::PddbRetcode::DiskFull);
         } else if truncate {
-            // Truncate the file, which we know exists
+            // Truncate the file, which we know exists, keeping its contents if the dict keeps versions
+            crate::snapshot_key(pddb_os, basis_cache, requested_dict, requested_key, bname);
             basis_cache
                 .key_update(
                     pddb_os,
@@ -407,6 +468,8 @@ pub(crate) fn open_key(
             offset: if append { len } else { 0 },
             length: len,
             deleted: false,
+            // a truncated key was already kept as a version above
+            versioned: truncate,
             conn,
             alloc_hint: if alloc_hint > 0 { Some(alloc_hint) } else { None },
         };
@@ -534,6 +597,10 @@ pub(crate) fn write_key(
     let file = get_fd(fds, fd)?;
     let mut retcode = crate::PddbRetcode::InternalError;
 
+    if !file.versioned {
+        crate::snapshot_key(pddb_os, basis_cache, &file.dict, &file.key, file.basis.as_deref());
+        file.versioned = true;
+    }
     for basis in basis_cache.access_list().iter() {
         log::debug!("write (spec: {:?}){:?} {}", file.basis, file.basis.as_ref().unwrap_or(basis), file.key);
         let length_to_write = mem.valid.map(|v| v.get()).unwrap_or_default();
@@ -0,0 +1,232 @@
+//! Saves the log server's ring buffer of recent records into the system basis, so that the
+//! last logs of a session that crashed can still be read after a reboot.
+//!
+//! Saving the log is off unless the user turns on the `save_log` preference, as it wears the
+//! flash. The preference is kept by `userprefs`, which passes it on with `Pddb::set_log_saving()`
+//! at boot and whenever it changes; only the status process may do so.
+//!
+//! Once on, the log is saved when the PDDB suspends and whenever a process panics, but no more
+//! often than every `MIN_SAVE_INTERVAL_MS`, and only if there is something new in it. The first
+//! save of each session moves the previous session's log from `sys.log:last` to
+//! `sys.log:previous`.
+//!
+//! When a process panics, the crash report assembled by the log server is also written to
+//! `sys.crash:crash-NNNN`, together with the last log records of the process that crashed. At
+//! most `MAX_CRASH_REPORTS` are written per session, in case a process panics over and over.
+
+use core::fmt::Write;
+
+use crate::api::PDDB_DEFAULT_SYSTEM_BASIS;
+use crate::backend::{BasisCache, PddbOs};
+
+pub(crate) const LOG_DICT: &str = "sys.log";
+pub(crate) const LOG_KEY_LAST: &str = "last";
+pub(crate) const LOG_KEY_PREVIOUS: &str = "previous";
+pub(crate) const CRASH_DICT: &str = "sys.crash";
+pub(crate) const CRASH_KEY_PREFIX: &str = "crash-";
+
+/// The log is saved at most this often
+const MIN_SAVE_INTERVAL_MS: u64 = 10 * 60 * 1000;
+/// The most crash reports saved per session
+const MAX_CRASH_REPORTS: u32 = 8;
+
+pub(crate) struct LogSaver {
+    /// Mirrors the user's `save_log` preference
+    enabled: bool,
+    /// Set once the previous session's log has been moved out of the way
+    rotated: bool,
+    /// The sequence number following the last record saved
+    saved_until: u64,
+    /// When the log was last saved
+    last_save_ms: Option<u64>,
+    crash_reports: u32,
+}
+
+impl LogSaver {
+    pub(crate) fn new() -> Self {
+        LogSaver { enabled: false, rotated: false, saved_until: 0, last_save_ms: None, crash_reports: 0 }
+    }
+
+    /// Turn saving the log on or off, following the user's preference
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        if enabled != self.enabled {
+            log::info!("saving the log is now {}", if enabled { "on" } else { "off" });
+        }
+        self.enabled = enabled;
+    }
+
+    /// Write the contents of the log server's ring buffer to `sys.log:last`. Does nothing if the
+    /// PDDB is not mounted, saving is turned off, the log was saved too recently, or nothing has
+    /// been logged since.
+    pub(crate) fn save(&mut self, basis_cache: &mut BasisCache, hw: &mut PddbOs, now_ms: u64) {
+        if basis_cache.basis_count() == 0 || !self.enabled {
+            return;
+        }
+        if let Some(last) = self.last_save_ms {
+            if now_ms.saturating_sub(last) < MIN_SAVE_INTERVAL_MS {
+                log::debug!("log was saved {} ms ago, not saving it again yet", now_ms - last);
+                return;
+            }
+        }
+        let (entries, next) = match log_server::read_log(self.saved_until) {
+            Ok((entries, _)) if entries.is_empty() => {
+                log::debug!("nothing new to save in the log");
+                return;
+            }
+            // the records since the last save were only read to see if there are any; the whole
+            // ring is saved
+            Ok(_) => match log_server::read_log(0) {
+                Ok(log) => log,
+                Err(e) => {
+                    log::warn!("couldn't read back the log: {:?}", e);
+                    return;
+                }
+            },
+            Err(e) => {
+                log::warn!("couldn't read back the log: {:?}", e);
+                return;
+            }
+        };
+        if !ensure_dict(basis_cache, hw, LOG_DICT) {
+            return;
+        }
+        let basis = Some(PDDB_DEFAULT_SYSTEM_BASIS);
+        if !self.rotated {
+            self.rotate(basis_cache, hw);
+            self.rotated = true;
+        }
+
+        let mut text = String::new();
+        for entry in entries.iter() {
+            writeln!(text, "{}", entry).ok();
+        }
+        match basis_cache.key_update(hw, LOG_DICT, LOG_KEY_LAST, text.as_bytes(), None, None, basis, true) {
+            Ok(_) => {
+                log::debug!("saved {} log records", entries.len());
+                self.saved_until = next;
+                self.last_save_ms = Some(now_ms);
+            }
+            Err(e) => log::warn!("couldn't save the log: {:?}", e),
+        }
+    }
+
+    /// Write crash report `report_id` from the log server to a new key in `sys.crash`. Does
+    /// nothing if the PDDB is not mounted, or if the log server no longer has the report.
+    pub(crate) fn save_crash_report(
+        &mut self,
+        basis_cache: &mut BasisCache,
+        hw: &mut PddbOs,
+        xns: &xous_names::XousNames,
+        uptime_ms: u64,
+        report_id: u32,
+    ) {
+        if self.crash_reports >= MAX_CRASH_REPORTS {
+            log::warn!("{} crash reports saved already, not saving report {}", self.crash_reports, report_id);
+            return;
+        }
+        if basis_cache.basis_count() == 0 || !ensure_dict(basis_cache, hw, CRASH_DICT) {
+            return;
+        }
+        let report = match log_server::read_crash_report(report_id) {
+            Ok(Some(report)) => report,
+            Ok(None) => {
+                log::warn!("crash report {} is gone, not saving it", report_id);
+                return;
+            }
+            Err(e) => {
+                log::warn!("couldn't read crash report {}: {:?}", report_id, e);
+                return;
+            }
+        };
+
+        // Processes that never set a program name can often still be recognized by their servers
+        let name = report.name.clone().unwrap_or_else(|| {
+            let servers: Vec<String> = xns
+                .list()
+                .unwrap_or_default()
+                .iter()
+                .filter(|s| s.owner == Some(report.pid))
+                .map(|s| s.name.to_str().to_owned())
+                .collect();
+            if servers.is_empty() { "unknown".to_owned() } else { format!("owner of {}", servers.join(", ")) }
+        });
+        let mut text = String::new();
+        writeln!(text, "process: {}", name).ok();
+        writeln!(text, "pid: {}", report.pid).ok();
+        writeln!(text, "uptime: {} ms", uptime_ms).ok();
+        // the kernel does not pass exception state on to userspace yet
+        writeln!(text, "registers: not available").ok();
+        writeln!(text, "\npanic:\n{}", report.text.trim_end()).ok();
+        writeln!(text, "\nlog:").ok();
+        if let Ok((entries, _)) = log_server::read_log(0) {
+            for entry in entries.iter().filter(|e| e.pid == report.pid) {
+                writeln!(text, "{}", entry).ok();
+            }
+        }
+
+        let basis = Some(PDDB_DEFAULT_SYSTEM_BASIS);
+        let next = basis_cache
+            .key_list(hw, CRASH_DICT, basis)
+            .map(|(keys, _, _)| {
+                keys.iter()
+                    .filter_map(|k| k.strip_prefix(CRASH_KEY_PREFIX).and_then(|n| n.parse::<u32>().ok()))
+                    .max()
+                    .map(|n| n + 1)
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+        let key = format!("{}{:04}", CRASH_KEY_PREFIX, next);
+        match basis_cache.key_update(hw, CRASH_DICT, &key, text.as_bytes(), None, None, basis, true) {
+            Ok(_) => {
+                log::info!("saved crash report of {} as {}:{}", name, CRASH_DICT, key);
+                self.crash_reports += 1;
+            }
+            Err(e) => log::warn!("couldn't save crash report: {:?}", e),
+        }
+    }
+
+    fn rotate(&mut self, basis_cache: &mut BasisCache, hw: &mut PddbOs) {
+        let basis = Some(PDDB_DEFAULT_SYSTEM_BASIS);
+        let Ok(attributes) = basis_cache.key_attributes(hw, LOG_DICT, LOG_KEY_LAST, basis) else {
+            return;
+        };
+        let mut previous = vec![0u8; attributes.len];
+        match basis_cache.key_read(hw, LOG_DICT, LOG_KEY_LAST, &mut previous, None, basis) {
+            Ok(len) => {
+                previous.truncate(len);
+                basis_cache
+                    .key_update(hw, LOG_DICT, LOG_KEY_PREVIOUS, &previous, None, None, basis, true)
+                    .unwrap_or_else(|e| log::warn!("couldn't keep the previous session's log: {:?}", e));
+            }
+            Err(e) => log::warn!("couldn't read the previous session's log: {:?}", e),
+        }
+    }
+}
+
+/// Whether `pid` is the status process, which owns the `save_log` preference. Hosted builds have
+/// no loader to name processes, and no memory isolation, so any process is taken at its word there.
+pub(crate) fn may_set_enabled(pid: xous::PID) -> bool {
+    if cfg!(not(target_os = "xous")) {
+        return true;
+    }
+    let mut name = [0u8; 8];
+    match xous::process_name(pid, &mut name) {
+        Ok(len) => name.get(..len) == Some(b"status".as_slice()),
+        Err(_) => false,
+    }
+}
+
+/// Create `dict` in the system basis if it does not exist yet. Returns `false` if it couldn't be.
+fn ensure_dict(basis_cache: &mut BasisCache, hw: &mut PddbOs, dict: &str) -> bool {
+    let basis = Some(PDDB_DEFAULT_SYSTEM_BASIS);
+    if basis_cache.dict_attributes(hw, dict, basis).is_ok() {
+        return true;
+    }
+    match basis_cache.dict_add(hw, dict, basis) {
+        Ok(_) => true,
+        Err(e) => {
+            log::warn!("couldn't create {}: {:?}", dict, e);
+            false
+        }
+    }
+}
@@ -373,6 +373,10 @@ mod ux;
 use ux::*;
 mod menu;
 use menu::*;
+mod logsave;
+use logsave::*;
+mod subscriptions;
+use subscriptions::*;
 
 mod libstd;
 
@@ -436,6 +440,10 @@ struct TokenRecord {
     pub basis: Option<String>,
     pub alloc_hint: Option<usize>,
     pub conn: Option<xous::CID>, // callback connection, if one was specified
+    /// set once the key's value before the first write through this token has been kept as a version
+    pub versioned: bool,
+    /// set when the token reads back a prior version of a key, which may not be written
+    pub read_only: bool,
 }
 
 struct FileHandle {
@@ -446,6 +454,8 @@ struct FileHandle {
     pub offset: u64,
     pub length: u64,
     pub conn: Option<xous::CID>, // callback connection, if one was specified
+    /// set once the key's value before the first write through this handle has been kept as a version
+    pub versioned: bool,
 
     /// This is set to `true` when a file is removed in order to prevent
     /// other operations from functioning.
@@ -598,6 +608,10 @@ fn wrapped_main() -> ! {
 
     // track the basis monitor requester.
     let mut basis_monitor_notifications = Vec::<xous::MessageEnvelope>::new();
+    // track the servers that want to hear about changes to dicts and bases
+    let mut subscriptions = Subscriptions::new();
+    // the basis export or import in progress, and the process running it
+    let mut archive: Option<(Option<xous::PID>, ArchiveSession)> = None;
 
     // track heap usage
     let mut initial_heap: usize = 0;
@@ -631,15 +645,64 @@ fn wrapped_main() -> ! {
     let mut susres =
         susres::Susres::new(Some(susres::SuspendOrder::Early), &xns, Opcode::SuspendResume as u32, my_cid)
             .expect("couldn't create suspend/resume object");
+
+    // keep the recent log in the system basis, so it survives a crash
+    let mut log_saver = LogSaver::new();
+    // panic notifications go to a private server that only the log server knows of, and are passed on
+    // from there; `SaveLog` from anyone else is ignored, as it writes to the flash
+    let panic_sid = xous::create_server().expect("couldn't create panic notification server");
+    log_server::hook_panic_notifier(panic_sid, Opcode::SaveLog as u32)
+        .unwrap_or_else(|e| log::warn!("couldn't hook panic notifications: {:?}", e));
+    thread::spawn(move || {
+        loop {
+            let msg = xous::receive_message(panic_sid).unwrap();
+            if let Some(scalar) = msg.body.scalar_message() {
+                xous::send_message(
+                    my_cid,
+                    xous::Message::new_scalar(Opcode::SaveLog as usize, scalar.arg1, scalar.arg2, 0, 0),
+                )
+                .ok();
+            }
+        }
+    });
     loop {
+        // finish any transactions interrupted in a basis that was just mounted
+        basis_cache.txn_recover(&mut pddb_os);
+        // send out the changes made while handling the previous message
+        subscriptions.notify(basis_cache.take_changes());
         let mut msg = xous::receive_message(pddb_sid).unwrap();
         let op: Opcode = FromPrimitive::from_usize(msg.body.id() & 0xffff).unwrap_or(Opcode::InvalidOpcode);
         log::debug!("{:x?}", op);
         match op {
             Opcode::SuspendResume => xous::msg_scalar_unpack!(msg, token, _, _, _, {
+                log_saver.save(&mut basis_cache, &mut pddb_os, tt.elapsed_ms());
                 basis_cache.suspend(&mut pddb_os);
                 susres.suspend_until_resume(token).expect("couldn't execute suspend/resume");
             }),
+            Opcode::SaveLog => xous::msg_scalar_unpack!(msg, pid, report_id, _, _, {
+                if msg.sender.pid().map(|pid| pid.get() as u32) != Some(xous::process::id()) {
+                    log::warn!("ignoring SaveLog from {:?}", msg.sender.pid());
+                    continue;
+                }
+                log::info!("PID {} panicked, saving the log", pid);
+                log_saver.save(&mut basis_cache, &mut pddb_os, tt.elapsed_ms());
+                log_saver.save_crash_report(
+                    &mut basis_cache,
+                    &mut pddb_os,
+                    &xns,
+                    tt.elapsed_ms(),
+                    report_id as u32,
+                );
+            }),
+            Opcode::SetLogSaving => msg_blocking_scalar_unpack!(msg, enabled, _, _, _, {
+                if msg.sender.pid().is_some_and(logsave::may_set_enabled) {
+                    log_saver.set_enabled(enabled != 0);
+                    xous::return_scalar(msg.sender, PddbRetcode::Ok as usize).ok();
+                } else {
+                    log::warn!("ignoring SetLogSaving from {:?}", msg.sender.pid());
+                    xous::return_scalar(msg.sender, PddbRetcode::AccessDenied as usize).ok();
+                }
+            }),
             Opcode::IsEfuseSecured => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
                 if pddb_os.is_efuse_secured() {
                     xous::return_scalar(msg.sender, 1).unwrap();
@@ -829,6 +892,21 @@ fn wrapped_main() -> ! {
             Opcode::BasisMonitor => {
                 basis_monitor_notifications.push(msg);
             }
+            Opcode::Subscribe => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut sub = buffer.to_original::<PddbSubscription, _>().unwrap();
+                sub.result = subscriptions.subscribe(&sub);
+                buffer.replace(sub).unwrap();
+            }
+            Opcode::Unsubscribe => msg_blocking_scalar_unpack!(msg, s0, s1, s2, s3, {
+                let sid = [s0 as u32, s1 as u32, s2 as u32, s3 as u32];
+                if subscriptions.unsubscribe(sid) {
+                    xous::return_scalar(msg.sender, PddbRetcode::Ok as usize).ok();
+                } else {
+                    xous::return_scalar(msg.sender, PddbRetcode::AccessDenied as usize).ok();
+                }
+            }),
             Opcode::ListBasisStd => {
                 if let Some(mem) = msg.body.memory_message_mut() {
                     mem.offset = None;
@@ -881,7 +959,56 @@ fn wrapped_main() -> ! {
                 }
             }
             Opcode::CreateBasisStd => {
-                unimplemented!()
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    mem.offset = None;
+                    let result = libstd::basis_name(mem, CREATE_BASIS_STD_TAG).and_then(|name| {
+                        libstd::basis_result(basis_create_interactive(
+                            &mut pddb_os,
+                            &mut basis_cache,
+                            pw_cid,
+                            &name,
+                        ))
+                    });
+                    if let Err(err) = result {
+                        mem.offset = xous::MemoryAddress::new(err as usize);
+                    }
+                }
+            }
+            Opcode::OpenBasisStd => {
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    mem.offset = None;
+                    let result = libstd::basis_name(mem, OPEN_BASIS_STD_TAG).and_then(|name| {
+                        libstd::basis_result(basis_unlock_interactive(
+                            &modals,
+                            &mut pddb_os,
+                            &mut basis_cache,
+                            pw_cid,
+                            &mut basis_monitor_notifications,
+                            &name,
+                            BasisRetentionPolicy::Persist,
+                        ))
+                    });
+                    if let Err(err) = result {
+                        mem.offset = xous::MemoryAddress::new(err as usize);
+                    }
+                }
+            }
+            Opcode::CloseBasisStd => {
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    mem.offset = None;
+                    notify_of_disconnect(&mut pddb_os, &token_dict, &mut basis_cache);
+                    match libstd::close_basis(mem, &mut pddb_os, &mut basis_cache, &mut fd_mapping) {
+                        Ok(_) => {
+                            if basis_monitor_notifications.len() > 0 {
+                                notify_basis_change(
+                                    &mut basis_monitor_notifications,
+                                    basis_cache.basis_list(),
+                                );
+                            }
+                        }
+                        Err(err) => mem.offset = xous::MemoryAddress::new(err as usize),
+                    }
+                }
             }
             Opcode::CreateBasis => {
                 let mut buffer =
@@ -889,35 +1016,12 @@ fn wrapped_main() -> ! {
                 let mut mgmt = buffer.to_original::<PddbBasisRequest, _>().unwrap();
                 match mgmt.code {
                     PddbRequestCode::Create => {
-                        let request = BasisRequestPassword { db_name: mgmt.name, plaintext_pw: None };
-                        let mut buf = Buffer::into_buf(request).unwrap();
-                        buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
-                        let ret = buf.to_original::<BasisRequestPassword, _>().unwrap();
-                        if let Some(pw) = ret.plaintext_pw {
-                            match basis_cache.basis_create(
-                                &mut pddb_os,
-                                mgmt.name.as_str().expect("name is not valid utf-8"),
-                                pw.as_str().expect("password was not valid utf-8"),
-                            ) {
-                                Ok(_) => {
-                                    log::info!(
-                                        "{}PDDB.CREATEOK,{},{}",
-                                        xous::BOOKEND_START,
-                                        mgmt.name.as_str().unwrap(),
-                                        xous::BOOKEND_END
-                                    );
-                                    mgmt.code = PddbRequestCode::NoErr
-                                }
-                                Err(e) => match e.kind() {
-                                    ErrorKind::AlreadyExists => {
-                                        mgmt.code = PddbRequestCode::DuplicateEntry;
-                                    }
-                                    _ => mgmt.code = PddbRequestCode::InternalError,
-                                },
-                            }
-                        } else {
-                            mgmt.code = PddbRequestCode::InternalError;
-                        }
+                        mgmt.code = basis_create_interactive(
+                            &mut pddb_os,
+                            &mut basis_cache,
+                            pw_cid,
+                            mgmt.name.as_str().expect("name is not valid utf-8"),
+                        );
                     }
                     _ => {
                         mgmt.code = PddbRequestCode::InternalError;
@@ -931,79 +1035,15 @@ fn wrapped_main() -> ! {
                 let mut mgmt = buffer.to_original::<PddbBasisRequest, _>().unwrap();
                 match mgmt.code {
                     PddbRequestCode::Open => {
-                        let mut finished = false;
-                        while !finished {
-                            let request = BasisRequestPassword { db_name: mgmt.name, plaintext_pw: None };
-                            let mut buf = Buffer::into_buf(request).unwrap();
-                            buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
-                            let ret = buf.to_original::<BasisRequestPassword, _>().unwrap();
-                            if let Some(pw) = ret.plaintext_pw {
-                                if let Some(basis) = basis_cache.basis_unlock(
-                                    &mut pddb_os,
-                                    mgmt.name.as_str().expect("name is not valid utf-8"),
-                                    pw.as_str().expect("password was not valid utf-8"),
-                                    mgmt.policy.unwrap_or(BasisRetentionPolicy::Persist),
-                                ) {
-                                    if basis_cache.basis_contains(&basis.name) {
-                                        basis_cache
-                                            .basis_unmount(&mut pddb_os, &basis.name)
-                                            .expect("couldn't unmount previously mounted basis of same name");
-                                        modals
-                                            .show_notification(
-                                                t!("pddb.unmount_previous", locales::LANG),
-                                                None,
-                                            )
-                                            .expect("notification failed");
-                                    }
-                                    basis_cache.basis_add(basis);
-                                    finished = true;
-                                    log::info!(
-                                        "{}PDDB.UNLOCKOK,{},{}",
-                                        xous::BOOKEND_START,
-                                        mgmt.name.as_str().unwrap(),
-                                        xous::BOOKEND_END
-                                    );
-                                    if basis_monitor_notifications.len() > 0 {
-                                        notify_basis_change(
-                                            &mut basis_monitor_notifications,
-                                            basis_cache.basis_list(),
-                                        );
-                                    }
-                                    mgmt.code = PddbRequestCode::NoErr;
-                                } else {
-                                    log::info!(
-                                        "{}PDDB.BADPASS,{},{}",
-                                        xous::BOOKEND_START,
-                                        mgmt.name.as_str().unwrap(),
-                                        xous::BOOKEND_END
-                                    );
-                                    modals
-                                        .add_list_item(t!("pddb.yes", locales::LANG))
-                                        .expect("couldn't build radio item list");
-                                    modals
-                                        .add_list_item(t!("pddb.no", locales::LANG))
-                                        .expect("couldn't build radio item list");
-                                    match modals.get_radiobutton(t!("pddb.badpass", locales::LANG)) {
-                                        Ok(response) => {
-                                            if response.as_str() == t!("pddb.yes", locales::LANG) {
-                                                finished = false;
-                                                // this will cause just another go-around
-                                            } else if response.as_str() == t!("pddb.no", locales::LANG) {
-                                                finished = true;
-                                                mgmt.code = PddbRequestCode::AccessDenied; // this will cause a return of AccessDenied
-                                            } else {
-                                                panic!("Got unexpected return from radiobutton");
-                                            }
-                                        }
-                                        _ => panic!("get_radiobutton failed"),
-                                    }
-                                    xous::yield_slice(); // allow a redraw to happen before repeating the request
-                                }
-                            } else {
-                                finished = true;
-                                log::error!("internal error in basis unlock, aborting!");
-                            }
-                        }
+                        mgmt.code = basis_unlock_interactive(
+                            &modals,
+                            &mut pddb_os,
+                            &mut basis_cache,
+                            pw_cid,
+                            &mut basis_monitor_notifications,
+                            mgmt.name.as_str().expect("name is not valid utf-8"),
+                            mgmt.policy.unwrap_or(BasisRetentionPolicy::Persist),
+                        );
                     }
                     _ => {
                         mgmt.code = PddbRequestCode::InternalError;
@@ -1084,6 +1124,23 @@ fn wrapped_main() -> ! {
                     };
                     let dict = req.dict.as_str().expect("dict utf-8 decode error");
                     let key = req.key.as_str().expect("key utf-8 decode error");
+                    // the names of prior versions can't be asked for directly, so that they can't be written
+                    if dict.contains(VERSION_SEPARATOR) || key.contains(VERSION_SEPARATOR) {
+                        log::warn!("refusing to open {:?}:{:?}", dict, key);
+                        req.result = PddbRequestCode::AccessDenied;
+                        buffer.replace(req).unwrap();
+                        break;
+                    }
+                    let version_key;
+                    let key = match req.version {
+                        Some(version) => {
+                            req.create_dict = false;
+                            req.create_key = false;
+                            version_key = version_key_name(key, version);
+                            version_key.as_str()
+                        }
+                        None => key,
+                    };
                     log::debug!("get: {:?} {}", bname, key);
                     #[cfg(feature = "perfcounter")]
                     pddb_os.perf_entry(
@@ -1154,6 +1211,9 @@ fn wrapped_main() -> ! {
                                         std::io::ErrorKind::NotFound => {
                                             req.result = PddbRequestCode::NotMounted
                                         }
+                                        std::io::ErrorKind::OutOfMemory if is_quota_exceeded(&e) => {
+                                            req.result = PddbRequestCode::QuotaExceeded
+                                        }
                                         std::io::ErrorKind::OutOfMemory => {
                                             req.result = PddbRequestCode::NoFreeSpace
                                         }
@@ -1188,6 +1248,8 @@ fn wrapped_main() -> ! {
                         basis: if let Some(name) = bname { Some(String::from(name)) } else { None },
                         conn: cid,
                         alloc_hint,
+                        versioned: false,
+                        read_only: req.version.is_some(),
                     };
                     token_dict.insert(token, token_record);
                     req.token = Some(token);
@@ -1268,14 +1330,15 @@ fn wrapped_main() -> ! {
                 }
             }
 
-            Opcode::DeleteKey => {
+            Opcode::DeleteKey | Opcode::DeleteKeyParanoid => {
                 let mut buffer =
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
                 let mut req: PddbKeyRequest = buffer.to_original::<PddbKeyRequest, _>().unwrap();
                 let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
                 let dict = req.dict.as_str().expect("dict utf-8 decode error");
                 let key = req.key.as_str().expect("key utf-8 decode error");
-                match basis_cache.key_remove(&mut pddb_os, dict, key, bname, false) {
+                let paranoid = matches!(op, Opcode::DeleteKeyParanoid);
+                match basis_cache.key_remove(&mut pddb_os, dict, key, bname, paranoid) {
                     Ok(_) => {
                         let mut evict_list = Vec::<ApiToken>::new();
                         // check to see if we need to eliminate any ApiTokens as a result of this.
@@ -1308,6 +1371,7 @@ fn wrapped_main() -> ! {
                     }
                     Err(e) => match e.kind() {
                         std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotFound,
+                        std::io::ErrorKind::OutOfMemory => req.result = PddbRequestCode::NoFreeSpace,
                         _ => req.result = PddbRequestCode::InternalError,
                     },
                 }
@@ -1408,6 +1472,73 @@ fn wrapped_main() -> ! {
                 }
                 buffer.replace(req).unwrap();
             }
+            Opcode::CreateDictVersioned => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut req: PddbVersionRequest = buffer.to_original::<PddbVersionRequest, _>().unwrap();
+                let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
+                let dict = req.dict.as_str().expect("dict utf-8 decode error");
+                let keep = req.keep.min(MAX_KEY_VERSIONS as u32) as u8;
+                // the setting lives with the dict, in whichever basis it turns out to be in
+                let target = match basis_cache.dict_attributes(&mut pddb_os, dict, bname) {
+                    Ok(attr) => Ok(attr.basis),
+                    Err(_) => basis_cache
+                        .dict_add(&mut pddb_os, dict, bname)
+                        .and_then(|_| basis_cache.dict_attributes(&mut pddb_os, dict, bname))
+                        .map(|attr| attr.basis),
+                };
+                match target.and_then(|b| basis_cache.dict_set_versions(&mut pddb_os, dict, keep, Some(&b))) {
+                    Ok(_) => req.result = PddbRequestCode::NoErr,
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::OutOfMemory => req.result = PddbRequestCode::NoFreeSpace,
+                        std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotMounted,
+                        _ => req.result = PddbRequestCode::InternalError,
+                    },
+                }
+                buffer.replace(req).unwrap();
+            }
+            Opcode::ListKeyVersions => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut req: PddbVersionRequest = buffer.to_original::<PddbVersionRequest, _>().unwrap();
+                let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
+                let dict = req.dict.as_str().expect("dict utf-8 decode error");
+                let key = req.key.as_str().expect("key utf-8 decode error");
+                // versions are kept in the same basis as the key
+                match basis_cache
+                    .key_attributes(&mut pddb_os, dict, key, bname)
+                    .and_then(|attr| basis_cache.key_versions(&mut pddb_os, dict, key, Some(&attr.basis)))
+                {
+                    Ok(versions) => {
+                        let newest = &versions[versions.len().saturating_sub(MAX_KEY_VERSIONS)..];
+                        req.versions[..newest.len()].copy_from_slice(newest);
+                        req.count = newest.len() as u32;
+                        req.result = PddbRequestCode::NoErr;
+                    }
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotFound,
+                        _ => req.result = PddbRequestCode::InternalError,
+                    },
+                }
+                buffer.replace(req).unwrap();
+            }
+            Opcode::CompactDict => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut req: PddbKeyRequest = buffer.to_original::<PddbKeyRequest, _>().unwrap();
+                let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
+                let dict = req.dict.as_str().expect("dict utf-8 decode error");
+                // open tokens refer to keys by name, so they stay valid across the renumbering
+                match basis_cache.dict_compact(&mut pddb_os, dict, bname) {
+                    Ok(_) => req.result = PddbRequestCode::NoErr,
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotFound,
+                        std::io::ErrorKind::OutOfMemory => req.result = PddbRequestCode::NoFreeSpace,
+                        _ => req.result = PddbRequestCode::InternalError,
+                    },
+                }
+                buffer.replace(req).unwrap();
+            }
             Opcode::DeleteDictStd => {
                 if let Some(mem) = msg.body.memory_message_mut() {
                     mem.offset = None;
@@ -2048,7 +2179,17 @@ fn wrapped_main() -> ! {
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
                 let pbuf = PddbBuf::from_slice_mut(buffer.as_mut()); // direct translation, no serialization necessary for performance
                 let token = pbuf.token;
-                if let Some(rec) = token_dict.get(&token) {
+                if let Some(rec) = token_dict.get_mut(&token).filter(|rec| !rec.read_only) {
+                    if !rec.versioned {
+                        snapshot_key(
+                            &mut pddb_os,
+                            &mut basis_cache,
+                            &rec.dict,
+                            &rec.key,
+                            rec.basis.as_deref(),
+                        );
+                        rec.versioned = true;
+                    }
                     for basis in basis_cache.access_list().iter() {
                         let temp = if let Some(name) = &rec.basis { Some(name) } else { Some(basis) };
                         log::debug!("write (spec: {:?}){:?} {}", rec.basis, temp, rec.key);
@@ -2071,11 +2212,18 @@ fn wrapped_main() -> ! {
                                 std::io::ErrorKind::UnexpectedEof => {
                                     pbuf.retcode = PddbRetcode::UnexpectedEof
                                 }
+                                std::io::ErrorKind::OutOfMemory if is_quota_exceeded(&e) => {
+                                    // the quota belongs to the basis that was written, don't try the next one
+                                    pbuf.retcode = PddbRetcode::QuotaExceeded;
+                                    break;
+                                }
                                 std::io::ErrorKind::OutOfMemory => pbuf.retcode = PddbRetcode::DiskFull,
                                 _ => pbuf.retcode = PddbRetcode::InternalError,
                             },
                         }
                     }
+                } else if token_dict.contains_key(&token) {
+                    pbuf.retcode = PddbRetcode::AccessDenied;
                 } else {
                     pbuf.retcode = PddbRetcode::BasisLost;
                 }
@@ -2119,6 +2267,246 @@ fn wrapped_main() -> ! {
                 };
             }),
 
+            Opcode::Transaction => {
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    let buf: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+                    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                    let code = match buf.get(TXN_HEADER_LEN..TXN_HEADER_LEN.saturating_add(len)) {
+                        Some(encoded) if len <= MAX_TRANSACTION_LEN => match Transaction::decode(encoded) {
+                            Some(txn) => match basis_cache.txn_commit(&mut pddb_os, &txn) {
+                                Ok(_) => PddbRetcode::Ok,
+                                Err(e) => {
+                                    log::warn!("transaction failed: {:?}", e);
+                                    match e.kind() {
+                                        std::io::ErrorKind::NotFound => PddbRetcode::BasisLost,
+                                        std::io::ErrorKind::OutOfMemory if is_quota_exceeded(&e) => {
+                                            PddbRetcode::QuotaExceeded
+                                        }
+                                        std::io::ErrorKind::OutOfMemory => PddbRetcode::DiskFull,
+                                        std::io::ErrorKind::InvalidInput => PddbRetcode::AccessDenied,
+                                        _ => PddbRetcode::InternalError,
+                                    }
+                                }
+                            },
+                            None => PddbRetcode::InternalError,
+                        },
+                        _ => PddbRetcode::InternalError,
+                    };
+                    buf[..4].copy_from_slice(&(code as u32).to_le_bytes());
+                }
+            }
+            Opcode::Usage => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut req = buffer.to_original::<PddbUsageRequest, _>().unwrap();
+                let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
+                let dict = if req.dict_specified {
+                    Some(req.dict.as_str().expect("dict utf-8 decode error").to_string())
+                } else {
+                    None
+                };
+                let usage = if let Some(dict) = &dict {
+                    basis_cache.dict_usage(&mut pddb_os, dict, bname)
+                } else {
+                    basis_cache.basis_usage(&mut pddb_os, bname)
+                };
+                match usage {
+                    Ok((basis, usage)) => {
+                        req.quota = if let Some(dict) = &dict {
+                            basis_cache.dict_quota(&mut pddb_os, dict, &basis)
+                        } else {
+                            None
+                        };
+                        req.basis = xous_ipc::String::from_str(&basis);
+                        req.basis_specified = true;
+                        req.usage = usage;
+                        req.free = basis_cache.free_space(&pddb_os);
+                        req.result = PddbRequestCode::NoErr;
+                    }
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotFound,
+                        _ => req.result = PddbRequestCode::InternalError,
+                    },
+                }
+                buffer.replace(req).unwrap();
+            }
+            Opcode::SetQuota => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut req = buffer.to_original::<PddbUsageRequest, _>().unwrap();
+                let bname = if req.basis_specified { Some(req.basis.as_str().unwrap()) } else { None };
+                let dict = req.dict.as_str().expect("dict utf-8 decode error");
+                match basis_cache.dict_set_quota(&mut pddb_os, dict, req.quota, bname) {
+                    Ok(_) => req.result = PddbRequestCode::NoErr,
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::NotFound => req.result = PddbRequestCode::NotMounted,
+                        std::io::ErrorKind::InvalidInput => req.result = PddbRequestCode::AccessDenied,
+                        std::io::ErrorKind::OutOfMemory => req.result = PddbRequestCode::NoFreeSpace,
+                        _ => req.result = PddbRequestCode::InternalError,
+                    },
+                }
+                buffer.replace(req).unwrap();
+            }
+            Opcode::ExportBasis => {
+                let pid = msg.sender.pid();
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    let buf: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+                    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                    let code = match buf
+                        .get(ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN.saturating_add(len))
+                        .and_then(ExportRequest::decode)
+                    {
+                        Some(req) => {
+                            archive_abandon(&mut pddb_os, &mut basis_cache, &mut archive);
+                            match archive_export_begin(&mut pddb_os, &mut basis_cache, pw_cid, &req) {
+                                Ok(export) => {
+                                    archive = Some((pid, ArchiveSession::Export(export)));
+                                    PddbRetcode::Ok
+                                }
+                                Err(e) => {
+                                    log::warn!("couldn't start export: {:?}", e);
+                                    archive_retcode(&e)
+                                }
+                            }
+                        }
+                        None => PddbRetcode::InternalError,
+                    };
+                    buf[..4].copy_from_slice(&(code as u32).to_le_bytes());
+                    buf[4..8].copy_from_slice(&0u32.to_le_bytes());
+                }
+            }
+            Opcode::ExportRead => {
+                let pid = msg.sender.pid();
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    let buf: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+                    let ours = matches!(&archive, Some((owner, ArchiveSession::Export(_))) if *owner == pid);
+                    let result = match &mut archive {
+                        Some((_, ArchiveSession::Export(export))) if ours => {
+                            export.next_chunk(&mut pddb_os, &mut basis_cache)
+                        }
+                        _ => Err(std::io::Error::new(ErrorKind::PermissionDenied, "no export in progress")),
+                    };
+                    let (code, len) = match result {
+                        Ok(Some(chunk)) => {
+                            match buf.get_mut(ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN + chunk.len()) {
+                                Some(dest) => {
+                                    dest.copy_from_slice(&chunk);
+                                    (PddbRetcode::Ok, chunk.len())
+                                }
+                                None => (PddbRetcode::InternalError, 0),
+                            }
+                        }
+                        Ok(None) => {
+                            log::info!("export finished");
+                            archive = None;
+                            (PddbRetcode::Ok, 0)
+                        }
+                        Err(e) => {
+                            log::warn!("export failed: {:?}", e);
+                            if ours {
+                                archive = None;
+                            }
+                            (archive_retcode(&e), 0)
+                        }
+                    };
+                    buf[..4].copy_from_slice(&(code as u32).to_le_bytes());
+                    buf[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+                }
+            }
+            Opcode::ImportBasis => {
+                let pid = msg.sender.pid();
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    let buf: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+                    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                    let code = match buf
+                        .get(ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN.saturating_add(len))
+                        .and_then(ImportRequest::decode)
+                    {
+                        Some(req) => {
+                            archive_abandon(&mut pddb_os, &mut basis_cache, &mut archive);
+                            match archive_import_begin(
+                                &modals,
+                                &mut pddb_os,
+                                &mut basis_cache,
+                                pw_cid,
+                                &mut basis_monitor_notifications,
+                                &req,
+                            ) {
+                                Ok(import) => {
+                                    archive = Some((pid, ArchiveSession::Import(import)));
+                                    PddbRetcode::Ok
+                                }
+                                Err(e) => {
+                                    log::warn!("couldn't start import: {:?}", e);
+                                    archive_retcode(&e)
+                                }
+                            }
+                        }
+                        None => PddbRetcode::InternalError,
+                    };
+                    buf[..4].copy_from_slice(&(code as u32).to_le_bytes());
+                    buf[4..8].copy_from_slice(&0u32.to_le_bytes());
+                }
+            }
+            Opcode::ImportWrite => {
+                let pid = msg.sender.pid();
+                if let Some(mem) = msg.body.memory_message_mut() {
+                    let buf: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+                    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+                    let ours = matches!(&archive, Some((owner, ArchiveSession::Import(_))) if *owner == pid);
+                    let result = match (
+                        &mut archive,
+                        buf.get(ARCHIVE_MSG_HEADER_LEN..ARCHIVE_MSG_HEADER_LEN.saturating_add(len)),
+                    ) {
+                        (Some((_, ArchiveSession::Import(import))), Some(record)) if ours => {
+                            // an empty record marks the end of the archive
+                            if record.is_empty() {
+                                if import.finished() {
+                                    Ok(true)
+                                } else {
+                                    Err(std::io::Error::new(ErrorKind::UnexpectedEof, "archive ended early"))
+                                }
+                            } else {
+                                import.apply(&mut pddb_os, &mut basis_cache, record).map(|_| false)
+                            }
+                        }
+                        _ => Err(std::io::Error::new(ErrorKind::PermissionDenied, "no import in progress")),
+                    };
+                    let code = match result {
+                        Ok(true) => {
+                            log::info!("import finished");
+                            archive = None;
+                            PddbRetcode::Ok
+                        }
+                        Ok(false) => PddbRetcode::Ok,
+                        Err(e) => {
+                            log::warn!("import failed: {:?}", e);
+                            if ours {
+                                archive_abandon(&mut pddb_os, &mut basis_cache, &mut archive);
+                                if !basis_monitor_notifications.is_empty() {
+                                    notify_basis_change(
+                                        &mut basis_monitor_notifications,
+                                        basis_cache.basis_list(),
+                                    );
+                                }
+                            }
+                            archive_retcode(&e)
+                        }
+                    };
+                    buf[..4].copy_from_slice(&(code as u32).to_le_bytes());
+                    buf[4..8].copy_from_slice(&0u32.to_le_bytes());
+                }
+            }
+            Opcode::ArchiveAbort => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
+                if matches!(&archive, Some((owner, _)) if *owner == msg.sender.pid()) {
+                    archive_abandon(&mut pddb_os, &mut basis_cache, &mut archive);
+                    if !basis_monitor_notifications.is_empty() {
+                        notify_basis_change(&mut basis_monitor_notifications, basis_cache.basis_list());
+                    }
+                }
+                xous::return_scalar(msg.sender, PddbRetcode::Ok as usize).ok();
+            }),
+
             Opcode::MenuListBasis => {
                 let bases = basis_cache.basis_list();
                 let mut note = String::from(t!("pddb.menu.listbasis_response", locales::LANG));
@@ -2642,6 +3030,27 @@ pub(crate) fn hw_testcase(pddb_os: &mut PddbOs) {
     pddb_os.dbg_dump(Some("manual".to_string()), None);
 }
 
+/// Keeps the current value of a key as a prior version, if its dict keeps versions. The key is looked
+/// for in `basis`, or in the open bases if none is given.
+pub(crate) fn snapshot_key(
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    dict: &str,
+    key: &str,
+    basis: Option<&str>,
+) {
+    let bname = match basis {
+        Some(name) => name.to_string(),
+        None => match basis_cache.key_attributes(pddb_os, dict, key, None) {
+            Ok(attr) => attr.basis,
+            Err(_) => return,
+        },
+    };
+    if let Err(e) = basis_cache.key_snapshot(pddb_os, dict, key, Some(&bname)) {
+        log::warn!("couldn't keep a prior version of {}:{}: {:?}", dict, key, e);
+    }
+}
+
 fn notify_of_disconnect(
     pddb_os: &mut PddbOs,
     token_dict: &HashMap<ApiToken, TokenRecord>,
@@ -2695,6 +3104,234 @@ fn notify_of_disconnect(
     }
 }
 
+/// Prompts for the password of a new basis, and creates it. Shared by the native and libstd APIs.
+fn basis_create_interactive(
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    pw_cid: xous::CID,
+    name: &str,
+) -> PddbRequestCode {
+    let request = BasisRequestPassword {
+        db_name: xous_ipc::String::<{ crate::api::BASIS_NAME_LEN }>::from_str(name),
+        plaintext_pw: None,
+    };
+    let mut buf = Buffer::into_buf(request).unwrap();
+    buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
+    let ret = buf.to_original::<BasisRequestPassword, _>().unwrap();
+    if let Some(pw) = ret.plaintext_pw {
+        match basis_cache.basis_create(pddb_os, name, pw.as_str().expect("password was not valid utf-8")) {
+            Ok(_) => {
+                log::info!("{}PDDB.CREATEOK,{},{}", xous::BOOKEND_START, name, xous::BOOKEND_END);
+                PddbRequestCode::NoErr
+            }
+            Err(e) => match e.kind() {
+                ErrorKind::AlreadyExists => PddbRequestCode::DuplicateEntry,
+                _ => PddbRequestCode::InternalError,
+            },
+        }
+    } else {
+        PddbRequestCode::InternalError
+    }
+}
+
+/// Prompts for the password of a basis and mounts it, offering another try if the password is wrong.
+/// Shared by the native and libstd APIs.
+fn basis_unlock_interactive(
+    modals: &modals::Modals,
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    pw_cid: xous::CID,
+    basis_monitor_notifications: &mut Vec<xous::MessageEnvelope>,
+    name: &str,
+    policy: BasisRetentionPolicy,
+) -> PddbRequestCode {
+    loop {
+        let request = BasisRequestPassword {
+            db_name: xous_ipc::String::<{ crate::api::BASIS_NAME_LEN }>::from_str(name),
+            plaintext_pw: None,
+        };
+        let mut buf = Buffer::into_buf(request).unwrap();
+        buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
+        let ret = buf.to_original::<BasisRequestPassword, _>().unwrap();
+        let pw = match ret.plaintext_pw {
+            Some(pw) => pw,
+            None => {
+                log::error!("internal error in basis unlock, aborting!");
+                return PddbRequestCode::InternalError;
+            }
+        };
+        if let Some(basis) = basis_cache.basis_unlock(
+            pddb_os,
+            name,
+            pw.as_str().expect("password was not valid utf-8"),
+            policy,
+        ) {
+            if basis_cache.basis_contains(&basis.name) {
+                basis_cache
+                    .basis_unmount(pddb_os, &basis.name)
+                    .expect("couldn't unmount previously mounted basis of same name");
+                modals
+                    .show_notification(t!("pddb.unmount_previous", locales::LANG), None)
+                    .expect("notification failed");
+            }
+            basis_cache.basis_add(basis);
+            log::info!("{}PDDB.UNLOCKOK,{},{}", xous::BOOKEND_START, name, xous::BOOKEND_END);
+            if basis_monitor_notifications.len() > 0 {
+                notify_basis_change(basis_monitor_notifications, basis_cache.basis_list());
+            }
+            return PddbRequestCode::NoErr;
+        }
+        log::info!("{}PDDB.BADPASS,{},{}", xous::BOOKEND_START, name, xous::BOOKEND_END);
+        modals.add_list_item(t!("pddb.yes", locales::LANG)).expect("couldn't build radio item list");
+        modals.add_list_item(t!("pddb.no", locales::LANG)).expect("couldn't build radio item list");
+        match modals.get_radiobutton(t!("pddb.badpass", locales::LANG)) {
+            Ok(response) => {
+                if response.as_str() == t!("pddb.no", locales::LANG) {
+                    return PddbRequestCode::AccessDenied;
+                } else if response.as_str() != t!("pddb.yes", locales::LANG) {
+                    panic!("Got unexpected return from radiobutton");
+                }
+                // "yes" causes just another go-around
+            }
+            _ => panic!("get_radiobutton failed"),
+        }
+        xous::yield_slice(); // allow a redraw to happen before repeating the request
+    }
+}
+
+/// Prompts for the password of an archive of the basis `name`.
+fn archive_password(pw_cid: xous::CID, name: &str) -> Option<xous_ipc::String<{ crate::api::PASSWORD_LEN }>> {
+    let request = BasisRequestPassword {
+        db_name: xous_ipc::String::<{ crate::api::BASIS_NAME_LEN }>::from_str(&format!(
+            "{} {}",
+            name,
+            t!("pddb.archive", locales::LANG)
+        )),
+        plaintext_pw: None,
+    };
+    let mut buf = Buffer::into_buf(request).unwrap();
+    buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
+    buf.to_original::<BasisRequestPassword, _>().unwrap().plaintext_pw
+}
+
+/// Prompts for a password for the archive, and starts exporting the basis named in `req`.
+fn archive_export_begin(
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    pw_cid: xous::CID,
+    req: &ExportRequest,
+) -> std::io::Result<BasisExport> {
+    let name = match req.basis.as_deref().or(basis_cache.basis_latest()) {
+        Some(name) => name.to_string(),
+        None => return Err(std::io::Error::new(ErrorKind::NotFound, "PDDB not mounted")),
+    };
+    let pw = archive_password(pw_cid, &name)
+        .ok_or_else(|| std::io::Error::new(ErrorKind::PermissionDenied, "no password given"))?;
+    BasisExport::new(
+        pddb_os,
+        basis_cache,
+        Some(&name),
+        req.dicts.as_deref(),
+        pw.as_str().expect("password was not valid utf-8"),
+    )
+}
+
+/// Prompts for the password of the archive, offering another try if it is wrong, and then for the
+/// password of the new basis named in `req`. Creates and mounts the new basis, ready for the records of
+/// the archive to be applied to it.
+fn archive_import_begin(
+    modals: &modals::Modals,
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    pw_cid: xous::CID,
+    basis_monitor_notifications: &mut Vec<xous::MessageEnvelope>,
+    req: &ImportRequest,
+) -> std::io::Result<BasisImport> {
+    if basis_cache.basis_contains(&req.name) {
+        return Err(std::io::Error::new(ErrorKind::AlreadyExists, "Basis already exists"));
+    }
+    let import = loop {
+        let pw = archive_password(pw_cid, &req.name)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::PermissionDenied, "no password given"))?;
+        match BasisImport::new(
+            pddb_os,
+            &req.name,
+            &req.start,
+            pw.as_str().expect("password was not valid utf-8"),
+        ) {
+            Ok(import) => break import,
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {}
+            Err(e) => return Err(e),
+        }
+        modals.add_list_item(t!("pddb.yes", locales::LANG)).expect("couldn't build radio item list");
+        modals.add_list_item(t!("pddb.no", locales::LANG)).expect("couldn't build radio item list");
+        match modals.get_radiobutton(t!("pddb.badpass", locales::LANG)) {
+            Ok(response) => {
+                if response.as_str() == t!("pddb.no", locales::LANG) {
+                    return Err(std::io::Error::new(ErrorKind::PermissionDenied, "wrong password"));
+                } else if response.as_str() != t!("pddb.yes", locales::LANG) {
+                    panic!("Got unexpected return from radiobutton");
+                }
+            }
+            _ => panic!("get_radiobutton failed"),
+        }
+        xous::yield_slice(); // allow a redraw to happen before repeating the request
+    };
+    log::info!("importing basis {} as {}", import.source(), req.name);
+
+    let request = BasisRequestPassword {
+        db_name: xous_ipc::String::<{ crate::api::BASIS_NAME_LEN }>::from_str(&req.name),
+        plaintext_pw: None,
+    };
+    let mut buf = Buffer::into_buf(request).unwrap();
+    buf.lend_mut(pw_cid, PwManagerOpcode::RequestPassword.to_u32().unwrap()).unwrap();
+    let pw = buf
+        .to_original::<BasisRequestPassword, _>()
+        .unwrap()
+        .plaintext_pw
+        .ok_or_else(|| std::io::Error::new(ErrorKind::PermissionDenied, "no password given"))?;
+    let pw = pw.as_str().expect("password was not valid utf-8");
+    basis_cache.basis_create(pddb_os, &req.name, pw)?;
+    let basis = basis_cache
+        .basis_unlock(pddb_os, &req.name, pw, BasisRetentionPolicy::Persist)
+        .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "couldn't mount new basis"))?;
+    basis_cache.basis_add(basis);
+    if !basis_monitor_notifications.is_empty() {
+        notify_basis_change(basis_monitor_notifications, basis_cache.basis_list());
+    }
+    Ok(import)
+}
+
+/// Drops the export or import in progress. An import is abandoned by deleting the basis it was loading.
+fn archive_abandon(
+    pddb_os: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    archive: &mut Option<(Option<xous::PID>, ArchiveSession)>,
+) {
+    if let Some((_, ArchiveSession::Import(import))) = archive.take() {
+        if !import.finished() {
+            log::warn!("abandoning import, deleting basis {}", import.basis());
+            basis_cache
+                .basis_discard(pddb_os, import.basis())
+                .map_err(|e| log::error!("couldn't delete partly imported basis: {:?}", e))
+                .ok();
+        }
+    }
+}
+
+fn archive_retcode(e: &std::io::Error) -> PddbRetcode {
+    match e.kind() {
+        ErrorKind::NotFound => PddbRetcode::BasisLost,
+        ErrorKind::PermissionDenied | ErrorKind::InvalidInput => PddbRetcode::AccessDenied,
+        ErrorKind::InvalidData => PddbRetcode::InvalidArchive,
+        ErrorKind::UnexpectedEof => PddbRetcode::UnexpectedEof,
+        ErrorKind::AlreadyExists => PddbRetcode::AlreadyExists,
+        ErrorKind::OutOfMemory if is_quota_exceeded(e) => PddbRetcode::QuotaExceeded,
+        ErrorKind::OutOfMemory => PddbRetcode::DiskFull,
+        _ => PddbRetcode::InternalError,
+    }
+}
+
 fn notify_basis_change(
     basis_monitor_notifications: &mut Vec<xous::MessageEnvelope>,
     basis_list: Vec<String>,
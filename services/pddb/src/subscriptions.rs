@@ -0,0 +1,174 @@
+//! Sends the changes recorded by the `BasisCache` to the servers that subscribed to them.
+//!
+//! A subscriber names a server, and optionally a dict and a basis to watch. It is sent every key
+//! created, updated or deleted in a watched dict, and every basis that is mounted or unmounted.
+//! The server has to be running when it subscribes: it is connected to without waiting for it to
+//! appear. Changes are sent without waiting for room in the server's queue, so a subscriber that
+//! falls behind can't stall the PDDB. The changes it has no room for are dropped, and it is sent a
+//! single `ChangesDropped` in their place once its queue has room again. A subscriber that can't be
+//! sent a change for any other reason is dropped.
+
+use xous::{CID, SID};
+use xous_ipc::Buffer;
+
+use crate::api::*;
+
+/// The kernel calls used to reach subscribers. These are kept behind a trait so that the
+/// bookkeeping can be exercised without a running kernel.
+pub(crate) trait Transport {
+    fn connect(&mut self, sid: SID) -> Result<CID, xous::Error>;
+    fn disconnect(&mut self, cid: CID);
+    fn send(&mut self, cid: CID, change: PddbChange) -> Result<(), xous::Error>;
+}
+
+/// Reaches subscribers through the kernel
+pub(crate) struct Kernel;
+
+impl Transport for Kernel {
+    // `try_connect()` so that a SID nobody serves can't leave the PDDB blocked forever
+    fn connect(&mut self, sid: SID) -> Result<CID, xous::Error> { xous::try_connect(sid) }
+
+    fn disconnect(&mut self, cid: CID) {
+        // Safety: the connection is only used for changes, and the subscriber using it is gone
+        unsafe { xous::disconnect(cid).ok() };
+    }
+
+    // `try_send()` so that a subscriber that doesn't drain its queue can't block the PDDB
+    fn send(&mut self, cid: CID, change: PddbChange) -> Result<(), xous::Error> {
+        let buf = Buffer::into_buf(change).expect("couldn't convert change");
+        buf.try_send(cid, PDDB_CHANGE_OPCODE).map(|_| ())
+    }
+}
+
+struct Watch {
+    /// `None` watches only the bases being mounted and unmounted
+    dict: Option<String>,
+    /// `None` watches every basis
+    basis: Option<String>,
+}
+impl Watch {
+    fn matches(&self, change: &PddbChange) -> bool {
+        let basis = change.basis.as_str().unwrap_or("");
+        if let Some(b) = &self.basis {
+            if b != basis {
+                return false;
+            }
+        }
+        match change.kind {
+            PddbChangeKind::BasisMounted | PddbChangeKind::BasisUnmounted => true,
+            _ => self.dict.as_deref() == Some(change.dict.as_str().unwrap_or("")),
+        }
+    }
+}
+
+struct Subscriber {
+    sid: [u32; 4],
+    cid: CID,
+    watches: Vec<Watch>,
+    /// Set when a change was dropped because the server's queue was full
+    dropped: bool,
+}
+
+pub(crate) struct Subscriptions<T: Transport = Kernel> {
+    subscribers: Vec<Subscriber>,
+    transport: T,
+}
+
+impl Subscriptions {
+    pub(crate) fn new() -> Self { Subscriptions::with_transport(Kernel) }
+}
+
+impl<T: Transport> Subscriptions<T> {
+    pub(crate) fn with_transport(transport: T) -> Self {
+        Subscriptions { subscribers: Vec::new(), transport }
+    }
+
+    /// Adds a watch for the server in `sub`. A server that subscribes several times shares one
+    /// connection, and is sent each change only once.
+    pub(crate) fn subscribe(&mut self, sub: &PddbSubscription) -> PddbRequestCode {
+        let watch = Watch {
+            dict: if sub.dict_specified {
+                Some(sub.dict.as_str().expect("dict utf-8 decode error").to_string())
+            } else {
+                None
+            },
+            basis: if sub.basis_specified {
+                Some(sub.basis.as_str().expect("basis utf-8 decode error").to_string())
+            } else {
+                None
+            },
+        };
+        if let Some(subscriber) = self.subscribers.iter_mut().find(|s| s.sid == sub.sid) {
+            subscriber.watches.push(watch);
+            return PddbRequestCode::NoErr;
+        }
+        match self.transport.connect(SID::from_array(sub.sid)) {
+            Ok(cid) => {
+                self.subscribers.push(Subscriber { sid: sub.sid, cid, watches: vec![watch], dropped: false });
+                PddbRequestCode::NoErr
+            }
+            Err(e) => {
+                log::error!("couldn't connect to subscriber: {:?}", e);
+                PddbRequestCode::InternalError
+            }
+        }
+    }
+
+    /// Removes every watch of the server `sid`, and releases the connection to it.
+    pub(crate) fn unsubscribe(&mut self, sid: [u32; 4]) -> bool {
+        if let Some(index) = self.subscribers.iter().position(|s| s.sid == sid) {
+            let subscriber = self.subscribers.remove(index);
+            self.transport.disconnect(subscriber.cid);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn notify(&mut self, changes: Vec<PddbChange>) {
+        let transport = &mut self.transport;
+        for change in changes {
+            self.subscribers.retain_mut(|subscriber| {
+                if !subscriber.watches.iter().any(|w| w.matches(&change)) {
+                    return true;
+                }
+                // the notice of dropped changes goes ahead of anything sent after them
+                if subscriber.dropped {
+                    let notice = PddbChange::new(PddbChangeKind::ChangesDropped, "", "", "");
+                    match transport.send(subscriber.cid, notice) {
+                        Ok(_) => subscriber.dropped = false,
+                        Err(xous::Error::ServerQueueFull) => return true,
+                        Err(e) => return Self::drop_subscriber(transport, subscriber, e),
+                    }
+                }
+                match transport.send(subscriber.cid, change) {
+                    Ok(_) => true,
+                    Err(xous::Error::ServerQueueFull) => {
+                        log::warn!("subscriber's queue is full, dropping changes until it has room");
+                        subscriber.dropped = true;
+                        true
+                    }
+                    Err(e) => Self::drop_subscriber(transport, subscriber, e),
+                }
+            });
+        }
+    }
+
+    fn drop_subscriber(transport: &mut T, subscriber: &Subscriber, e: xous::Error) -> bool {
+        // most likely the server has exited, and its SID may yet be reused by another
+        log::warn!("couldn't send change to subscriber, dropping it: {:?}", e);
+        transport.disconnect(subscriber.cid);
+        false
+    }
+}
+
+impl PddbChange {
+    pub(crate) fn new(kind: PddbChangeKind, basis: &str, dict: &str, key: &str) -> Self {
+        PddbChange {
+            kind,
+            basis: xous_ipc::String::from_str(basis),
+            dict: xous_ipc::String::from_str(dict),
+            key: xous_ipc::String::from_str(key),
+        }
+    }
+}
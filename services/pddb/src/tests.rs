@@ -1,6 +1,8 @@
 use core::sync::atomic::{AtomicU64, Ordering};
-use std::collections::{BTreeSet, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::io::Result;
+use std::rc::Rc;
 
 use rand_chacha::rand_core::RngCore;
 use rand_chacha::rand_core::SeedableRng;
@@ -423,6 +425,756 @@ pub(crate) fn delete_pattern(
     Ok(())
 }
 
+/// Fills a dictionary, deletes most of it to fragment the key index and small pool, then compacts it.
+/// Returns the keys that should have survived, for `compact_check` to verify again after a remount.
+pub(crate) fn compact_test(
+    hw: &mut PddbOs,
+    basis_cache: &mut BasisCache,
+    maybe_num_keys: Option<usize>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    const COMPACT_DICT: &str = "compacttest";
+    let num_keys = maybe_num_keys.unwrap_or(100);
+
+    let mut keys = Vec::<(String, Vec<u8>)>::new();
+    for keynum in 1..=num_keys {
+        let (keyname, keydata) = gen_key(COMPACT_DICT, keynum, LOWER_BOUND, UPPER_BOUND - 4);
+        basis_cache.key_update(hw, COMPACT_DICT, &keyname, &keydata, None, None, None, false)?;
+        keys.push((keyname, keydata));
+    }
+    basis_cache.sync(hw, None, false)?;
+    // keep every third key, so that there are live keys spread across the whole index
+    let mut survivors = Vec::<(String, Vec<u8>)>::new();
+    for (index, (keyname, keydata)) in keys.into_iter().enumerate() {
+        if index % 3 == 2 {
+            survivors.push((keyname, keydata));
+        } else {
+            basis_cache.key_remove(hw, COMPACT_DICT, &keyname, None, false)?;
+        }
+    }
+    basis_cache.sync(hw, None, false)?;
+    let before = basis_cache.dict_attributes(hw, COMPACT_DICT, None)?;
+    log::info!("before compaction: {:?}", before);
+
+    basis_cache.dict_compact(hw, COMPACT_DICT, None)?;
+    let after = basis_cache.dict_attributes(hw, COMPACT_DICT, None)?;
+    log::info!("after compaction: {:?}", after);
+    assert!(after.num_keys as usize == survivors.len(), "key count changed across compaction");
+    assert!(after.free_key_index == after.num_keys + 1, "key index was not compacted");
+    assert!(after.small_key_count <= before.small_key_count, "small pool grew across compaction");
+    compact_check(hw, basis_cache, &survivors);
+
+    // the freed index space must be usable again
+    let (keyname, keydata) = gen_key(COMPACT_DICT, num_keys + 1, LOWER_BOUND, UPPER_BOUND - 4);
+    basis_cache.key_update(hw, COMPACT_DICT, &keyname, &keydata, None, None, None, false)?;
+    survivors.push((keyname, keydata));
+    basis_cache.sync(hw, None, false)?;
+    compact_check(hw, basis_cache, &survivors);
+    Ok(survivors)
+}
+
+/// Checks that exactly the keys in `expected` are in the compaction test dictionary, with their data intact.
+pub(crate) fn compact_check(hw: &mut PddbOs, basis_cache: &mut BasisCache, expected: &[(String, Vec<u8>)]) {
+    const COMPACT_DICT: &str = "compacttest";
+    let (key_list, _, _) = basis_cache.key_list(hw, COMPACT_DICT, None).unwrap();
+    assert!(key_list.len() == expected.len(), "compacted dictionary has the wrong number of keys");
+    for (keyname, keydata) in expected.iter() {
+        let mut readback = vec![0u8; keydata.len()];
+        match basis_cache.key_read(hw, COMPACT_DICT, keyname, &mut readback, None, None) {
+            Ok(len) => {
+                assert!(len == keydata.len(), "length mismatch on {}", keyname);
+                assert!(&readback == keydata, "data mismatch on {}", keyname);
+            }
+            Err(e) => panic!("couldn't read {} after compaction: {:?}", keyname, e),
+        }
+    }
+    log::info!("compacted dictionary verified: {} keys", expected.len());
+}
+
+/// Compacts a fragmented dict over and over, each time cutting the power at a later write to the disk,
+/// then remounts and checks that every key is still there with its data, and that nothing of the
+/// compaction is left behind. Stops after the first run that completes before the power is cut. Leaves
+/// `basis_cache` freshly mounted.
+pub(crate) fn compact_power_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const CUT_DICT: &str = "compactcut";
+    let mut keys = Vec::<(String, Vec<u8>)>::new();
+    for keynum in 1..=16 {
+        let (keyname, keydata) = gen_key(CUT_DICT, keynum, LOWER_BOUND, UPPER_BOUND - 4);
+        basis_cache.key_update(hw, CUT_DICT, &keyname, &keydata, None, None, None, false)?;
+        keys.push((keyname, keydata));
+    }
+    basis_cache.sync(hw, None, false)?;
+    let mut expected = Vec::<(String, Vec<u8>)>::new();
+    for (index, (keyname, keydata)) in keys.into_iter().enumerate() {
+        if index % 2 == 1 {
+            expected.push((keyname, keydata));
+        } else {
+            basis_cache.key_remove(hw, CUT_DICT, &keyname, None, false)?;
+        }
+    }
+    basis_cache.sync(hw, None, false)?;
+    let image = hw.dbg_raw_image().to_vec();
+
+    let remount = |hw: &mut PddbOs, basis_cache: &mut BasisCache| {
+        *basis_cache = BasisCache::new();
+        basis_cache.basis_add(hw.pddb_mount().expect("couldn't remount the PDDB"));
+        basis_cache.txn_recover(hw);
+    };
+
+    let mut cut = 0;
+    loop {
+        hw.dbg_restore_image(&image);
+        remount(hw, basis_cache);
+        hw.dbg_power_loss_after(Some(cut));
+        // the disk stops taking writes part-way, so the in-memory structures may not survive; that's ok,
+        // because they are thrown away by the remount
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            basis_cache.dict_compact(hw, CUT_DICT, None)
+        }));
+        let lost = hw.dbg_power_lost();
+        hw.dbg_power_loss_after(None);
+        remount(hw, basis_cache);
+
+        let (key_list, _, _) = basis_cache.key_list(hw, CUT_DICT, None)?;
+        assert!(key_list.len() == expected.len(), "keys lost with power lost at write {}", cut);
+        for (keyname, keydata) in expected.iter() {
+            let mut readback = vec![0u8; keydata.len()];
+            let len = basis_cache.key_read(hw, CUT_DICT, keyname, &mut readback, None, None)?;
+            assert!(
+                len == keydata.len() && &readback == keydata,
+                "{} damaged with power lost at write {}",
+                keyname,
+                cut
+            );
+        }
+        assert!(
+            basis_cache.key_attributes(hw, TXN_DICT, COMPACT_LOG_KEY, None).is_err(),
+            "compaction log left behind after recovery"
+        );
+        assert!(
+            basis_cache.dict_attributes(hw, COMPACT_COPY_DICT, None).is_err(),
+            "compacted copy left behind after recovery"
+        );
+        if !lost {
+            assert!(matches!(result, Ok(Ok(()))), "compaction failed without a power loss");
+            let after = basis_cache.dict_attributes(hw, CUT_DICT, None)?;
+            assert!(after.free_key_index == after.num_keys + 1, "key index was not compacted");
+            break;
+        }
+        cut += 1;
+    }
+    log::info!("compaction power loss test passed, with power lost at each of {} writes", cut);
+    Ok(())
+}
+
+/// Patches and reads back parts of a large key, checking the results against a copy held in RAM. The
+/// same pages are hit repeatedly, so that most accesses go through the large key page cache, which is
+/// also pruned part way through.
+pub(crate) fn large_cache_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const LARGE_DICT: &str = "largecache";
+    const LARGE_KEY: &str = "bigkey";
+    let mut rng = ChaCha8Rng::seed_from_u64(
+        RNG_LOCAL_STATE.load(Ordering::SeqCst)
+            + xous::TESTING_RNG_SEED.load(core::sync::atomic::Ordering::SeqCst),
+    );
+    let mut model = vec![0u8; VPAGE_SIZE * 6 + 123];
+    rng.fill_bytes(&mut model);
+    basis_cache.key_update(hw, LARGE_DICT, LARGE_KEY, &model, None, None, None, true)?;
+    basis_cache.sync(hw, None, false)?;
+
+    for round in 0..64 {
+        // small patches and reads that mostly straddle page boundaries
+        let offset = (rng.next_u32() as usize) % (model.len() - 64);
+        let len = 1 + (rng.next_u32() as usize) % 64;
+        if round % 2 == 0 {
+            let mut patch = vec![0u8; len];
+            rng.fill_bytes(&mut patch);
+            basis_cache.key_update(hw, LARGE_DICT, LARGE_KEY, &patch, Some(offset), None, None, false)?;
+            model[offset..offset + len].copy_from_slice(&patch);
+        }
+        let mut readback = vec![0u8; len];
+        let read = basis_cache.key_read(hw, LARGE_DICT, LARGE_KEY, &mut readback, Some(offset), None)?;
+        assert!(read == len, "short read of large key");
+        assert!(readback == model[offset..offset + len], "large key data mismatch at {}", offset);
+        if round == 32 {
+            log::info!("large key cache holds {} bytes, pruning", basis_cache.cache_size());
+            basis_cache.cache_prune(hw, usize::MAX);
+        }
+    }
+    basis_cache.sync(hw, None, false)?;
+
+    let mut readback = vec![0u8; model.len()];
+    let read = basis_cache.key_read(hw, LARGE_DICT, LARGE_KEY, &mut readback, None, None)?;
+    assert!(read == model.len() && readback == model, "large key data mismatch after sync");
+    RNG_LOCAL_STATE.store(rng.next_u64(), Ordering::SeqCst);
+    log::info!("large key cache test passed");
+    Ok(())
+}
+
+/// Erases a small key that shares its pool page with other keys, then scans the raw disk image to
+/// make sure neither its plaintext nor the ciphertext of the page that held it survived, and that the
+/// keys it shared the page with are still intact on disk.
+pub(crate) fn paranoid_erase_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const ERASE_DICT: &str = "erasetest";
+    const SECRET_KEY: &str = "secret";
+    let mut rng = ChaCha8Rng::seed_from_u64(
+        RNG_LOCAL_STATE.load(Ordering::SeqCst)
+            + xous::TESTING_RNG_SEED.load(core::sync::atomic::Ordering::SeqCst),
+    );
+    let mut neighbours = Vec::<(String, Vec<u8>)>::new();
+    let mut secret = Vec::<u8>::new();
+    for i in 0..8 {
+        if i == 4 {
+            secret.extend_from_slice(b"paranoid erase marker ");
+            let mut noise = [0u8; 64];
+            rng.fill_bytes(&mut noise);
+            secret.extend_from_slice(&noise);
+            basis_cache.key_update(hw, ERASE_DICT, SECRET_KEY, &secret, None, None, None, true)?;
+        }
+        let mut data = vec![0u8; 64 + (rng.next_u32() as usize) % 192];
+        rng.fill_bytes(&mut data);
+        let name = format!("neighbour{}", i);
+        basis_cache.key_update(hw, ERASE_DICT, &name, &data, None, None, None, true)?;
+        neighbours.push((name, data));
+    }
+    basis_cache.sync(hw, None, false)?;
+
+    let pages = basis_cache.dbg_key_pages(hw, ERASE_DICT, SECRET_KEY, None);
+    assert!(pages.len() == 1, "secret should be in exactly one small pool page");
+    assert!(
+        neighbours.iter().any(|(name, _)| basis_cache.dbg_key_pages(hw, ERASE_DICT, name, None) == pages),
+        "secret should share its small pool page with another key"
+    );
+    let old_page = hw.dbg_data_page(&pages[0]);
+
+    basis_cache.key_remove(hw, ERASE_DICT, SECRET_KEY, None, true)?;
+
+    let contains = |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+    let image = hw.dbg_raw_image();
+    assert!(!contains(image, &secret), "plaintext of erased key found on disk");
+    // skip the nonce, and look for any run of the old ciphertext
+    for chunk in old_page[size_of::<aes_gcm_siv::Nonce>()..].chunks(256) {
+        assert!(!contains(image, chunk), "ciphertext of erased key's page found on disk");
+    }
+
+    // drop the cached copies, so the neighbours are read back from the rewritten page
+    basis_cache.cache_prune(hw, usize::MAX);
+    for (name, data) in neighbours.iter() {
+        let mut readback = vec![0u8; data.len()];
+        let read = basis_cache.key_read(hw, ERASE_DICT, name, &mut readback, None, None)?;
+        assert!(read == data.len() && &readback == data, "neighbour {} damaged by erase", name);
+    }
+    assert!(
+        basis_cache.key_attributes(hw, ERASE_DICT, SECRET_KEY, None).is_err(),
+        "erased key is still listed"
+    );
+    RNG_LOCAL_STATE.store(rng.next_u64(), Ordering::SeqCst);
+    log::info!("paranoid erase test passed");
+    Ok(())
+}
+
+/// Creates, updates and deletes keys, and checks that the changes reported to subscribers name the
+/// right keys, in order, without the keys that hold prior versions.
+pub(crate) fn change_log_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const CHANGE_DICT: &str = "changetest";
+    let basis = basis_cache.basis_latest().expect("PDDB is not mounted").to_string();
+    basis_cache.take_changes();
+    basis_cache.key_update(hw, CHANGE_DICT, "a", b"one", None, None, None, true)?;
+    basis_cache.key_update(hw, CHANGE_DICT, "b", b"two", None, None, None, true)?;
+    basis_cache.dict_set_versions(hw, CHANGE_DICT, 2, None)?;
+    basis_cache.key_update(hw, CHANGE_DICT, "a", b"three", None, None, None, true)?;
+    basis_cache.key_snapshot(hw, CHANGE_DICT, "a", None)?;
+    basis_cache.key_remove(hw, CHANGE_DICT, "a", None, false)?;
+    basis_cache.dict_remove(hw, CHANGE_DICT, None, false)?;
+
+    let changes: Vec<(PddbChangeKind, String, String, String)> = basis_cache
+        .take_changes()
+        .iter()
+        .map(|c| {
+            (
+                c.kind,
+                c.basis.as_str().unwrap().to_string(),
+                c.dict.as_str().unwrap().to_string(),
+                c.key.as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    let expected: Vec<(PddbChangeKind, String, String, String)> = [
+        (PddbChangeKind::KeyCreated, "a"),
+        (PddbChangeKind::KeyCreated, "b"),
+        (PddbChangeKind::KeyUpdated, "a"),
+        (PddbChangeKind::KeyDeleted, "a"),
+        (PddbChangeKind::KeyDeleted, "b"),
+    ]
+    .iter()
+    .map(|&(kind, key)| (kind, basis.clone(), CHANGE_DICT.to_string(), key.to_string()))
+    .collect();
+    assert!(changes == expected, "unexpected change log: {:?}", changes);
+    log::info!("change log test passed");
+    Ok(())
+}
+
+/// Servers that hold up to a fixed number of changes each, and never drain their queues
+struct StalledQueues {
+    /// The SID and queue capacity of each server; the CID of a server is its index plus one
+    servers: Vec<([u32; 4], usize)>,
+    queued: Rc<RefCell<Vec<(xous::CID, PddbChange)>>>,
+}
+impl Transport for StalledQueues {
+    fn connect(&mut self, sid: xous::SID) -> core::result::Result<xous::CID, xous::Error> {
+        match self.servers.iter().position(|(s, _)| *s == sid.to_array()) {
+            Some(index) => Ok(index as xous::CID + 1),
+            None => Err(xous::Error::ServerNotFound),
+        }
+    }
+
+    fn disconnect(&mut self, cid: xous::CID) { self.servers[cid as usize - 1].0 = [0; 4]; }
+
+    fn send(&mut self, cid: xous::CID, change: PddbChange) -> core::result::Result<(), xous::Error> {
+        let (sid, capacity) = self.servers[cid as usize - 1];
+        let mut queued = self.queued.borrow_mut();
+        if sid == [0; 4] {
+            Err(xous::Error::ServerNotFound)
+        } else if queued.iter().filter(|(c, _)| *c == cid).count() >= capacity {
+            Err(xous::Error::ServerQueueFull)
+        } else {
+            queued.push((cid, change));
+            Ok(())
+        }
+    }
+}
+
+/// Checks that a subscriber that never drains its queue only loses the changes it has no room for,
+/// that it is told about the loss once it has room again, and that other subscribers are still sent
+/// every change. Also checks that a server that doesn't exist can't subscribe.
+pub(crate) fn stalled_subscriber_test() {
+    const STALLED: [u32; 4] = [1, 2, 3, 4];
+    const HEALTHY: [u32; 4] = [5, 6, 7, 8];
+    let subscription = |sid: [u32; 4]| PddbSubscription {
+        sid,
+        dict_specified: true,
+        dict: xous_ipc::String::from_str("watched"),
+        basis_specified: false,
+        basis: xous_ipc::String::new(),
+        result: PddbRequestCode::Uninit,
+    };
+    let queued = Rc::new(RefCell::new(Vec::new()));
+    let mut subscriptions = Subscriptions::with_transport(StalledQueues {
+        servers: vec![(STALLED, 2), (HEALTHY, 32)],
+        queued: queued.clone(),
+    });
+    assert!(matches!(subscriptions.subscribe(&subscription([9, 9, 9, 9])), PddbRequestCode::InternalError));
+    assert!(matches!(subscriptions.subscribe(&subscription(STALLED)), PddbRequestCode::NoErr));
+    assert!(matches!(subscriptions.subscribe(&subscription(HEALTHY)), PddbRequestCode::NoErr));
+
+    let update = |key: &str| PddbChange::new(PddbChangeKind::KeyUpdated, "", "watched", key);
+    let received = |cid: xous::CID| -> Vec<(PddbChangeKind, String)> {
+        queued
+            .borrow()
+            .iter()
+            .filter(|(c, _)| *c == cid)
+            .map(|(_, change)| (change.kind, change.key.as_str().unwrap().to_string()))
+            .collect()
+    };
+    subscriptions.notify(["a", "b", "c", "d"].iter().map(|k| update(k)).collect());
+    assert!(
+        received(1)
+            == vec![(PddbChangeKind::KeyUpdated, "a".into()), (PddbChangeKind::KeyUpdated, "b".into())]
+    );
+    assert!(received(2).len() == 4);
+
+    // the stalled server finally reads its queue
+    queued.borrow_mut().retain(|(c, _)| *c != 1);
+    subscriptions.notify(vec![update("e")]);
+    assert!(
+        received(1)
+            == vec![(PddbChangeKind::ChangesDropped, "".into()), (PddbChangeKind::KeyUpdated, "e".into())]
+    );
+    assert!(received(2).len() == 5);
+    assert!(subscriptions.unsubscribe(STALLED));
+    assert!(subscriptions.unsubscribe(HEALTHY));
+    log::info!("stalled subscriber test passed");
+}
+
+/// Commits a transaction over and over, each time cutting the power at a later write to the disk,
+/// then remounts and checks that the transaction either took effect in full or not at all. Stops
+/// after the first run that completes before the power is cut. Leaves `basis_cache` freshly mounted.
+pub(crate) fn transaction_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const TXN_TEST_DICT: &str = "txntest";
+    const KEYS: [&str; 4] = ["a", "b", "c", "large"];
+    let mut rng = ChaCha8Rng::seed_from_u64(
+        RNG_LOCAL_STATE.load(Ordering::SeqCst)
+            + xous::TESTING_RNG_SEED.load(core::sync::atomic::Ordering::SeqCst),
+    );
+    let mut large = vec![0u8; 9000];
+    rng.fill_bytes(&mut large);
+    basis_cache.key_update(hw, TXN_TEST_DICT, "a", b"a before", None, None, None, true)?;
+    basis_cache.key_update(hw, TXN_TEST_DICT, "b", b"b before", None, None, None, true)?;
+    basis_cache.sync(hw, None, false)?;
+    let image = hw.dbg_raw_image().to_vec();
+
+    let mut large_after = large.clone();
+    large_after.reverse();
+    let txn = Transaction {
+        basis: None,
+        ops: vec![
+            TxnOp::Write { dict: TXN_TEST_DICT.into(), key: "a".into(), data: b"a after".to_vec() },
+            TxnOp::Delete { dict: TXN_TEST_DICT.into(), key: "b".into() },
+            TxnOp::Write { dict: TXN_TEST_DICT.into(), key: "c".into(), data: b"c after".to_vec() },
+            TxnOp::Write { dict: TXN_TEST_DICT.into(), key: "large".into(), data: large_after.clone() },
+        ],
+    };
+    let before = vec![Some(b"a before".to_vec()), Some(b"b before".to_vec()), None, None];
+    let after = vec![Some(b"a after".to_vec()), None, Some(b"c after".to_vec()), Some(large_after)];
+
+    let remount = |hw: &mut PddbOs, basis_cache: &mut BasisCache| {
+        *basis_cache = BasisCache::new();
+        basis_cache.basis_add(hw.pddb_mount().expect("couldn't remount the PDDB"));
+        basis_cache.txn_recover(hw);
+    };
+    let state = |hw: &mut PddbOs, basis_cache: &mut BasisCache| -> Vec<Option<Vec<u8>>> {
+        KEYS.iter()
+            .map(|key| {
+                let attr = basis_cache.key_attributes(hw, TXN_TEST_DICT, key, None).ok()?;
+                let mut data = vec![0u8; attr.len];
+                let read = basis_cache.key_read(hw, TXN_TEST_DICT, key, &mut data, None, None).ok()?;
+                data.truncate(read);
+                Some(data)
+            })
+            .collect()
+    };
+
+    let mut cut = 0;
+    loop {
+        hw.dbg_restore_image(&image);
+        remount(hw, basis_cache);
+        hw.dbg_power_loss_after(Some(cut));
+        // the disk stops taking writes part-way, so the in-memory structures may not survive; that's ok,
+        // because they are thrown away by the remount
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| basis_cache.txn_commit(hw, &txn)));
+        let lost = hw.dbg_power_lost();
+        hw.dbg_power_loss_after(None);
+        remount(hw, basis_cache);
+        let found = state(hw, basis_cache);
+        if !lost {
+            assert!(matches!(result, Ok(Ok(()))), "transaction failed without a power loss");
+            assert!(found == after, "transaction incomplete after committing");
+            break;
+        }
+        assert!(
+            found == before || found == after,
+            "transaction partially applied with power lost at write {}",
+            cut
+        );
+        assert!(
+            basis_cache.key_attributes(hw, TXN_DICT, TXN_LOG_KEY, None).is_err(),
+            "transaction log left behind after recovery"
+        );
+        cut += 1;
+    }
+    log::info!("transaction test passed, with power lost at each of {} writes", cut);
+    RNG_LOCAL_STATE.store(rng.next_u64(), Ordering::SeqCst);
+    Ok(())
+}
+
+/// Sets a quota on a dict, and checks that writes and transactions that would take the dict over it are
+/// refused while the ones that fit go through, that the quota outlives a remount, and that the usage
+/// reported for the dict follows its keys. Leaves `basis_cache` freshly mounted.
+pub(crate) fn quota_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const QUOTA_TEST_DICT: &str = "quotatest";
+    let write = |dict: &str, key: &str, len: usize| TxnOp::Write {
+        dict: dict.to_string(),
+        key: key.to_string(),
+        data: vec![0x5a; len],
+    };
+    basis_cache.key_update(hw, QUOTA_TEST_DICT, "a", &[1u8; 600], None, None, None, true)?;
+    basis_cache.dict_set_quota(hw, QUOTA_TEST_DICT, Some(1000), None)?;
+    let (_, usage) = basis_cache.dict_usage(hw, QUOTA_TEST_DICT, None)?;
+    assert!(usage.key_count == 1 && usage.used == 600, "unexpected usage: {:?}", usage);
+    assert!(usage.allocated >= usage.used && usage.index > 0, "unexpected usage: {:?}", usage);
+
+    // filling the dict right up to its quota is allowed
+    basis_cache.key_update(hw, QUOTA_TEST_DICT, "b", &[2u8; 400], None, None, None, true)?;
+    let e = basis_cache
+        .key_update(hw, QUOTA_TEST_DICT, "c", &[3u8; 1], None, None, None, true)
+        .expect_err("created a key past the quota");
+    assert!(is_quota_exceeded(&e), "unexpected error: {:?}", e);
+    let e = basis_cache
+        .key_update(hw, QUOTA_TEST_DICT, "a", &[1u8; 10], Some(600), None, None, false)
+        .expect_err("extended a key past the quota");
+    assert!(is_quota_exceeded(&e), "unexpected error: {:?}", e);
+    basis_cache.key_update(hw, QUOTA_TEST_DICT, "a", &[4u8; 10], Some(0), None, None, false)?;
+
+    // transactions are held to the quota when they finish, not part-way through
+    let txn = Transaction {
+        basis: None,
+        ops: vec![
+            write(QUOTA_TEST_DICT, "c", 500),
+            TxnOp::Delete { dict: QUOTA_TEST_DICT.to_string(), key: "a".to_string() },
+        ],
+    };
+    basis_cache.txn_commit(hw, &txn)?;
+    let txn = Transaction {
+        basis: None,
+        ops: vec![
+            TxnOp::Delete { dict: QUOTA_TEST_DICT.to_string(), key: "b".to_string() },
+            write(QUOTA_TEST_DICT, "d", 600),
+        ],
+    };
+    let e = basis_cache.txn_commit(hw, &txn).expect_err("committed a transaction past the quota");
+    assert!(is_quota_exceeded(&e), "unexpected error: {:?}", e);
+    assert!(
+        basis_cache.key_attributes(hw, QUOTA_TEST_DICT, "b", None).is_ok(),
+        "refused transaction was partially applied"
+    );
+
+    *basis_cache = BasisCache::new();
+    basis_cache.basis_add(hw.pddb_mount().expect("couldn't remount the PDDB"));
+    let basis = basis_cache.basis_latest().expect("PDDB is not mounted").to_string();
+    assert!(basis_cache.dict_quota(hw, QUOTA_TEST_DICT, &basis) == Some(1000), "quota lost on remount");
+    basis_cache.dict_set_quota(hw, QUOTA_TEST_DICT, None, None)?;
+    basis_cache.key_update(hw, QUOTA_TEST_DICT, "d", &[5u8; 600], None, None, None, true)?;
+    let (_, usage) = basis_cache.dict_usage(hw, QUOTA_TEST_DICT, None)?;
+    assert!(usage.key_count == 3 && usage.used == 1500, "unexpected usage: {:?}", usage);
+    let (_, total) = basis_cache.basis_usage(hw, None)?;
+    assert!(total.used >= usage.used && total.key_count >= usage.key_count, "unexpected total: {:?}", total);
+    basis_cache.dict_remove(hw, QUOTA_TEST_DICT, None, false)?;
+    log::info!("quota test passed");
+    Ok(())
+}
+
+/// Keeps versions of a key that spans several pages, and checks that the oldest versions are dropped past
+/// the dict's limit, that each version reads back as the value it was taken from, that versions are left
+/// out of the key list, that version numbers don't wrap, and that the versions go with their key.
+pub(crate) fn versioning_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const VERSION_DICT: &str = "versiontest";
+    fn read(hw: &mut PddbOs, basis_cache: &mut BasisCache, key: &str) -> Result<Vec<u8>> {
+        let attr = basis_cache.key_attributes(hw, VERSION_DICT, key, None)?;
+        let mut data = vec![0u8; attr.len];
+        let read = basis_cache.key_read(hw, VERSION_DICT, key, &mut data, None, None)?;
+        data.truncate(read);
+        Ok(data)
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(
+        RNG_LOCAL_STATE.load(Ordering::SeqCst)
+            + xous::TESTING_RNG_SEED.load(core::sync::atomic::Ordering::SeqCst),
+    );
+    let values: Vec<Vec<u8>> = (0..4)
+        .map(|i| {
+            let mut value = vec![0u8; VPAGE_SIZE * 2 + 100 * (i + 1)];
+            rng.fill_bytes(&mut value);
+            value
+        })
+        .collect();
+    basis_cache.key_update(hw, VERSION_DICT, "key", &values[0], None, None, None, true)?;
+    basis_cache.key_update(hw, VERSION_DICT, "other", b"other", None, None, None, true)?;
+    basis_cache.dict_set_versions(hw, VERSION_DICT, 2, None)?;
+    for value in values[1..].iter() {
+        basis_cache.key_snapshot(hw, VERSION_DICT, "key", None)?;
+        basis_cache.key_update(hw, VERSION_DICT, "key", value, None, None, None, true)?;
+    }
+
+    // three versions were taken, of which the dict keeps the newest two
+    let versions = basis_cache.key_versions(hw, VERSION_DICT, "key", None)?;
+    assert!(versions == vec![2, 3], "unexpected versions: {:?}", versions);
+    assert!(read(hw, basis_cache, &version_key_name("key", 2))? == values[1], "version 2 is corrupt");
+    assert!(read(hw, basis_cache, &version_key_name("key", 3))? == values[2], "version 3 is corrupt");
+    assert!(read(hw, basis_cache, "key")? == values[3], "current value is corrupt");
+    let (keys, key_count, _) = basis_cache.key_list(hw, VERSION_DICT, None)?;
+    assert!(keys.len() == 2 && keys.contains("key") && keys.contains("other"), "unexpected keys: {:?}", keys);
+    assert!(key_count == 2, "version keys counted in the key list: {}", key_count);
+
+    // restoring a version is a write of its contents back over the key
+    let restored = read(hw, basis_cache, &version_key_name("key", 2))?;
+    basis_cache.key_snapshot(hw, VERSION_DICT, "key", None)?;
+    basis_cache.key_update(hw, VERSION_DICT, "key", &restored, None, None, None, true)?;
+    assert!(read(hw, basis_cache, "key")? == values[1], "restored value is corrupt");
+    assert!(read(hw, basis_cache, &version_key_name("key", 4))? == values[3], "version 4 is corrupt");
+
+    // once the last version number is taken, no more versions are kept rather than overwriting one
+    basis_cache.key_update(
+        hw,
+        VERSION_DICT,
+        &version_key_name("key", u32::MAX),
+        b"last",
+        None,
+        None,
+        None,
+        true,
+    )?;
+    basis_cache.key_snapshot(hw, VERSION_DICT, "key", None).expect_err("version number wrapped");
+    let versions = basis_cache.key_versions(hw, VERSION_DICT, "key", None)?;
+    assert!(versions == vec![3, 4, u32::MAX], "unexpected versions: {:?}", versions);
+
+    basis_cache.key_remove(hw, VERSION_DICT, "key", None, false)?;
+    let versions = basis_cache.key_versions(hw, VERSION_DICT, "key", None)?;
+    assert!(versions.is_empty(), "versions outlived their key: {:?}", versions);
+    basis_cache.dict_remove(hw, VERSION_DICT, None, false)?;
+    log::info!("versioning test passed");
+    RNG_LOCAL_STATE.store(rng.next_u64(), Ordering::SeqCst);
+    Ok(())
+}
+
+pub(crate) fn archive_test(hw: &mut PddbOs, basis_cache: &mut BasisCache, source: &str) -> Result<()> {
+    const ARCHIVE_DICT: &str = "archivetest";
+    const IMPORTED_BASIS: &str = "Basis3";
+    const ARCHIVE_PW: &str = "archive password";
+    const IMPORTED_PW: &str = "imported basis password";
+    // a key spread over several records, an empty key, and a versioned dict
+    let large: Vec<u8> = (0..ARCHIVE_DATA_CHUNK * 2 + 100).map(|i| i as u8).collect();
+    basis_cache.key_update(hw, ARCHIVE_DICT, "large", &large, None, None, Some(source), true)?;
+    basis_cache.key_update(hw, ARCHIVE_DICT, "empty", &[], None, None, Some(source), true)?;
+    basis_cache.dict_set_versions(hw, ARCHIVE_DICT, 2, Some(source))?;
+
+    let mut export = BasisExport::new(hw, basis_cache, Some(source), None, ARCHIVE_PW)?;
+    let mut archive = Vec::new();
+    while let Some(chunk) = export.next_chunk(hw, basis_cache)? {
+        archive.extend_from_slice(&chunk);
+    }
+    let mut records = Vec::new();
+    let mut rest = &archive[ARCHIVE_HEADER_LEN..];
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        records.push(&rest[4..4 + len]);
+        rest = &rest[4 + len..];
+    }
+    let mut start = archive[..ARCHIVE_HEADER_LEN].to_vec();
+    start.extend_from_slice(records[0]);
+    let e = BasisImport::new(hw, IMPORTED_BASIS, &start, "not the password")
+        .err()
+        .expect("opened an archive with the wrong password");
+    assert!(e.kind() == std::io::ErrorKind::PermissionDenied, "unexpected error: {:?}", e);
+
+    basis_cache.basis_create(hw, IMPORTED_BASIS, IMPORTED_PW)?;
+    let basis = basis_cache
+        .basis_unlock(hw, IMPORTED_BASIS, IMPORTED_PW, BasisRetentionPolicy::Persist)
+        .expect("couldn't mount the imported basis");
+    basis_cache.basis_add(basis);
+    let mut import = BasisImport::new(hw, IMPORTED_BASIS, &start, ARCHIVE_PW)?;
+    let mut tampered = records[1].to_vec();
+    tampered[0] ^= 1;
+    let e = import.apply(hw, basis_cache, &tampered).expect_err("applied a tampered record");
+    assert!(e.kind() == std::io::ErrorKind::InvalidData, "unexpected error: {:?}", e);
+    for record in records[1..].iter() {
+        assert!(!import.finished(), "import finished before the end of the archive");
+        import.apply(hw, basis_cache, record)?;
+    }
+    assert!(import.finished(), "import didn't finish at the end of the archive");
+
+    let dicts = basis_cache.dict_list(hw, Some(source));
+    assert!(dicts == basis_cache.dict_list(hw, Some(IMPORTED_BASIS)), "imported dicts differ");
+    for dict in dicts.iter() {
+        let src = basis_cache.dict_attributes(hw, dict, Some(source))?;
+        let dst = basis_cache.dict_attributes(hw, dict, Some(IMPORTED_BASIS))?;
+        assert!(src.flags.versions() == dst.flags.versions(), "versions of {} differ", dict);
+        let (keys, _, _) = basis_cache.key_list(hw, dict, Some(source))?;
+        let keys: BTreeSet<String> = keys.into_iter().filter(|k| !k.contains(VERSION_SEPARATOR)).collect();
+        let (imported, _, _) = basis_cache.key_list(hw, dict, Some(IMPORTED_BASIS))?;
+        assert!(keys == imported, "imported keys of {} differ", dict);
+        for key in keys.iter() {
+            let len = basis_cache.key_attributes(hw, dict, key, Some(source))?.len;
+            let mut expected = vec![0u8; len];
+            basis_cache.key_read(hw, dict, key, &mut expected, None, Some(source))?;
+            let mut data = vec![0u8; basis_cache.key_attributes(hw, dict, key, Some(IMPORTED_BASIS))?.len];
+            basis_cache.key_read(hw, dict, key, &mut data, None, Some(IMPORTED_BASIS))?;
+            assert!(data == expected, "imported contents of {}:{} differ", dict, key);
+        }
+    }
+
+    basis_cache.basis_discard(hw, IMPORTED_BASIS)?;
+    assert!(
+        basis_cache.basis_unlock(hw, IMPORTED_BASIS, IMPORTED_PW, BasisRetentionPolicy::Persist).is_none(),
+        "discarded basis could still be mounted"
+    );
+    basis_cache.dict_remove(hw, ARCHIVE_DICT, Some(source), false)?;
+    log::info!("archive test passed");
+    Ok(())
+}
+
+/// Creates, unlocks and locks a basis with requests laid out as libstd sends them, taking the steps of
+/// the `CreateBasisStd`, `OpenBasisStd` and `CloseBasisStd` handlers, except that the password is given
+/// rather than prompted for. Checks that locking the basis closes the files opened in it.
+pub(crate) fn std_basis_test(hw: &mut PddbOs, basis_cache: &mut BasisCache) -> Result<()> {
+    const STD_BASIS: &str = "StdBasis";
+    const STD_BASIS_PW: &str = "std basis password";
+    const STD_DICT: &str = "stdtest";
+    fn request<R>(tag: [u8; 4], name: &str, handler: impl FnOnce(&mut xous::MemoryMessage) -> R) -> R {
+        use crate::libstd::senres::{Senres, SenresMut, Stack};
+        let mut request = Stack::<4096>::new();
+        request.writer(tag).expect("couldn't write request").append(name);
+        let buf = unsafe { xous::MemoryRange::new(request.as_mut_ptr() as usize, request.len()) }.unwrap();
+        handler(&mut xous::MemoryMessage { id: 0, buf, offset: None, valid: None })
+    }
+    let create = |hw: &mut PddbOs, basis_cache: &mut BasisCache, mem: &mut xous::MemoryMessage| {
+        libstd::basis_name(mem, CREATE_BASIS_STD_TAG).and_then(|name| {
+            libstd::basis_result(match basis_cache.basis_create(hw, &name, STD_BASIS_PW) {
+                Ok(_) => PddbRequestCode::NoErr,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => PddbRequestCode::DuplicateEntry,
+                Err(_) => PddbRequestCode::InternalError,
+            })
+        })
+    };
+
+    request(CREATE_BASIS_STD_TAG, STD_BASIS, |mem| create(hw, basis_cache, mem)).expect("couldn't create");
+    let e = request(CREATE_BASIS_STD_TAG, STD_BASIS, |mem| create(hw, basis_cache, mem));
+    assert!(e == Err(PddbRetcode::AccessDenied), "created a basis twice: {:?}", e);
+    let e = request(CREATE_BASIS_STD_TAG, "Std:Basis", |mem| create(hw, basis_cache, mem));
+    assert!(e == Err(PddbRetcode::AccessDenied), "created a basis with a path separator: {:?}", e);
+    // a request is only read by the handler it was made for
+    let e = request(OPEN_BASIS_STD_TAG, STD_BASIS, |mem| create(hw, basis_cache, mem));
+    assert!(e == Err(PddbRetcode::InternalError), "read an open request as a create: {:?}", e);
+
+    request(OPEN_BASIS_STD_TAG, STD_BASIS, |mem| {
+        libstd::basis_name(mem, OPEN_BASIS_STD_TAG).and_then(|name| {
+            let basis = basis_cache.basis_unlock(hw, &name, STD_BASIS_PW, BasisRetentionPolicy::Persist);
+            libstd::basis_result(match basis {
+                Some(basis) => {
+                    basis_cache.basis_add(basis);
+                    PddbRequestCode::NoErr
+                }
+                None => PddbRequestCode::AccessDenied,
+            })
+        })
+    })
+    .expect("couldn't unlock");
+    assert!(basis_cache.basis_contains(STD_BASIS), "basis not mounted after unlocking");
+    basis_cache.key_update(hw, STD_DICT, "key", b"value", None, None, Some(STD_BASIS), true)?;
+
+    let handle = |basis: &str| FileHandle {
+        dict: STD_DICT.to_string(),
+        key: "key".to_string(),
+        basis: Some(basis.to_string()),
+        alloc_hint: None,
+        offset: 0,
+        length: 5,
+        conn: None,
+        versioned: false,
+        deleted: false,
+    };
+    let mut fds = HashMap::new();
+    fds.insert(None, vec![Some(handle(STD_BASIS)), None, Some(handle(PDDB_DEFAULT_SYSTEM_BASIS))]);
+    let close = |hw: &mut PddbOs, basis_cache: &mut BasisCache, fds: &mut HashMap<_, _>| {
+        request(CLOSE_BASIS_STD_TAG, STD_BASIS, |mem| libstd::close_basis(mem, hw, basis_cache, fds))
+    };
+    close(hw, basis_cache, &mut fds).expect("couldn't lock");
+    assert!(!basis_cache.basis_contains(STD_BASIS), "basis still mounted after locking");
+    let closed: Vec<Option<bool>> = fds[&None].iter().map(|fd| fd.as_ref().map(|fd| fd.deleted)).collect();
+    assert!(closed == vec![Some(true), None, Some(false)], "unexpected files after locking: {:?}", closed);
+    let e = close(hw, basis_cache, &mut fds);
+    assert!(e == Err(PddbRetcode::BasisLost), "locked a basis twice: {:?}", e);
+
+    let basis = basis_cache
+        .basis_unlock(hw, STD_BASIS, STD_BASIS_PW, BasisRetentionPolicy::Persist)
+        .expect("couldn't mount the basis again");
+    basis_cache.basis_add(basis);
+    let mut data = [0u8; 5];
+    basis_cache.key_read(hw, STD_DICT, "key", &mut data, None, Some(STD_BASIS))?;
+    assert!(&data == b"value", "basis contents lost across locking");
+    basis_cache.basis_discard(hw, STD_BASIS)?;
+    log::info!("std basis test passed");
+    Ok(())
+}
+
 pub(crate) fn list_all(hw: &mut PddbOs, basis_cache: &mut BasisCache) {
     #[cfg(feature = "deterministic")]
     let mut dict_list = BTreeSet::<String>::new();
@@ -556,6 +1308,34 @@ pub(crate) fn ci_tests(pddb_os: &mut PddbOs) -> Result<()> {
         pddb_os.dbg_dump(Some("dachecke4".to_string()), None);
         test_prune(pddb_os, &mut basis_cache);
 
+        log::info!("Doing large key cache test");
+        large_cache_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing paranoid erase test");
+        paranoid_erase_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing change log test");
+        change_log_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing stalled subscriber test");
+        stalled_subscriber_test();
+
+        log::info!("Doing transaction test");
+        transaction_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing compaction power loss test");
+        compact_power_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing quota test");
+        quota_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing versioning test");
+        versioning_test(pddb_os, &mut basis_cache)?;
+
+        log::info!("Doing compaction test");
+        let compacted = compact_test(pddb_os, &mut basis_cache, None)?;
+        pddb_os.dbg_dump(Some("compacte".to_string()), None);
+
         let mut pre_list = HashSet::<String>::new();
         for dict in basis_cache.dict_list(pddb_os, None).iter() {
             let (key_list, _, _) = basis_cache.key_list(pddb_os, dict, None).unwrap();
@@ -571,6 +1351,7 @@ pub(crate) fn ci_tests(pddb_os: &mut PddbOs) -> Result<()> {
             list_all(pddb_os, &mut basis_cache);
             pddb_os.dbg_dump(Some("remounte".to_string()), Some(&export));
         }
+        compact_check(pddb_os, &mut basis_cache, &compacted);
 
         log::info!("Mounting the second basis");
         if let Some(basis2) =
@@ -657,6 +1438,12 @@ pub(crate) fn ci_tests(pddb_os: &mut PddbOs) -> Result<()> {
         );
         list_all(pddb_os, &mut basis_cache);
 
+        log::info!("Doing archive test");
+        archive_test(pddb_os, &mut basis_cache, EXTRA_BASIS)?;
+
+        log::info!("Doing std basis test");
+        std_basis_test(pddb_os, &mut basis_cache)?;
+
         log::info!("CI done");
         xous::rsyscall(xous::SysCall::Shutdown).unwrap();
         Ok(())
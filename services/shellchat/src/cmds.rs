@@ -48,7 +48,6 @@ pub struct CommonEnv {
     cb_registrations: HashMap<u32, String<256>>,
     trng: Trng,
     netmgr: net::NetManager,
-    #[allow(dead_code)]
     xns: xous_names::XousNames,
     boot_instant: std::time::Instant,
     /// make this communal so any number of commands can trigger or reset the performance counter, and/or
@@ -129,6 +128,10 @@ mod pddb_cmd;
 use pddb_cmd::*;
 mod usb;
 use usb::*;
+mod names;
+use names::*;
+mod crash;
+use crash::*;
 
 #[cfg(not(feature = "no-codec"))]
 mod test;
@@ -176,6 +179,7 @@ pub struct CmdEnv {
     pddb_cmd: PddbCmd,
     wlan_cmd: Wlan,
     usb_cmd: Usb,
+    crash_cmd: Crash,
 
     #[cfg(not(feature = "no-codec"))]
     test_cmd: Test,
@@ -311,6 +315,10 @@ impl CmdEnv {
                 log::debug!("usb");
                 Usb::new()
             },
+            crash_cmd: {
+                log::debug!("crash");
+                Crash::new()
+            },
 
             #[cfg(not(feature = "no-codec"))]
             test_cmd: {
@@ -343,6 +351,7 @@ impl CmdEnv {
         let mut backlight_cmd = Backlight {};
         let mut accel_cmd = Accel {};
         let mut console_cmd = Console {};
+        let mut names_cmd = Names {};
         let commands: &mut [&mut dyn ShellCmdApi] = &mut [
             ///// 4. add your command to this array, so that it can be looked up and dispatched
             &mut echo_cmd,
@@ -367,6 +376,8 @@ impl CmdEnv {
             &mut self.net_cmd,
             &mut self.pddb_cmd,
             &mut self.usb_cmd,
+            &mut names_cmd,
+            &mut self.crash_cmd,
             #[cfg(not(feature = "no-codec"))]
             &mut self.test_cmd,
             #[cfg(feature = "tts")]
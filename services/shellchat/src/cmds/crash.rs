@@ -0,0 +1,109 @@
+use core::fmt::Write as FmtWrite;
+use std::io::Read;
+
+use xous_ipc::String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+/// Crash reports are filed here by the PDDB when a process panics
+const CRASH_DICT: &str = "sys.crash";
+
+pub struct Crash {
+    pddb: pddb::Pddb,
+}
+impl Crash {
+    pub fn new() -> Crash { Crash { pddb: pddb::Pddb::new() } }
+
+    fn read_report(&self, key: &str) -> std::io::Result<std::string::String> {
+        let mut report = self.pddb.get(
+            CRASH_DICT,
+            key,
+            Some(pddb::PDDB_DEFAULT_SYSTEM_BASIS),
+            false,
+            false,
+            None,
+            None::<fn()>,
+        )?;
+        let mut text = std::string::String::new();
+        report.read_to_string(&mut text)?;
+        Ok(text)
+    }
+}
+
+impl<'a> ShellCmdApi<'a> for Crash {
+    cmd_api!(crash);
+
+    fn process(
+        &mut self,
+        args: String<1024>,
+        _env: &mut CommonEnv,
+    ) -> Result<Option<String<1024>>, xous::Error> {
+        let mut ret = String::<1024>::new();
+        let helpstring = "crash [list] [export <key|all>] [clear]";
+
+        let mut tokens = args.as_str().unwrap().split(' ');
+
+        if let Some(sub_cmd) = tokens.next() {
+            let mut keys =
+                self.pddb.list_keys(CRASH_DICT, Some(pddb::PDDB_DEFAULT_SYSTEM_BASIS)).unwrap_or_default();
+            keys.sort();
+            match sub_cmd {
+                "list" => {
+                    if keys.is_empty() {
+                        write!(ret, "No crash reports").unwrap();
+                    }
+                    for key in keys.iter() {
+                        // the first line of a report names the process that crashed
+                        let process = self
+                            .read_report(key)
+                            .map(|text| text.lines().next().unwrap_or_default().to_owned())
+                            .unwrap_or_else(|e| format!("unreadable: {:?}", e));
+                        write!(ret, "{} {}\n", key, process).ok();
+                    }
+                }
+                "export" => {
+                    // reports are too long for the screen, so they are written out to the log
+                    let selected: Vec<&std::string::String> = match tokens.next() {
+                        Some("all") => keys.iter().collect(),
+                        Some(key) => keys.iter().filter(|k| k.as_str() == key).collect(),
+                        None => {
+                            write!(ret, "{}", helpstring).unwrap();
+                            return Ok(Some(ret));
+                        }
+                    };
+                    if selected.is_empty() {
+                        write!(ret, "No matching crash reports").unwrap();
+                    }
+                    for key in selected {
+                        match self.read_report(key) {
+                            Ok(text) => {
+                                log::info!("---- {}:{} ----\n{}", CRASH_DICT, key, text);
+                                write!(ret, "Exported {} to the log\n", key).ok();
+                            }
+                            Err(e) => write!(ret, "Couldn't read {}: {:?}\n", key, e).unwrap(),
+                        }
+                    }
+                }
+                "clear" => {
+                    if keys.is_empty() {
+                        write!(ret, "No crash reports").unwrap();
+                    } else {
+                        let count = keys.len();
+                        match self
+                            .pddb
+                            .delete_key_list(CRASH_DICT, keys, Some(pddb::PDDB_DEFAULT_SYSTEM_BASIS))
+                            .and_then(|_| self.pddb.sync())
+                        {
+                            Ok(_) => write!(ret, "Removed {} crash reports", count).unwrap(),
+                            Err(e) => write!(ret, "Couldn't remove crash reports: {:?}", e).unwrap(),
+                        }
+                    }
+                }
+                _ => write!(ret, "{}", helpstring).unwrap(),
+            }
+        } else {
+            write!(ret, "{}", helpstring).unwrap();
+        }
+        Ok(Some(ret))
+    }
+}
@@ -0,0 +1,76 @@
+use core::fmt::Write;
+
+use xous_ipc::String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+#[derive(Debug)]
+pub struct Names {}
+
+impl<'a> ShellCmdApi<'a> for Names {
+    cmd_api!(names);
+
+    // inserts boilerplate for command API
+
+    fn process(
+        &mut self,
+        args: String<1024>,
+        env: &mut CommonEnv,
+    ) -> Result<Option<String<1024>>, xous::Error> {
+        let mut ret = String::<1024>::new();
+        let helpstring = "names [list] [info <name>] [denials]";
+
+        let mut tokens = args.as_str().unwrap().split(' ');
+
+        if let Some(sub_cmd) = tokens.next() {
+            match sub_cmd {
+                "list" => {
+                    // the full list rarely fits on the screen, so it is also sent to the log
+                    let servers = env.xns.list()?;
+                    write!(ret, "{} servers (pid conns/max):\n", servers.len()).unwrap();
+                    for server in servers.iter() {
+                        let owner = server.owner.map(|pid| pid.to_string()).unwrap_or("?".to_string());
+                        let max =
+                            server.max_connections.map(|max| max.to_string()).unwrap_or("-".to_string());
+                        let line = format!("{} {} {}/{}", server.name, owner, server.connections, max);
+                        log::info!("{}", line);
+                        write!(ret, "{}\n", line).unwrap();
+                    }
+                }
+                "info" => {
+                    let name = tokens.collect::<Vec<&str>>().join(" ");
+                    match env.xns.server_info(&name)? {
+                        Some(server) => {
+                            write!(ret, "{}\n", server.name).unwrap();
+                            match server.owner {
+                                Some(pid) => write!(ret, "registered by PID {}\n", pid).unwrap(),
+                                None => write!(ret, "registered by an unknown process\n").unwrap(),
+                            }
+                            write!(ret, "{} connections", server.connections).unwrap();
+                            if let (Some(max), Some(remaining)) = (server.max_connections, server.remaining())
+                            {
+                                write!(ret, " of {}, {} remaining", max, remaining).unwrap();
+                            } else {
+                                write!(ret, ", no limit").unwrap();
+                            }
+                        }
+                        None => write!(ret, "No server registered as '{}'", name).unwrap(),
+                    }
+                }
+                "denials" => {
+                    let denials = env.xns.list_denials()?;
+                    if denials.is_empty() {
+                        write!(ret, "No connections have been denied").unwrap();
+                    }
+                    for denial in denials.iter() {
+                        write!(ret, "PID {} -> {}: {:?}\n", denial.pid, denial.name, denial.reason).unwrap();
+                    }
+                }
+                _ => write!(ret, "{}", helpstring).unwrap(),
+            }
+        } else {
+            write!(ret, "{}", helpstring).unwrap();
+        }
+        Ok(Some(ret))
+    }
+}
@@ -369,6 +369,170 @@ impl<'a> ShellCmdApi<'a> for NetCmd {
                         }
                     }
                 }
+                "dot" => {
+                    // DNS-over-TLS upstreams; the `tls` command is for trusting their CAs, and the
+                    // preferences menu for turning DNS-over-TLS on
+                    let parse_addr = |addr: &str| {
+                        addr.parse::<std::net::SocketAddr>().ok().or_else(|| {
+                            addr.parse::<IpAddr>()
+                                .ok()
+                                .map(|ip| std::net::SocketAddr::new(ip, dns::DNS_TLS_PORT))
+                        })
+                    };
+                    match (tokens.next(), tokens.next(), tokens.next()) {
+                        (Some("add"), Some(addr), Some(name)) => match parse_addr(addr) {
+                            Some(addr) => {
+                                let upstream = dns::TlsUpstream { addr, auth_name: name.to_owned() };
+                                match self.dns.add_tls_upstream(&upstream) {
+                                    Ok(_) => write!(ret, "Added DNS-over-TLS upstream {} ({})", addr, name),
+                                    Err(e) => write!(ret, "Couldn't add upstream: {:?}", e),
+                                }
+                                .unwrap();
+                            }
+                            None => write!(ret, "Bad address: {}", addr).unwrap(),
+                        },
+                        (Some("del"), Some(addr), None) => match parse_addr(addr) {
+                            Some(addr) => match self.dns.remove_tls_upstream(&addr) {
+                                Ok(_) => write!(ret, "Removed DNS-over-TLS upstream {}", addr).unwrap(),
+                                Err(e) => write!(ret, "Couldn't remove upstream: {:?}", e).unwrap(),
+                            },
+                            None => write!(ret, "Bad address: {}", addr).unwrap(),
+                        },
+                        (None, _, _) => {
+                            let upstreams = self.dns.tls_upstreams();
+                            if upstreams.is_empty() {
+                                write!(ret, "No DNS-over-TLS upstreams").unwrap();
+                            }
+                            for upstream in upstreams.iter() {
+                                write!(ret, "{} ({})\n", upstream.addr, upstream.auth_name).unwrap();
+                            }
+                        }
+                        _ => write!(ret, "net dot [add addr[:port] name] [del addr[:port]]").unwrap(),
+                    }
+                }
+                "netstat" => {
+                    let stats = env.netmgr.netstat();
+                    let iface = stats.iface;
+                    write!(
+                        ret,
+                        "rx {}p/{}B err {} drop {}, tx {}p/{}B err {}\n",
+                        iface.traffic.rx_packets,
+                        iface.traffic.rx_bytes,
+                        iface.rx_errors,
+                        iface.rx_dropped,
+                        iface.traffic.tx_packets,
+                        iface.traffic.tx_bytes,
+                        iface.tx_errors
+                    )
+                    .ok();
+                    let endpoint = |addr: Option<net::NetIpAddr>, port: u16| match addr {
+                        Some(addr) => std::net::SocketAddr::new(IpAddr::from(addr), port).to_string(),
+                        None => format!("*:{}", port),
+                    };
+                    for info in stats.sockets.iter().flatten() {
+                        let (proto, state) = match info.state {
+                            net::SocketState::Udp => ("udp", "".to_string()),
+                            net::SocketState::Tcp(state) => ("tcp", format!(" {:?}", state)),
+                        };
+                        let pid = info.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".to_string());
+                        let remote = match info.remote_addr {
+                            Some(_) => endpoint(info.remote_addr, info.remote_port),
+                            None => "*".to_string(),
+                        };
+                        // the buffer fills up with a lot of sockets open, so the list is cut short
+                        write!(
+                            ret,
+                            "{} {} {}{} pid {} rx {}p/{}B tx {}p/{}B\n",
+                            proto,
+                            endpoint(info.local_addr, info.local_port),
+                            remote,
+                            state,
+                            pid,
+                            info.traffic.rx_packets,
+                            info.traffic.rx_bytes,
+                            info.traffic.tx_packets,
+                            info.traffic.tx_bytes
+                        )
+                        .ok();
+                    }
+                    if stats.socket_count as usize > stats.sockets.len() {
+                        write!(ret, "...and {} more", stats.socket_count as usize - stats.sockets.len()).ok();
+                    }
+                }
+                "fw" => {
+                    let usage = "net fw [add rule process] [del n process] [clear process]";
+                    let rules = env.netmgr.firewall_rules();
+                    let sub = tokens.next();
+                    // a rule is four tokens; process names may contain spaces, so they come last
+                    let rule = if sub == Some("add") {
+                        Some(tokens.by_ref().take(4).collect::<Vec<&str>>().join(" "))
+                    } else {
+                        None
+                    };
+                    let index = if sub == Some("del") { tokens.next() } else { None };
+                    let process = tokens.collect::<Vec<&str>>().join(" ");
+                    let current = rules
+                        .iter()
+                        .find(|(name, _)| *name == process)
+                        .map(|(_, r)| r.clone())
+                        .unwrap_or_default();
+                    match sub {
+                        None => {
+                            if rules.is_empty() {
+                                write!(ret, "No firewall rules").ok();
+                            }
+                            for (name, process_rules) in rules.iter() {
+                                write!(ret, "{}:\n", name).ok();
+                                for (i, rule) in process_rules.iter().enumerate() {
+                                    write!(ret, "  {}: {}\n", i, rule).ok();
+                                }
+                            }
+                        }
+                        Some("add") if !process.is_empty() => {
+                            match rule.unwrap_or_default().parse::<net::FirewallRule>() {
+                                Ok(rule) => {
+                                    let mut updated = current;
+                                    updated.push(rule);
+                                    match env.netmgr.set_firewall_rules(&process, &updated) {
+                                        Ok(_) => write!(ret, "Added rule for {}", process).ok(),
+                                        Err(e) => write!(ret, "Couldn't add rule: {:?}", e).ok(),
+                                    };
+                                }
+                                Err(_) => {
+                                    write!(
+                                        ret,
+                                        "Rule is <allow|deny> <connect|listen|udp|send|*> <dest> <ports>"
+                                    )
+                                    .ok();
+                                }
+                            }
+                        }
+                        Some("del") if !process.is_empty() => {
+                            match index.and_then(|n| n.parse::<usize>().ok()) {
+                                Some(n) if n < current.len() => {
+                                    let mut updated = current;
+                                    updated.remove(n);
+                                    match env.netmgr.set_firewall_rules(&process, &updated) {
+                                        Ok(_) => write!(ret, "Removed rule {} of {}", n, process).ok(),
+                                        Err(e) => write!(ret, "Couldn't remove rule: {:?}", e).ok(),
+                                    };
+                                }
+                                _ => {
+                                    write!(ret, "No such rule").ok();
+                                }
+                            }
+                        }
+                        Some("clear") if !process.is_empty() => {
+                            match env.netmgr.set_firewall_rules(&process, &[]) {
+                                Ok(_) => write!(ret, "Cleared rules of {}", process).ok(),
+                                Err(e) => write!(ret, "Couldn't clear rules: {:?}", e).ok(),
+                            };
+                        }
+                        _ => {
+                            write!(ret, "{}", usage).ok();
+                        }
+                    }
+                }
                 #[cfg(feature = "nettest")]
                 "test" => {
                     crate::nettests::start_batch_tests();
@@ -67,9 +67,9 @@ impl<'a> ShellCmdApi<'a> for PddbCmd {
     ) -> Result<Option<String<1024>>, xous::Error> {
         let mut ret = String::<1024>::new();
         #[cfg(not(feature = "pddbtest"))]
-        let helpstring = "pddb [basislist] [basiscreate] [basisunlock] [basislock] [basisdelete] [default]\n[dictlist] [keylist] [write] [writeover] [query] [copy] [dictdelete] [keydelete] [churn] [flush] [sync]";
+        let helpstring = "pddb [basislist] [basiscreate] [basisunlock] [basislock] [basisdelete] [default]\n[dictlist] [keylist] [write] [writeover] [query] [copy] [dictdelete] [keydelete] [compact] [usage] [export] [import] [churn] [flush] [sync]";
         #[cfg(feature = "pddbtest")]
-        let helpstring = "pddb [basislist] [basiscreate] [basisunlock] [basislock] [basisdelete] [default]\n[dictlist] [keylist] [write] [writeover] [query] [copy] [dictdelete] [keydelete] [churn] [flush] [sync]\n[test]";
+        let helpstring = "pddb [basislist] [basiscreate] [basisunlock] [basislock] [basisdelete] [default]\n[dictlist] [keylist] [write] [writeover] [query] [copy] [dictdelete] [keydelete] [compact] [usage] [export] [import] [churn] [flush] [sync]\n[test] [archivetest]";
 
         let mut tokens = args.as_str().unwrap().split(' ');
         if let Some(sub_cmd) = tokens.next() {
@@ -317,7 +317,13 @@ impl<'a> ShellCmdApi<'a> for PddbCmd {
                 "keydelete" => {
                     if let Some(descriptor) = tokens.next() {
                         if let Some((dict, keyname)) = descriptor.split_once(':') {
-                            match self.pddb.delete_key(dict, keyname, None) {
+                            // `paranoid` overwrites the key's record on disk, instead of just unlinking it
+                            let result = if tokens.next() == Some("paranoid") {
+                                self.pddb.delete_key_paranoid(dict, keyname, None)
+                            } else {
+                                self.pddb.delete_key(dict, keyname, None)
+                            };
+                            match result {
                                 Ok(_) => {
                                     write!(ret, "Deleted {}:{}\n", dict, keyname).unwrap();
                                     // you must call sync after all deletions are done
@@ -359,6 +365,70 @@ impl<'a> ShellCmdApi<'a> for PddbCmd {
                         write!(ret, "Missing dictionary name").unwrap();
                     }
                 }
+                "compact" => {
+                    if let Some(dict) = tokens.next() {
+                        match self.pddb.compact(dict, tokens.next()) {
+                            Ok(_) => write!(ret, "Compacted dictionary {}", dict).unwrap(),
+                            Err(e) => write!(ret, "{} not found or other error: {:?}", dict, e).unwrap(),
+                        }
+                    } else {
+                        write!(ret, "Missing dictionary name").unwrap();
+                    }
+                }
+                "usage" => match self.pddb.usage(tokens.next()) {
+                    Ok(usage) => {
+                        write!(
+                            ret,
+                            "{}: {} keys, {} bytes in {} allocated ({} slack), {} free\n",
+                            usage.name,
+                            usage.total.key_count,
+                            usage.total.used,
+                            usage.total.allocated,
+                            usage.total.fragmentation(),
+                            usage.free
+                        )
+                        .ok();
+                        for dict in usage.dicts.iter() {
+                            write!(
+                                ret,
+                                "{}: {} keys, {} bytes in {}",
+                                dict.name, dict.usage.key_count, dict.usage.used, dict.usage.allocated
+                            )
+                            .ok();
+                            if let Some(quota) = dict.quota {
+                                write!(ret, ", quota {}", quota).ok();
+                            }
+                            if write!(ret, "\n").is_err() {
+                                break; // overflowed return buffer
+                            }
+                        }
+                    }
+                    Err(e) => write!(ret, "Error encountered reporting usage: {:?}", e).ok().unwrap_or(()),
+                },
+                // in hosted mode the archive is a file on the host; on Xous it is a key, as with `copy`
+                "export" => {
+                    if let Some(path) = tokens.next() {
+                        let basis = tokens.next();
+                        let dicts: Vec<&str> = tokens.collect();
+                        let dicts = if dicts.is_empty() { None } else { Some(dicts.as_slice()) };
+                        match self.pddb.export_basis_to_file(basis, dicts, path) {
+                            Ok(len) => write!(ret, "Exported {} bytes to {}", len, path).ok(),
+                            Err(e) => write!(ret, "Couldn't export to {}: {:?}", path, e).ok(),
+                        };
+                    } else {
+                        write!(ret, "usage: pddb export [file] [basis name] [dict...]").ok();
+                    }
+                }
+                "import" => {
+                    if let (Some(bname), Some(path)) = (tokens.next(), tokens.next()) {
+                        match self.pddb.import_basis_from_file(bname, path) {
+                            Ok(_) => write!(ret, "Imported {} as basis {}", path, bname).ok(),
+                            Err(e) => write!(ret, "Couldn't import {}: {:?}", path, e).ok(),
+                        };
+                    } else {
+                        write!(ret, "usage: pddb import [basis name] [file]").ok();
+                    }
+                }
                 "keylist" => {
                     if let Some(dict) = tokens.next() {
                         match self.pddb.list_keys(dict, None) {
@@ -1320,6 +1390,105 @@ impl<'a> ShellCmdApi<'a> for PddbCmd {
                         }
                     }
                 }
+                // Exports a dict of the current basis to a file on the host, loads it back as a new basis and
+                // compares the two, then checks that a truncated archive leaves no basis behind. The PDDB
+                // asks for the archive's password on export and on each import, and for the password of the
+                // new basis.
+                #[cfg(all(feature = "pddbtest", not(target_os = "xous")))]
+                "archivetest" => {
+                    const ARCHIVE_DICT: &'static str = "archivetest";
+                    const ARCHIVE_BASIS: &'static str = "archivetest";
+                    const ARCHIVE_PATH: &'static str = "../tools/pddb-images/archivetest.archive";
+                    const TRUNCATED_PATH: &'static str = "../tools/pddb-images/archivetest-truncated.archive";
+                    // an empty key, keys within a page, and keys spanning several pages
+                    let vectors: Vec<(std::string::String, Vec<u8>)> = (0..8usize)
+                        .map(|i| (format!("key{}", i), (0..i * 1500).map(|b| (b + i) as u8).collect()))
+                        .collect();
+                    for (name, data) in vectors.iter() {
+                        let mut key = self
+                            .pddb
+                            .get(ARCHIVE_DICT, name, None, true, true, Some(data.len().max(1)), None::<fn()>)
+                            .expect("couldn't create test key");
+                        key.write_all(data).expect("couldn't write test key");
+                    }
+                    self.pddb.sync().unwrap();
+
+                    let mut passed = true;
+                    match self.pddb.export_basis_to_file(None, Some(&[ARCHIVE_DICT][..]), ARCHIVE_PATH) {
+                        Ok(len) => log::info!("exported {} bytes to {}", len, ARCHIVE_PATH),
+                        Err(e) => {
+                            log::info!("export failed: {:?}", e);
+                            passed = false;
+                        }
+                    }
+                    if passed {
+                        if let Err(e) = self.pddb.import_basis_from_file(ARCHIVE_BASIS, ARCHIVE_PATH) {
+                            log::info!("import failed: {:?}", e);
+                            passed = false;
+                        }
+                    }
+                    if passed {
+                        let imported =
+                            self.pddb.list_keys(ARCHIVE_DICT, Some(ARCHIVE_BASIS)).unwrap_or(Vec::new());
+                        if imported.len() != vectors.len() {
+                            log::info!("imported {} keys, expected {}", imported.len(), vectors.len());
+                            passed = false;
+                        }
+                        for (name, data) in vectors.iter() {
+                            let mut readback = Vec::new();
+                            match self.pddb.get(
+                                ARCHIVE_DICT,
+                                name,
+                                Some(ARCHIVE_BASIS),
+                                false,
+                                false,
+                                None,
+                                None::<fn()>,
+                            ) {
+                                Ok(mut key)
+                                    if key.read_to_end(&mut readback).is_ok() && readback == *data => {}
+                                _ => {
+                                    log::info!(
+                                        "{}:{} did not come through the archive intact",
+                                        ARCHIVE_DICT,
+                                        name
+                                    );
+                                    passed = false;
+                                }
+                            }
+                        }
+                    }
+                    self.pddb.delete_basis(ARCHIVE_BASIS).ok();
+
+                    match std::fs::read(ARCHIVE_PATH) {
+                        Ok(archive) if archive.len() > 100 => {
+                            std::fs::write(TRUNCATED_PATH, &archive[..archive.len() - 100])
+                                .expect("couldn't write truncated archive");
+                            match self.pddb.import_basis_from_file(ARCHIVE_BASIS, TRUNCATED_PATH) {
+                                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+                                result => {
+                                    log::info!("truncated archive gave {:?}", result);
+                                    passed = false;
+                                }
+                            }
+                            if self.pddb.list_basis().iter().any(|b| b == ARCHIVE_BASIS) {
+                                log::info!("truncated import left basis {} behind", ARCHIVE_BASIS);
+                                self.pddb.delete_basis(ARCHIVE_BASIS).ok();
+                                passed = false;
+                            }
+                        }
+                        _ => passed = false,
+                    }
+                    self.pddb.delete_dict(ARCHIVE_DICT, None).ok();
+                    self.pddb.sync().ok();
+                    if passed {
+                        log::info!("archive test passed");
+                        write!(ret, "archive test passed").ok();
+                    } else {
+                        log::info!("archive test failed");
+                        write!(ret, "archive test failed").ok();
+                    }
+                }
                 "prune" => {
                     #[cfg(not(target_os = "xous"))]
                     self.pddb.dbg_prune().ok();
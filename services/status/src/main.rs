@@ -96,6 +96,8 @@ pub(crate) enum StatusOpcode {
 
     /// Raise the preferences menu
     Preferences,
+    /// A process panicked (arg1 = PID, arg2 = crash report ID)
+    ProcessCrashed,
     Quit,
 }
 
@@ -807,6 +809,8 @@ fn wrapped_main() -> ! {
 
             log::debug!("pddb ready, loading preferences now!");
 
+            prefs.push_save_log().unwrap_or_else(|error| log::error!("cannot set log saving: {:?}", error));
+
             match all_prefs.wifi_kill {
                 true => netmgr.connection_manager_wifi_off_and_stop(),
                 false => netmgr.connection_manager_wifi_on(),
@@ -981,6 +985,10 @@ fn wrapped_main() -> ! {
     let mut wifi_bars: [PixelColor; 5] =
         [PixelColor::Light, PixelColor::Light, PixelColor::Light, PixelColor::Light, PixelColor::Light];
 
+    // tell the user when a process crashes
+    log_server::hook_panic_notifier(status_sid, StatusOpcode::ProcessCrashed.to_u32().unwrap())
+        .unwrap_or_else(|e| log::warn!("couldn't hook panic notifications: {:?}", e));
+
     pump_run.store(true, Ordering::Relaxed); // start status thread updating
     loop {
         let msg = xous::receive_message(status_sid).unwrap();
@@ -1120,6 +1128,18 @@ fn wrapped_main() -> ! {
                     unsafe { xous_ipc::Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
                 wifi_status = WlanStatus::from_ipc(buffer.to_original::<com::WlanStatusIpc, _>().unwrap());
             }
+            Some(StatusOpcode::ProcessCrashed) => msg_scalar_unpack!(msg, pid, report_id, _, _, {
+                let name = match log_server::read_crash_report(report_id as u32) {
+                    Ok(Some(report)) => report.name,
+                    _ => None,
+                }
+                .unwrap_or_else(|| format!("PID {}", pid));
+                log::warn!("{} crashed", name);
+                sec_notes
+                    .lock()
+                    .unwrap()
+                    .insert("secnote.crash".to_string(), t!("secnote.crash", locales::LANG).replace("{name}", &name));
+            }),
             Some(StatusOpcode::Preferences) => {
                 ticktimer.sleep_ms(100).ok(); // yield for a moment to allow the previous menu to close
                 gam.raise_menu(gam::PREFERENCES_MENU_NAME).unwrap();
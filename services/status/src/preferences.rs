@@ -30,6 +30,8 @@ enum DevicePrefsOp {
     AudioOff,
     HeadsetVolume,
     EarpieceVolume,
+    DnsOverTls,
+    SaveLog,
 
     // Those are reserved for internal use
     UpdateMenuAudioEnabled = 399,
@@ -60,6 +62,8 @@ impl Display for DevicePrefsOp {
             Self::AudioOff => write!(f, "{}", t!("prefs.disable_audio", locales::LANG)),
             Self::HeadsetVolume => write!(f, "{}", t!("prefs.headphone_volume", locales::LANG)),
             Self::EarpieceVolume => write!(f, "{}", t!("prefs.speaker_volume", locales::LANG)),
+            Self::DnsOverTls => write!(f, "{}", t!("prefs.dns_over_tls", locales::LANG)),
+            Self::SaveLog => write!(f, "{}", t!("prefs.save_log", locales::LANG)),
 
             _ => unimplemented!("should not end up here!"),
         }
@@ -104,6 +108,7 @@ struct DevicePrefs {
     menu_global_conn: xous::CID,
     status_cid: xous::CID,
     netmgr: net::NetManager,
+    dns: dns::Dns,
 }
 
 impl PrefHandler for DevicePrefs {
@@ -196,6 +201,7 @@ impl DevicePrefs {
             menu_global_conn: menu_conn,
             status_cid: status_conn,
             netmgr: net::NetManager::new(),
+            dns: dns::Dns::new(&xns).unwrap(),
         }
     }
 
@@ -221,6 +227,8 @@ impl DevicePrefs {
             // as scripts.
             SetTime,
             SetTimezone,
+            DnsOverTls,
+            SaveLog,
         ];
         #[cfg(not(feature = "no-codec"))]
         if self.codec.is_running().unwrap_or_default() {
@@ -252,6 +260,8 @@ impl DevicePrefs {
             WLANMenu => self.wlan_menu(),
             SetTime => self.set_time_menu(),
             SetTimezone => self.set_timezone_menu(),
+            DnsOverTls => self.dns_over_tls(),
+            SaveLog => self.save_log(),
             #[cfg(not(feature = "no-codec"))]
             AudioOn => self.audio_on(),
             #[cfg(not(feature = "no-codec"))]
@@ -396,6 +406,25 @@ impl DevicePrefs {
         Ok(self.up.set_reboot_on_autosleep(new_result)?)
     }
 
+    fn save_log(&self) -> Result<(), DevicePrefsError> {
+        let cv = self.up.save_log_or_default()?;
+
+        self.modals.add_list(vec![t!("prefs.yes", locales::LANG), t!("prefs.no", locales::LANG)]).unwrap();
+        let new_result = yes_no_to_bool(
+            self.modals
+                .get_radiobutton(&format!(
+                    "{} {}",
+                    t!("prefs.current_setting", locales::LANG),
+                    bool_to_yes_no(cv)
+                ))
+                .unwrap()
+                .as_str(),
+        );
+
+        self.up.set_save_log(new_result)?;
+        Ok(self.up.push_save_log()?)
+    }
+
     fn wifi_kill(&mut self) -> Result<(), DevicePrefsError> {
         let cv = self.up.wifi_kill_or_default()?;
 
@@ -448,6 +477,31 @@ impl DevicePrefs {
         Ok(self.up.set_connect_known_networks_on_boot(new_result)?)
     }
 
+    fn dns_over_tls(&mut self) -> Result<(), DevicePrefsError> {
+        let cv = dns::DnsTlsMode::from_u32(self.up.dns_tls_mode_or_default()?).unwrap_or_default();
+
+        let modes = [
+            (dns::DnsTlsMode::Off, t!("prefs.dns_tls_off", locales::LANG)),
+            (dns::DnsTlsMode::Opportunistic, t!("prefs.dns_tls_opportunistic", locales::LANG)),
+            (dns::DnsTlsMode::Strict, t!("prefs.dns_tls_strict", locales::LANG)),
+        ];
+        self.modals.add_list(modes.iter().map(|(_, name)| *name).collect()).unwrap();
+
+        let current = modes.iter().find(|(mode, _)| *mode == cv).map(|(_, name)| *name).unwrap_or_default();
+        let new_result = self
+            .modals
+            .get_radiobutton(&format!("{} {}", t!("prefs.current_setting", locales::LANG), current))
+            .unwrap();
+        let new_mode =
+            modes.iter().find(|(_, name)| *name == new_result.as_str()).map(|(mode, _)| *mode).unwrap_or(cv);
+
+        self.up.set_dns_tls_mode(new_mode as u32)?;
+        // the resolver only reads the setting when asked to
+        self.dns.reload_tls_config()?;
+
+        Ok(())
+    }
+
     fn wlan_menu(&self) -> Result<(), DevicePrefsError> {
         std::thread::sleep(std::time::Duration::from_millis(100));
         self.gam.raise_menu(gam::WIFI_MENU_NAME).unwrap();
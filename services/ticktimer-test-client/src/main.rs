@@ -1,6 +1,8 @@
 #![cfg_attr(target_os = "none", no_std)]
 #![cfg_attr(target_os = "none", no_main)]
 
+mod sync_tests;
+
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -11,6 +13,11 @@ fn main() -> ! {
     log::set_max_level(log::LevelFilter::Trace);
     info!("my PID is {}", xous::process::id());
 
+    match sync_tests::run_all() {
+        0 => info!("all synchronization tests passed"),
+        failures => log::error!("{} synchronization tests failed", failures),
+    }
+
     #[cfg(feature = "susres-testing")]
     const DELAY_MS: u64 = 2000;
     #[cfg(not(feature = "susres-testing"))]
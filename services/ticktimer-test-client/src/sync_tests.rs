@@ -0,0 +1,173 @@
+//! Exercises the RwLock and semaphore primitives served by the ticktimer. Ordering between
+//! threads is set up with short sleeps, which is plenty on an otherwise idle system.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use log::{error, info};
+use ticktimer::Ticktimer;
+
+/// Long enough for a freshly spawned thread to have sent its request to the ticktimer
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Run every test, returning the number that failed
+pub fn run_all() -> usize {
+    let tt = Arc::new(Ticktimer::new().unwrap());
+    let tests: [(&str, fn(&Arc<Ticktimer>) -> bool); 6] = [
+        ("readers share the lock", readers_share),
+        ("writer excludes readers", writer_excludes),
+        ("writer is not starved by readers", writer_not_starved),
+        ("semaphore limits holders", semaphore_limits),
+        ("semaphore is first-come first-served", semaphore_fifo),
+        ("freeing an rwlock releases waiters", free_releases_waiters),
+    ];
+    let mut failures = 0;
+    for (name, test) in tests.iter() {
+        if test(&tt) {
+            info!("PASS: {}", name);
+        } else {
+            error!("FAIL: {}", name);
+            failures += 1;
+        }
+    }
+    failures
+}
+
+fn readers_share(tt: &Arc<Ticktimer>) -> bool {
+    const LOCK: usize = 0x1000;
+    let active = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..3)
+        .map(|_| {
+            let (tt, active, most) = (tt.clone(), active.clone(), most.clone());
+            spawn(move || {
+                tt.lock_read(LOCK).unwrap();
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                sleep(SETTLE * 2);
+                active.fetch_sub(1, Ordering::SeqCst);
+                tt.unlock_rwlock(LOCK);
+            })
+        })
+        .collect();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    tt.free_rwlock(LOCK);
+    most.load(Ordering::SeqCst) == 3
+}
+
+fn writer_excludes(tt: &Arc<Ticktimer>) -> bool {
+    const LOCK: usize = 0x2000;
+    let entered = Arc::new(AtomicBool::new(false));
+    tt.lock_write(LOCK).unwrap();
+    let reader = {
+        let (tt, entered) = (tt.clone(), entered.clone());
+        spawn(move || {
+            tt.lock_read(LOCK).unwrap();
+            entered.store(true, Ordering::SeqCst);
+            tt.unlock_rwlock(LOCK);
+        })
+    };
+    sleep(SETTLE);
+    let blocked = !entered.load(Ordering::SeqCst);
+    tt.unlock_rwlock(LOCK);
+    reader.join().unwrap();
+    tt.free_rwlock(LOCK);
+    blocked && entered.load(Ordering::SeqCst)
+}
+
+fn writer_not_starved(tt: &Arc<Ticktimer>) -> bool {
+    const LOCK: usize = 0x3000;
+    let order = Arc::new(Mutex::new(Vec::new()));
+    tt.lock_read(LOCK).unwrap();
+
+    // A writer queues up behind our read lock...
+    let writer = {
+        let (tt, order) = (tt.clone(), order.clone());
+        spawn(move || {
+            tt.lock_write(LOCK).unwrap();
+            order.lock().unwrap().push("writer");
+            sleep(SETTLE / 2);
+            tt.unlock_rwlock(LOCK);
+        })
+    };
+    sleep(SETTLE);
+
+    // ...and a later reader must wait for the writer, even though the lock is held for reading
+    let reader = {
+        let (tt, order) = (tt.clone(), order.clone());
+        spawn(move || {
+            tt.lock_read(LOCK).unwrap();
+            order.lock().unwrap().push("reader");
+            tt.unlock_rwlock(LOCK);
+        })
+    };
+    sleep(SETTLE);
+    let reader_waited = order.lock().unwrap().is_empty();
+
+    tt.unlock_rwlock(LOCK);
+    writer.join().unwrap();
+    reader.join().unwrap();
+    tt.free_rwlock(LOCK);
+    reader_waited && *order.lock().unwrap() == ["writer", "reader"]
+}
+
+fn semaphore_limits(tt: &Arc<Ticktimer>) -> bool {
+    const SEM: usize = 0x4000;
+    let active = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    tt.release_semaphore(SEM, 2);
+    let threads: Vec<_> = (0..5)
+        .map(|_| {
+            let (tt, active, most) = (tt.clone(), active.clone(), most.clone());
+            spawn(move || {
+                tt.acquire_semaphore(SEM).unwrap();
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                sleep(SETTLE / 2);
+                active.fetch_sub(1, Ordering::SeqCst);
+                tt.release_semaphore(SEM, 1);
+            })
+        })
+        .collect();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    tt.free_semaphore(SEM);
+    most.load(Ordering::SeqCst) == 2
+}
+
+fn semaphore_fifo(tt: &Arc<Ticktimer>) -> bool {
+    const SEM: usize = 0x5000;
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let (tt, order) = (tt.clone(), order.clone());
+            let thread = spawn(move || {
+                tt.acquire_semaphore(SEM).unwrap();
+                order.lock().unwrap().push(i);
+            });
+            // make sure the waiters arrive in a known order
+            sleep(SETTLE / 2);
+            thread
+        })
+        .collect();
+    for _ in 0..4 {
+        tt.release_semaphore(SEM, 1);
+        sleep(SETTLE / 2);
+    }
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    tt.free_semaphore(SEM);
+    *order.lock().unwrap() == [0, 1, 2, 3]
+}
+
+fn free_releases_waiters(tt: &Arc<Ticktimer>) -> bool {
+    const LOCK: usize = 0x6000;
+    tt.lock_write(LOCK).unwrap();
+    let waiter = {
+        let tt = tt.clone();
+        spawn(move || tt.lock_write(LOCK))
+    };
+    sleep(SETTLE);
+    tt.free_rwlock(LOCK);
+    waiter.join().unwrap() == Err(xous::Error::ServerNotFound)
+}
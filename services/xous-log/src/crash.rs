@@ -0,0 +1,73 @@
+use std::collections::{HashMap, VecDeque};
+
+use xous_api_log::api::{CrashReport, CRASH_TEXT_LEN};
+
+/// The number of crash reports to hold on to until someone reads them back
+const CRASH_REPORT_LIMIT: usize = 4;
+
+struct Report {
+    id: u32,
+    pid: u8,
+    name: Option<String>,
+    text: Vec<u8>,
+}
+
+/// Assembles crash reports out of the `ProgramName` and panic messages that processes send
+pub struct CrashCollector {
+    /// Names given with `ProgramName`, by PID
+    names: HashMap<u8, String>,
+    /// Panic text of processes that are in the middle of panicking
+    panicking: HashMap<u8, Vec<u8>>,
+    reports: VecDeque<Report>,
+    next_id: u32,
+}
+
+impl CrashCollector {
+    pub fn new() -> Self {
+        CrashCollector {
+            names: HashMap::new(),
+            panicking: HashMap::new(),
+            reports: VecDeque::with_capacity(CRASH_REPORT_LIMIT),
+            next_id: 1,
+        }
+    }
+
+    pub fn set_name(&mut self, pid: u8, name: &str) { self.names.insert(pid, name.to_owned()); }
+
+    pub fn panic_started(&mut self, pid: u8) { self.panicking.insert(pid, Vec::new()); }
+
+    pub fn panic_text(&mut self, pid: u8, text: &[u8]) {
+        let buffer = self.panicking.entry(pid).or_default();
+        let room = CRASH_TEXT_LEN.saturating_sub(buffer.len());
+        buffer.extend_from_slice(&text[..text.len().min(room)]);
+    }
+
+    /// File the panic of `pid` as a new crash report, returning the report's ID
+    pub fn panic_finished(&mut self, pid: u8) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        if self.reports.len() == CRASH_REPORT_LIMIT {
+            self.reports.pop_front();
+        }
+        // The process is going away, and its PID may be reused by a process with another name
+        let name = self.names.remove(&pid);
+        let text = self.panicking.remove(&pid).unwrap_or_default();
+        self.reports.push_back(Report { id, pid, name, text });
+        id
+    }
+
+    /// Fill in `request` with the report whose ID it names, or set its ID to 0 if there is none
+    pub fn read(&self, request: &mut CrashReport) {
+        let Some(report) = self.reports.iter().find(|r| r.id == request.id) else {
+            request.id = 0;
+            return;
+        };
+        request.pid = report.pid as u32;
+        let name = report.name.as_deref().unwrap_or_default().as_bytes();
+        let name = &name[..name.len().min(request.name.len())];
+        request.name[..name.len()].copy_from_slice(name);
+        request.name_length = name.len() as u32;
+        request.text[..report.text.len()].copy_from_slice(&report.text);
+        request.text_length = report.text.len() as u32;
+    }
+}
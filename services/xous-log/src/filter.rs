@@ -0,0 +1,82 @@
+/// Per-module level filters, set at runtime with `SetModuleLevel`
+pub struct ModuleFilters {
+    /// Module prefixes and the most verbose `log::Level` (as a `u32`) allowed through for each
+    filters: Vec<(Vec<u8>, u32)>,
+}
+
+impl ModuleFilters {
+    pub fn new() -> Self { ModuleFilters { filters: Vec::new() } }
+
+    /// Set the filter for `module`, or remove it if `level` is `None`
+    pub fn set(&mut self, module: &[u8], level: Option<u32>) {
+        self.filters.retain(|(m, _)| m != module);
+        if let Some(level) = level {
+            self.filters.push((module.to_vec(), level));
+        }
+    }
+
+    /// Returns `true` if a record at `level` from `module` should be kept. The filter with the
+    /// longest matching prefix applies; `foo` matches `foo` and `foo::bar`, but not `foobar`.
+    pub fn allows(&self, module: &[u8], level: u32) -> bool {
+        self.filters
+            .iter()
+            .filter(|(prefix, _)| {
+                module.starts_with(prefix)
+                    && (module.len() == prefix.len() || module[prefix.len()..].starts_with(b"::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, max)| level <= *max)
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERROR: u32 = log::Level::Error as u32;
+    const WARN: u32 = log::Level::Warn as u32;
+    const INFO: u32 = log::Level::Info as u32;
+    const DEBUG: u32 = log::Level::Debug as u32;
+
+    #[test]
+    fn no_filters_allow_everything() {
+        let filters = ModuleFilters::new();
+        assert!(filters.allows(b"net", DEBUG));
+        assert!(filters.allows(b"", log::Level::Trace as u32));
+    }
+
+    #[test]
+    fn prefixes_match_whole_path_segments() {
+        let mut filters = ModuleFilters::new();
+        filters.set(b"net", Some(WARN));
+        assert!(filters.allows(b"net", WARN));
+        assert!(filters.allows(b"net", ERROR));
+        assert!(!filters.allows(b"net", INFO));
+        assert!(!filters.allows(b"net::device", INFO));
+        // not a submodule of `net`
+        assert!(filters.allows(b"network", DEBUG));
+        assert!(filters.allows(b"ne", DEBUG));
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let mut filters = ModuleFilters::new();
+        filters.set(b"net", Some(ERROR));
+        filters.set(b"net::device", Some(DEBUG));
+        assert!(filters.allows(b"net::device", DEBUG));
+        assert!(filters.allows(b"net::device::rx", DEBUG));
+        assert!(!filters.allows(b"net::ipconfig", WARN));
+    }
+
+    #[test]
+    fn set_replaces_and_removes() {
+        let mut filters = ModuleFilters::new();
+        filters.set(b"net", Some(ERROR));
+        filters.set(b"net", Some(INFO));
+        assert!(filters.allows(b"net", INFO));
+        assert!(!filters.allows(b"net", DEBUG));
+        filters.set(b"net", None);
+        assert!(filters.allows(b"net", DEBUG));
+    }
+}
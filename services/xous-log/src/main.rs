@@ -5,6 +5,9 @@ use xous_api_log::api;
 
 #[macro_use]
 mod platform;
+mod crash;
+mod filter;
+mod ring;
 
 use core::fmt::Write;
 
@@ -39,6 +42,20 @@ fn usb_send_str(conn: xous::CID, s: &str) {
     buf.send(conn, 8192 /* LogString */).expect("usb error");
 }
 
+/// Whether `pid` may change the level filters: only the shell and the status process, which act
+/// for the user. Hosted builds have no loader to name processes, and no memory isolation, so any
+/// process may there.
+fn may_set_filters(pid: xous::PID) -> bool {
+    if cfg!(not(target_os = "xous")) {
+        return true;
+    }
+    let mut name = [0u8; 16];
+    match xous::process_name(pid, &mut name) {
+        Ok(len) => matches!(name.get(..len), Some(b"shellchat") | Some(b"status")),
+        Err(_) => false,
+    }
+}
+
 fn reader_thread(arg: usize) {
     let output = unsafe { &mut *(arg as *mut implementation::OutputWriter) };
     writeln!(output, "LOG: Xous Logging Server starting up...").ok();
@@ -50,6 +67,13 @@ fn reader_thread(arg: usize) {
     #[cfg(feature = "usb")]
     let mut usb_str = xous_ipc::String::<4000>::new();
 
+    // recent records, so they can be read back with `ReadLog`
+    let mut log_ring = ring::LogRing::new();
+    let mut filters = filter::ModuleFilters::new();
+    // servers that asked to hear about panics, and the message ID to send to each
+    let mut panic_hooks: Vec<(xous::CID, usize)> = Vec::new();
+    let mut crashes = crash::CrashCollector::new();
+
     println!("LOG: my PID is {}", xous::process::id());
     let mut counter: usize = 0;
     loop {
@@ -96,6 +120,18 @@ fn reader_thread(arg: usize) {
 
                         let module_slice = &lr.module[0..lr.module_length as usize];
 
+                        if !filters.allows(module_slice, lr.level) {
+                            continue;
+                        }
+                        log_ring.push(
+                            sender.pid().map(|v| v.get()).unwrap_or_default(),
+                            lr.level,
+                            lr.line.map(|l| l.get()).unwrap_or_default(),
+                            module_slice,
+                            file_slice,
+                            args_slice,
+                        );
+
                         write!(output, "{}:", level).ok();
                         for c in module_slice {
                             output.putc(*c);
@@ -179,6 +215,69 @@ fn reader_thread(arg: usize) {
                             usb_send_str(conn, unsafe { std::str::from_utf8_unchecked(buffer) });
                         }
                     }
+                    api::Opcode::ProgramName => {
+                        // Safe because the buffer is only read from, and invalid UTF-8 is rejected below.
+                        let name = unsafe { xous::StringBuffer::from_memory_message(mem) };
+                        if let (Some(pid), Ok(name)) = (sender.pid(), name.as_str()) {
+                            crashes.set_name(pid.get(), name);
+                        }
+                    }
+                    api::Opcode::ReadCrashReport => {
+                        if !matches!(envelope.body, xous::Message::MutableBorrow(_))
+                            || mem.buf.len() < core::mem::size_of::<api::CrashReport>()
+                        {
+                            writeln!(output, "ReadCrashReport requires a mutably lent CrashReport").ok();
+                            continue;
+                        }
+                        // Safe because the buffer is large enough and was lent to us mutably, and there
+                        // are no invalid values in the resulting struct.
+                        let report = unsafe { &mut *(mem.buf.as_mut_ptr() as *mut api::CrashReport) };
+                        crashes.read(report);
+                    }
+                    api::Opcode::ReadLog => {
+                        if !matches!(envelope.body, xous::Message::MutableBorrow(_))
+                            || mem.buf.len() < core::mem::size_of::<api::LogReadout>()
+                        {
+                            writeln!(output, "ReadLog requires a mutably lent LogReadout").ok();
+                            continue;
+                        }
+                        // Safe because the buffer is large enough and was lent to us mutably, and there
+                        // are no invalid values in the resulting struct.
+                        let readout = unsafe { &mut *(mem.buf.as_mut_ptr() as *mut api::LogReadout) };
+                        log_ring.read(readout);
+                    }
+                    api::Opcode::SetModuleLevel => {
+                        if !sender.pid().is_some_and(may_set_filters) {
+                            writeln!(output, "LOG: ignoring SetModuleLevel from {:?}", sender.pid()).ok();
+                            continue;
+                        }
+                        if mem.buf.len() < core::mem::size_of::<api::ModuleLevel>() {
+                            continue;
+                        }
+                        // This transmute is safe because even if the resulting buffer is garbage,
+                        // there are no invalid values in the resulting struct.
+                        let request = unsafe { &*(mem.buf.as_ptr() as *const api::ModuleLevel) };
+                        if request.module_length as usize > request.module.len() {
+                            continue;
+                        }
+                        let module = &request.module[..request.module_length as usize];
+                        let level =
+                            if request.level == api::LEVEL_FILTER_UNSET { None } else { Some(request.level) };
+                        filters.set(module, level);
+                    }
+                    api::Opcode::HookPanicNotifier => {
+                        if mem.buf.len() < core::mem::size_of::<api::PanicHook>() {
+                            continue;
+                        }
+                        // This transmute is safe because even if the resulting buffer is garbage,
+                        // there are no invalid values in the resulting struct.
+                        let hook = unsafe { &*(mem.buf.as_ptr() as *const api::PanicHook) };
+                        // `try_connect()` so that a bogus SID cannot leave the logger blocked forever
+                        match xous::try_connect(xous::SID::from_array(hook.sid)) {
+                            Ok(cid) => panic_hooks.push((cid, hook.id as usize)),
+                            Err(e) => writeln!(output, "LOG: couldn't hook panic notifier: {:?}", e).unwrap(),
+                        }
+                    }
                     _ => {
                         writeln!(output, "Unhandled opcode").unwrap();
                     }
@@ -189,6 +288,7 @@ fn reader_thread(arg: usize) {
                 match scalar.id {
                     1000 => {
                         writeln!(output, "PANIC in PID {}:", sender_pid).unwrap();
+                        crashes.panic_started(sender_pid.get());
                         #[cfg(feature="usb")]
                         if let Some(conn) = usb_serial {
                             usb_send_str(conn, &format!("PANIC in PID {}:", sender_pid));
@@ -215,6 +315,7 @@ fn reader_thread(arg: usize) {
                             *dest = *src;
                         }
                         let total_chars = scalar.id - 1100;
+                        crashes.panic_text(sender_pid.get(), &output_bfr[..total_chars.min(output_bfr.len())]);
                         for (idx, c) in output_bfr.iter().enumerate() {
                             if idx >= total_chars {
                                 break;
@@ -231,6 +332,13 @@ fn reader_thread(arg: usize) {
                     }
                     1200 => {
                         writeln!(output, "Terminating process").unwrap();
+                        let report_id = crashes.panic_finished(sender_pid.get());
+                        // never block on a hook, so that a stalled listener cannot stop the logger
+                        for (cid, id) in panic_hooks.iter() {
+                            let notification =
+                                xous::Message::new_scalar(*id, sender_pid.get() as usize, report_id as usize, 0, 0);
+                            xous::try_send_message(*cid, notification).ok();
+                        }
                         #[cfg(feature="usb")]
                         if let Some(conn) = usb_serial {
                             usb_send_str(conn, "Terminating process");
@@ -0,0 +1,213 @@
+use std::collections::VecDeque;
+
+use xous_api_log::api::{LogReadout, LOG_ENTRY_HEADER_LEN, LOG_READOUT_DATA_LEN};
+
+/// Approximate number of bytes of record text to keep
+const RING_CAPACITY: usize = 32 * 1024;
+/// Longer messages are truncated before they are stored
+const MAX_MESSAGE_LEN: usize = 1024;
+
+struct Entry {
+    seq: u64,
+    pid: u8,
+    level: u8,
+    line: u32,
+    module: Vec<u8>,
+    file: Vec<u8>,
+    message: Vec<u8>,
+}
+
+impl Entry {
+    fn encoded_len(&self) -> usize {
+        LOG_ENTRY_HEADER_LEN + self.module.len() + self.file.len() + self.message.len()
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..8].copy_from_slice(&self.seq.to_le_bytes());
+        out[8] = self.pid;
+        out[9] = self.level;
+        out[10..14].copy_from_slice(&self.line.to_le_bytes());
+        out[14..16].copy_from_slice(&(self.module.len() as u16).to_le_bytes());
+        out[16..18].copy_from_slice(&(self.file.len() as u16).to_le_bytes());
+        out[18..20].copy_from_slice(&(self.message.len() as u16).to_le_bytes());
+        let mut offset = LOG_ENTRY_HEADER_LEN;
+        for field in [&self.module, &self.file, &self.message].iter() {
+            out[offset..offset + field.len()].copy_from_slice(field);
+            offset += field.len();
+        }
+    }
+}
+
+/// The most recent log records, kept in RAM so they can be read back with `ReadLog`
+pub struct LogRing {
+    entries: VecDeque<Entry>,
+    bytes: usize,
+    next_seq: u64,
+}
+
+impl LogRing {
+    pub fn new() -> Self { LogRing { entries: VecDeque::new(), bytes: 0, next_seq: 0 } }
+
+    pub fn push(&mut self, pid: u8, level: u32, line: u32, module: &[u8], file: &[u8], message: &[u8]) {
+        let entry = Entry {
+            seq: self.next_seq,
+            pid,
+            level: level as u8,
+            line,
+            module: module.to_vec(),
+            file: file.to_vec(),
+            message: message[..message.len().min(MAX_MESSAGE_LEN)].to_vec(),
+        };
+        self.next_seq += 1;
+        self.bytes += entry.encoded_len();
+        self.entries.push_back(entry);
+        while self.bytes > RING_CAPACITY {
+            match self.entries.pop_front() {
+                Some(old) => self.bytes -= old.encoded_len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Fill `readout` with as many records as fit, starting at `readout.since`
+    pub fn read(&self, readout: &mut LogReadout) {
+        let oldest = self.entries.front().map(|e| e.seq).unwrap_or(self.next_seq);
+        let first = readout.since.max(oldest);
+        readout.oldest = oldest;
+        readout.count = 0;
+        readout.length = 0;
+
+        let mut offset = 0;
+        let mut next = first;
+        for entry in self.entries.iter().skip((first - oldest) as usize) {
+            let len = entry.encoded_len();
+            if offset + len > LOG_READOUT_DATA_LEN {
+                break;
+            }
+            entry.encode(&mut readout.data[offset..offset + len]);
+            offset += len;
+            next = entry.seq + 1;
+            readout.count += 1;
+        }
+        readout.length = offset as u32;
+        readout.since = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    /// The sequence numbers and messages of the entries in `readout`
+    fn decode(readout: &LogReadout) -> Vec<(u64, Vec<u8>)> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        for _ in 0..readout.count {
+            let header = &readout.data[offset..offset + LOG_ENTRY_HEADER_LEN];
+            let seq = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let module_len = u16::from_le_bytes([header[14], header[15]]) as usize;
+            let file_len = u16::from_le_bytes([header[16], header[17]]) as usize;
+            let message_len = u16::from_le_bytes([header[18], header[19]]) as usize;
+            let message_start = offset + LOG_ENTRY_HEADER_LEN + module_len + file_len;
+            entries.push((seq, readout.data[message_start..message_start + message_len].to_vec()));
+            offset = message_start + message_len;
+        }
+        assert_eq!(offset, readout.length as usize);
+        entries
+    }
+
+    fn read(ring: &LogRing, since: u64) -> (Vec<(u64, Vec<u8>)>, Box<LogReadout>) {
+        let mut readout = Box::new(LogReadout::default());
+        readout.since = since;
+        ring.read(&mut readout);
+        (decode(&readout), readout)
+    }
+
+    #[test]
+    fn read_since() {
+        let mut ring = LogRing::new();
+        for i in 0..5u8 {
+            ring.push(2, 3, 10, b"app", b"src/main.rs", &[b'a' + i]);
+        }
+        let (entries, readout) = read(&ring, 0);
+        assert_eq!(entries.iter().map(|(seq, _)| *seq).collect::<Vec<u64>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(entries[4].1, b"e");
+        assert_eq!((readout.oldest, readout.since), (0, 5));
+
+        let (entries, readout) = read(&ring, 3);
+        assert_eq!(entries, vec![(3, b"d".to_vec()), (4, b"e".to_vec())]);
+        assert_eq!(readout.since, 5);
+
+        // nothing new
+        let (entries, readout) = read(&ring, 5);
+        assert!(entries.is_empty());
+        assert_eq!((readout.count, readout.since), (0, 5));
+    }
+
+    #[test]
+    fn header_layout() {
+        let mut ring = LogRing::new();
+        ring.push(7, 2, 0x01020304, b"mod", b"file.rs", b"hello");
+        let (_, readout) = read(&ring, 0);
+        let data = &readout.data;
+        assert_eq!(data[8], 7);
+        assert_eq!(data[9], 2);
+        assert_eq!(u32::from_le_bytes(data[10..14].try_into().unwrap()), 0x01020304);
+        assert_eq!(&data[LOG_ENTRY_HEADER_LEN..LOG_ENTRY_HEADER_LEN + 3], b"mod");
+        assert_eq!(&data[LOG_ENTRY_HEADER_LEN + 3..LOG_ENTRY_HEADER_LEN + 10], b"file.rs");
+        assert_eq!(&data[LOG_ENTRY_HEADER_LEN + 10..LOG_ENTRY_HEADER_LEN + 15], b"hello");
+        assert_eq!(readout.length as usize, LOG_ENTRY_HEADER_LEN + 15);
+    }
+
+    #[test]
+    fn long_messages_are_truncated() {
+        let mut ring = LogRing::new();
+        ring.push(2, 3, 0, b"", b"", &[b'x'; MAX_MESSAGE_LEN * 2]);
+        let (entries, _) = read(&ring, 0);
+        assert_eq!(entries[0].1.len(), MAX_MESSAGE_LEN);
+    }
+
+    #[test]
+    fn oldest_records_are_dropped() {
+        let mut ring = LogRing::new();
+        let message = [b'm'; 100];
+        let per_entry = LOG_ENTRY_HEADER_LEN + message.len();
+        let total = RING_CAPACITY / per_entry * 2;
+        for _ in 0..total {
+            ring.push(2, 3, 0, b"", b"", &message);
+        }
+        assert!(ring.bytes <= RING_CAPACITY);
+        assert_eq!(ring.bytes, ring.entries.len() * per_entry);
+        let kept = ring.entries.len() as u64;
+        assert_eq!(kept, (RING_CAPACITY / per_entry) as u64);
+
+        // asking for records that are gone starts at the oldest one left
+        let (entries, readout) = read(&ring, 0);
+        assert_eq!(readout.oldest, total as u64 - kept);
+        assert_eq!(entries[0].0, readout.oldest);
+    }
+
+    #[test]
+    fn readout_is_paged() {
+        let mut ring = LogRing::new();
+        let message = [b'm'; 500];
+        for _ in 0..20 {
+            ring.push(2, 3, 0, b"", b"", &message);
+        }
+        let per_page = LOG_READOUT_DATA_LEN / (LOG_ENTRY_HEADER_LEN + message.len());
+        let mut since = 0;
+        let mut seen = Vec::new();
+        loop {
+            let (entries, readout) = read(&ring, since);
+            if entries.is_empty() {
+                break;
+            }
+            assert!(entries.len() <= per_page);
+            seen.extend(entries.iter().map(|(seq, _)| *seq));
+            since = readout.since;
+        }
+        assert_eq!(seen, (0..20).collect::<Vec<u64>>());
+    }
+}
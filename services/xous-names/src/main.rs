@@ -1,10 +1,13 @@
 #![cfg_attr(target_os = "none", no_std)]
 #![cfg_attr(target_os = "none", no_main)]
 
+mod policy;
+
 use std::collections::HashMap;
 
 use log::{error, info};
 use num_traits::FromPrimitive;
+use policy::Policy;
 use xous::{msg_blocking_scalar_unpack, MessageEnvelope};
 use xous_api_names::api::*;
 use xous_api_names::*;
@@ -27,6 +30,9 @@ enum ConnectError {
 
     /// The server does not currently exist, and a blocking request was made
     ServerNotFound = 5,
+
+    /// The connection policy does not allow this process to connect to the server
+    AccessDenied = 6,
 }
 
 #[derive(PartialEq)]
@@ -108,21 +114,24 @@ struct Connection {
     pub current_conns: u32, // number of unauthenticated (inherently trusted) connections
     pub max_conns: Option<u32>, // if None, unlimited connections allowed
     pub _allow_authenticate: bool,
-    pub _auth_conns: u32,        // number of authenticated connections
-    pub token: Option<[u32; 4]>, // a random number that must be presented to allow for disconnection
+    pub _auth_conns: u32,         // number of authenticated connections
+    pub token: Option<[u32; 4]>,  // a random number that must be presented to allow for disconnection
+    pub owner: Option<xous::PID>, // the process that registered this name
 }
 #[derive(Debug)]
 struct CheckedHashMap {
     pub map: HashMap<XousServerName, Connection>,
+    pub policy: Policy,
 }
 impl CheckedHashMap {
-    pub fn new() -> Self { CheckedHashMap { map: HashMap::new() } }
+    pub fn new() -> Self { CheckedHashMap { map: HashMap::new(), policy: Policy::new() } }
 
     pub fn insert(
         &mut self,
         name: XousServerName,
         sid: xous::SID,
         max_conns: Option<u32>,
+        owner: Option<xous::PID>,
     ) -> Result<(), xous::Error> {
         let token =
             // for use with 1-connection servers, provision a one-time use token for disconnects
@@ -144,6 +153,7 @@ impl CheckedHashMap {
                 _allow_authenticate: false, // for now, we don't support authenticated connections
                 _auth_conns: 0,
                 token,
+                owner,
             },
         );
         Ok(())
@@ -167,9 +177,62 @@ impl CheckedHashMap {
         removed_name
     }
 
+    /// Remove every name that was registered by `pid`, returning the names that were removed.
+    pub fn remove_owned_by(&mut self, pid: xous::PID) -> Vec<XousServerName> {
+        let removed: Vec<XousServerName> = self
+            .map
+            .iter()
+            .filter(|(_, mapping)| mapping.owner == Some(pid))
+            .map(|(name, _)| *name)
+            .collect();
+        for name in removed.iter() {
+            self.map.remove(name);
+        }
+        removed
+    }
+
     pub fn contains_key(&self, name: &XousServerName) -> bool { self.map.contains_key(name) }
 
-    pub fn connect(&mut self, name: &XousServerName) -> (Option<xous::SID>, Option<[u32; 4]>) {
+    fn record(name: &XousServerName, entry: &Connection) -> ServerRecord {
+        ServerRecord {
+            name: String::<64>::from_str(name.to_str()),
+            owner: entry.owner.map(|pid| pid.get()),
+            connections: entry.current_conns,
+            max_connections: entry.max_conns,
+        }
+    }
+
+    /// Describe every registered server, sorted by name
+    pub fn records(&self) -> Vec<ServerRecord> {
+        let mut names: Vec<&XousServerName> = self.map.keys().collect();
+        names.sort_by(|a, b| a.to_str().cmp(b.to_str()));
+        names.into_iter().map(|name| Self::record(name, &self.map[name])).collect()
+    }
+
+    /// Describe the server registered as `name`, if any
+    pub fn server_record(&self, name: &XousServerName) -> Option<ServerRecord> {
+        self.map.get(name).map(|entry| Self::record(name, entry))
+    }
+
+    /// Attempt to broker a connection to `name` on behalf of `pid`. Requests refused by the
+    /// connection policy are logged and recorded, and return the reason for the refusal.
+    pub fn connect(
+        &mut self,
+        name: &XousServerName,
+        pid: xous::PID,
+    ) -> Result<(Option<xous::SID>, Option<[u32; 4]>), api::DenialReason> {
+        if let Err(reason) = self.policy.check(name, pid, is_process) {
+            self.policy.deny(name, pid, reason);
+            return Err(reason);
+        }
+        let result = self.connect_unchecked(name);
+        if result.0.is_some() {
+            self.policy.grant(name, pid);
+        }
+        Ok(result)
+    }
+
+    fn connect_unchecked(&mut self, name: &XousServerName) -> (Option<xous::SID>, Option<[u32; 4]>) {
         if let Some(entry) = self.map.get_mut(name) {
             match entry.max_conns {
                 // single-connection case
@@ -209,7 +272,7 @@ impl CheckedHashMap {
         }
     }
 
-    pub fn trusted_init_done(&self) -> bool {
+    pub fn trusted_init_done(&mut self) -> bool {
         let mut trusted_done = true;
         for (name, entry) in self.map.iter() {
             if let Some(max) = entry.max_conns {
@@ -219,6 +282,9 @@ impl CheckedHashMap {
                 }
             }
         }
+        if trusted_done {
+            self.policy.seal();
+        }
         trusted_done
     }
 
@@ -262,6 +328,20 @@ impl CheckedHashMap {
     }
 }
 
+/// Report whether the loader gave `pid` the name `name`
+#[cfg(target_os = "xous")]
+fn is_process(pid: xous::PID, name: &str) -> bool {
+    let mut buf = [0u8; 64];
+    match xous::process_name(pid, &mut buf) {
+        Ok(len) => len == name.len() && buf.get(..len) == Some(name.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+/// Hosted mode has no loader and no memory isolation, so every process is taken at its word
+#[cfg(not(target_os = "xous"))]
+fn is_process(_pid: xous::PID, _name: &str) -> bool { true }
+
 fn name_from_msg(env: &MessageEnvelope) -> Result<XousServerName, ConnectError> {
     let msg = env.body.memory_message().ok_or(ConnectError::InvalidMessageType)?;
     let valid_bytes = msg.valid.map(|v| v.get()).unwrap_or_else(|| msg.buf.len());
@@ -290,7 +370,8 @@ fn blocking_connect(
 
     // If the server already exists, attempt to make the connection. The connection can
     // only succeed if the server is in the name_table.
-    if let (Some(server_sid), token) = name_table.connect(&name) {
+    let connection = name_table.connect(&name, sender_pid).map_err(|_| ConnectError::AccessDenied)?;
+    if let (Some(server_sid), token) = connection {
         log::trace!(
             "Found entry in the table (sid: {:?}, token: {:?}) -- attempting to call connect_for_process()",
             server_sid,
@@ -312,6 +393,7 @@ fn blocking_connect(
             // The server connection process failed inside the kernel for one reason or
             // another, so remove the entry from the `name_table` and return an error
             name_table.disconnect(server_sid);
+            name_table.policy.release(&name, sender_pid);
             return Err(ConnectError::KernelConnectFailure);
         }
     }
@@ -351,6 +433,9 @@ fn main() -> ! {
 
     let name_server =
         xous::create_server_with_address(b"xous-name-server").expect("Couldn't create xousnames-server");
+    // Have the kernel tell us when a process exits, so its names can be reclaimed
+    xous::set_peer_exit_notification(name_server, api::Opcode::PeerExited as usize)
+        .expect("couldn't register for peer exit notifications");
 
     let d11ctimeout = D11cTimeout::new();
 
@@ -368,6 +453,7 @@ fn main() -> ! {
         log::trace!("received message: {:?}", msg);
         match FromPrimitive::from_usize(msg.body.id()) {
             Some(api::Opcode::Register) => {
+                let owner = msg.sender.pid();
                 let mem = msg.body.memory_message_mut().unwrap();
                 let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
                 let registration = buffer.to_original::<Registration, _>().unwrap();
@@ -382,7 +468,7 @@ fn main() -> ! {
                 if !name_table.contains_key(&name) {
                     let new_sid = xous::create_server_id().expect("create server failed, maybe OOM?");
                     name_table
-                        .insert(name, new_sid, registration.conn_limit)
+                        .insert(name, new_sid, registration.conn_limit, owner)
                         .expect("register name failure, maybe out of HashMap capacity?");
                     log::trace!("request successful, SID is {:?}", new_sid);
                     should_connect = true;
@@ -467,9 +553,10 @@ fn main() -> ! {
                     name_string.as_str().expect("couldn't convert server name to string"),
                 );
                 log::trace!("Lookup request for '{}'", name);
+                let sender_pid = msg.sender.pid().expect("can't extract sender PID on Lookup");
                 let response: api::Return;
-                if let (Some(server_sid), token) = name_table.connect(&name) {
-                    let sender_pid = msg.sender.pid().expect("can't extract sender PID on Lookup");
+                let connection = name_table.connect(&name, sender_pid);
+                if let Ok((Some(server_sid), token)) = connection {
                     match xous::connect_for_process(sender_pid, server_sid).expect("can't broker connection")
                     {
                         xous::Result::ConnectionID(connection_id) => {
@@ -481,10 +568,15 @@ fn main() -> ! {
                             for (_name, conn) in name_table.map.iter() {
                                 log::debug!("{:?}", conn);
                             }
+                            name_table.policy.release(&name, sender_pid);
                             d11ctimeout.hosted_delay();
                             response = api::Return::Failure
                         }
                     }
+                } else if connection.is_err() {
+                    // the denial has already been logged by the connection policy
+                    d11ctimeout.hosted_delay();
+                    response = api::Return::Failure
                 } else {
                     log::debug!("Can't find request '{}' in table, dumping table:", name);
                     for (_name, conn) in name_table.map.iter() {
@@ -526,12 +618,64 @@ fn main() -> ! {
                 let disconnect = buffer.to_original::<Disconnect, _>().unwrap();
                 let name = XousServerName::from_str(disconnect.name.as_str().unwrap());
                 let response = if name_table.disconnect_with_token(&name, disconnect.token) {
+                    if let Some(pid) = msg.sender.pid() {
+                        name_table.policy.release(&name, pid);
+                    }
                     api::Return::Success
                 } else {
                     api::Return::Failure
                 };
                 buffer.replace(response).expect("Can't return buffer");
             }
+            Some(api::Opcode::PeerExited) => {
+                if msg.body.is_blocking() {
+                    continue;
+                }
+                let pid = match msg.body.scalar_message().and_then(|s| xous::PID::new(s.arg1 as u8)) {
+                    Some(pid) => pid,
+                    None => continue,
+                };
+                // Anyone can send to our well-known address, so confirm with the kernel that the
+                // process really is gone before dropping its names.
+                if xous::process_info(pid) != Err(xous::Error::ProcessNotFound) {
+                    log::warn!("ignoring exit notification for PID {}, which is still running", pid);
+                    continue;
+                }
+                for name in name_table.remove_owned_by(pid) {
+                    info!("{} server has exited, unregistering", name);
+                    name_table.policy.release_server(&name);
+                }
+                name_table.policy.release_all(pid);
+            }
+            Some(api::Opcode::ListDenials) => {
+                let mem = msg.body.memory_message_mut().unwrap();
+                let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+                let mut denials = DenialList { list: [None; DENIAL_LOG_LEN] };
+                for (slot, denial) in denials.list.iter_mut().zip(name_table.policy.denials()) {
+                    *slot = Some(*denial);
+                }
+                buffer.replace(denials).expect("ListDenials can't serialize return value");
+            }
+            Some(api::Opcode::ListServers) => {
+                let mem = msg.body.memory_message_mut().unwrap();
+                let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+                let mut page = buffer.to_original::<ServerList, _>().unwrap();
+                let records = name_table.records();
+                page.total = records.len() as u32;
+                page.list = [None; SERVER_LIST_LEN];
+                for (slot, record) in page.list.iter_mut().zip(records.iter().skip(page.start as usize)) {
+                    *slot = Some(*record);
+                }
+                buffer.replace(page).expect("ListServers can't serialize return value");
+            }
+            Some(api::Opcode::ServerInfo) => {
+                let mem = msg.body.memory_message_mut().unwrap();
+                let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+                let mut query = buffer.to_original::<ServerQuery, _>().unwrap();
+                let name = XousServerName::from_str(query.name.as_str().unwrap_or(""));
+                query.record = name_table.server_record(&name);
+                buffer.replace(query).expect("ServerInfo can't serialize return value");
+            }
             None => {
                 error!("couldn't decode message: {:?}", msg);
                 break;
@@ -0,0 +1,358 @@
+//! Declarative connection policy for xous-names.
+//!
+//! The policy is a plain-text manifest compiled into the name server (see `policy.conf`
+//! at the root of this crate). Each non-comment line describes who may connect to a
+//! single server:
+//!
+//! ```text
+//! <server name> | <clients> | <options>
+//! ```
+//!
+//! * `<clients>` is a comma-separated list of `pid:<n>`, `process:<process name>` or `*`.
+//! * `<options>` is an optional, space-separated list of `max=<n>` (the number of connections each client may
+//!   hold at once) and `boot-only` (connections are only brokered until `TrustedInitDone` first reports
+//!   success).
+//!
+//! A process is known by the name the loader gave it when the image was built, which is the
+//! file name of its program (`process:shellchat`), and which the kernel reports through
+//! `xous::process_name()`. A process cannot choose this name: processes that were not part
+//! of the boot image have none, and so only match `pid:` and `*` clients.
+//!
+//! Hosted builds have no loader, and therefore no process names. They also have no memory
+//! isolation, so `process:` clients match any process there rather than locking everyone out.
+//!
+//! Servers without a rule keep the historic behaviour of allowing anyone to connect.
+//!
+//! Connections count against `max=` from the moment they are brokered until the client gives
+//! them back with `Disconnect`, the client exits, or the server's owner exits and frees its name.
+
+use std::collections::{HashMap, VecDeque};
+
+use xous_api_names::api::{Denial, DenialReason, XousServerName, DENIAL_LOG_LEN};
+
+/// The manifest that is loaded at boot
+const POLICY_MANIFEST: &str = include_str!("../policy.conf");
+
+#[derive(Debug, Clone, PartialEq)]
+enum Client {
+    Any,
+    Pid(xous::PID),
+    Process(String),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    clients: Vec<Client>,
+    max_per_client: Option<u32>,
+    boot_only: bool,
+}
+
+#[derive(Debug)]
+pub struct Policy {
+    rules: HashMap<XousServerName, Rule>,
+    /// Connections currently held under a rule, keyed by server and client
+    grants: HashMap<(XousServerName, xous::PID), u32>,
+    /// Latched once `TrustedInitDone` has succeeded
+    sealed: bool,
+    denials: VecDeque<Denial>,
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        let mut policy = Policy {
+            rules: HashMap::new(),
+            grants: HashMap::new(),
+            sealed: false,
+            denials: VecDeque::with_capacity(DENIAL_LOG_LEN),
+        };
+        policy.load(POLICY_MANIFEST);
+        policy
+    }
+
+    /// Parse `manifest`, adding its rules to the policy. Malformed lines are logged and skipped.
+    fn load(&mut self, manifest: &str) {
+        for (lineno, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_rule(line) {
+                Ok((name, rule)) => {
+                    if self.rules.contains_key(&name) {
+                        log::error!("policy line {}: duplicate rule for {}, ignoring", lineno + 1, name);
+                        continue;
+                    }
+                    log::debug!("policy rule for {}: {:?}", name, rule);
+                    self.rules.insert(name, rule);
+                }
+                Err(e) => log::error!("policy line {}: {}, ignoring", lineno + 1, e),
+            }
+        }
+        log::info!("loaded {} connection policy rules", self.rules.len());
+    }
+
+    /// Check whether `pid` may open one more connection to `server`. `is_process` reports
+    /// whether `pid` was given a particular name by the loader, and is used to resolve
+    /// `process:` clients.
+    pub fn check<F>(&self, server: &XousServerName, pid: xous::PID, is_process: F) -> Result<(), DenialReason>
+    where
+        F: Fn(xous::PID, &str) -> bool,
+    {
+        let rule = match self.rules.get(server) {
+            Some(rule) => rule,
+            None => return Ok(()),
+        };
+        let listed = rule.clients.iter().any(|client| match client {
+            Client::Any => true,
+            Client::Pid(p) => *p == pid,
+            Client::Process(name) => is_process(pid, name.as_str()),
+        });
+        if !listed {
+            return Err(DenialReason::NotListed);
+        }
+        if rule.boot_only && self.sealed {
+            return Err(DenialReason::AfterTrustedInit);
+        }
+        if let Some(max) = rule.max_per_client {
+            if self.grants.get(&(*server, pid)).copied().unwrap_or(0) >= max {
+                return Err(DenialReason::ConnectionLimit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record that `pid` now holds one more connection to `server`
+    pub fn grant(&mut self, server: &XousServerName, pid: xous::PID) {
+        if self.rules.contains_key(server) {
+            *self.grants.entry((*server, pid)).or_insert(0) += 1;
+        }
+    }
+
+    /// Record that `pid` has given up one connection to `server`
+    pub fn release(&mut self, server: &XousServerName, pid: xous::PID) {
+        let key = (*server, pid);
+        match self.grants.get_mut(&key) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.grants.remove(&key);
+            }
+            None => {}
+        }
+    }
+
+    /// Forget every connection held by `pid`, e.g. because the process exited
+    pub fn release_all(&mut self, pid: xous::PID) { self.grants.retain(|(_, p), _| *p != pid); }
+
+    /// Forget every connection held to `server`, e.g. because its owner exited and the name
+    /// may be registered again
+    pub fn release_server(&mut self, server: &XousServerName) { self.grants.retain(|(s, _), _| s != server); }
+
+    /// Stop brokering `boot-only` connections
+    pub fn seal(&mut self) {
+        if !self.sealed {
+            log::info!("trusted init done, boot-only connections are now closed");
+            self.sealed = true;
+        }
+    }
+
+    /// Log a denied connection and keep it for later inspection
+    pub fn deny(&mut self, server: &XousServerName, pid: xous::PID, reason: DenialReason) {
+        log::warn!("denied connection to {} from PID {}: {:?}", server, pid, reason);
+        if self.denials.len() == DENIAL_LOG_LEN {
+            self.denials.pop_front();
+        }
+        self.denials.push_back(Denial {
+            name: xous_ipc::String::<64>::from_str(server.to_str()),
+            pid: pid.get(),
+            reason,
+        });
+    }
+
+    /// The most recent denials, oldest first
+    pub fn denials(&self) -> impl Iterator<Item = &Denial> { self.denials.iter() }
+}
+
+fn parse_rule(line: &str) -> Result<(XousServerName, Rule), &'static str> {
+    let mut fields = line.split('|').map(|f| f.trim());
+    let name = fields.next().filter(|n| !n.is_empty()).ok_or("missing server name")?;
+    if name.len() >= 64 {
+        return Err("server name too long");
+    }
+    let clients = fields.next().ok_or("missing client list")?;
+    let options = fields.next().unwrap_or("");
+    if fields.next().is_some() {
+        return Err("too many fields");
+    }
+
+    let mut rule = Rule { clients: Vec::new(), max_per_client: None, boot_only: false };
+    for client in clients.split(',').map(|c| c.trim()) {
+        rule.clients.push(if client == "*" {
+            Client::Any
+        } else if let Some(pid) = client.strip_prefix("pid:") {
+            let pid = pid.trim().parse::<u8>().map_err(|_| "invalid PID")?;
+            Client::Pid(xous::PID::new(pid).ok_or("invalid PID")?)
+        } else if let Some(name) = client.strip_prefix("process:") {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("missing process name");
+            }
+            Client::Process(name.to_owned())
+        } else {
+            return Err("unrecognized client");
+        });
+    }
+    for option in options.split_whitespace() {
+        if option == "boot-only" {
+            rule.boot_only = true;
+        } else if let Some(max) = option.strip_prefix("max=") {
+            rule.max_per_client = Some(max.parse::<u32>().map_err(|_| "invalid max")?);
+        } else {
+            return Err("unrecognized option");
+        }
+    }
+    Ok((XousServerName::from_str(name), rule))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(manifest: &str) -> Policy {
+        let mut policy = Policy {
+            rules: HashMap::new(),
+            grants: HashMap::new(),
+            sealed: false,
+            denials: VecDeque::with_capacity(DENIAL_LOG_LEN),
+        };
+        policy.load(manifest);
+        policy
+    }
+
+    fn name(name: &str) -> XousServerName { XousServerName::from_str(name) }
+
+    fn pid(pid: u8) -> xous::PID { xous::PID::new(pid).unwrap() }
+
+    #[test]
+    fn parse_rules() {
+        let (server, rule) = parse_rule("_Server_ | pid:3, process:shellchat ,* | max=2 boot-only").unwrap();
+        assert_eq!(server, name("_Server_"));
+        assert_eq!(rule.clients, vec![Client::Pid(pid(3)), Client::Process("shellchat".into()), Client::Any]);
+        assert_eq!(rule.max_per_client, Some(2));
+        assert!(rule.boot_only);
+
+        let (_, rule) = parse_rule("_Server_ | *").unwrap();
+        assert_eq!(rule.max_per_client, None);
+        assert!(!rule.boot_only);
+
+        assert_eq!(parse_rule(" | *").unwrap_err(), "missing server name");
+        assert_eq!(parse_rule("_Server_").unwrap_err(), "missing client list");
+        assert_eq!(parse_rule("_Server_ | * | | ").unwrap_err(), "too many fields");
+        assert_eq!(parse_rule("_Server_ | pid:0").unwrap_err(), "invalid PID");
+        assert_eq!(parse_rule("_Server_ | pid:256").unwrap_err(), "invalid PID");
+        assert_eq!(parse_rule("_Server_ | proc:3").unwrap_err(), "unrecognized client");
+        // server names can be registered by anyone, so they do not identify a client
+        assert_eq!(parse_rule("_Server_ | name:_Shell_").unwrap_err(), "unrecognized client");
+        assert_eq!(parse_rule("_Server_ | process: ").unwrap_err(), "missing process name");
+        assert_eq!(parse_rule("_Server_ | * | max=lots").unwrap_err(), "invalid max");
+        assert_eq!(parse_rule("_Server_ | * | forever").unwrap_err(), "unrecognized option");
+        assert_eq!(parse_rule(&format!("{} | *", "x".repeat(64))).unwrap_err(), "server name too long");
+    }
+
+    #[test]
+    fn load_skips_bad_lines() {
+        let policy = policy(
+            "# comment\n\
+             \n\
+             _A_ | pid:2\n\
+             _A_ | *\n\
+             _B_ | bogus\n\
+             _C_ | * | max=1\n",
+        );
+        assert_eq!(policy.rules.len(), 2);
+        assert_eq!(policy.rules[&name("_A_")].clients, vec![Client::Pid(pid(2))]);
+        assert!(policy.rules.contains_key(&name("_C_")));
+    }
+
+    #[test]
+    fn shipped_manifest_loads() {
+        let rules = POLICY_MANIFEST.lines().filter(|l| !l.trim().is_empty() && !l.trim().starts_with('#'));
+        assert_eq!(Policy::new().rules.len(), rules.count());
+    }
+
+    #[test]
+    fn check_matches_clients() {
+        let policy = policy("_A_ | pid:2, process:shellchat\n_B_ | *\n");
+        let unnamed = |_: xous::PID, _: &str| false;
+        let shellchat = |_: xous::PID, name: &str| name == "shellchat";
+        let status = |_: xous::PID, name: &str| name == "status";
+
+        assert_eq!(policy.check(&name("_A_"), pid(2), unnamed), Ok(()));
+        assert_eq!(policy.check(&name("_A_"), pid(5), shellchat), Ok(()));
+        assert_eq!(policy.check(&name("_A_"), pid(5), status), Err(DenialReason::NotListed));
+        assert_eq!(policy.check(&name("_A_"), pid(5), unnamed), Err(DenialReason::NotListed));
+        assert_eq!(policy.check(&name("_B_"), pid(5), unnamed), Ok(()));
+        // servers without a rule are open to anyone
+        assert_eq!(policy.check(&name("_Unlisted_"), pid(5), unnamed), Ok(()));
+    }
+
+    #[test]
+    fn boot_only_closes_when_sealed() {
+        let mut policy = policy("_A_ | * | boot-only\n_B_ | *\n");
+        assert_eq!(policy.check(&name("_A_"), pid(2), |_, _| false), Ok(()));
+        policy.seal();
+        assert_eq!(policy.check(&name("_A_"), pid(2), |_, _| false), Err(DenialReason::AfterTrustedInit));
+        assert_eq!(policy.check(&name("_B_"), pid(2), |_, _| false), Ok(()));
+    }
+
+    #[test]
+    fn connection_limit_is_released() {
+        let mut policy = policy("_A_ | * | max=2\n");
+        let server = name("_A_");
+        for _ in 0..2 {
+            assert_eq!(policy.check(&server, pid(2), |_, _| false), Ok(()));
+            policy.grant(&server, pid(2));
+        }
+        assert_eq!(policy.check(&server, pid(2), |_, _| false), Err(DenialReason::ConnectionLimit));
+        // the limit is per client
+        assert_eq!(policy.check(&server, pid(3), |_, _| false), Ok(()));
+
+        // a disconnect frees up one connection
+        policy.release(&server, pid(2));
+        assert_eq!(policy.check(&server, pid(2), |_, _| false), Ok(()));
+        policy.grant(&server, pid(2));
+        assert_eq!(policy.check(&server, pid(2), |_, _| false), Err(DenialReason::ConnectionLimit));
+
+        // so does the client exiting...
+        policy.release_all(pid(2));
+        assert!(policy.grants.is_empty());
+        assert_eq!(policy.check(&server, pid(2), |_, _| false), Ok(()));
+
+        // ...and the server exiting
+        policy.grant(&server, pid(2));
+        policy.grant(&server, pid(2));
+        policy.grant(&server, pid(3));
+        policy.release_server(&server);
+        assert!(policy.grants.is_empty());
+
+        // releasing what was never granted is harmless
+        policy.release(&server, pid(4));
+        assert!(policy.grants.is_empty());
+    }
+
+    #[test]
+    fn grants_only_count_under_a_rule() {
+        let mut policy = policy("_A_ | * | max=1\n");
+        policy.grant(&name("_Unlisted_"), pid(2));
+        assert!(policy.grants.is_empty());
+    }
+
+    #[test]
+    fn denials_are_bounded() {
+        let mut policy = policy("");
+        for i in 0..DENIAL_LOG_LEN + 3 {
+            policy.deny(&name("_A_"), pid(1 + i as u8), DenialReason::NotListed);
+        }
+        assert_eq!(policy.denials().count(), DENIAL_LOG_LEN);
+        assert_eq!(policy.denials().next().unwrap().pid, 4);
+    }
+}
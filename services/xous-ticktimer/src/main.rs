@@ -15,6 +15,10 @@ use platform::implementation::*;
 use platform::*;
 #[cfg(not(any(target_arch = "arm", feature = "cramium-soc", feature = "cramium-fpga")))]
 use susres::SuspendOrder;
+mod sync;
+use sync::SyncTable;
+mod timers;
+use timers::TimerTable;
 
 fn main() -> ! {
     log_server::init_wait().unwrap();
@@ -89,6 +93,14 @@ fn main() -> ! {
     let mut mutex_hash: HashMap<Option<xous::PID>, HashMap<usize, VecDeque<xous::MessageSender>>> =
         HashMap::new();
 
+    // Timers created with `ScheduleTimer`. Their pending expiries are kept in `sleep_heap`
+    // alongside sleep requests and condvar timeouts, but the table decides when they are due.
+    let mut timers = TimerTable::new();
+
+    // RwLocks and semaphores. Unlike Mutexes these have no fast path in the caller, so
+    // every operation comes through here and the table is always authoritative.
+    let mut sync_table = SyncTable::new();
+
     let mut msg_opt = None;
     let mut return_type = 0;
     loop {
@@ -101,6 +113,17 @@ fn main() -> ! {
         let msg = msg_opt.as_mut().unwrap();
         let opcode = num_traits::FromPrimitive::from_usize(msg.body.id()).unwrap_or(api::Opcode::InvalidCall);
         log::trace!("msg {:?}: {:x?}", opcode, msg);
+
+        // A timer that expired while our queue was full never got its `RecalculateSleep`, so
+        // deliver it now rather than wait for some other timeout to fire.
+        if !matches!(opcode, api::Opcode::RecalculateSleep) && timers.overdue(ticktimer.elapsed_ms() as i64) {
+            ticktimer.stop_sleep(&mut sleep_heap);
+            for request in timers.sweep(&mut sleep_heap, ticktimer.elapsed_ms() as i64) {
+                unsafe { ticktimer.recalculate_sleep_offline(&mut sleep_heap, Some(request)) };
+            }
+            ticktimer.start_sleep(&mut sleep_heap);
+        }
+
         match opcode {
             api::Opcode::ElapsedMs => {
                 if let Some(scalar) = msg.body.scalar_message_mut() {
@@ -185,6 +208,12 @@ fn main() -> ! {
                     }
                 }
 
+                // Deliver every timer that is due and work out when each should next fire. This
+                // also picks up timers whose own `RecalculateSleep` was dropped on a full queue.
+                for request in timers.sweep(&mut sleep_heap, ticktimer.elapsed_ms() as i64) {
+                    unsafe { ticktimer.recalculate_sleep_offline(&mut sleep_heap, Some(request)) };
+                }
+
                 // Recalculate sleep with the newly-adjusted hash and re-enable
                 // the sleep interrupt.
                 unsafe { ticktimer.recalculate_sleep_offline(&mut sleep_heap, None) };
@@ -531,9 +560,188 @@ fn main() -> ! {
                 ticktimer.start_sleep(&mut sleep_heap);
             }
 
+            api::Opcode::ScheduleTimer => {
+                let pid = msg.sender.pid();
+                let Some(mem) = msg.body.memory_message_mut() else {
+                    log::error!("sender made ScheduleTimer request that wasn't a memory message");
+                    continue;
+                };
+                let mut buf = unsafe { xous_ipc::Buffer::from_memory_message_mut(mem) };
+                let mut hook = buf.to_original::<api::TimerHook, _>().unwrap();
+
+                match timers.add(pid, &hook, ticktimer.elapsed_ms() as i64) {
+                    Ok((timer_id, request)) => {
+                        log::debug!("PID {:?} scheduled timer {}: {:?}", pid, timer_id, hook);
+                        ticktimer.recalculate_sleep(&mut sleep_heap, Some(request));
+                        hook.timer_id = Some(timer_id as u32);
+                    }
+                    Err(e) => {
+                        log::error!("couldn't schedule timer for PID {:?}: {:?}", pid, e);
+                        hook.timer_id = None;
+                    }
+                }
+                buf.replace(hook).unwrap();
+            }
+
+            api::Opcode::CancelTimer => {
+                let pid = msg.sender.pid();
+                let Some(scalar) = msg.body.scalar_message_mut() else {
+                    log::error!("sender made CancelTimer request that wasn't a Scalar message");
+                    continue;
+                };
+                let timer_id = scalar.arg1;
+
+                let cancelled = timers.cancel(timer_id, pid);
+                if cancelled {
+                    // Remove the pending expiry, unless it has already fired and is
+                    // waiting for a `RecalculateSleep`, which will then find no timer.
+                    ticktimer.stop_sleep(&mut sleep_heap);
+                    sleep_heap.retain(|_, v| !(v.kind == RequestKind::Timer && v.data == timer_id));
+                    unsafe { ticktimer.recalculate_sleep_offline(&mut sleep_heap, None) };
+                    ticktimer.start_sleep(&mut sleep_heap);
+                }
+
+                scalar.arg1 = if cancelled { 1 } else { 0 };
+                return_type = 1;
+            }
+
+            api::Opcode::LockRead | api::Opcode::LockWrite => {
+                let pid = msg.sender.pid();
+                if !msg.body.is_blocking() {
+                    log::error!("sender made {:?} request that was not blocking", opcode);
+                    continue;
+                }
+                let Some(scalar) = msg.body.scalar_message_mut() else {
+                    log::error!("sender made {:?} request that was not a scalar message", opcode);
+                    continue;
+                };
+
+                let lock_id = scalar.arg1;
+                let locked = if matches!(opcode, api::Opcode::LockRead) {
+                    sync_table.lock_read(pid, lock_id, msg.sender)
+                } else {
+                    sync_table.lock_write(pid, lock_id, msg.sender)
+                };
+                if locked {
+                    scalar.arg1 = 0;
+                    return_type = 1;
+                    continue;
+                }
+
+                // The sender will be woken up when the lock is handed to it by `Unlock`.
+                // Forget the contents of `msg_opt` without running its destructor.
+                core::mem::forget(msg_opt.take());
+            }
+
+            api::Opcode::Unlock => {
+                let pid = msg.sender.pid();
+                let Some(scalar) = msg.body.scalar_message() else {
+                    log::error!("sender made Unlock request that was not a scalar message");
+                    continue;
+                };
+                match sync_table.unlock(pid, scalar.arg1) {
+                    Some(waiters) => wake_waiters(&mut sync_table, pid, waiters, 0),
+                    None => log::warn!(
+                        "Process {} attempted to unlock an RwLock {:08x} that is not locked",
+                        pid.map(|v| v.get()).unwrap_or_default(),
+                        scalar.arg1
+                    ),
+                }
+            }
+
+            api::Opcode::FreeRwLock => {
+                let pid = msg.sender.pid();
+                let Some(scalar) = msg.body.scalar_message() else {
+                    log::error!("sender tried to free an RwLock using a non-scalar message");
+                    continue;
+                };
+                let waiters = sync_table.free_rwlock(pid, scalar.arg1);
+                if !waiters.is_empty() {
+                    log::error!(
+                        "When freeing RwLock {:08x}, there were {} threads waiting for it",
+                        scalar.arg1,
+                        waiters.len()
+                    );
+                }
+                wake_waiters(&mut sync_table, pid, waiters, 1);
+            }
+
+            api::Opcode::AcquireSemaphore => {
+                let pid = msg.sender.pid();
+                if !msg.body.is_blocking() {
+                    log::error!("sender made AcquireSemaphore request that was not blocking");
+                    continue;
+                }
+                let Some(scalar) = msg.body.scalar_message_mut() else {
+                    log::error!("sender made AcquireSemaphore request that was not a scalar message");
+                    continue;
+                };
+
+                if sync_table.acquire(pid, scalar.arg1, msg.sender) {
+                    scalar.arg1 = 0;
+                    return_type = 1;
+                    continue;
+                }
+
+                // The sender will be woken up when a permit is handed to it by `ReleaseSemaphore`.
+                // Forget the contents of `msg_opt` without running its destructor.
+                core::mem::forget(msg_opt.take());
+            }
+
+            api::Opcode::ReleaseSemaphore => {
+                let pid = msg.sender.pid();
+                let Some(scalar) = msg.body.scalar_message() else {
+                    log::error!("sender made ReleaseSemaphore request that was not a scalar message");
+                    continue;
+                };
+                let waiters = sync_table.release(pid, scalar.arg1, scalar.arg2);
+                wake_waiters(&mut sync_table, pid, waiters, 0);
+            }
+
+            api::Opcode::FreeSemaphore => {
+                let pid = msg.sender.pid();
+                let Some(scalar) = msg.body.scalar_message() else {
+                    log::error!("sender tried to free a semaphore using a non-scalar message");
+                    continue;
+                };
+                let waiters = sync_table.free_semaphore(pid, scalar.arg1);
+                if !waiters.is_empty() {
+                    log::error!(
+                        "When freeing semaphore {:08x}, there were {} threads waiting for it",
+                        scalar.arg1,
+                        waiters.len()
+                    );
+                }
+                wake_waiters(&mut sync_table, pid, waiters, 1);
+            }
+
             api::Opcode::InvalidCall => {
                 error!("couldn't convert opcode");
             }
         }
     }
 }
+
+/// Respond to threads that were blocked on an RwLock or semaphore. `value` is `0` if the
+/// thread now holds the lock or permit, and `1` if the object was freed out from under it.
+fn wake_waiters(
+    sync_table: &mut SyncTable,
+    pid: Option<xous::PID>,
+    waiters: Vec<xous::MessageSender>,
+    value: usize,
+) {
+    for sender in waiters {
+        match xous::return_scalar(sender, value) {
+            Ok(_) => {}
+            Err(xous::Error::ProcessNotFound) => {
+                log::error!(
+                    "process {} exited -- removing its RwLocks and semaphores",
+                    pid.map(|v| v.get()).unwrap_or_default()
+                );
+                sync_table.remove_process(pid);
+                return;
+            }
+            Err(e) => panic!("unexpected error responding to scalar: {:?}", e),
+        }
+    }
+}
@@ -7,7 +7,7 @@ use log::{info, trace};
 use utralib::*;
 use xous::arch::irq::IrqNumber;
 
-use crate::platform::{TimeoutExpiry, TimerRequest};
+use crate::platform::{RequestKind, TimeoutExpiry, TimerRequest};
 
 const MASTER_CLOCK_SPEED: u32 = 164000000 / 2;
 const TICKS_PER_MS: u32 = MASTER_CLOCK_SPEED / 128 / 1000;
@@ -32,7 +32,9 @@ fn handle_irq(_irq_no: usize, arg: *mut usize) {
     // enabled when this value is not None.
     let response = xtt.current_response.take();
     if let Some(response) = response {
-        xous::return_scalar(response.sender, response.kind as usize).ok();
+        if response.kind != RequestKind::Timer {
+            xous::return_scalar(response.sender, response.kind as usize).ok();
+        }
 
         // This is dangerous and may return an error if the queue is full.
         // Which is fine, because the queue is always recalculated any time a message arrives.
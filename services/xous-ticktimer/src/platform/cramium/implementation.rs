@@ -8,6 +8,7 @@ use susres::{RegManager, RegOrField, SuspendResume};
 use utralib::generated::*;
 use xous::definitions::MessageSender;
 
+use crate::RequestKind;
 use crate::TimeoutExpiry;
 use crate::TimerRequest;
 
@@ -51,7 +52,9 @@ fn handle_irq(_irq_no: usize, arg: *mut usize) {
     // enabled when this value is not None. Furthermore, the value is
     // only ever updated when interrupts are disabled.
     let response = xtt.current_response.take().unwrap();
-    xous::return_scalar(response.sender, response.kind as usize).ok();
+    if response.kind != RequestKind::Timer {
+        xous::return_scalar(response.sender, response.kind as usize).ok();
+    }
 
     // Disable the timer
     xtt.csr.wfo(utra::ticktimer::EV_ENABLE_ALARM, 0);
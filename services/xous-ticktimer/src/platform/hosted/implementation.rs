@@ -16,7 +16,7 @@ static LAST_RESPONDER: AtomicUsize = AtomicUsize::new(0);
 #[derive(Debug)]
 enum SleepComms {
     InterruptSleep,
-    StartSleep(xous::MessageSender, i64 /* ms */, u64 /* elapsed */),
+    StartSleep(TimerRequest, u64 /* elapsed */),
 }
 pub struct XousTickTimer {
     start: std::time::Instant,
@@ -46,8 +46,10 @@ impl XousTickTimer {
                         let response = current_response.take().unwrap();
                         #[cfg(feature = "debug-print")]
                         log::info!("Returning scalar to {}", response.sender);
-                        xous::return_scalar(response.sender, response.kind as usize)
-                            .expect("couldn't send response");
+                        if response.kind != RequestKind::Timer {
+                            xous::return_scalar(response.sender, response.kind as usize)
+                                .expect("couldn't send response");
+                        }
 
                         // This is dangerous and may panic if the queue is full.
                         xous::try_send_message(
@@ -71,27 +73,22 @@ impl XousTickTimer {
                         timeout = None;
                         time_remaining_sender.send(current_response.take()).unwrap()
                     }
-                    Ok(SleepComms::StartSleep(new_sender, expiry, elapsed)) => {
-                        let mut duration = expiry - (elapsed as i64);
+                    Ok(SleepComms::StartSleep(request, elapsed)) => {
+                        let mut duration = request.msec.to_i64() - (elapsed as i64);
                         if duration > 0 {
                             #[cfg(feature = "debug-print")]
-                            log::info!("Starting sleep for {} ms, returning to {}", duration, new_sender);
+                            log::info!("Starting sleep for {} ms, returning to {}", duration, request.sender);
                         } else {
                             #[cfg(feature = "debug-print")]
                             log::info!(
                                 "Clamping duration to 0 (was: {})m returning to {}",
                                 duration,
-                                new_sender
+                                request.sender
                             );
                             duration = 0;
                         }
                         timeout = Some(Duration::from_millis(duration.try_into().unwrap()));
-                        current_response = Some(TimerRequest {
-                            sender: new_sender,
-                            msec: expiry.into(),
-                            kind: RequestKind::Sleep,
-                            data: 0,
-                        });
+                        current_response = Some(request);
                     }
                 }
             }
@@ -123,9 +120,8 @@ impl XousTickTimer {
             self.elapsed_ms(),
             request.sender
         );
-        self.sleep_comms
-            .send(SleepComms::StartSleep(request.sender, request.msec.to_i64(), self.elapsed_ms()))
-            .unwrap();
+        let elapsed = self.elapsed_ms();
+        self.sleep_comms.send(SleepComms::StartSleep(request, elapsed)).unwrap();
     }
 
     #[allow(dead_code)]
@@ -79,6 +79,9 @@ impl From<usize> for TimeoutExpiry {
 pub enum RequestKind {
     Sleep = 0,
     Timeout = 1,
+    /// A timer created with `ScheduleTimer`. There is no blocked sender to respond to; instead
+    /// the expiry is delivered by the main loop when it processes `RecalculateSleep`.
+    Timer = 2,
 }
 
 #[derive(Eq)]
@@ -0,0 +1,157 @@
+use std::collections::{HashMap, VecDeque};
+
+use xous::{MessageSender, PID};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Access {
+    Read,
+    Write,
+}
+
+/// A reader-writer lock. Waiters are kept in a single queue in arrival order, and a reader
+/// may only skip the queue when it is empty. This keeps a steady stream of readers from
+/// starving a waiting writer, and a burst of writers from starving readers.
+#[derive(Debug, Default)]
+struct RwLock {
+    readers: usize,
+    writer: bool,
+    waiting: VecDeque<(MessageSender, Access)>,
+}
+
+impl RwLock {
+    fn is_free(&self) -> bool { self.readers == 0 && !self.writer }
+
+    /// Grant the lock to as many waiters at the front of the queue as possible: either one
+    /// writer, or every reader up to the next writer.
+    fn grant_waiting(&mut self) -> Vec<MessageSender> {
+        let mut granted = Vec::new();
+        while let Some((sender, access)) = self.waiting.front().copied() {
+            match access {
+                Access::Write if self.is_free() => self.writer = true,
+                Access::Read if !self.writer => self.readers += 1,
+                _ => break,
+            }
+            self.waiting.pop_front();
+            granted.push(sender);
+            if access == Access::Write {
+                break;
+            }
+        }
+        granted
+    }
+}
+
+#[derive(Debug, Default)]
+struct Semaphore {
+    permits: usize,
+    waiting: VecDeque<MessageSender>,
+}
+
+/// State for the RwLocks and semaphores that processes have asked us to manage. As with
+/// Mutexes, each process has its own namespace, so an ID is usually just the address of
+/// the object in the caller's memory.
+#[derive(Debug, Default)]
+pub(crate) struct SyncTable {
+    rwlocks: HashMap<Option<PID>, HashMap<usize, RwLock>>,
+    semaphores: HashMap<Option<PID>, HashMap<usize, Semaphore>>,
+}
+
+impl SyncTable {
+    pub fn new() -> Self { Self::default() }
+
+    fn rwlock(&mut self, pid: Option<PID>, id: usize) -> &mut RwLock {
+        self.rwlocks.entry(pid).or_default().entry(id).or_default()
+    }
+
+    fn semaphore(&mut self, pid: Option<PID>, id: usize) -> &mut Semaphore {
+        self.semaphores.entry(pid).or_default().entry(id).or_default()
+    }
+
+    /// Lock RwLock `id` for reading on behalf of `sender`. Returns `true` if the lock was
+    /// taken immediately, or `false` if `sender` was queued and should not be replied to yet.
+    pub fn lock_read(&mut self, pid: Option<PID>, id: usize, sender: MessageSender) -> bool {
+        let lock = self.rwlock(pid, id);
+        if !lock.writer && lock.waiting.is_empty() {
+            lock.readers += 1;
+            true
+        } else {
+            lock.waiting.push_back((sender, Access::Read));
+            false
+        }
+    }
+
+    /// Lock RwLock `id` for writing on behalf of `sender`. Returns `true` if the lock was
+    /// taken immediately, or `false` if `sender` was queued and should not be replied to yet.
+    pub fn lock_write(&mut self, pid: Option<PID>, id: usize, sender: MessageSender) -> bool {
+        let lock = self.rwlock(pid, id);
+        if lock.is_free() && lock.waiting.is_empty() {
+            lock.writer = true;
+            true
+        } else {
+            lock.waiting.push_back((sender, Access::Write));
+            false
+        }
+    }
+
+    /// Release one hold on RwLock `id`. Returns the waiters that now hold the lock, or `None`
+    /// if the lock was not held at all.
+    pub fn unlock(&mut self, pid: Option<PID>, id: usize) -> Option<Vec<MessageSender>> {
+        let lock = self.rwlock(pid, id);
+        if lock.writer {
+            lock.writer = false;
+        } else if lock.readers > 0 {
+            lock.readers -= 1;
+        } else {
+            return None;
+        }
+        Some(lock.grant_waiting())
+    }
+
+    /// Forget RwLock `id`, returning any threads that were still waiting for it
+    pub fn free_rwlock(&mut self, pid: Option<PID>, id: usize) -> Vec<MessageSender> {
+        self.rwlocks
+            .entry(pid)
+            .or_default()
+            .remove(&id)
+            .map(|lock| lock.waiting.into_iter().map(|(sender, _)| sender).collect())
+            .unwrap_or_default()
+    }
+
+    /// Take a permit from semaphore `id` on behalf of `sender`. Returns `true` if a permit
+    /// was available, or `false` if `sender` was queued and should not be replied to yet.
+    pub fn acquire(&mut self, pid: Option<PID>, id: usize, sender: MessageSender) -> bool {
+        let semaphore = self.semaphore(pid, id);
+        if semaphore.permits > 0 && semaphore.waiting.is_empty() {
+            semaphore.permits -= 1;
+            true
+        } else {
+            semaphore.waiting.push_back(sender);
+            false
+        }
+    }
+
+    /// Add `count` permits to semaphore `id`, returning the waiters that were given one
+    pub fn release(&mut self, pid: Option<PID>, id: usize, count: usize) -> Vec<MessageSender> {
+        let semaphore = self.semaphore(pid, id);
+        semaphore.permits = semaphore.permits.saturating_add(count);
+        let woken = semaphore.permits.min(semaphore.waiting.len());
+        semaphore.permits -= woken;
+        semaphore.waiting.drain(..woken).collect()
+    }
+
+    /// Forget semaphore `id`, returning any threads that were still waiting for it
+    pub fn free_semaphore(&mut self, pid: Option<PID>, id: usize) -> Vec<MessageSender> {
+        self.semaphores
+            .entry(pid)
+            .or_default()
+            .remove(&id)
+            .map(|semaphore| semaphore.waiting.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget everything belonging to `pid`, e.g. because the process exited
+    pub fn remove_process(&mut self, pid: Option<PID>) {
+        self.rwlocks.remove(&pid);
+        self.semaphores.remove(&pid);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use xous_api_ticktimer::api::TimerHook;
+
+use crate::{RequestKind, TimeoutExpiry, TimerRequest};
+
+/// Timer IDs double as the `MessageSender` of the timer's entry in the sleep heap. Keeping them
+/// below 2^24 leaves the PID field of the sender at 0, so they can never collide with a real
+/// message sender.
+const MAX_TIMER_ID: usize = (1 << 24) - 1;
+
+/// How long to wait before retrying a one-shot timer whose server's queue was full
+const ONE_SHOT_RETRY_MS: i64 = 10;
+
+/// The kernel calls used to deliver expiries. These are kept behind a trait so that the
+/// bookkeeping can be exercised without a running kernel.
+pub(crate) trait Delivery {
+    fn connect(&mut self, sid: xous::SID) -> Result<xous::CID, xous::Error>;
+    fn disconnect(&mut self, cid: xous::CID);
+    fn send(&mut self, cid: xous::CID, msg: xous::Message) -> Result<(), xous::Error>;
+}
+
+/// Delivers expiries through the kernel
+pub(crate) struct Kernel;
+
+impl Delivery for Kernel {
+    // `try_connect()` so that a bogus SID cannot leave the ticktimer blocked forever
+    fn connect(&mut self, sid: xous::SID) -> Result<xous::CID, xous::Error> { xous::try_connect(sid) }
+
+    fn disconnect(&mut self, cid: xous::CID) {
+        // Safety: the connection is only used for timers, and no timers refer to it any more
+        unsafe { xous::disconnect(cid).ok() };
+    }
+
+    fn send(&mut self, cid: xous::CID, msg: xous::Message) -> Result<(), xous::Error> {
+        xous::try_send_message(cid, msg).map(|_| ())
+    }
+}
+
+/// A timer created with `ScheduleTimer`
+struct Timer {
+    /// The process that created the timer, which is the only one allowed to cancel it
+    owner: Option<xous::PID>,
+    /// The server that expiries are delivered to
+    sid: [u32; 4],
+    /// The ID of the scalar message delivered to `sid`
+    opcode: u32,
+    period: i64,
+    one_shot: bool,
+    /// The time at which the timer next expires, in ms since boot
+    expiry: i64,
+}
+
+/// Bookkeeping for every timer created with `ScheduleTimer`. Pending expiries live in the
+/// regular sleep heap as `RequestKind::Timer` requests, but the table is authoritative: the main
+/// loop calls `sweep()` to deliver every timer that is due, whether or not the `RecalculateSleep`
+/// message for its expiry made it through the ticktimer's queue.
+pub(crate) struct TimerTable<D: Delivery = Kernel> {
+    delivery: D,
+    timers: HashMap<usize, Timer>,
+    /// Connections to the servers that expiries are delivered to, along with the number of
+    /// timers using each
+    connections: HashMap<[u32; 4], (xous::CID, usize)>,
+    next_id: usize,
+}
+
+impl TimerTable {
+    pub fn new() -> Self { Self::with_delivery(Kernel) }
+}
+
+impl<D: Delivery> TimerTable<D> {
+    pub fn with_delivery(delivery: D) -> Self {
+        TimerTable { delivery, timers: HashMap::new(), connections: HashMap::new(), next_id: 1 }
+    }
+
+    /// Create a new timer for `owner`, returning its ID along with the request that should be
+    /// added to the sleep heap.
+    pub fn add(
+        &mut self,
+        owner: Option<xous::PID>,
+        hook: &TimerHook,
+        now: i64,
+    ) -> Result<(usize, TimerRequest), xous::Error> {
+        if hook.period_ms == 0 {
+            return Err(xous::Error::InvalidLimit);
+        }
+        if self.timers.len() >= MAX_TIMER_ID {
+            return Err(xous::Error::OutOfMemory);
+        }
+        while self.timers.contains_key(&self.next_id) {
+            self.next_id = if self.next_id >= MAX_TIMER_ID { 1 } else { self.next_id + 1 };
+        }
+        let timer_id = self.next_id;
+        self.next_id = if self.next_id >= MAX_TIMER_ID { 1 } else { self.next_id + 1 };
+
+        let sid = [hook.sid.0, hook.sid.1, hook.sid.2, hook.sid.3];
+        if let Some((_, users)) = self.connections.get_mut(&sid) {
+            *users += 1;
+        } else {
+            let cid = self.delivery.connect(xous::SID::from_array(sid))?;
+            self.connections.insert(sid, (cid, 1));
+        }
+
+        let period = hook.period_ms as i64;
+        self.timers.insert(
+            timer_id,
+            Timer { owner, sid, opcode: hook.opcode, period, one_shot: hook.one_shot, expiry: now + period },
+        );
+        Ok((timer_id, Self::request(timer_id, period)))
+    }
+
+    fn request(timer_id: usize, msec: i64) -> TimerRequest {
+        TimerRequest {
+            msec: msec.into(),
+            sender: xous::MessageSender::from_usize(timer_id),
+            kind: RequestKind::Timer,
+            data: timer_id,
+        }
+    }
+
+    fn remove(&mut self, timer_id: usize) -> bool {
+        let Some(timer) = self.timers.remove(&timer_id) else {
+            return false;
+        };
+        if let Some((cid, users)) = self.connections.get_mut(&timer.sid) {
+            *users -= 1;
+            if *users == 0 {
+                self.delivery.disconnect(*cid);
+                self.connections.remove(&timer.sid);
+            }
+        }
+        true
+    }
+
+    /// Remove a timer, provided it belongs to `pid`. The caller is responsible for removing
+    /// any pending request from the sleep heap.
+    pub fn cancel(&mut self, timer_id: usize, pid: Option<xous::PID>) -> bool {
+        match self.timers.get(&timer_id) {
+            Some(timer) if timer.owner == pid => self.remove(timer_id),
+            _ => false,
+        }
+    }
+
+    /// Whether any timer is due at `now`
+    pub fn overdue(&self, now: i64) -> bool { self.timers.values().any(|timer| timer.expiry <= now) }
+
+    /// Deliver every timer that is due at `now`, and return the requests that should be added to
+    /// the sleep heap for their next expiries. Any other timer that has no request in the sleep
+    /// heap, because the `RecalculateSleep` message for it was dropped on a full queue, is re-armed
+    /// as well. Must be called with the sleep timer stopped.
+    pub fn sweep(
+        &mut self,
+        sleep_heap: &mut BTreeMap<TimeoutExpiry, TimerRequest>,
+        now: i64,
+    ) -> Vec<TimerRequest> {
+        let mut due: Vec<(i64, usize)> = self
+            .timers
+            .iter()
+            .filter(|(_, timer)| timer.expiry <= now)
+            .map(|(timer_id, timer)| (timer.expiry, *timer_id))
+            .collect();
+        due.sort_unstable();
+        sleep_heap.retain(|_, v| !(v.kind == RequestKind::Timer && due.iter().any(|(_, id)| *id == v.data)));
+        let armed: HashSet<usize> =
+            sleep_heap.values().filter(|v| v.kind == RequestKind::Timer).map(|v| v.data).collect();
+
+        for (_, timer_id) in due {
+            self.fire(timer_id, now);
+        }
+        self.timers
+            .iter()
+            .filter(|(timer_id, _)| !armed.contains(timer_id))
+            .map(|(timer_id, timer)| Self::request(*timer_id, timer.expiry - now))
+            .collect()
+    }
+
+    /// Deliver an expiry of `timer_id`, and work out when it next expires. Expiries that were
+    /// missed entirely, for example across a suspend, are coalesced.
+    fn fire(&mut self, timer_id: usize, now: i64) {
+        let Some(timer) = self.timers.get_mut(&timer_id) else {
+            return;
+        };
+        let Some((cid, _)) = self.connections.get(&timer.sid) else {
+            return;
+        };
+        let result =
+            self.delivery.send(*cid, xous::Message::new_scalar(timer.opcode as usize, timer_id, 0, 0, 0));
+        match result {
+            Ok(_) if timer.one_shot => {
+                self.remove(timer_id);
+            }
+            // Rather than block the ticktimer on a server that isn't keeping up, try a one-shot
+            // timer again shortly, and skip this expiry of a periodic timer
+            Err(xous::Error::ServerQueueFull) if timer.one_shot => timer.expiry = now + ONE_SHOT_RETRY_MS,
+            Ok(_) | Err(xous::Error::ServerQueueFull) => {
+                timer.expiry += timer.period;
+                if timer.expiry <= now {
+                    timer.expiry += (now - timer.expiry) / timer.period * timer.period + timer.period;
+                }
+            }
+            Err(e) => {
+                log::warn!("timer {} could not be delivered ({:?}), removing it", timer_id, e);
+                self.remove(timer_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::TimeoutExpiry;
+
+    /// Records connections and expiries instead of talking to the kernel
+    #[derive(Default)]
+    struct Recorder {
+        next_cid: xous::CID,
+        connected: Vec<xous::CID>,
+        disconnected: Vec<xous::CID>,
+        /// The opcode of every expiry that was delivered, in order
+        delivered: Vec<u32>,
+        /// The timer ID passed along with every expiry that was delivered, in order
+        timer_ids: Vec<usize>,
+        /// Report every server's queue as full
+        full: bool,
+    }
+
+    impl Delivery for Recorder {
+        fn connect(&mut self, _sid: xous::SID) -> Result<xous::CID, xous::Error> {
+            self.next_cid += 1;
+            self.connected.push(self.next_cid);
+            Ok(self.next_cid)
+        }
+
+        fn disconnect(&mut self, cid: xous::CID) { self.disconnected.push(cid); }
+
+        fn send(&mut self, _cid: xous::CID, msg: xous::Message) -> Result<(), xous::Error> {
+            let xous::Message::Scalar(scalar) = msg else {
+                return Err(xous::Error::InternalError);
+            };
+            if self.full {
+                return Err(xous::Error::ServerQueueFull);
+            }
+            self.delivered.push(scalar.id as u32);
+            self.timer_ids.push(scalar.arg1);
+            Ok(())
+        }
+    }
+
+    fn hook(opcode: u32, period_ms: u32, one_shot: bool) -> TimerHook {
+        TimerHook { sid: (1, 2, 3, 4), opcode, period_ms, one_shot, timer_id: None }
+    }
+
+    /// Add a request to the sleep heap the way `recalculate_sleep_offline()` does
+    fn schedule(heap: &mut BTreeMap<TimeoutExpiry, TimerRequest>, mut request: TimerRequest, now: i64) {
+        request.msec += now;
+        while heap.contains_key(&request.msec) {
+            request.msec += 1;
+        }
+        heap.insert(request.msec, request);
+    }
+
+    /// Fire everything in the sleep heap that expires at or before `until`, sweeping the table
+    /// and rescheduling timers as the main loop does
+    fn run(table: &mut TimerTable<Recorder>, heap: &mut BTreeMap<TimeoutExpiry, TimerRequest>, until: i64) {
+        while heap.first_key_value().is_some_and(|(msec, _)| msec.to_i64() <= until) {
+            let (msec, _) = heap.pop_first().unwrap();
+            for next in table.sweep(heap, msec.to_i64()) {
+                schedule(heap, next, msec.to_i64());
+            }
+        }
+    }
+
+    #[test]
+    fn timers_fire_in_expiry_order() {
+        let mut table = TimerTable::with_delivery(Recorder::default());
+        let mut heap = BTreeMap::new();
+        for (opcode, period, one_shot) in [(30, 30, true), (10, 10, true), (20, 20, true), (15, 15, false)] {
+            let (_, request) = table.add(None, &hook(opcode, period, one_shot), 0).unwrap();
+            schedule(&mut heap, request, 0);
+        }
+        // All four timers deliver to one server
+        assert_eq!(table.delivery.connected, [1]);
+
+        run(&mut table, &mut heap, 45);
+        assert_eq!(table.delivery.delivered, [10, 15, 20, 30, 15, 15]);
+        // Only the periodic timer is left, still waiting for its expiry at 60ms
+        assert_eq!(table.timers.len(), 1);
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.first_key_value().unwrap().0.to_i64(), 60);
+    }
+
+    #[test]
+    fn cancelling_before_expiry_stops_delivery() {
+        let mut table = TimerTable::with_delivery(Recorder::default());
+        let mut heap = BTreeMap::new();
+        let owner = xous::PID::new(2);
+        let (early, request) = table.add(owner, &hook(1, 10, true), 0).unwrap();
+        schedule(&mut heap, request, 0);
+        let (periodic, request) = table.add(owner, &hook(2, 25, false), 0).unwrap();
+        schedule(&mut heap, request, 0);
+
+        // Only the process that created a timer may cancel it
+        assert!(!table.cancel(early, xous::PID::new(3)));
+        assert!(!table.cancel(early, None));
+
+        assert!(table.cancel(early, owner));
+        heap.retain(|_, v| !(v.kind == RequestKind::Timer && v.data == early));
+        run(&mut table, &mut heap, 60);
+        assert_eq!(table.delivery.delivered, [2, 2]);
+        // The other timer still uses the connection
+        assert!(table.delivery.disconnected.is_empty());
+
+        assert!(table.cancel(periodic, owner));
+        heap.retain(|_, v| !(v.kind == RequestKind::Timer && v.data == periodic));
+        run(&mut table, &mut heap, 1000);
+        assert_eq!(table.delivery.delivered, [2, 2]);
+        assert!(heap.is_empty());
+        assert_eq!(table.delivery.disconnected, [1]);
+
+        // An expiry that had already been taken off the heap when the timer was cancelled is
+        // not delivered by the next sweep
+        let (late, _) = table.add(owner, &hook(3, 10, false), 0).unwrap();
+        assert!(table.cancel(late, owner));
+        assert!(table.sweep(&mut heap, 10).is_empty());
+        assert_eq!(table.delivery.delivered, [2, 2]);
+    }
+
+    #[test]
+    fn cancelling_a_fired_timer_does_nothing() {
+        let mut table = TimerTable::with_delivery(Recorder::default());
+        let mut heap = BTreeMap::new();
+        let owner = xous::PID::new(2);
+        let (timer_id, request) = table.add(owner, &hook(1, 10, true), 0).unwrap();
+        schedule(&mut heap, request, 0);
+        run(&mut table, &mut heap, 10);
+        assert_eq!(table.delivery.delivered, [1]);
+        assert_eq!(table.delivery.disconnected, [1]);
+
+        assert!(!table.cancel(timer_id, owner));
+        assert!(table.sweep(&mut heap, 20).is_empty());
+        assert_eq!(table.delivery.delivered, [1]);
+        assert_eq!(table.delivery.disconnected, [1]);
+
+        // The ID is not reused straight away, so a stale cancel cannot hit a newer timer
+        let (next_id, _) = table.add(owner, &hook(2, 10, true), 20).unwrap();
+        assert_ne!(next_id, timer_id);
+        assert!(!table.cancel(timer_id, owner));
+        assert_eq!(table.timers.len(), 1);
+    }
+
+    #[test]
+    fn expiries_lost_from_the_heap_are_recovered() {
+        let mut table = TimerTable::with_delivery(Recorder::default());
+        let mut heap = BTreeMap::new();
+        let (periodic, request) = table.add(None, &hook(1, 10, false), 0).unwrap();
+        schedule(&mut heap, request, 0);
+        let (one_shot, request) = table.add(None, &hook(2, 15, true), 0).unwrap();
+        schedule(&mut heap, request, 0);
+
+        // Both expiries fire, but neither `RecalculateSleep` makes it through
+        heap.pop_first().unwrap();
+        heap.pop_first().unwrap();
+        assert!(!table.overdue(9));
+        assert!(table.overdue(20));
+
+        // The next sweep delivers both, and re-arms the periodic timer
+        let requests = table.sweep(&mut heap, 20);
+        assert_eq!(table.delivery.delivered, [1, 2]);
+        assert_eq!(table.delivery.timer_ids, [periodic, one_shot]);
+        assert_eq!(table.timers.len(), 1);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].data, periodic);
+        assert_eq!(requests[0].msec.to_i64(), 10);
+
+        // A timer that is not yet due but has no entry in the heap is re-armed without firing
+        let requests = table.sweep(&mut heap, 25);
+        assert_eq!(table.delivery.delivered, [1, 2]);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].msec.to_i64(), 5);
+        schedule(&mut heap, requests.into_iter().next().unwrap(), 25);
+        assert!(table.sweep(&mut heap, 25).is_empty());
+
+        run(&mut table, &mut heap, 30);
+        assert_eq!(table.delivery.delivered, [1, 2, 1]);
+    }
+
+    #[test]
+    fn full_queues_retry_one_shots_and_skip_periodic_expiries() {
+        let mut table = TimerTable::with_delivery(Recorder::default());
+        let mut heap = BTreeMap::new();
+        for (opcode, one_shot) in [(1, false), (2, true)] {
+            let (_, request) = table.add(None, &hook(opcode, 100, one_shot), 0).unwrap();
+            schedule(&mut heap, request, 0);
+        }
+
+        table.delivery.full = true;
+        run(&mut table, &mut heap, 100);
+        assert!(table.delivery.delivered.is_empty());
+        // Neither timer is dropped: the one-shot is retried shortly, the periodic timer waits
+        // for its next period
+        assert_eq!(table.timers.len(), 2);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.first_key_value().unwrap().0.to_i64(), 100 + ONE_SHOT_RETRY_MS);
+
+        table.delivery.full = false;
+        run(&mut table, &mut heap, 200);
+        assert_eq!(table.delivery.delivered, [2, 1]);
+        assert_eq!(table.timers.len(), 1);
+        assert!(table.delivery.disconnected.is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+//! Read-only decoding of PDDB images.
+//!
+//! Region sizes, strides, the `PhysPage` bitfield and the decoders for the static crypto data, page table
+//! entries, dictionaries and key descriptors all come from the backend's own `layout.rs`. What is left
+//! here is the walk over those records, which mirrors the `PddbOs` and cache methods named on each step.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::error::Error;
+
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
+use aes::Aes256;
+use aes_gcm_siv::aead::{Aead, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use sha2::{Digest, Sha512_256};
+
+use crate::keys::BasisKeys;
+use crate::layout::{
+    record_name, DictRecord, KeyRecord, PhysPage, PteRecord, ScdRecord, SpaceState, DICT_MAXCOUNT, DICT_VSIZE,
+    DK_PER_VPAGE, DK_STRIDE, FASTSPACE_PAGES, FSCB_PAGES, JOURNAL_LEN, KCOM_CT_LEN, KCOM_LEN, KCOM_NONCE_LEN,
+    KEY_MAXCOUNT, LARGE_POOL_START, MBBB_PAGES, NONCE_LEN, PAGE_SIZE, PDDB_FAST_SPACE_SYSTEM_BASIS, PDDB_MAGIC,
+    PDDB_VERSION, PTE_LEN, TAG_LEN, VPAGE_SIZE,
+};
+use crate::murmur3::murmur3_32;
+
+/// `StaticCryptoData` fills exactly one page
+const SCD_PAGES: usize = 1;
+// api.rs; the rest of the layout is shared with the backend through `crate::layout`
+const VERSION_SEPARATOR: char = '\u{1}';
+
+fn le_u32(bytes: &[u8]) -> u32 { u32::from_le_bytes(bytes[..4].try_into().unwrap()) }
+
+fn is_blank(block: &[u8]) -> bool { block.iter().all(|&b| b == 0xFF) }
+
+/// A PDDB image: the page table, static crypto data, MBBB, FSCB and data regions, back to back.
+/// Region sizes are derived from the length of the image, the same way `PddbOs::new()` does.
+pub struct PddbImage {
+    raw: Vec<u8>,
+    dna: u64,
+    scd_base: usize,
+    mbbb_base: usize,
+    fscb_base: usize,
+    data_base: usize,
+}
+
+impl PddbImage {
+    pub fn new(raw: Vec<u8>, dna: u64) -> Result<PddbImage, Box<dyn Error>> {
+        if raw.len() % PAGE_SIZE != 0 {
+            return Err(format!("image length 0x{:x} is not a multiple of the page size", raw.len()).into());
+        }
+        let pt_len = raw.len() / PAGE_SIZE * PTE_LEN;
+        let scd_base = (pt_len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let mbbb_base = scd_base + SCD_PAGES * PAGE_SIZE;
+        let fscb_base = mbbb_base + MBBB_PAGES * PAGE_SIZE;
+        let data_base = fscb_base + FSCB_PAGES * PAGE_SIZE;
+        if data_base >= raw.len() {
+            return Err(format!("image of 0x{:x} bytes is too small to hold a PDDB", raw.len()).into());
+        }
+        log::debug!(
+            "pt: 0x0, scd: 0x{:x}, mbbb: 0x{:x}, fscb: 0x{:x}, data: 0x{:x}",
+            scd_base,
+            mbbb_base,
+            fscb_base,
+            data_base
+        );
+        Ok(PddbImage { raw, dna, scd_base, mbbb_base, fscb_base, data_base })
+    }
+
+    /// The `StaticCryptoData` page, which holds the wrapped system basis keys and the salt
+    pub fn static_crypto_data(&self) -> ScdRecord<'_> {
+        ScdRecord::decode(&self.raw[self.scd_base..self.scd_base + PAGE_SIZE])
+    }
+
+    fn data_page(&self, page_number: u32) -> Option<&[u8]> {
+        let start = self.data_base + page_number as usize * PAGE_SIZE;
+        self.raw.get(start..start + PAGE_SIZE)
+    }
+
+    /// Mirrors `PddbOs::data_aad()`
+    fn aad(&self, name: &str) -> Vec<u8> {
+        let mut aad = Vec::new();
+        aad.extend_from_slice(name.as_bytes());
+        aad.extend_from_slice(&PDDB_VERSION.to_le_bytes());
+        aad.extend_from_slice(&self.dna.to_le_bytes());
+        aad
+    }
+
+    /// The first page of the MBBB that is in use, mirroring `PddbOs::mbbb_retrieve()`
+    fn mbbb_page(&self) -> Option<&[u8]> {
+        self.raw[self.mbbb_base..self.fscb_base].chunks(PAGE_SIZE).find(|page| !is_blank(&page[..16]))
+    }
+
+    /// Mirrors `PddbOs::data_decrypt_page()`: the plaintext still has the journal number at the front
+    fn decrypt_page(&self, cipher: &Aes256GcmSiv, aad: &[u8], page_number: u32) -> Option<Vec<u8>> {
+        let page = self.data_page(page_number)?;
+        cipher.decrypt(Nonce::from_slice(&page[..NONCE_LEN]), Payload { msg: &page[NONCE_LEN..], aad }).ok()
+    }
+
+    /// Mirrors `PddbOs::data_decrypt_page_with_commit()`, used for the basis root
+    fn decrypt_page_with_commit(&self, data_key: &[u8; 32], aad: &[u8], page_number: u32) -> Option<Vec<u8>> {
+        let page = self.data_page(page_number)?;
+        let nonce = &page[..NONCE_LEN];
+        let ct = &page[NONCE_LEN..NONCE_LEN + KCOM_CT_LEN];
+        let kcom_nonce = &page[NONCE_LEN + KCOM_CT_LEN..NONCE_LEN + KCOM_CT_LEN + KCOM_NONCE_LEN];
+        let kcom_stored = &page
+            [NONCE_LEN + KCOM_CT_LEN + KCOM_NONCE_LEN..NONCE_LEN + KCOM_CT_LEN + KCOM_NONCE_LEN + KCOM_LEN];
+        let mac = &page[PAGE_SIZE - TAG_LEN..];
+
+        let (k_enc, k_com) = kcom_func(data_key, kcom_nonce);
+        if k_com[..] != kcom_stored[..] {
+            log::debug!("key commitment failed on page {}", page_number);
+            return None;
+        }
+        let mut ct_plus_mac = ct.to_vec();
+        ct_plus_mac.extend_from_slice(mac);
+        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&k_enc));
+        cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: &ct_plus_mac, aad }).ok()
+    }
+
+    /// Build the virtual to physical map of the basis that owns `keys`, mirroring
+    /// `PddbOs::pt_scan_key()`. Conflicting entries for the same virtual page go to the page with the
+    /// newest journal number.
+    fn pt_scan(&self, name: &str, keys: &BasisKeys) -> HashMap<u64, u32> {
+        let pt_cipher = Aes256::new(GenericArray::from_slice(&keys.pt));
+        let data_cipher = Aes256GcmSiv::new(GenericArray::from_slice(&keys.data));
+        let aad = self.aad(name);
+        let journal = |vaddr: u64, page_number: u32| -> Option<u32> {
+            let data = if vaddr == VPAGE_SIZE as u64 {
+                self.decrypt_page_with_commit(&keys.data, &aad, page_number)
+            } else {
+                self.decrypt_page(&data_cipher, &aad, page_number)
+            };
+            data.map(|d| le_u32(&d))
+        };
+
+        let pt_len = self.raw.len() / PAGE_SIZE * PTE_LEN;
+        let mut map = HashMap::<u64, u32>::new();
+        for (page_index, pt_page) in self.raw[..pt_len].chunks(PAGE_SIZE).enumerate() {
+            // a page of the page table that is being rewritten is stashed in the MBBB
+            let page = if is_blank(&pt_page[..16]) { self.mbbb_page().unwrap_or(pt_page) } else { pt_page };
+            for (index, candidate) in page.chunks(PTE_LEN).enumerate() {
+                let mut block = GenericArray::clone_from_slice(candidate);
+                pt_cipher.decrypt_block(&mut block);
+                let Some(pte) = PteRecord::decode(&block) else {
+                    continue;
+                };
+                let vaddr = pte.vaddr();
+                let page_number = (page_index * PAGE_SIZE / PTE_LEN + index) as u32;
+                if let Some(&prev) = map.get(&vaddr) {
+                    match (journal(vaddr, prev), journal(vaddr, page_number)) {
+                        (Some(prev_j), Some(new_j)) if new_j > prev_j => {
+                            map.insert(vaddr, page_number);
+                        }
+                        (Some(prev_j), Some(new_j)) if new_j == prev_j => {
+                            log::warn!(
+                                "{}: pages {} and {} both hold v{:x} at journal {}",
+                                name,
+                                prev,
+                                page_number,
+                                vaddr,
+                                new_j
+                            );
+                        }
+                        (None, Some(_)) => {
+                            map.insert(vaddr, page_number);
+                        }
+                        _ => {}
+                    }
+                } else {
+                    map.insert(vaddr, page_number);
+                }
+            }
+        }
+        map
+    }
+
+    /// Mount the basis `name` with `keys`. Fails if the keys don't open any basis root in the image.
+    pub fn open_basis(&self, name: &str, keys: &BasisKeys) -> Result<Basis<'_>, Box<dyn Error>> {
+        let v2p = self.pt_scan(name, keys);
+        let aad = self.aad(name);
+        let root_page = *v2p
+            .get(&(VPAGE_SIZE as u64))
+            .ok_or_else(|| format!("basis {}: no root page found; wrong key or password?", name))?;
+        let root = self
+            .decrypt_page_with_commit(&keys.data, &aad, root_page)
+            .ok_or_else(|| format!("basis {}: root page {} does not decrypt", name, root_page))?;
+        // `BasisRoot` from basis.rs, after the journal
+        let root = &root[JOURNAL_LEN..];
+        if root[..4] != PDDB_MAGIC {
+            return Err(format!("basis {}: root has bad magic {:x?}", name, &root[..4]).into());
+        }
+        let version = le_u32(&root[4..]);
+        if version != PDDB_VERSION {
+            return Err(format!("basis {}: version 0x{:x} is not supported", name, version).into());
+        }
+        let stored_name = String::from_utf8_lossy(record_name(&root[16..]));
+        if stored_name != name {
+            log::warn!("basis {} calls itself {}", name, stored_name);
+        }
+        Ok(Basis {
+            image: self,
+            name: name.to_string(),
+            cipher: Aes256GcmSiv::new(GenericArray::from_slice(&keys.data)),
+            aad,
+            v2p,
+            age: le_u32(&root[8..]),
+            num_dicts: le_u32(&root[12..]),
+        })
+    }
+
+    /// Read back the FSCB using the system basis keys, mirroring `PddbOs::fast_space_read()`. Returns the
+    /// newest record of each page that the FSCB knows about.
+    pub fn fast_space(&self, system: &BasisKeys) -> Result<HashMap<u32, PhysPage>, Box<dyn Error>> {
+        let fscb = &self.raw[self.fscb_base..self.data_base];
+        let mut records = HashMap::<u32, PhysPage>::new();
+        let mut update_pages = Vec::new();
+        let mut found_fastspace = false;
+        for (index, page) in fscb.chunks(PAGE_SIZE).enumerate() {
+            if is_blank(&page[..32]) {
+                continue;
+            } else if is_blank(&page[..16]) {
+                update_pages.push(page);
+            } else if !found_fastspace {
+                found_fastspace = true;
+                let start = index * PAGE_SIZE;
+                let record = fscb
+                    .get(start..start + FASTSPACE_PAGES * PAGE_SIZE)
+                    .ok_or("FastSpace record runs off the end of the FSCB")?;
+                let mut aad = Vec::new();
+                aad.extend_from_slice(PDDB_FAST_SPACE_SYSTEM_BASIS.as_bytes());
+                aad.extend_from_slice(&PDDB_VERSION.to_le_bytes());
+                aad.extend_from_slice(&self.dna.to_le_bytes());
+                let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&system.data));
+                let free_pool = cipher
+                    .decrypt(
+                        Nonce::from_slice(&record[..NONCE_LEN]),
+                        Payload { msg: &record[NONCE_LEN..], aad: &aad },
+                    )
+                    .map_err(|_| format!("FastSpace record at FSCB page {} does not decrypt", index))?;
+                for raw in free_pool.chunks_exact(4) {
+                    let pp = PhysPage(le_u32(raw));
+                    if pp.valid() && pp.space_state() == SpaceState::Free {
+                        records.insert(pp.page_number(), pp);
+                    }
+                }
+            }
+        }
+        if !found_fastspace {
+            return Err("no FastSpace record in the FSCB".into());
+        }
+
+        // `SpaceUpdate` records from fastspace.rs, applied in journal order
+        let cipher = Aes256::new(GenericArray::from_slice(&system.pt));
+        for page in update_pages {
+            for ct in page[16..].chunks_exact(16) {
+                if is_blank(ct) {
+                    break;
+                }
+                let mut block = GenericArray::clone_from_slice(ct);
+                cipher.decrypt_block(&mut block);
+                let seed = u32::from_be_bytes(block[4..8].try_into().unwrap());
+                if murmur3_32(&block[..12], seed) != le_u32(&block[12..16]) {
+                    log::warn!("possibly corrupted FSCB update record: {:x?}", block);
+                    continue;
+                }
+                let pp = PhysPage(le_u32(&block[8..12]));
+                if !pp.valid() {
+                    continue;
+                }
+                match records.get(&pp.page_number()) {
+                    Some(prev) if pp.journal() < prev.journal() => {}
+                    _ => {
+                        records.insert(pp.page_number(), pp);
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Derive a key commitment, mirroring `kcom_func()` in hw.rs. Returns (k_enc, k_com).
+fn kcom_func(key: &[u8; 32], nonce_com: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut h_enc = Sha512_256::new();
+    h_enc.update(key);
+    h_enc.update([0x43, 0x6f, 0x6, 0xd6, 0xd, 0x69, 0x74, 0x01, 0x01]);
+    h_enc.update(nonce_com);
+    let mut h_com = Sha512_256::new();
+    h_com.update(key);
+    h_com.update([0x43, 0x6f, 0x6, 0xd6, 0xd, 0x69, 0x74, 0x01, 0x02]);
+    h_com.update(nonce_com);
+    (h_enc.finalize().into(), h_com.finalize().into())
+}
+
+/// A mounted basis
+pub struct Basis<'a> {
+    image: &'a PddbImage,
+    pub name: String,
+    cipher: Aes256GcmSiv,
+    aad: Vec<u8>,
+    v2p: HashMap<u64, u32>,
+    pub age: u32,
+    pub num_dicts: u32,
+}
+
+/// A dictionary, as stored in the `Dictionary` record at the start of its virtual region
+pub struct Dict {
+    pub index: u32,
+    pub name: String,
+    pub age: u32,
+    pub num_keys: u32,
+    /// Number of prior versions kept of each key, from the `DictFlags`
+    pub versions: u8,
+    pub keys: BTreeMap<String, Key>,
+    /// Problems found while walking the key descriptors
+    pub errors: Vec<String>,
+}
+
+/// A key, as described by its `KeyDescriptor`
+pub struct Key {
+    pub start: u64,
+    pub len: u64,
+    pub reserved: u64,
+    pub age: u32,
+    pub unresolved: bool,
+}
+impl Key {
+    pub fn is_large(&self) -> bool { self.start >= LARGE_POOL_START }
+}
+
+impl<'a> Basis<'a> {
+    fn page(&self, vaddr: u64) -> Option<Vec<u8>> {
+        self.v2p.get(&vaddr).and_then(|&pp| self.image.decrypt_page(&self.cipher, &self.aad, pp))
+    }
+
+    /// Physical pages mapped by this basis, by virtual address
+    pub fn mapped_pages(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.v2p.iter().map(|(&v, &p)| (v, p))
+    }
+
+    /// Walk the dictionary records, mirroring the dict scan in `BasisCacheEntry::dict_fill()`
+    pub fn dicts(&self) -> Vec<Dict> {
+        let mut dicts = Vec::new();
+        for index in 1..=DICT_MAXCOUNT as u32 {
+            if dicts.len() as u32 >= self.num_dicts {
+                break;
+            }
+            let Some(page) = self.page(index as u64 * DICT_VSIZE) else {
+                continue;
+            };
+            let record = DictRecord::decode(&page[JOURNAL_LEN..]);
+            if !record.flags.valid() {
+                continue;
+            }
+            let Ok(name) = std::str::from_utf8(record.name) else {
+                log::warn!("{}: dict {} has a name that is not utf-8", self.name, index);
+                continue;
+            };
+            let mut dict = Dict {
+                index,
+                name: name.to_string(),
+                age: record.age,
+                num_keys: record.num_keys,
+                versions: record.flags.versions(),
+                keys: BTreeMap::new(),
+                errors: Vec::new(),
+            };
+            self.fill_keys(&mut dict);
+            dicts.push(dict);
+        }
+        dicts
+    }
+
+    /// Walk the key descriptors of `dict`, mirroring `DictCacheEntry::fill()`
+    fn fill_keys(&self, dict: &mut Dict) {
+        let mut entry = 1;
+        let mut cached: Option<(u64, Option<Vec<u8>>)> = None;
+        while entry < KEY_MAXCOUNT && (dict.keys.len() as u32) < dict.num_keys {
+            let vaddr = dict.index as u64 * DICT_VSIZE + (entry / DK_PER_VPAGE) as u64 * VPAGE_SIZE as u64;
+            if cached.as_ref().map(|(v, _)| *v) != Some(vaddr) {
+                cached = Some((vaddr, self.page(vaddr)));
+            }
+            let Some(page) = cached.as_ref().and_then(|(_, p)| p.as_ref()) else {
+                entry += DK_PER_VPAGE;
+                continue;
+            };
+            let start = JOURNAL_LEN + (entry % DK_PER_VPAGE) * DK_STRIDE;
+            let desc = KeyRecord::decode(&page[start..start + DK_STRIDE]);
+            if desc.valid() {
+                match std::str::from_utf8(desc.name) {
+                    Ok(name) => {
+                        dict.keys.insert(
+                            name.to_string(),
+                            Key {
+                                start: desc.start,
+                                len: desc.len,
+                                reserved: desc.reserved,
+                                age: desc.age,
+                                unresolved: desc.unresolved(),
+                            },
+                        );
+                    }
+                    Err(_) => {
+                        dict.errors.push(format!("key descriptor {} has a name that is not utf-8", entry))
+                    }
+                }
+            }
+            entry += 1;
+        }
+        if (dict.keys.len() as u32) < dict.num_keys {
+            dict.errors.push(format!("expected {} keys, only found {}", dict.num_keys, dict.keys.len()));
+        }
+    }
+
+    /// Read the contents of `key`. Fails if any page of the key is missing or doesn't decrypt.
+    pub fn read_key(&self, key: &Key) -> Result<Vec<u8>, String> {
+        let mut data = Vec::with_capacity(key.len as usize);
+        let end = key.start + key.len;
+        let mut addr = key.start;
+        while addr < end {
+            let vpage = addr / VPAGE_SIZE as u64 * VPAGE_SIZE as u64;
+            let page = self.page(vpage).ok_or_else(|| format!("page v{:x} is missing or corrupt", vpage))?;
+            let offset = (addr - vpage) as usize;
+            let take = (VPAGE_SIZE - offset).min((end - addr) as usize);
+            data.extend_from_slice(&page[JOURNAL_LEN + offset..JOURNAL_LEN + offset + take]);
+            addr += take as u64;
+        }
+        Ok(data)
+    }
+}
+
+/// Splits a key name into the name of the key it keeps a prior version of, and the version number
+pub fn version_of(key: &str) -> Option<(&str, &str)> { key.split_once(VERSION_SEPARATOR) }
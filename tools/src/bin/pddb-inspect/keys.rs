@@ -0,0 +1,240 @@
+//! Recovery of basis keys: from the key export written next to hosted-mode images, from a
+//! plaintext KEYROM plus the unlock PIN, or from a basis name and password.
+
+use std::convert::TryInto;
+use std::error::Error;
+
+use aes_gcm_siv::aead::{Aead, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, KeyInit, Nonce};
+use aes_kw::KekAes256;
+use sha2::{Digest, Sha256, Sha512_256};
+
+use crate::bcrypt::bcrypt;
+use crate::bip39::BIP39_TABLE;
+use crate::layout::{ScdRecord, SCD_VERSION};
+
+// api.rs
+const BCRYPT_COST: u32 = 7;
+const BASIS_NAME_LEN: usize = 64;
+pub const PDDB_DEFAULT_SYSTEM_BASIS: &str = ".System";
+// root-keys: KEYROM word offsets of the user key, the pepper and the rollback counter
+const KEYROM_USER_KEY: usize = 40;
+const KEYROM_PEPPER: usize = 248;
+const KEYROM_ROLLBACK: usize = 254;
+// root-keys backups.rs
+const BACKUP_AAD: &str = "PDDB backup v0.1.0";
+const BACKUP_VERSION_CHECKSUMS: u32 = 0x1_0001;
+/// Size of the `BackupHeader`, including the `op` field and alignment padding
+const BACKUP_HEADER_LEN: usize = 152;
+const BACKUP_KEYROM_LEN: usize = 1024;
+const BACKUP_PT_LEN: usize = BACKUP_HEADER_LEN + BACKUP_KEYROM_LEN + 64;
+/// The PDDB image starts on the first page after the backup header
+pub const BACKUP_PDDB_OFFSET: usize = 4096;
+
+/// The page table and data keys of a basis, as in the backend's `BasisKeys`
+#[derive(Clone)]
+pub struct BasisKeys {
+    pub pt: [u8; 32],
+    pub data: [u8; 32],
+}
+
+/// Read the key export that hosted mode writes next to its image (see `EmuStorage::dump_keys()`):
+/// a u32 count, then per basis a 64-byte NUL padded name, the data key and the page table key.
+pub fn read_hosted_keys(export: &[u8]) -> Result<Vec<(String, BasisKeys)>, Box<dyn Error>> {
+    const ENTRY_LEN: usize = BASIS_NAME_LEN + 32 + 32;
+    let count =
+        u32::from_le_bytes(export.get(..4).ok_or("key export is empty")?.try_into().unwrap()) as usize;
+    let mut keys = Vec::new();
+    for i in 0..count {
+        let entry = export
+            .get(4 + i * ENTRY_LEN..4 + (i + 1) * ENTRY_LEN)
+            .ok_or_else(|| format!("key export is truncated at entry {}", i))?;
+        let name_len = entry[..BASIS_NAME_LEN].iter().position(|&b| b == 0).unwrap_or(BASIS_NAME_LEN);
+        let name = String::from_utf8_lossy(&entry[..name_len]).to_string();
+        let data = entry[BASIS_NAME_LEN..BASIS_NAME_LEN + 32].try_into().unwrap();
+        let pt = entry[BASIS_NAME_LEN + 32..].try_into().unwrap();
+        keys.push((name, BasisKeys { pt, data }));
+    }
+    Ok(keys)
+}
+
+/// Fetch `len` bytes of the KEYROM starting at word `index`, in the byte order root-keys uses for keys
+fn keyrom_key(keyrom: &[u8], index: usize, len: usize) -> Vec<u8> {
+    keyrom[index * 4..(index * 4) + len]
+        .chunks_exact(4)
+        .flat_map(|w| u32::from_be_bytes(w.try_into().unwrap()).to_le_bytes())
+        .collect()
+}
+
+/// Recover the system basis keys from a plaintext KEYROM and the unlock PIN. This undoes the
+/// key wrapping that root-keys applies to the `StaticCryptoData` keys.
+pub fn system_keys(keyrom: &[u8], scd: &ScdRecord, pin: &str) -> Result<BasisKeys, Box<dyn Error>> {
+    if scd.version != SCD_VERSION {
+        return Err(
+            format!("static crypto data has version {}, expected {}", scd.version, SCD_VERSION).into()
+        );
+    }
+    let user_key_enc = keyrom_key(keyrom, KEYROM_USER_KEY, 32);
+    let mut pepper = keyrom_key(keyrom, KEYROM_PEPPER, 16);
+    pepper[0] ^= 1; // the "boot" password type is encoded into the pepper
+
+    let mut hashed_pw = [0u8; 24];
+    bcrypt(BCRYPT_COST, &pepper, pin, &mut hashed_pw);
+    let user_pw = Sha512_256::digest(hashed_pw);
+    let mut user_key = [0u8; 32];
+    for ((dst, &enc), &pw) in user_key.iter_mut().zip(user_key_enc.iter()).zip(user_pw.iter()) {
+        *dst = enc ^ pw;
+    }
+    let rollback =
+        u32::from_le_bytes(keyrom[KEYROM_ROLLBACK * 4..KEYROM_ROLLBACK * 4 + 4].try_into().unwrap());
+    for _ in 0..255u32.saturating_sub(rollback) {
+        user_key = Sha512_256::digest(user_key).into();
+    }
+
+    let kek = KekAes256::from(user_key);
+    let unwrap = |wrapped: &[u8]| -> Result<[u8; 32], Box<dyn Error>> {
+        let key = kek
+            .unwrap_with_padding_vec(wrapped)
+            .map_err(|_| "system basis key does not unwrap; wrong PIN?")?;
+        key.as_slice().try_into().map_err(|_| "unwrapped system basis key has the wrong length".into())
+    };
+    Ok(BasisKeys { pt: unwrap(scd.system_key_pt)?, data: unwrap(scd.system_key)? })
+}
+
+/// Derive the keys of a secret basis from its name and password, mirroring `PddbOs::basis_derive_key()`
+pub fn derive_basis_keys(scd: &ScdRecord, name: &str, password: &str) -> BasisKeys {
+    let salt_base = scd.salt_base;
+    let mut bname_copy = [0u8; BASIS_NAME_LEN];
+    for (src, dst) in name.bytes().zip(bname_copy.iter_mut()) {
+        *dst = src;
+    }
+    let mut plaintext_pw = [0u8; 73];
+    for (src, dst) in password.bytes().zip(plaintext_pw[..72].iter_mut()) {
+        *dst = src;
+    }
+    let mut hasher = Sha512_256::new();
+    hasher.update(&salt_base[32..]);
+    hasher.update(bname_copy);
+    hasher.update(plaintext_pw);
+    let salt = hasher.finalize();
+
+    let mut hashed_password = [0u8; 24];
+    bcrypt(BCRYPT_COST, &salt[..16], password, &mut hashed_password);
+    let hk = hkdf::Hkdf::<Sha256>::new(Some(&salt_base[..32]), &hashed_password);
+    let mut keys = BasisKeys { pt: [0u8; 32], data: [0u8; 32] };
+    hk.expand(b"pddb page table key", &mut keys.pt).expect("invalid length specified for HKDF");
+    hk.expand(b"pddb data key", &mut keys.data).expect("invalid length specified for HKDF");
+    keys
+}
+
+/// Decode a BIP-39 phrase into the key it encodes, checking its checksum
+pub fn bip39_to_key(phrase: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if ![12, 15, 18, 21, 24].contains(&words.len()) {
+        return Err("BIP-39 phrase has the wrong number of words".into());
+    }
+    let mut bits = Vec::<bool>::new();
+    for word in words {
+        let index = BIP39_TABLE
+            .iter()
+            .position(|&w| w == word)
+            .ok_or_else(|| format!("{} is not a BIP-39 word", word))?;
+        bits.extend((0..11).rev().map(|b| index & (1 << b) != 0));
+    }
+    let checksum_bits = bits.len() / 33;
+    let key: Vec<u8> = bits[..bits.len() - checksum_bits]
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &b| (acc << 1) | b as u8))
+        .collect();
+    let digest = Sha256::digest(&key);
+    let checksum_ok = bits[bits.len() - checksum_bits..]
+        .iter()
+        .enumerate()
+        .all(|(i, &b)| (digest[0] & (0x80 >> i) != 0) == b);
+    if !checksum_ok {
+        return Err("checksum did not match on BIP-39 phrase".into());
+    }
+    Ok(key)
+}
+
+/// The parts of a backup that matter for reading back the PDDB inside it
+pub struct Backup {
+    pub dna: u64,
+    pub keyrom: Vec<u8>,
+    /// Set for backups that carry checksums over their contents; lists the blocks that failed
+    pub bad_blocks: Option<Vec<usize>>,
+}
+
+/// Decrypt and check the header of a backup made by root-keys, using the backup key
+pub fn open_backup(backup: &[u8], backup_key: &[u8]) -> Result<Backup, Box<dyn Error>> {
+    const CT_LEN: usize = BACKUP_PT_LEN + 16;
+    fn field<'a>(backup: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let f = backup.get(*offset..*offset + len).ok_or("backup header is truncated")?;
+        *offset += len;
+        Ok(f)
+    }
+    // `BackupDataCt` follows the plaintext copy of the header
+    let mut offset = BACKUP_HEADER_LEN;
+    let nonce = field(backup, &mut offset, 12)?;
+    let ct_plus_mac = field(backup, &mut offset, CT_LEN)?;
+    let commit_nonce = field(backup, &mut offset, 32)?;
+    let commitment = field(backup, &mut offset, 32)?;
+    offset += 4; // padded out to 8 bytes
+    let checked_len = offset;
+    let checksum = field(backup, &mut offset, 32)?;
+
+    let mut h_enc = Sha512_256::new();
+    h_enc.update(backup_key);
+    h_enc.update([0x43, 0x6f, 0x6, 0xd6, 0xd, 0x69, 0x74, 0x01, 0x01]);
+    h_enc.update(commit_nonce);
+    let mut h_com = Sha512_256::new();
+    h_com.update(backup_key);
+    h_com.update([0x43, 0x6f, 0x6, 0xd6, 0xd, 0x69, 0x74, 0x01, 0x02]);
+    h_com.update(commit_nonce);
+    if h_com.finalize()[..] != commitment[..] {
+        return Err("backup key commitment is incorrect; wrong backup key?".into());
+    }
+    let cipher = Aes256GcmSiv::new(&h_enc.finalize());
+    let pt = cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ct_plus_mac, aad: BACKUP_AAD.as_bytes() })
+        .map_err(|_| "backup header did not pass AES-GCM-SIV validation")?;
+
+    // `BackupHeader` from root-keys api.rs
+    let le_u32 = |offset: usize| u32::from_le_bytes(pt[offset..offset + 4].try_into().unwrap());
+    let version = le_u32(0);
+    let dna = u64::from_le_bytes(pt[88..96].try_into().unwrap());
+    let checksum_region_len = le_u32(96) as usize * 4096;
+    let total_checksums = le_u32(100) as usize;
+    let header_total_size = le_u32(104) as usize;
+    log::info!(
+        "backup version 0x{:x}, timestamp {} ms",
+        version,
+        u64::from_le_bytes(pt[72..80].try_into().unwrap())
+    );
+
+    let bad_blocks = if version == BACKUP_VERSION_CHECKSUMS && total_checksums != 0 {
+        if Sha512_256::digest(&backup[..checked_len])[..] != checksum[..] {
+            return Err("backup header failed its hash integrity check".into());
+        }
+        let checksums = backup
+            .get(header_total_size - total_checksums * 16..header_total_size)
+            .ok_or("backup checksums are truncated")?;
+        let mut bad = Vec::new();
+        for (block, expected) in checksums.chunks_exact(16).enumerate() {
+            let start = header_total_size + block * checksum_region_len;
+            let region = backup.get(start..start + checksum_region_len).unwrap_or(&[]);
+            if region.is_empty() || Sha512_256::digest(region)[..16] != expected[..] {
+                bad.push(block);
+            }
+        }
+        Some(bad)
+    } else {
+        None
+    };
+
+    Ok(Backup {
+        dna,
+        keyrom: pt[BACKUP_HEADER_LEN..BACKUP_HEADER_LEN + BACKUP_KEYROM_LEN].to_vec(),
+        bad_blocks,
+    })
+}
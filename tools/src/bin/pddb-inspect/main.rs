@@ -0,0 +1,441 @@
+//! Offline inspector for PDDB images: mounts a hosted-mode image, a Renode flash image or a backup,
+//! lists and extracts its contents, and checks it for consistency.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+
+mod image;
+mod keys;
+
+// These are shared verbatim with the PDDB
+#[path = "../../../../services/pddb/src/backend/bcrypt.rs"]
+mod bcrypt;
+#[path = "../../../../services/pddb/src/backend/layout.rs"]
+#[allow(dead_code)]
+mod layout;
+#[path = "../../../../services/pddb/src/backend/murmur3.rs"]
+mod murmur3;
+// and this with the backup key dialog in the GAM
+#[path = "../../../../services/gam/src/bip39/en.rs"]
+mod bip39;
+/// Stands in for the PDDB's api module, which `bcrypt` refers to
+mod api {
+    pub(crate) const PASSWORD_LEN: usize = 72;
+}
+
+use image::{Basis, Key, PddbImage};
+use layout::SpaceState;
+use keys::{BasisKeys, PDDB_DEFAULT_SYSTEM_BASIS};
+
+const IMAGE_DIR: &str = "tools/pddb-images";
+const RENODE_IMAGE: &str = "tools/pddb-images/renode.bin";
+const RENODE_KEYBOX: &str = "emulation/renode-keybox.bin";
+/// Location of the PDDB within the Renode flash image
+const RENODE_PDDB_START: usize = 0x01D8_0000;
+const RENODE_PDDB_END: usize = 0x07F8_0000;
+const SMALLDB_LEN: usize = 4 * 1024 * 1024;
+/// The backup key that is used when none has been set up
+const ZERO_KEY_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+
+fn main() {
+    env_logger::init();
+    let matches = App::new("pddb-inspect")
+        .version(crate_version!())
+        .about("Inspect, extract and verify PDDB images offline")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .value_name("name")
+                .takes_value(true)
+                .default_value("pddb")
+                .help("Hosted mode image root name; reads <name>.bin and <name>.key from tools/pddb-images"),
+        )
+        .arg(
+            Arg::with_name("image")
+                .long("image")
+                .value_name("file")
+                .takes_value(true)
+                .help("Hosted mode image to read, instead of the one given by --name"),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .value_name("file")
+                .takes_value(true)
+                .help("Key export to go with --image"),
+        )
+        .arg(
+            Arg::with_name("renode")
+                .long("renode")
+                .conflicts_with_all(&["image", "backup"])
+                .help("Read the PDDB out of the Renode flash image, using the Renode keybox"),
+        )
+        .arg(
+            Arg::with_name("smalldb")
+                .long("smalldb")
+                .requires("renode")
+                .help("The Renode image was built with a 4MiB PDDB"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .long("backup")
+                .value_name("file")
+                .takes_value(true)
+                .conflicts_with("image")
+                .help("Read the PDDB out of a backup file"),
+        )
+        .arg(
+            Arg::with_name("backup-key")
+                .long("backup-key")
+                .value_name("words")
+                .takes_value(true)
+                .default_value(ZERO_KEY_MNEMONIC)
+                .help("Backup key as BIP-39 words"),
+        )
+        .arg(
+            Arg::with_name("pin")
+                .short("p")
+                .long("pin")
+                .value_name("pin")
+                .takes_value(true)
+                .default_value("a")
+                .help("Unlock PIN, for Renode images and backups"),
+        )
+        .arg(
+            Arg::with_name("basis")
+                .long("basis")
+                .value_name("name:pass")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Secret basis to unlock, as `name:pass`. May be given more than once."),
+        )
+        .arg(
+            Arg::with_name("dna")
+                .long("dna")
+                .value_name("hex")
+                .takes_value(true)
+                .help("Override the device DNA the image is bound to (0 for hosted mode)"),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List the bases, dicts and keys in the image")
+                .arg(Arg::with_name("data").long("data").help("Also print the start of each key's data")),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Write keys out to files, as <out>/<basis>/<dict>/<key>")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("dir")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Directory to write the keys into"),
+                )
+                .arg(
+                    Arg::with_name("from-basis").long("from-basis").takes_value(true).help("Only this basis"),
+                )
+                .arg(Arg::with_name("dict").long("dict").takes_value(true).help("Only this dict"))
+                .arg(Arg::with_name("key").long("key").takes_value(true).help("Only this key")),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check the image for consistency; exits with an error if it isn't")
+                .arg(
+                    Arg::with_name("ci")
+                        .long("ci")
+                        .help("Also check the checksums that the PDDB CI tests append to every key"),
+                ),
+        )
+        .get_matches();
+
+    let result = run(&matches);
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// An image with the keys of every basis that could be found for it
+struct Mounted {
+    image: PddbImage,
+    keys: Vec<(String, BasisKeys)>,
+    /// Set when the image came out of a backup that carries checksums
+    bad_blocks: Option<Vec<usize>>,
+}
+
+fn mount(matches: &ArgMatches) -> Result<Mounted, Box<dyn Error>> {
+    let dna_override = match matches.value_of("dna") {
+        Some(dna) => Some(u64::from_str_radix(dna.trim_start_matches("0x"), 16)?),
+        None => None,
+    };
+    let pin = matches.value_of("pin").unwrap();
+    let mut bad_blocks = None;
+    let (image, mut keys) = if matches.is_present("renode") {
+        let keyrom = fs::read(RENODE_KEYBOX)?;
+        let flash = fs::read(RENODE_IMAGE)?;
+        let end =
+            if matches.is_present("smalldb") { RENODE_PDDB_START + SMALLDB_LEN } else { RENODE_PDDB_END };
+        let raw = flash.get(RENODE_PDDB_START..end).ok_or("Renode image is too short to hold a PDDB")?;
+        let image = PddbImage::new(raw.to_vec(), dna_override.unwrap_or(0))?;
+        let system = keys::system_keys(&keyrom, &image.static_crypto_data(), pin)?;
+        (image, vec![(PDDB_DEFAULT_SYSTEM_BASIS.to_string(), system)])
+    } else if let Some(path) = matches.value_of("backup") {
+        let backup = fs::read(path)?;
+        let backup_key = keys::bip39_to_key(matches.value_of("backup-key").unwrap())?;
+        let header = keys::open_backup(&backup, &backup_key)?;
+        bad_blocks = header.bad_blocks;
+        let raw = backup.get(keys::BACKUP_PDDB_OFFSET..).ok_or("backup has no PDDB")?;
+        let image = PddbImage::new(raw.to_vec(), dna_override.unwrap_or(header.dna))?;
+        let system = keys::system_keys(&header.keyrom, &image.static_crypto_data(), pin)?;
+        (image, vec![(PDDB_DEFAULT_SYSTEM_BASIS.to_string(), system)])
+    } else {
+        let name = matches.value_of("name").unwrap();
+        let image_path = match matches.value_of("image") {
+            Some(path) => PathBuf::from(path),
+            None => Path::new(IMAGE_DIR).join(format!("{}.bin", name)),
+        };
+        let keys_path = match matches.value_of("keys") {
+            Some(path) => PathBuf::from(path),
+            None => image_path.with_extension("key"),
+        };
+        let image = PddbImage::new(fs::read(&image_path)?, dna_override.unwrap_or(0))?;
+        let keys = match fs::read(&keys_path) {
+            Ok(export) => keys::read_hosted_keys(&export)?,
+            Err(e) => {
+                eprintln!("no key export at {}: {}; only --basis keys will be used", keys_path.display(), e);
+                Vec::new()
+            }
+        };
+        (image, keys)
+    };
+    for credentials in matches.values_of("basis").into_iter().flatten() {
+        let (name, password) = credentials
+            .split_once(':')
+            .ok_or_else(|| format!("basis credentials `{}` are not of the form name:pass", credentials))?;
+        keys.retain(|(n, _)| n != name);
+        keys.push((name.to_string(), keys::derive_basis_keys(&image.static_crypto_data(), name, password)));
+    }
+    Ok(Mounted { image, keys, bad_blocks })
+}
+
+fn run(matches: &ArgMatches) -> Result<bool, Box<dyn Error>> {
+    let mounted = mount(matches)?;
+    let mut bases = Vec::new();
+    let mut ok = true;
+    for (name, keys) in mounted.keys.iter() {
+        match mounted.image.open_basis(name, keys) {
+            Ok(basis) => bases.push(basis),
+            Err(e) => {
+                eprintln!("{}", e);
+                ok = false;
+            }
+        }
+    }
+
+    match matches.subcommand() {
+        ("list", Some(sub)) => list(&bases, sub.is_present("data")),
+        ("extract", Some(sub)) => ok &= extract(&bases, sub)?,
+        ("verify", Some(sub)) => {
+            let system = mounted.keys.iter().find(|(n, _)| n == PDDB_DEFAULT_SYSTEM_BASIS).map(|(_, k)| k);
+            ok &= verify(&mounted, &bases, system, sub.is_present("ci"))
+        }
+        _ => unreachable!("clap requires a subcommand"),
+    }
+    Ok(ok)
+}
+
+fn list(bases: &[Basis], show_data: bool) {
+    for basis in bases {
+        println!("basis {} (age {}, {} dicts)", basis.name, basis.age, basis.num_dicts);
+        for dict in basis.dicts() {
+            let versions = if dict.versions != 0 {
+                format!(", keeps {} versions", dict.versions)
+            } else {
+                String::new()
+            };
+            println!(
+                "  dict {} (index {}, age {}, {} keys{})",
+                dict.name, dict.index, dict.age, dict.num_keys, versions
+            );
+            for (name, key) in dict.keys.iter() {
+                let name = match image::version_of(name) {
+                    Some((key, version)) => format!("{} (version {})", key, version),
+                    None => name.clone(),
+                };
+                println!(
+                    "    {} ({} bytes of {} reserved, {} pool at 0x{:x}, age {}{})",
+                    name,
+                    key.len,
+                    key.reserved,
+                    if key.is_large() { "large" } else { "small" },
+                    key.start,
+                    key.age,
+                    if key.unresolved { ", unresolved" } else { "" }
+                );
+                if show_data {
+                    match basis.read_key(key) {
+                        Ok(data) => println!("      {:02x?}", &data[..data.len().min(32)]),
+                        Err(e) => println!("      unreadable: {}", e),
+                    }
+                }
+            }
+            for error in dict.errors.iter() {
+                println!("    error: {}", error);
+            }
+        }
+    }
+}
+
+/// Turns a basis, dict or key name into something that can be used as a file name
+fn file_name(name: &str) -> String {
+    name.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }).collect()
+}
+
+fn extract(bases: &[Basis], sub: &ArgMatches) -> Result<bool, Box<dyn Error>> {
+    let out = Path::new(sub.value_of("out").unwrap());
+    let mut ok = true;
+    let mut count = 0;
+    for basis in bases.iter().filter(|b| sub.value_of("from-basis").map_or(true, |n| n == b.name)) {
+        for dict in basis.dicts().iter().filter(|d| sub.value_of("dict").map_or(true, |n| n == d.name)) {
+            let dir = out.join(file_name(&basis.name)).join(file_name(&dict.name));
+            for (name, key) in dict.keys.iter().filter(|(k, _)| sub.value_of("key").map_or(true, |n| n == *k))
+            {
+                match basis.read_key(key) {
+                    Ok(data) => {
+                        fs::create_dir_all(&dir)?;
+                        let path = dir.join(file_name(name));
+                        fs::write(&path, data)?;
+                        println!("{}:{}:{} -> {}", basis.name, dict.name, name, path.display());
+                        count += 1;
+                    }
+                    Err(e) => {
+                        eprintln!("{}:{}:{} could not be read: {}", basis.name, dict.name, name, e);
+                        ok = false;
+                    }
+                }
+            }
+        }
+    }
+    println!("extracted {} keys", count);
+    Ok(ok)
+}
+
+/// Checks the trailing checksum that the PDDB CI tests append to the keys they write
+fn ci_checksum_ok(data: &[u8]) -> bool {
+    if data.len() < 4 {
+        return false;
+    }
+    let (body, sum) = data.split_at(data.len() - 4);
+    let mut padded = body.to_vec();
+    padded.resize((body.len() + 3) & !3, 0);
+    murmur3::murmur3_32(&padded, 0) == u32::from_le_bytes([sum[0], sum[1], sum[2], sum[3]])
+}
+
+fn check_key(basis: &Basis, dict: &str, name: &str, key: &Key, ci: bool) -> Result<(), String> {
+    if key.len > key.reserved {
+        return Err(format!("length {} exceeds the {} bytes reserved", key.len, key.reserved));
+    }
+    let data = basis.read_key(key)?;
+    if ci && !ci_checksum_ok(&data) {
+        return Err("CI checksum does not match".to_string());
+    }
+    // CI key names are of the form sanitycheck|<dict>|<key>|len<length>
+    if ci && name.split('|').nth(1).map_or(false, |d| d != dict) {
+        return Err(format!("key is named for another dict than {}", dict));
+    }
+    Ok(())
+}
+
+fn verify(mounted: &Mounted, bases: &[Basis], system: Option<&BasisKeys>, ci: bool) -> bool {
+    let mut ok = true;
+    let mut all_dicts = true;
+    if let Some(bad_blocks) = &mounted.bad_blocks {
+        if bad_blocks.is_empty() {
+            println!("backup checksums: OK");
+        } else {
+            println!("backup checksums: FAIL on blocks {:?}", bad_blocks);
+            ok = false;
+        }
+    }
+
+    for basis in bases {
+        let dicts = basis.dicts();
+        if dicts.len() as u32 != basis.num_dicts {
+            println!("basis {}: expected {} dicts, only found {}", basis.name, basis.num_dicts, dicts.len());
+            all_dicts = false;
+        }
+        for dict in dicts.iter() {
+            let mut dict_ok = dict.errors.is_empty();
+            for error in dict.errors.iter() {
+                println!("{}:{}: {}", basis.name, dict.name, error);
+            }
+            for (name, key) in dict.keys.iter() {
+                if let Err(e) = check_key(basis, &dict.name, name, key, ci) {
+                    println!("{}:{}:{}: {}", basis.name, dict.name, name, e);
+                    dict_ok = false;
+                }
+            }
+            println!(
+                "{}:{} ({} keys): {}",
+                basis.name,
+                dict.name,
+                dict.keys.len(),
+                if dict_ok { "OK" } else { "FAIL" }
+            );
+            ok &= dict_ok;
+        }
+    }
+
+    // every page should belong to at most one basis, and none of them should be up for reuse
+    let mut owners = HashMap::<u32, &str>::new();
+    for basis in bases {
+        for (vaddr, page) in basis.mapped_pages() {
+            if let Some(other) = owners.insert(page, &basis.name) {
+                println!("page {} is mapped by both {} and {} (v{:x})", page, other, basis.name, vaddr);
+                ok = false;
+            }
+        }
+    }
+    match system.map(|keys| mounted.image.fast_space(keys)) {
+        Some(Ok(space)) => {
+            for (&page, owner) in owners.iter() {
+                if let Some(record) = space.get(&page) {
+                    if record.space_state() == SpaceState::Free || record.space_state() == SpaceState::Dirty {
+                        println!(
+                            "page {} is in use by {} but the FSCB has it as {:?}",
+                            page,
+                            owner,
+                            record.space_state()
+                        );
+                        ok = false;
+                    }
+                }
+            }
+            println!("free space: {} pages in use, {} pages tracked by the FSCB", owners.len(), space.len());
+        }
+        Some(Err(e)) => {
+            println!("free space: {}", e);
+            ok = false;
+        }
+        None => println!("free space: not checked, the system basis keys are not known"),
+    }
+
+    if all_dicts {
+        println!("All dicts were found.");
+    } else {
+        println!("Missing dictionaries, something is wrong.");
+        ok = false;
+    }
+    ok
+}
@@ -2,8 +2,8 @@ use core::convert::TryInto;
 
 use rkyv::{ser::Serializer, Fallible};
 use xous::{
-    map_memory, send_message, unmap_memory, Error, MemoryAddress, MemoryFlags, MemoryMessage, MemoryRange,
-    MemorySize, Message, Result, CID,
+    map_memory, send_message, try_send_message, unmap_memory, Error, MemoryAddress, MemoryFlags,
+    MemoryMessage, MemoryRange, MemorySize, Message, Result, CID,
 };
 
 #[derive(Debug)]
@@ -165,6 +165,23 @@ impl<'a> Buffer<'a> {
         Ok(result)
     }
 
+    /// Like `send()`, but fails with `ServerQueueFull` rather than blocking when the server's queue
+    /// is full. The buffer is only given up if the message was queued.
+    #[allow(dead_code)]
+    pub fn try_send(mut self, connection: CID, id: u32) -> core::result::Result<Result, Error> {
+        let msg = MemoryMessage {
+            id: id as usize,
+            buf: self.valid,
+            offset: self.offset,
+            valid: MemorySize::new(self.slice.len()),
+        };
+        let result = try_send_message(connection, Message::Move(msg))?;
+
+        // prevents it from being Dropped.
+        self.should_drop = false;
+        Ok(result)
+    }
+
     #[allow(dead_code)]
     pub fn into_buf<S>(src: S) -> core::result::Result<Self, ()>
     where
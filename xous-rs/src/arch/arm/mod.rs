@@ -2,6 +2,7 @@ use crate::definitions::SysCallResult;
 use crate::MemoryAddress;
 use crate::MemoryFlags;
 use crate::MemoryRange;
+use crate::ThreadPriority;
 use crate::TID;
 
 pub mod irq;
@@ -272,11 +273,20 @@ pub struct ThreadInit {
     pub arg2: usize,
     pub arg3: usize,
     pub arg4: usize,
+    /// Priority to start the thread at, or `None` to inherit the creator's
+    pub priority: Option<ThreadPriority>,
 }
 
 impl ThreadInit {
     pub fn new(call: usize, stack: MemoryRange, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> Self {
-        ThreadInit { call, stack, arg1, arg2, arg3, arg4 }
+        ThreadInit { call, stack, arg1, arg2, arg3, arg4, priority: None }
+    }
+
+    /// Start the thread at `priority` instead of the priority of the thread creating it. The priority
+    /// is passed to the kernel in place of `arg4`, so the thread must not need a fourth argument.
+    pub fn with_priority(self, priority: ThreadPriority) -> Self {
+        debug_assert!(self.arg4 == 0, "threads started at a priority can't take a fourth argument");
+        ThreadInit { priority: Some(priority), ..self }
     }
 }
 
@@ -289,6 +299,7 @@ impl Default for ThreadInit {
             arg2: 0,
             arg3: 0,
             arg4: 0,
+            priority: None,
         }
     }
 }
@@ -312,6 +323,7 @@ pub fn args_to_thread(
         arg2: a5,
         arg3: a6,
         arg4: a7,
+        priority: None,
     })
 }
 
@@ -7,8 +7,18 @@ use crate::{Result, TID};
 thread_local!(pub static THREAD_ID: RefCell<Option<TID>> = RefCell::new(None));
 
 /// Describes the parameters required to create a new thread on this platform.
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct ThreadInit {}
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ThreadInit {
+    /// Priority to start the thread at, or `None` to inherit the creator's
+    pub priority: Option<crate::ThreadPriority>,
+}
+
+impl ThreadInit {
+    /// Start the thread at `priority` instead of the priority of the thread creating it
+    pub fn with_priority(self, priority: crate::ThreadPriority) -> Self {
+        ThreadInit { priority: Some(priority) }
+    }
+}
 pub struct WaitHandle<T>(std::thread::JoinHandle<T>);
 
 pub fn thread_to_args(call: usize, _init: &ThreadInit) -> [usize; 8] { [call, 0, 0, 0, 0, 0, 0, 0] }
@@ -22,14 +32,14 @@ pub fn args_to_thread(
     _a6: usize,
     _a7: usize,
 ) -> core::result::Result<ThreadInit, crate::Error> {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_0_pre<U>(_f: &fn() -> U) -> core::result::Result<ThreadInit, crate::Error>
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_1_pre<U>(
     _f: &fn(usize) -> U,
@@ -38,7 +48,7 @@ pub fn create_thread_1_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_2_pre<U>(
     _f: &fn(usize, usize) -> U,
@@ -48,7 +58,7 @@ pub fn create_thread_2_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_3_pre<U>(
     _f: &fn(usize, usize, usize) -> U,
@@ -59,7 +69,7 @@ pub fn create_thread_3_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_4_pre<U>(
     _f: &fn(usize, usize, usize, usize) -> U,
@@ -71,7 +81,7 @@ pub fn create_thread_4_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_0_post<U>(
@@ -142,7 +152,7 @@ where
     T: Send + 'static,
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_simple_post<T, U>(
@@ -163,7 +173,7 @@ where
     F: Send + 'static,
     T: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 /// Spawn a new thread with the given thread ID.
@@ -198,7 +208,7 @@ pub(crate) fn thread_id() -> TID {
         if let Some(tid) = *tid.borrow() {
             return tid;
         }
-        let call = crate::SysCall::CreateThread(ThreadInit {});
+        let call = crate::SysCall::CreateThread(ThreadInit::default());
 
         let fake_tid = FAKE_THREAD_COUNTER.fetch_add(1, Ordering::SeqCst);
         // println!(
@@ -1,4 +1,4 @@
-use crate::{MemoryRange, TID};
+use crate::{MemoryRange, ThreadPriority, TID};
 
 mod mem;
 pub use mem::*;
@@ -16,6 +16,8 @@ pub struct ThreadInit {
     pub arg2: usize,
     pub arg3: usize,
     pub arg4: usize,
+    /// Priority to start the thread at, or `None` to inherit the creator's
+    pub priority: Option<ThreadPriority>,
     // pub name: [u8; 12],
 }
 
@@ -36,9 +38,17 @@ impl ThreadInit {
             arg2,
             arg3,
             arg4,
+            priority: None,
             // name,
         }
     }
+
+    /// Start the thread at `priority` instead of the priority of the thread creating it. The priority
+    /// is passed to the kernel in place of `arg4`, so the thread must not need a fourth argument.
+    pub fn with_priority(self, priority: ThreadPriority) -> Self {
+        debug_assert!(self.arg4 == 0, "threads started at a priority can't take a fourth argument");
+        ThreadInit { priority: Some(priority), ..self }
+    }
 }
 
 impl Default for ThreadInit {
@@ -50,6 +60,7 @@ impl Default for ThreadInit {
             arg2: 0,
             arg3: 0,
             arg4: 0,
+            priority: None,
             // name: [0; 12],
         }
     }
@@ -92,6 +103,7 @@ pub fn args_to_thread(
         arg2: a5,
         arg3: a6,
         arg4: a7,
+        priority: None,
         // name: [0; 12],
     })
 }
@@ -38,8 +38,18 @@ impl From<&str> for ProcessKey {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct ThreadInit {}
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ThreadInit {
+    /// Priority to start the thread at, or `None` to inherit the creator's
+    pub priority: Option<crate::ThreadPriority>,
+}
+
+impl ThreadInit {
+    /// Start the thread at `priority` instead of the priority of the thread creating it
+    pub fn with_priority(self, priority: crate::ThreadPriority) -> Self {
+        ThreadInit { priority: Some(priority) }
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProcessInit {
@@ -282,7 +292,7 @@ pub fn args_to_thread(
     _a6: usize,
     _a7: usize,
 ) -> core::result::Result<ThreadInit, crate::Error> {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn args_to_process(
@@ -348,7 +358,7 @@ pub fn create_thread_0_pre<U>(_f: &fn() -> U) -> core::result::Result<ThreadInit
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_1_pre<U>(
     _f: &fn(usize) -> U,
@@ -357,7 +367,7 @@ pub fn create_thread_1_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_2_pre<U>(
     _f: &fn(usize, usize) -> U,
@@ -367,7 +377,7 @@ pub fn create_thread_2_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_3_pre<U>(
     _f: &fn(usize, usize, usize) -> U,
@@ -378,7 +388,7 @@ pub fn create_thread_3_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 pub fn create_thread_4_pre<U>(
     _f: &fn(usize, usize, usize, usize) -> U,
@@ -390,7 +400,7 @@ pub fn create_thread_4_pre<U>(
 where
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_0_post<U>(
@@ -461,7 +471,7 @@ where
     T: Send + 'static,
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_simple_post<T, U>(
@@ -482,7 +492,7 @@ where
     F: Send + 'static,
     T: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::default())
 }
 
 pub fn create_thread_post<F, U>(f: F, thread_id: TID) -> core::result::Result<WaitHandle<U>, crate::Error>
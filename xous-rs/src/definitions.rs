@@ -86,6 +86,12 @@ pub use messages::*;
 pub mod limits;
 pub use limits::*;
 
+pub mod priority;
+pub use priority::*;
+
+pub mod processinfo;
+pub use processinfo::*;
+
 use crate::arch::ProcessStartup;
 
 /// Server ID
@@ -0,0 +1,43 @@
+/// The scheduling priority of a thread. When more than one thread in a process
+/// is ready to run, the kernel picks the thread with the highest priority,
+/// round-robining between threads that share the same priority. Likewise, when
+/// more than one process is ready, the kernel picks the process whose ready
+/// threads include the highest priority.
+///
+/// Threads that are repeatedly passed over are temporarily boosted above every
+/// priority, `Realtime` included, so that a busy high-priority thread cannot
+/// starve the rest of the process; processes that are passed over are boosted
+/// the same way. Priorities are therefore a preference, not a guarantee: any
+/// ready thread eventually runs.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ThreadPriority {
+    /// Preferably run only when nothing else in the process is ready. A thread
+    /// that keeps being passed over is still boosted, and runs all the same.
+    Idle = 0,
+    /// Background work such as scrubbing or housekeeping
+    Low = 1,
+    /// The default priority for all new threads
+    #[default]
+    Normal = 2,
+    /// Latency-sensitive work such as UI and input handling
+    High = 3,
+    /// Reserved for threads that must respond promptly. Starved threads of any
+    /// priority are still let in ahead of it.
+    Realtime = 4,
+}
+
+impl ThreadPriority {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(ThreadPriority::Idle),
+            1 => Some(ThreadPriority::Low),
+            2 => Some(ThreadPriority::Normal),
+            3 => Some(ThreadPriority::High),
+            4 => Some(ThreadPriority::Realtime),
+            _ => None,
+        }
+    }
+
+    pub fn to_usize(&self) -> usize { *self as usize }
+}
@@ -0,0 +1,56 @@
+/// A snapshot of the resources held by a process, as returned by the
+/// `GetProcessInfo` syscall.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct ProcessInfo {
+    /// Current size of the heap, in bytes
+    pub heap_size: usize,
+
+    /// Number of pages of memory owned by this process. This includes
+    /// pages mapped from peripherals. Always `0` in hosted mode.
+    pub mapped_pages: usize,
+
+    /// Number of threads currently allocated. Reported in 8 bits.
+    pub thread_count: usize,
+
+    /// Number of servers owned by this process. Reported in 8 bits.
+    pub server_count: usize,
+
+    /// Number of connections this process has open to servers. Reported in 8 bits.
+    pub connection_count: usize,
+
+    /// CPU time used by this process, in milliseconds. This is sampled: each
+    /// tick of the preemption timer, every `BASE_QUANTA_MS`, is charged to the
+    /// process it interrupted. Always `0` in hosted mode.
+    pub cpu_time_ms: u64,
+}
+
+impl ProcessInfo {
+    /// Pack this struct into the five arguments of a `Scalar5` result.
+    ///
+    /// The thread, server and connection counts share one argument, 8 bits each.
+    /// The kernel's limits (32 threads and connections, 128 servers) fit, and
+    /// anything larger saturates at 255 rather than wrapping.
+    pub fn to_scalar5(&self) -> [usize; 5] {
+        [
+            self.heap_size,
+            self.mapped_pages,
+            self.thread_count.min(0xff)
+                | (self.server_count.min(0xff) << 8)
+                | (self.connection_count.min(0xff) << 16),
+            self.cpu_time_ms as u32 as usize,
+            (self.cpu_time_ms >> 32) as u32 as usize,
+        ]
+    }
+
+    /// Unpack the five arguments of a `Scalar5` result into a `ProcessInfo`
+    pub fn from_scalar5(args: [usize; 5]) -> Self {
+        ProcessInfo {
+            heap_size: args[0],
+            mapped_pages: args[1],
+            thread_count: args[2] & 0xff,
+            server_count: (args[2] >> 8) & 0xff,
+            connection_count: (args[2] >> 16) & 0xff,
+            cpu_time_ms: (args[3] as u32 as u64) | ((args[4] as u32 as u64) << 32),
+        }
+    }
+}
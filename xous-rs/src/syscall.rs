@@ -9,8 +9,8 @@ use crate::Exception
 pub use crate::arch::ProcessArgsAsThread;
 use crate::{
     pid_from_usize, CpuID, Error, MemoryAddress, MemoryFlags, MemoryMessage, MemoryRange, MemorySize,
-    MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs, ProcessInit, Result, ScalarMessage,
-    SysCallResult, ThreadInit, CID, PID, SID, TID,
+    MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs, ProcessInfo, ProcessInit, Result,
+    ScalarMessage, SysCallResult, ThreadInit, ThreadPriority, CID, PID, SID, TID,
 };
 
 #[derive(Debug, PartialEq)]
@@ -353,7 +353,9 @@ pub enum SysCall {
     /// Return two scalars to the sender
     ReturnScalar2(MessageSender, usize, usize),
 
-    /// Spawn a new thread
+    /// Spawn a new thread. A thread with a priority set in its `ThreadInit` is
+    /// created with `SysCallNumber::CreateThreadWithPriority`, which carries the
+    /// priority in the last argument register.
     CreateThread(ThreadInit),
 
     /// Create a new process, setting the current process as the parent ID.
@@ -561,6 +563,89 @@ pub enum SysCall {
     #[cfg(feature = "raw-trng")]
     RawTrng(usize, usize, usize, usize, usize, usize, usize),
 
+    /// Set the scheduling priority of a thread within the current process.
+    /// When several threads of a process are ready to run, the thread with
+    /// the highest priority is picked first. Threads that have been passed
+    /// over too many times are boosted so they will eventually run.
+    ///
+    /// New threads inherit the priority of the thread that created them, unless
+    /// a priority was set in the `ThreadInit` passed to `CreateThread`.
+    ///
+    /// ## Arguments
+    ///
+    /// * **TID**: The thread to adjust. Pass `0` to adjust the calling thread.
+    /// * **Priority**: The new `ThreadPriority` for this thread.
+    ///
+    /// ## Returns
+    ///
+    /// Returns a Scalar1 containing the previous priority of the thread.
+    ///
+    /// ## Errors
+    ///
+    /// * **InvalidThread**: The thread ID is out of range
+    /// * **ThreadNotAvailable**: The thread does not exist
+    /// * **InvalidSyscall**: The priority value was not valid
+    SetThreadPriority(TID, ThreadPriority),
+
+    /// Get a snapshot of the resources currently held by a process.
+    ///
+    /// ## Arguments
+    ///
+    /// * **PID**: The process to query
+    ///
+    /// ## Returns
+    ///
+    /// Returns a Scalar5 that may be decoded with `ProcessInfo::from_scalar5()`.
+    ///
+    /// ## Errors
+    ///
+    /// * **ProcessNotFound**: The process does not exist
+    GetProcessInfo(PID),
+
+    /// Ask the kernel to send a message to one of this process' servers
+    /// whenever a peer process exits. A peer is any process that this process
+    /// is connected to, or that is connected to one of this process' servers.
+    ///
+    /// The notification is a `Scalar` message with the given ID. `arg1`
+    /// contains the PID of the process that exited, which is also given as
+    /// the message sender. Calling this again replaces the previous
+    /// registration.
+    ///
+    /// ## Arguments
+    ///
+    /// * **SID**: A server owned by this process
+    /// * **Opcode**: The message ID to use for notifications
+    ///
+    /// ## Returns
+    ///
+    /// Returns `Ok` if the registration was accepted.
+    ///
+    /// ## Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist or is owned by another process
+    SetPeerExitNotification(SID, usize),
+
+    /// Read part of the name the loader gave a process. Unlike server names,
+    /// these are fixed when the image is built and cannot be chosen by the
+    /// process itself. Processes created after boot have no name.
+    ///
+    /// ## Arguments
+    ///
+    /// * **PID**: The process to query
+    /// * **offset**: The byte offset into the name to start reading from
+    ///
+    /// ## Returns
+    ///
+    /// Returns a Scalar5 containing the length of the whole name in bytes,
+    /// followed by up to 16 bytes of the name starting at `offset`, packed
+    /// four bytes per word in little-endian order. A length of 0 means the
+    /// process has no name.
+    ///
+    /// ## Errors
+    ///
+    /// * **ProcessNotFound**: The process does not exist
+    GetProcessName(PID, usize),
+
     /// This syscall does not exist. It captures all possible
     /// arguments so detailed analysis can be performed.
     Invalid(usize, usize, usize, usize, usize, usize, usize),
@@ -618,6 +703,11 @@ pub enum SysCallNumber {
     SwapOp = 44,
     #[cfg(feature = "raw-trng")]
     RawTrng = 45,
+    SetThreadPriority = 46,
+    GetProcessInfo = 47,
+    SetPeerExitNotification = 48,
+    CreateThreadWithPriority = 49,
+    GetProcessName = 50,
 }
 
 impl SysCallNumber {
@@ -673,6 +763,11 @@ impl SysCallNumber {
             44 => SwapOp,
             #[cfg(feature = "raw-trng")]
             45 => RawTrng,
+            46 => SetThreadPriority,
+            47 => GetProcessInfo,
+            48 => SetPeerExitNotification,
+            49 => CreateThreadWithPriority,
+            50 => GetProcessName,
             _ => Invalid,
         }
     }
@@ -837,9 +932,15 @@ impl SysCall {
                 *return_type,
             ],
 
-            SysCall::CreateThread(init) => {
-                crate::arch::thread_to_args(SysCallNumber::CreateThread as usize, init)
-            }
+            SysCall::CreateThread(init) => match init.priority {
+                None => crate::arch::thread_to_args(SysCallNumber::CreateThread as usize, init),
+                Some(priority) => {
+                    let mut args =
+                        crate::arch::thread_to_args(SysCallNumber::CreateThreadWithPriority as usize, init);
+                    args[7] = priority.to_usize();
+                    args
+                }
+            },
             SysCall::CreateProcess(init) => Self::add_opcode(SysCallNumber::CreateProcess, init.into()),
             SysCall::TerminateProcess(exit_code) => {
                 [SysCallNumber::TerminateProcess as usize, *exit_code as usize, 0, 0, 0, 0, 0, 0]
@@ -926,6 +1027,28 @@ impl SysCall {
             SysCall::RawTrng(a1, a2, a3, a4, a5, a6, a7) => {
                 [SysCallNumber::RawTrng as usize, *a1, *a2, *a3, *a4, *a5, *a6, *a7]
             }
+            SysCall::SetThreadPriority(tid, priority) => {
+                [SysCallNumber::SetThreadPriority as usize, *tid, priority.to_usize(), 0, 0, 0, 0, 0]
+            }
+            SysCall::GetProcessInfo(pid) => {
+                [SysCallNumber::GetProcessInfo as usize, pid.get() as usize, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::SetPeerExitNotification(sid, opcode) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::SetPeerExitNotification as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *opcode,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::GetProcessName(pid, offset) => {
+                [SysCallNumber::GetProcessName as usize, pid.get() as usize, *offset, 0, 0, 0, 0, 0]
+            }
             SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7) => {
                 [SysCallNumber::Invalid as usize, *a1, *a2, *a3, *a4, *a5, *a6, *a7]
             }
@@ -1005,6 +1128,10 @@ impl SysCall {
             SysCallNumber::CreateThread => {
                 SysCall::CreateThread(crate::arch::args_to_thread(a1, a2, a3, a4, a5, a6, a7)?)
             }
+            SysCallNumber::CreateThreadWithPriority => SysCall::CreateThread(
+                crate::arch::args_to_thread(a1, a2, a3, a4, a5, a6, 0)?
+                    .with_priority(ThreadPriority::from_usize(a7).ok_or(Error::InvalidSyscall)?),
+            ),
             SysCallNumber::CreateProcess => SysCall::CreateProcess([a1, a2, a3, a4, a5, a6, a7].try_into()?),
             SysCallNumber::TerminateProcess => SysCall::TerminateProcess(a1 as u32),
             SysCallNumber::Shutdown => SysCall::Shutdown,
@@ -1080,6 +1207,15 @@ impl SysCall {
             SysCallNumber::SwapOp => SysCall::SwapOp(a1, a2, a3, a4, a5, a6, a7),
             #[cfg(feature = "raw-trng")]
             SysCallNumber::RawTrng => SysCall::RawTrng(a1, a2, a3, a4, a5, a6, a7),
+            SysCallNumber::SetThreadPriority => SysCall::SetThreadPriority(
+                a1 as _,
+                ThreadPriority::from_usize(a2).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::GetProcessInfo => SysCall::GetProcessInfo(pid_from_usize(a1)?),
+            SysCallNumber::SetPeerExitNotification => {
+                SysCall::SetPeerExitNotification(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _), a5)
+            }
+            SysCallNumber::GetProcessName => SysCall::GetProcessName(pid_from_usize(a1)?, a2),
             SysCallNumber::Invalid => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
         })
     }
@@ -1703,6 +1839,28 @@ where
     })
 }
 
+/// Create a new thread that starts at `priority` rather than inheriting the
+/// priority of the calling thread. The priority is in place before the new
+/// thread is first scheduled.
+pub fn create_thread_with_priority<F, T>(
+    priority: ThreadPriority,
+    f: F,
+) -> core::result::Result<crate::arch::WaitHandle<T>, Error>
+where
+    F: FnOnce() -> T,
+    F: Send + 'static,
+    T: Send + 'static,
+{
+    let thread_info = crate::arch::create_thread_pre(&f)?.with_priority(priority);
+    rsyscall(SysCall::CreateThread(thread_info)).and_then(|result| {
+        if let Result::ThreadID(thread_id) = result {
+            crate::arch::create_thread_post(f, thread_id)
+        } else {
+            Err(Error::InternalError)
+        }
+    })
+}
+
 /// Wait for a thread to finish. This is equivalent to `join_thread`
 pub fn wait_thread<T>(joiner: crate::arch::WaitHandle<T>) -> SysCallResult {
     crate::arch::wait_thread(joiner)
@@ -1798,6 +1956,82 @@ pub fn join_thread(tid: TID) -> core::result::Result<usize, Error> {
     })
 }
 
+/// Set the scheduling priority of a thread in the current process. Pass a
+/// `tid` of `0` to adjust the calling thread. Returns the previous priority.
+///
+/// # Errors
+///
+/// * **InvalidThread**: The thread ID is out of range
+/// * **ThreadNotAvailable**: The thread does not exist
+pub fn set_thread_priority(
+    tid: TID,
+    priority: ThreadPriority,
+) -> core::result::Result<ThreadPriority, Error> {
+    rsyscall(SysCall::SetThreadPriority(tid, priority)).and_then(|result| {
+        if let Result::Scalar1(previous) = result {
+            ThreadPriority::from_usize(previous).ok_or(Error::InternalError)
+        } else {
+            Err(Error::InternalError)
+        }
+    })
+}
+
+/// Get a snapshot of the memory, threads, servers, connections and CPU time
+/// held by the specified process.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: The process does not exist
+pub fn process_info(pid: PID) -> core::result::Result<ProcessInfo, Error> {
+    rsyscall(SysCall::GetProcessInfo(pid)).and_then(|result| {
+        if let Result::Scalar5(a1, a2, a3, a4, a5) = result {
+            Ok(ProcessInfo::from_scalar5([a1, a2, a3, a4, a5]))
+        } else {
+            Err(Error::InternalError)
+        }
+    })
+}
+
+/// Have the kernel send a `Scalar` message with ID `opcode` to `sid` whenever a
+/// peer of this process exits. `arg1` of the message holds the PID of the
+/// process that exited.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The server does not exist or is owned by another process
+pub fn set_peer_exit_notification(sid: SID, opcode: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetPeerExitNotification(sid, opcode))
+        .and_then(|result| if let Result::Ok = result { Ok(()) } else { Err(Error::InternalError) })
+}
+
+/// Copy the name the loader gave the specified process into `name`, and
+/// return the full length of the name in bytes. If `name` is too short the
+/// name is truncated. Processes that were not part of the boot image have no
+/// name, and return a length of 0.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: The process does not exist
+pub fn process_name(pid: PID, name: &mut [u8]) -> core::result::Result<usize, Error> {
+    let mut offset = 0;
+    loop {
+        let (len, words) = match rsyscall(SysCall::GetProcessName(pid, offset))? {
+            Result::Scalar5(len, w0, w1, w2, w3) => (len, [w0, w1, w2, w3]),
+            _ => return Err(Error::InternalError),
+        };
+        for (i, word) in words.iter().enumerate() {
+            for (j, byte) in (*word as u32).to_le_bytes().iter().enumerate() {
+                let index = offset + i * 4 + j;
+                if index >= len || index >= name.len() {
+                    return Ok(len);
+                }
+                name[index] = *byte;
+            }
+        }
+        offset += 16;
+    }
+}
+
 /// Reply to the message, if one exists, and receive the next one.
 /// If no message exists, delegate the call to `receive_syscall()`.
 pub fn reply_and_receive_next(